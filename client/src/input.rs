@@ -4,10 +4,16 @@ use std::{
 };
 
 use native::{
-    client::{create_request, read_response, ConsistencyLevel, QUERY, READY, RESULT, STARTUP},
+    client::{
+        create_query_request, create_request, read_response, ConsistencyLevel, QueryParameters,
+        READY, RESULT, STARTUP,
+    },
     server::ERROR,
 };
 
+/// The number of rows of each page of the results of a query.
+const PAGE_SIZE: i32 = 20;
+
 pub(crate) fn handle_input() {
     let mut input = String::new();
     println!("Enter a command:");
@@ -40,58 +46,99 @@ fn handle_connection(parts: Vec<&str>) {
         println!("Failed to connect to ip: {addr}");
         return;
     };
-    let frame = create_request(STARTUP, 1, None, None).unwrap();
+    let frame = create_request(STARTUP, 0, None, None).unwrap();
 
     frame.write(&mut stream).unwrap();
 
-    let mut reader = BufReader::new(&mut stream);
-    let frame = read_response(&mut reader).unwrap();
-    drop(reader);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let Ok(frame) = read_response(&mut reader) else {
+        println!("Connection closed by the server");
+        return;
+    };
     if frame.header.opcode != READY {
         println!("Server not ready");
         return;
     }
+    println!("Connected to {addr}. Type DISCONNECT to close the session.");
 
-    println!("Enter a query:");
-    let mut buffer = String::new();
-    stdin().read_line(&mut buffer).unwrap();
-
-    if buffer == "DISCONNECT\n" {
-        return;
-    }
+    let mut stream_id: u16 = 0;
+    loop {
+        println!("Enter a query:");
+        let mut buffer = String::new();
+        if stdin().read_line(&mut buffer).unwrap_or(0) == 0 || buffer.trim() == "DISCONNECT" {
+            return;
+        }
+        if buffer.trim().is_empty() {
+            continue;
+        }
 
-    let mut cl = String::new();
-    println!("Enter consistency level (ONE, TWO, THREE, QUORUM, ALL):");
-    stdin().read_line(&mut cl).unwrap();
+        let mut cl = String::new();
+        println!("Enter consistency level (ONE, TWO, THREE, QUORUM, ALL, LOCAL_ONE, LOCAL_QUORUM, EACH_QUORUM, SERIAL, LOCAL_SERIAL):");
+        stdin().read_line(&mut cl).unwrap();
 
-    let consistency = ConsistencyLevel::from_str(cl.trim()).unwrap_or_else(|_| {
-        println!("Invalid consistency level, using ONE");
-        ConsistencyLevel::One
-    });
+        let consistency = cl.trim().parse().unwrap_or_else(|_| {
+            println!("Invalid consistency level, using ONE");
+            ConsistencyLevel::One
+        });
 
-    let frame = create_request(QUERY, 1, Some(&buffer), Some(consistency)).unwrap();
+        let mut parameters = QueryParameters::new(consistency);
+        parameters.page_size = Some(PAGE_SIZE);
+        // The query is sent again with the paging state of each page, until there are no more or the
+        // user stops.
+        loop {
+            stream_id = stream_id.wrapping_add(1) & 0x7FFF;
+            let frame = match create_query_request(stream_id, &buffer, parameters.clone()) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    println!("Invalid query: {e}\n");
+                    break;
+                }
+            };
 
-    frame.write(&mut stream).unwrap();
+            if frame.write(&mut stream).is_err() {
+                println!("Connection closed by the server");
+                return;
+            }
 
-    let mut reader = BufReader::new(&mut stream);
-    let res_frame = read_response(&mut reader).unwrap();
-    match res_frame.header.opcode {
-        RESULT => {
-            if let Some(rows) = res_frame.body.get_rows() {
-                println!("Rows:");
-                for row in rows {
-                    println!("\t{}", row.join(", "));
+            let Ok(res_frame) = read_response(&mut reader) else {
+                println!("Connection closed by the server");
+                return;
+            };
+            let rows = res_frame.body.get_rows().unwrap_or_default();
+            match res_frame.header.opcode {
+                RESULT if rows.is_empty() && res_frame.body.get_paging_state().is_some() => {}
+                RESULT => {
+                    if !rows.is_empty() {
+                        println!("Rows:");
+                        for row in &rows {
+                            println!("\t{}", row.join(", "));
+                        }
+                    } else {
+                        println!("No rows returned");
+                    }
+                }
+                ERROR => {
+                    println!("Error: {:?}", res_frame.body.get_error().unwrap());
+                }
+                _ => {
+                    println!("Invalid response!");
                 }
-            } else {
-                println!("No rows returned");
+            }
+            let Some(paging_state) = res_frame.body.get_paging_state() else {
+                break;
+            };
+            parameters.paging_state = Some(paging_state.to_vec());
+            // The rows a page read may all be deleted or not match the query, so an empty page is
+            // followed by the next one right away.
+            if rows.is_empty() {
+                continue;
+            }
+            println!("Press Enter for more rows, or type q to stop:");
+            let mut answer = String::new();
+            if stdin().read_line(&mut answer).unwrap_or(0) == 0 || answer.trim() == "q" {
+                break;
             }
         }
-        ERROR => {
-            println!("Error: {:?}", res_frame.body.get_error().unwrap());
-        }
-        _ => {
-            println!("Invalid response!");
-        }
+        println!();
     }
-    println!("");
 }
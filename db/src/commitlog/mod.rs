@@ -0,0 +1,301 @@
+pub(crate) mod mutation;
+pub(crate) mod segment;
+
+use std::{
+    fs::{create_dir_all, read_dir, remove_dir, remove_file},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, RwLock, RwLockReadGuard, Weak,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+use shared::io_error;
+
+use mutation::Mutation;
+use segment::Segment;
+
+/// Name of the directory, inside the node's directory, where the segments are stored.
+pub(crate) const COMMITLOG_DIR: &str = "commitlog";
+
+/// Decides when the commit log is flushed to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// The log is flushed every `sync_period_ms` milliseconds in the background.
+    /// Writes are acknowledged without waiting for the flush, so a crash may lose the writes of the last period.
+    Periodic,
+    /// The log is flushed on every write, before it is acknowledged.
+    Batch,
+}
+
+/// Configuration of the commit log.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CommitLogOptions {
+    pub sync_mode: SyncMode,
+    pub sync_period_ms: u64,
+    /// Maximum size in bytes of a segment before a new one is started.
+    pub segment_size: u64,
+}
+
+impl Default for CommitLogOptions {
+    fn default() -> Self {
+        CommitLogOptions {
+            sync_mode: SyncMode::Periodic,
+            sync_period_ms: 10_000,
+            segment_size: 32 * 1024 * 1024,
+        }
+    }
+}
+
+/// Write-ahead log of the mutations made to the tables of the node.
+///
+/// Every mutation of a keyspace with `durable_writes` is appended to the active segment before it is
+/// applied to the memtable of the table, so it can be replayed if the node crashes before the memtable
+/// is flushed to an SSTable.
+///
+/// When the active segment is full, a new one becomes the active segment, the memtables are flushed by the
+/// context and only then the full segment is removed. The segments left behind by a previous run of the node
+/// are replayed by `initialize_context` on startup.
+#[derive(Debug)]
+pub(crate) struct CommitLog {
+    state: Arc<Mutex<CommitLogState>>,
+    /// Held by the writers from the moment they append their mutations until they are applied to the
+    /// memtables, so a segment is only replaced once the mutations appended to it are in the memtables.
+    writes: RwLock<()>,
+    options: CommitLogOptions,
+}
+
+#[derive(Debug)]
+struct CommitLogState {
+    dir: PathBuf,
+    active: Option<Segment>,
+}
+
+impl CommitLog {
+    /// Creates the commit log of the node. No segment is created until the first mutation is appended.
+    pub(crate) fn new(node: &Path, options: CommitLogOptions) -> Self {
+        let state = Arc::new(Mutex::new(CommitLogState {
+            dir: node.join(COMMITLOG_DIR),
+            active: None,
+        }));
+        if options.sync_mode == SyncMode::Periodic {
+            spawn_periodic_sync(
+                Arc::downgrade(&state),
+                Duration::from_millis(options.sync_period_ms),
+            );
+        }
+        CommitLog {
+            state,
+            writes: RwLock::new(()),
+            options,
+        }
+    }
+
+    /// Appends the mutations to the log, flushing it to disk if the sync mode is `Batch`.
+    ///
+    /// Must be called before the mutations are applied to the memtables, holding the returned guard until
+    /// they are, so the segment is not removed before they can be flushed.
+    pub(crate) fn append(
+        &self,
+        mutations: &[Mutation],
+    ) -> std::io::Result<RwLockReadGuard<'_, ()>> {
+        let guard = self
+            .writes
+            .read()
+            .map_err(|_| io_error!("Commit log poisoned"))?;
+        if mutations.is_empty() {
+            return Ok(guard);
+        }
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io_error!("Commit log poisoned"))?;
+        if state.active.is_none() {
+            create_dir_all(&state.dir)?;
+            let segment = Segment::create(state.dir.join(new_segment_name()))?;
+            state.active = Some(segment);
+        }
+        let segment = state.active.as_mut().unwrap();
+        segment.append(mutations)?;
+        if self.options.sync_mode == SyncMode::Batch {
+            segment.sync()?;
+        }
+        Ok(guard)
+    }
+
+    /// Whether the active segment reached its maximum size, so it should be replaced.
+    fn is_segment_full(&self, state: &CommitLogState) -> bool {
+        state
+            .active
+            .as_ref()
+            .is_some_and(|segment| segment.size() >= self.options.segment_size)
+    }
+
+    /// Replaces the active segment with a new one if it is full, once the mutations appended to it are
+    /// applied to the memtables. The writers that find it full at once only replace it once.
+    ///
+    /// # Returns
+    ///
+    /// * The path of the replaced segment, to be removed with `remove_segment` once every memtable has
+    ///   been flushed, or `None` if the active segment is not full.
+    pub(crate) fn roll_over(&self) -> std::io::Result<Option<PathBuf>> {
+        let _writes = self
+            .writes
+            .write()
+            .map_err(|_| io_error!("Commit log poisoned"))?;
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io_error!("Commit log poisoned"))?;
+        if !self.is_segment_full(&state) {
+            return Ok(None);
+        }
+        let segment = Segment::create(state.dir.join(new_segment_name()))?;
+        let full = state.active.replace(segment);
+        Ok(full.map(|segment| segment.path().to_path_buf()))
+    }
+
+    /// Removes a segment replaced by `roll_over`. Must only be called once every memtable has been flushed.
+    pub(crate) fn remove_segment(&self, path: &Path) -> std::io::Result<()> {
+        remove_file(path)
+    }
+
+    /// Removes the active segment. Must only be called once every memtable has been flushed.
+    pub(crate) fn discard(&self) -> std::io::Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io_error!("Commit log poisoned"))?;
+        if let Some(segment) = state.active.take() {
+            remove_file(segment.path())?;
+        }
+        Ok(())
+    }
+
+    /// Removes the commit log dir if it is empty.
+    pub(crate) fn remove_dir_if_empty(&self) {
+        if let Ok(state) = self.state.lock() {
+            let _ = remove_dir(&state.dir);
+        }
+    }
+}
+
+fn spawn_periodic_sync(state: Weak<Mutex<CommitLogState>>, period: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(period);
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        let Ok(state) = state.lock() else {
+            return;
+        };
+        if let Some(segment) = &state.active {
+            let _ = segment.sync();
+        }
+    });
+}
+
+/// Segment names are `CommitLog-<instance>-<sequence>.log`, where the instance identifies the
+/// running process, so segments still being written are never taken as left behind by a crash.
+fn new_segment_name() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "CommitLog-{}-{}.log",
+        instance_id(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn instance_id() -> u128 {
+    static INSTANCE: OnceLock<u128> = OnceLock::new();
+    *INSTANCE.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos())
+            .unwrap_or_default()
+    })
+}
+
+fn is_own_segment(name: &str) -> bool {
+    name.starts_with(&format!("CommitLog-{}-", instance_id()))
+}
+
+/// Returns the segments left in the commit log dir of the node by previous runs, in the order
+/// they were written.
+pub(crate) fn get_segments_to_replay(node: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let dir = node.join(COMMITLOG_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut segments = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some((instance, sequence)) = name
+            .strip_prefix("CommitLog-")
+            .and_then(|name| name.strip_suffix(".log"))
+            .and_then(|name| name.split_once('-'))
+        else {
+            continue;
+        };
+        if is_own_segment(name) {
+            continue;
+        }
+        let (Ok(instance), Ok(sequence)) = (instance.parse::<u128>(), sequence.parse::<u64>())
+        else {
+            continue;
+        };
+        segments.push(((instance, sequence), path));
+    }
+    segments.sort_by_key(|(id, _)| *id);
+    Ok(segments.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{mutation::MutationKind, *};
+
+    #[test]
+    fn test_commitlog_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let node = dir.path();
+        let options = CommitLogOptions {
+            sync_mode: SyncMode::Batch,
+            sync_period_ms: 0,
+            segment_size: 1,
+        };
+        let commitlog = CommitLog::new(node, options);
+        let mutation = Mutation {
+            keyspace: "ks".to_string(),
+            table: "table".to_string(),
+            timestamp: 1,
+            kind: MutationKind::Delete(HashMap::from([("id".to_string(), "1".to_string())])),
+        };
+        let empty = commitlog.roll_over().unwrap();
+        drop(commitlog.append(std::slice::from_ref(&mutation)).unwrap());
+        let full = commitlog.roll_over().unwrap();
+        let segments = read_dir(node.join(COMMITLOG_DIR)).unwrap().count();
+        // Segments of the running node are not replayed.
+        let to_replay = get_segments_to_replay(node).unwrap();
+        commitlog.remove_segment(full.as_ref().unwrap()).unwrap();
+        commitlog.discard().unwrap();
+        commitlog.remove_dir_if_empty();
+        let exists = node.join(COMMITLOG_DIR).exists();
+
+        assert!(empty.is_none());
+        // The full segment is replaced with a new one, and kept until it is removed.
+        assert!(full.is_some());
+        assert_eq!(segments, 2);
+        assert!(to_replay.is_empty());
+        assert!(!exists);
+    }
+}
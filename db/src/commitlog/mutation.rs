@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    storage::{key::Key, Entry},
+    Schema,
+};
+
+/// A change made to a single row of a table, as written in the commit log.
+///
+/// Mutations are idempotent: replaying one that was already applied to the table leaves it unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Mutation {
+    pub(crate) keyspace: String,
+    pub(crate) table: String,
+    /// Write time of the mutation, in microseconds since the epoch.
+    pub(crate) timestamp: i64,
+    pub(crate) kind: MutationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum MutationKind {
+    /// Upsert (primary key values and written columns, expiration). Writes the cells of the row with that
+    /// primary key, which expire at the given local time in seconds since the epoch if it was written with
+    /// a TTL.
+    Upsert(HashMap<String, String>, Option<i64>),
+    /// Delete (primary key values). Writes a tombstone that hides the cells of the row with that primary
+    /// key written until then.
+    Delete(HashMap<String, String>),
+    /// Write (encoded key, entry). Merges the entry into the one of the key, for the tombstones of columns
+    /// and ranges of rows and for the rows written by a read repair.
+    Write(Key, Entry),
+    /// Batch (mutations of the table). Applies the mutations together, so they are logged as a single
+    /// record and a torn record drops all of them.
+    Batch(Vec<Mutation>),
+}
+
+/// Returns the values of the primary key columns of the row.
+pub(crate) fn primary_key_values(
+    schema: &Schema,
+    row: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let primary_key = schema.get_primary_key();
+    primary_key
+        .get_partition_key()
+        .iter()
+        .chain(primary_key.get_clustering_key())
+        .map(|col| {
+            let value = row.get(col).cloned().unwrap_or("NULL".to_string());
+            (col.clone(), value)
+        })
+        .collect()
+}
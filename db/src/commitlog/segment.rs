@@ -0,0 +1,145 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use shared::map_io_error;
+
+use super::mutation::Mutation;
+
+/// Size in bytes of the header of each record: the length of the payload followed by its CRC32.
+const RECORD_HEADER_SIZE: u64 = 8;
+
+/// A file of the commit log where mutations are appended.
+///
+/// Each record of the segment has the following format:
+/// - The length of the payload as a big endian `u32`.
+/// - The CRC32 of the payload as a big endian `u32`.
+/// - The payload: the mutation serialized with `bincode`.
+#[derive(Debug)]
+pub(crate) struct Segment {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl Segment {
+    /// Creates a new empty segment at the specified path.
+    pub(crate) fn create(path: PathBuf) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)?;
+        Ok(Segment {
+            path,
+            file,
+            size: 0,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Appends the mutations to the segment with a single write.
+    /// The data is not guaranteed to be on disk until `sync` is called.
+    pub(crate) fn append(&mut self, mutations: &[Mutation]) -> std::io::Result<()> {
+        let mut buffer = Vec::new();
+        for mutation in mutations {
+            let payload = bincode::serialize(mutation)
+                .map_err(map_io_error!("Failed to serialize mutation"))?;
+            buffer.extend((payload.len() as u32).to_be_bytes());
+            buffer.extend(crc32fast::hash(&payload).to_be_bytes());
+            buffer.extend(payload);
+        }
+        self.file.write_all(&buffer)?;
+        self.size += buffer.len() as u64;
+        Ok(())
+    }
+
+    /// Flushes the content of the segment to disk.
+    pub(crate) fn sync(&self) -> std::io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// Reads all the mutations of the segment at the specified path.
+///
+/// A torn or corrupted record means the node crashed while it was being written, so it could not
+/// have been acknowledged: the reading stops there and the mutations read so far are returned.
+pub(crate) fn read_segment(path: &Path) -> std::io::Result<Vec<Mutation>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut mutations = Vec::new();
+    loop {
+        let mut header = [0u8; RECORD_HEADER_SIZE as usize];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let crc = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+
+        let mut payload = Vec::new();
+        reader
+            .by_ref()
+            .take(length as u64)
+            .read_to_end(&mut payload)?;
+        if payload.len() != length as usize || crc32fast::hash(&payload) != crc {
+            break;
+        }
+        match bincode::deserialize(&payload) {
+            Ok(mutation) => mutations.push(mutation),
+            Err(_) => break,
+        }
+    }
+    Ok(mutations)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commitlog::mutation::MutationKind;
+
+    fn mutation(id: &str) -> Mutation {
+        Mutation {
+            keyspace: "ks".to_string(),
+            table: "table".to_string(),
+            timestamp: 1,
+            kind: MutationKind::Upsert(HashMap::from([("id".to_string(), id.to_string())]), None),
+        }
+    }
+
+    #[test]
+    fn test_segment_append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("segment.log");
+        let mut segment = Segment::create(path.clone()).unwrap();
+        segment.append(&[mutation("1"), mutation("2")]).unwrap();
+        segment.append(&[mutation("3")]).unwrap();
+        segment.sync().unwrap();
+        let mutations = read_segment(&path).unwrap();
+        assert_eq!(mutations, vec![mutation("1"), mutation("2"), mutation("3")]);
+    }
+
+    #[test]
+    fn test_segment_torn_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("segment.log");
+        let mut segment = Segment::create(path.clone()).unwrap();
+        segment.append(&[mutation("1"), mutation("2")]).unwrap();
+        let size = segment.size();
+        drop(segment);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(size - 1).unwrap();
+        let mutations = read_segment(&path).unwrap();
+        assert_eq!(mutations, vec![mutation("1")]);
+    }
+}
@@ -1,17 +1,25 @@
 use std::{
     collections::HashMap,
+    ops::Bound,
     path::{Path, PathBuf},
 };
 
 use shared::{io_error, not_found_error};
 
 use crate::{
+    commitlog::{
+        get_segments_to_replay, mutation::Mutation, segment::read_segment, CommitLog,
+        CommitLogOptions, COMMITLOG_DIR,
+    },
     models::{
-        keyspace::{create_keyspace, drop_keyspace, get_keyspace_options},
+        keyspace::{
+            alter_keyspace, create_keyspace, drop_keyspace, get_keyspace_options_or_legacy,
+        },
         schema::Schema,
-        tables::Tables,
+        tables::{Tables, UpdateVisitor},
     },
-    Options,
+    storage::{compaction::Compactor, stored_row::StoredRow},
+    Options, Replication,
 };
 
 /// Represents the node's context.
@@ -21,9 +29,15 @@ use crate::{
 pub struct Context {
     ctx: HashMap<String, Tables>,
     pub node_dir: PathBuf,
+    commitlog: CommitLog,
+    compactor: Compactor,
 }
 
-/// Initializes the context with the keyspaces and tables in the node on startup.
+/// Dirs of the node's directory that are not keyspaces.
+const RESERVED_DIRS: [&str; 3] = ["hints", "batchlog", COMMITLOG_DIR];
+
+/// Initializes the context with the keyspaces and tables in the node on startup,
+/// using the default configuration for the commit log.
 ///
 /// # Arguments
 ///
@@ -37,29 +51,102 @@ pub struct Context {
 ///
 /// * Returns an `Error` if there is an issue while reading the keyspaces and tables.
 pub fn initialize_context(node: &Path) -> std::io::Result<Context> {
+    initialize_context_with_commitlog(node, CommitLogOptions::default())
+}
+
+/// Initializes the context with the keyspaces and tables in the node on startup.
+///
+/// Any segment of the commit log left behind by a previous run of the node is replayed into
+/// the tables before the context is returned.
+///
+/// # Arguments
+///
+/// * `node` - A reference to the path of the node's directory.
+/// * `options` - The configuration of the commit log.
+///
+/// # Returns
+///
+/// * Returns a `Context` with the keyspaces and tables in the node.
+///
+/// # Errors
+///
+/// * Returns an `Error` if there is an issue while reading the keyspaces and tables, or while replaying the commit log.
+pub fn initialize_context_with_commitlog(
+    node: &Path,
+    options: CommitLogOptions,
+) -> std::io::Result<Context> {
+    let compactor = Compactor::start();
     let mut ctx = HashMap::new();
     for entry in std::fs::read_dir(node)? {
         let keyspace_path = entry?.path();
         if keyspace_path.is_dir()
-            && keyspace_path.file_name().unwrap().to_str().unwrap_or("") != "hints"
+            && !RESERVED_DIRS.contains(&keyspace_path.file_name().unwrap().to_str().unwrap_or(""))
         {
             let keyspace =
                 get_file_name(&keyspace_path, "Invalid path for node's dir".to_string())?;
-            let tables = Tables::get_tables_schema(&keyspace_path)?;
+            let tables = Tables::get_tables_schema(&keyspace_path, &compactor)?;
             ctx.insert(keyspace, tables);
         }
     }
+    replay_commitlog(node, &ctx)?;
     Ok(Context {
         ctx,
         node_dir: node.to_path_buf(),
+        commitlog: CommitLog::new(node, options),
+        compactor,
     })
 }
 
+/// Replays the segments of the commit log left behind by a previous run of the node, and
+/// removes them once the memtables are flushed to SSTables.
+fn replay_commitlog(node: &Path, ctx: &HashMap<String, Tables>) -> std::io::Result<()> {
+    let segments = get_segments_to_replay(node)?;
+    let mut mutations: Vec<((String, String), Vec<Mutation>)> = Vec::new();
+    for segment in &segments {
+        for mutation in read_segment(segment)? {
+            let table = (mutation.keyspace.clone(), mutation.table.clone());
+            match mutations.iter_mut().find(|(key, _)| key == &table) {
+                Some((_, table_mutations)) => table_mutations.push(mutation),
+                None => mutations.push((table, vec![mutation])),
+            }
+        }
+    }
+    for ((keyspace, table), table_mutations) in mutations {
+        if let Some(tables) = ctx.get(&keyspace) {
+            tables.replay_mutations(&table, &table_mutations)?;
+        }
+    }
+    for tables in ctx.values() {
+        tables.flush()?;
+    }
+    for segment in segments {
+        std::fs::remove_file(segment)?;
+    }
+    Ok(())
+}
+
 impl Context {
     pub fn is_a_keyspace(&self, keyspace: &str) -> bool {
         self.ctx.contains_key(keyspace)
     }
 
+    /// Returns the names of the keyspaces of the node.
+    pub fn get_keyspaces(&self) -> Vec<String> {
+        self.ctx.keys().cloned().collect()
+    }
+
+    /// Returns the names of the tables of a keyspace of the node.
+    ///
+    /// # Errors
+    ///
+    /// * Returns a `NotFound` error if the keyspace does not exist.
+    pub fn get_tables(&self, keyspace: &str) -> std::io::Result<Vec<String>> {
+        self.ctx
+            .get(keyspace)
+            .map(Tables::get_table_names)
+            .ok_or(not_found_error!("Keyspace does not exist"))
+    }
+
     /// Creates a new keyspace in the node with the specified options.
     ///
     /// # Arguments
@@ -69,10 +156,44 @@ impl Context {
     pub fn create_keyspace(&mut self, keyspace: &Path, options: &Options) -> std::io::Result<()> {
         create_keyspace(keyspace, options)?;
         let keyspace_name = get_file_name(keyspace, "Invalid keyspace path".to_string())?;
-        self.ctx.insert(keyspace_name, Tables::new());
+        self.ctx.insert(keyspace_name, Tables::new(options.clone()));
+        Ok(())
+    }
+
+    /// Replaces the options of a keyspace of the node.
+    /// The new `durable_writes` and replication apply to the queries made from then on.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyspace` - The path of the keyspace.
+    /// * `options` - The new options of the keyspace.
+    pub fn alter_keyspace(&mut self, keyspace: &Path, options: &Options) -> std::io::Result<()> {
+        let keyspace_name = get_file_name(keyspace, "Invalid keyspace path".to_string())?;
+        let tables = self
+            .ctx
+            .get_mut(&keyspace_name)
+            .ok_or(not_found_error!("Keyspace does not exist"))?;
+        alter_keyspace(keyspace, options)?;
+        tables.set_options(options.clone());
         Ok(())
     }
 
+    /// Returns the replication of a keyspace of the node, which decides how many nodes hold each row.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyspace` - The name of the keyspace.
+    ///
+    /// # Errors
+    ///
+    /// * Returns a `NotFound` error if the keyspace does not exist.
+    pub fn get_replication(&self, keyspace: &str) -> std::io::Result<Replication> {
+        self.ctx
+            .get(keyspace)
+            .map(|tables| tables.get_options().replication.clone())
+            .ok_or(not_found_error!("Keyspace does not exist"))
+    }
+
     /// Creates a new table within the keyspace that is currently set in the table context.
     ///
     /// # Arguments
@@ -87,7 +208,7 @@ impl Context {
         self.ctx
             .get_mut(&keyspace)
             .ok_or(not_found_error!("Keyspace does not exist"))?
-            .create_table(table, schema.clone())
+            .create_table(table, schema.clone(), &self.compactor)
     }
 
     /// Drops the keyspace from the node.
@@ -121,8 +242,9 @@ impl Context {
             .drop_table(table)
     }
 
+    /// Returns the options of the keyspace, or the legacy ones if it was created before they were kept.
     pub fn get_keyspace_options(&self, keyspace: &Path) -> std::io::Result<Options> {
-        get_keyspace_options(keyspace)
+        get_keyspace_options_or_legacy(keyspace)
     }
 
     /// Returns the schema of the table from the keyspace that is set.
@@ -159,52 +281,367 @@ impl Context {
             .read_table(table, visitor)
     }
 
-    /// Appends the data to the table from the keyspace that is currently set in the connection context.
+    /// Reads only the rows of a partition of the table from the keyspace that is currently set in the connection context.
     ///
     /// # Arguments
     ///
     /// * `table` - The path of the table dir.
-    /// * `data` - A `HashMap` of the data to be appended to the table.
-    pub fn append_to_table(
-        &mut self,
+    /// * `partition_key` - The values of the partition key columns of the table.
+    /// * `visitor` - A function that takes a reference to a `HashMap` of each row of the partition.
+    pub fn read_partition(
+        &self,
         table: &Path,
-        data: HashMap<String, String>,
+        partition_key: &HashMap<String, String>,
+        visitor: &mut dyn FnMut(HashMap<String, String>) -> std::io::Result<()>,
     ) -> std::io::Result<()> {
         let keyspace = get_file_name(
             table.parent().ok_or(io_error!("Invalid table path"))?,
             "Invalid keyspace path".to_string(),
         )?;
         self.ctx
-            .get_mut(&keyspace)
+            .get(&keyspace)
             .ok_or(not_found_error!("Keyspace does not exist"))?
-            .append_to_table(table, &data)
+            .read_partition(table, partition_key, visitor)
+    }
+
+    /// Reads the stored rows of a partition of the table from the keyspace that is currently set in the connection context.
+    /// Stored rows keep the time of every write and the tombstones of the deletions, so the partitions read from
+    /// different replicas can be reconciled.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `partition_key` - The values of the partition key columns of the table.
+    /// * `visitor` - A function that takes each stored row of the partition, in key order.
+    pub fn read_stored_rows(
+        &self,
+        table: &Path,
+        partition_key: &HashMap<String, String>,
+        visitor: &mut dyn FnMut(StoredRow) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let keyspace = get_file_name(
+            table.parent().ok_or(io_error!("Invalid table path"))?,
+            "Invalid keyspace path".to_string(),
+        )?;
+        self.ctx
+            .get(&keyspace)
+            .ok_or(not_found_error!("Keyspace does not exist"))?
+            .read_stored_rows(table, partition_key, visitor)
+    }
+
+    /// Reads the stored rows of every partition of the table, with their write times and tombstones, such as
+    /// to stream them to another node.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `since` - If given, only the data this node received after this time is read, such as the
+    ///   data that was not repaired yet.
+    /// * `visitor` - A function that takes each stored row of the table, in key order.
+    pub fn scan_stored_rows(
+        &self,
+        table: &Path,
+        since: Option<i64>,
+        visitor: &mut dyn FnMut(StoredRow) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let keyspace = get_file_name(
+            table.parent().ok_or(io_error!("Invalid table path"))?,
+            "Invalid keyspace path".to_string(),
+        )?;
+        self.ctx
+            .get(&keyspace)
+            .ok_or(not_found_error!("Keyspace does not exist"))?
+            .scan_stored_rows(table, since, visitor)
+    }
+
+    /// Starts a batch of writes to the table from the keyspace that is currently set in the connection
+    /// context, which are applied together with `WriteBatch::apply`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    pub fn write_batch(&self, table: &Path) -> std::io::Result<WriteBatch<'_>> {
+        let keyspace = get_file_name(
+            table.parent().ok_or(io_error!("Invalid table path"))?,
+            "Invalid keyspace path".to_string(),
+        )?;
+        let tables = self
+            .ctx
+            .get(&keyspace)
+            .ok_or(not_found_error!("Keyspace does not exist"))?;
+        Ok(WriteBatch {
+            ctx: self,
+            tables,
+            table: table.to_path_buf(),
+            mutations: Vec::new(),
+        })
+    }
+
+    /// Merges stored rows read from other replicas into the table from the keyspace that is currently set
+    /// in the connection context.
+    /// If the keyspace has `durable_writes`, the rows are written to the commit log first.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `rows` - The stored rows to write.
+    pub fn write_stored_rows(&self, table: &Path, rows: Vec<StoredRow>) -> std::io::Result<()> {
+        let mut batch = self.write_batch(table)?;
+        batch.write_stored_rows(rows)?;
+        batch.apply()
+    }
+
+    /// Deletes the rows of the table from the keyspace that is currently set in the connection context
+    /// selected by the values of their primary key, without reading them.
+    /// A tombstone is written that hides the cells of the rows written until `timestamp`.
+    /// If the keyspace has `durable_writes`, the tombstone is written to the commit log first.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `key` - The values of the partition key columns and of the first clustering columns. Without clustering
+    ///   columns the whole partition is deleted, and with all of them a single row.
+    /// * `bounds` - The bounds of the values of the next clustering column, to delete a range of rows.
+    /// * `timestamp` - The time of the deletion, in microseconds since the epoch.
+    pub fn delete_rows(
+        &self,
+        table: &Path,
+        key: &HashMap<String, String>,
+        bounds: (Bound<String>, Bound<String>),
+        timestamp: i64,
+    ) -> std::io::Result<()> {
+        let mut batch = self.write_batch(table)?;
+        batch.delete_rows(key, bounds, timestamp)?;
+        batch.apply()
+    }
+
+    /// Deletes the values of columns of a row of the table from the keyspace that is currently set in the
+    /// connection context, writing a tombstone for each of them.
+    /// If the keyspace has `durable_writes`, the tombstones are written to the commit log first.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `primary_key` - The values of the primary key columns of the row.
+    /// * `columns` - The columns to delete, which can not be part of the primary key.
+    /// * `timestamp` - The time of the deletion, in microseconds since the epoch.
+    pub fn delete_columns(
+        &self,
+        table: &Path,
+        primary_key: &HashMap<String, String>,
+        columns: &[String],
+        timestamp: i64,
+    ) -> std::io::Result<()> {
+        let mut batch = self.write_batch(table)?;
+        batch.delete_columns(primary_key, columns, timestamp)?;
+        batch.apply()
+    }
+
+    /// Appends the data to the table from the keyspace that is currently set in the connection context.
+    /// The columns of the row are written over the ones of the row with the same primary key, if any,
+    /// unless they were written later than `timestamp`.
+    /// If the keyspace has `durable_writes`, the data is written to the commit log first.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `data` - A `HashMap` of the data to be appended to the table.
+    /// * `timestamp` - The write time of the data, in microseconds since the epoch.
+    /// * `ttl` - The seconds after which the data expires, or `None` to use the `default_time_to_live` of the table.
+    pub fn append_to_table(
+        &self,
+        table: &Path,
+        data: HashMap<String, String>,
+        timestamp: i64,
+        ttl: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mut batch = self.write_batch(table)?;
+        batch.append_to_table(&data, timestamp, ttl)?;
+        batch.apply()
+    }
+
+    /// Writes the columns of a row of the table from the keyspace that is currently set in the connection
+    /// context, selected by the values of its primary key, without reading the table.
+    /// If the keyspace has `durable_writes`, the change is written to the commit log first.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table.
+    /// * `primary_key` - The values of every primary key column of the row.
+    /// * `columns` - The values of the columns to write, none of them of the primary key.
+    /// * `timestamp` - The write time of the change, in microseconds since the epoch.
+    /// * `ttl` - The seconds after which the written cells expire, or `None` to use the `default_time_to_live` of the table.
+    pub fn update_row(
+        &self,
+        table: &Path,
+        primary_key: &HashMap<String, String>,
+        columns: &HashMap<String, String>,
+        timestamp: i64,
+        ttl: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mut batch = self.write_batch(table)?;
+        batch.update_row(primary_key, columns, timestamp, ttl)?;
+        batch.apply()
     }
 
     /// Updates the table from the keyspace that is currently set in the connection context.
+    /// If the keyspace has `durable_writes`, the changed rows are written to the commit log first.
     ///
     /// # Arguments
     ///
     /// * `table` - The path of the table.
+    /// * `timestamp` - The write time of the changes, in microseconds since the epoch.
+    /// * `ttl` - The seconds after which the changes expire, or `None` to use the `default_time_to_live` of the table.
     /// * `visitor` - A function that takes a reference to a `HashMap` of the data in the table and returns an `Option<HashMap<String, String>>`.
     ///
     /// The function should return `Some` with the updated data if the data should be updated, otherwise `None`.
     /// In case of None, the data will not be present in the table (deleted).  
     /// In case of some column that is not present in the hashmap, the column will not be updated.
     pub fn update_table(
-        &mut self,
+        &self,
         table: &Path,
-        visitor: &mut dyn FnMut(
-            HashMap<String, String>,
-        ) -> std::io::Result<Option<HashMap<String, String>>>,
+        timestamp: i64,
+        ttl: Option<u64>,
+        visitor: &mut UpdateVisitor,
     ) -> std::io::Result<()> {
-        let keyspace = get_file_name(
-            table.parent().ok_or(io_error!("Invalid table path"))?,
-            "Invalid keyspace path".to_string(),
-        )?;
-        self.ctx
-            .get_mut(&keyspace)
-            .ok_or(not_found_error!("Keyspace does not exist"))?
-            .update_table(table, visitor)
+        let mut batch = self.write_batch(table)?;
+        batch.update_table(timestamp, ttl, visitor)?;
+        batch.apply()
+    }
+
+    /// Flushes the memtables of every table of the node to SSTables.
+    pub fn flush(&self) -> std::io::Result<()> {
+        for tables in self.ctx.values() {
+            tables.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the commit log if the keyspace has `durable_writes`.
+    /// If the active segment is full, the writes go to a new segment and the memtables are flushed before the
+    /// full one is removed, so the writes of other connections appended to it are not lost.
+    fn get_commitlog(&self, tables: &Tables) -> std::io::Result<Option<&CommitLog>> {
+        if !tables.is_durable() {
+            return Ok(None);
+        }
+        if let Some(full) = self.commitlog.roll_over()? {
+            self.flush()?;
+            self.commitlog.remove_segment(&full)?;
+        }
+        Ok(Some(&self.commitlog))
+    }
+}
+
+/// Writes to a table of the node that are applied together, so the readers of the table see all of them
+/// or none, and a crash replays all of them from the commit log or none. Each write is checked when it is
+/// added, and nothing is written until the batch is applied.
+#[derive(Debug)]
+pub struct WriteBatch<'a> {
+    ctx: &'a Context,
+    tables: &'a Tables,
+    table: PathBuf,
+    mutations: Vec<Mutation>,
+}
+
+impl WriteBatch<'_> {
+    /// Returns the path of the table dir the batch writes to.
+    pub fn table(&self) -> &Path {
+        &self.table
+    }
+
+    /// Adds the stored rows read from other replicas, as `Context::write_stored_rows` writes them.
+    pub fn write_stored_rows(&mut self, rows: Vec<StoredRow>) -> std::io::Result<()> {
+        let mutations = self.tables.stored_rows_mutations(&self.table, rows)?;
+        self.mutations.extend(mutations);
+        Ok(())
+    }
+
+    /// Adds a tombstone of rows selected by their primary key, as `Context::delete_rows` writes it.
+    pub fn delete_rows(
+        &mut self,
+        key: &HashMap<String, String>,
+        bounds: (Bound<String>, Bound<String>),
+        timestamp: i64,
+    ) -> std::io::Result<()> {
+        let mutations = self
+            .tables
+            .delete_rows_mutations(&self.table, key, bounds, timestamp)?;
+        self.mutations.extend(mutations);
+        Ok(())
+    }
+
+    /// Adds the tombstones of columns of a row, as `Context::delete_columns` writes them.
+    pub fn delete_columns(
+        &mut self,
+        primary_key: &HashMap<String, String>,
+        columns: &[String],
+        timestamp: i64,
+    ) -> std::io::Result<()> {
+        let mutations =
+            self.tables
+                .delete_columns_mutations(&self.table, primary_key, columns, timestamp)?;
+        self.mutations.extend(mutations);
+        Ok(())
+    }
+
+    /// Adds a row, as `Context::append_to_table` writes it.
+    pub fn append_to_table(
+        &mut self,
+        data: &HashMap<String, String>,
+        timestamp: i64,
+        ttl: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mutations = self
+            .tables
+            .append_mutations(&self.table, data, timestamp, ttl)?;
+        self.mutations.extend(mutations);
+        Ok(())
+    }
+
+    /// Adds the columns of a row selected by its primary key, as `Context::update_row` writes them.
+    pub fn update_row(
+        &mut self,
+        primary_key: &HashMap<String, String>,
+        columns: &HashMap<String, String>,
+        timestamp: i64,
+        ttl: Option<u64>,
+    ) -> std::io::Result<()> {
+        let mutations =
+            self.tables
+                .update_row_mutations(&self.table, primary_key, columns, timestamp, ttl)?;
+        self.mutations.extend(mutations);
+        Ok(())
+    }
+
+    /// Adds the changes the visitor makes to the rows of the table, as `Context::update_table` writes
+    /// them. The rows are read as they are before the batch is applied.
+    pub fn update_table(
+        &mut self,
+        timestamp: i64,
+        ttl: Option<u64>,
+        visitor: &mut UpdateVisitor,
+    ) -> std::io::Result<()> {
+        let mutations = self
+            .tables
+            .update_table_mutations(&self.table, timestamp, ttl, visitor)?;
+        self.mutations.extend(mutations);
+        Ok(())
+    }
+
+    /// Applies the writes of the batch under a single lock of the table. If the keyspace has
+    /// `durable_writes`, they are first written to the commit log as a single record.
+    pub fn apply(self) -> std::io::Result<()> {
+        let commitlog = self.ctx.get_commitlog(self.tables)?;
+        self.tables.write(&self.table, self.mutations, commitlog)
+    }
+}
+
+impl Drop for Context {
+    /// Flushes the memtables on shutdown, so the commit log is no longer needed.
+    fn drop(&mut self) {
+        if self.flush().is_ok() && self.commitlog.discard().is_ok() {
+            self.commitlog.remove_dir_if_empty();
+        }
     }
 }
 
@@ -215,3 +652,274 @@ pub(crate) fn get_file_name(path: &Path, msg: String) -> std::io::Result<String>
             .map(|s| s.to_string())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, read_dir};
+
+    use super::*;
+    use crate::{
+        commitlog::segment::read_segment,
+        commitlog::{mutation::MutationKind, segment::Segment},
+        storage::{cell::current_timestamp, sstable::SSTable},
+        PrimaryKey, SchemaType, SyncMode,
+    };
+
+    #[test]
+    fn test_replay_commitlog() {
+        let dir = tempfile::tempdir().unwrap();
+        let node = dir.path();
+        let keyspace = node.join("ks");
+        let table = keyspace.join("users");
+        let mut ctx = initialize_context(node).unwrap();
+        ctx.create_keyspace(
+            &keyspace,
+            &Options::new(true, "SimpleStrategy".to_string(), 1),
+        )
+        .unwrap();
+        let schema = Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        );
+        ctx.create_table(&table, &schema).unwrap();
+        ctx.append_to_table(
+            &table,
+            HashMap::from([
+                ("id".to_string(), "1".to_string()),
+                ("name".to_string(), "John".to_string()),
+            ]),
+            current_timestamp(),
+            None,
+        )
+        .unwrap();
+        drop(ctx);
+
+        // Segment left behind by a previous run of the node.
+        create_dir_all(node.join(COMMITLOG_DIR)).unwrap();
+        let mut segment =
+            Segment::create(node.join(COMMITLOG_DIR).join("CommitLog-0-0.log")).unwrap();
+        let now = current_timestamp();
+        let mutation = |timestamp, kind| Mutation {
+            keyspace: "ks".to_string(),
+            table: "users".to_string(),
+            timestamp: now + timestamp,
+            kind,
+        };
+        segment
+            .append(&[
+                mutation(
+                    1,
+                    MutationKind::Upsert(
+                        HashMap::from([
+                            ("id".to_string(), "1".to_string()),
+                            ("name".to_string(), "John".to_string()),
+                        ]),
+                        None,
+                    ),
+                ),
+                mutation(
+                    2,
+                    MutationKind::Upsert(
+                        HashMap::from([
+                            ("id".to_string(), "2".to_string()),
+                            ("name".to_string(), "Jane".to_string()),
+                        ]),
+                        None,
+                    ),
+                ),
+                mutation(
+                    3,
+                    MutationKind::Upsert(
+                        HashMap::from([
+                            ("id".to_string(), "1".to_string()),
+                            ("name".to_string(), "Johnny".to_string()),
+                        ]),
+                        None,
+                    ),
+                ),
+                mutation(
+                    4,
+                    MutationKind::Delete(HashMap::from([("id".to_string(), "3".to_string())])),
+                ),
+                mutation(
+                    5,
+                    MutationKind::Upsert(
+                        HashMap::from([
+                            ("id".to_string(), "3".to_string()),
+                            ("name".to_string(), "Jim".to_string()),
+                        ]),
+                        None,
+                    ),
+                ),
+                mutation(
+                    6,
+                    MutationKind::Delete(HashMap::from([("id".to_string(), "2".to_string())])),
+                ),
+            ])
+            .unwrap();
+        drop(segment);
+
+        let ctx = initialize_context(node).unwrap();
+        let mut rows = Vec::new();
+        ctx.read_table(&table, &mut |row| {
+            rows.push((row["id"].clone(), row["name"].clone()));
+            Ok(())
+        })
+        .unwrap();
+        rows.sort();
+        let segment_left = node.join(COMMITLOG_DIR).join("CommitLog-0-0.log").exists();
+        let sstables = SSTable::get_generations(&table).unwrap();
+        drop(ctx);
+
+        assert!(!segment_left);
+        assert_eq!(
+            rows,
+            vec![
+                ("1".to_string(), "Johnny".to_string()),
+                ("3".to_string(), "Jim".to_string()),
+            ]
+        );
+        // The replayed mutations are flushed before the segment is removed.
+        assert_eq!(sstables, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_concurrent_writes_survive_commitlog_rollover() {
+        let dir = tempfile::tempdir().unwrap();
+        let node = dir.path();
+        let keyspace = node.join("ks");
+        let table = keyspace.join("users");
+        // Every write fills the segment, so the writers keep replacing it while the others append.
+        let options = CommitLogOptions {
+            sync_mode: SyncMode::Batch,
+            sync_period_ms: 0,
+            segment_size: 1,
+        };
+        let mut ctx = initialize_context_with_commitlog(node, options).unwrap();
+        ctx.create_keyspace(
+            &keyspace,
+            &Options::new(true, "SimpleStrategy".to_string(), 1),
+        )
+        .unwrap();
+        let schema = Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        );
+        ctx.create_table(&table, &schema).unwrap();
+        std::thread::scope(|scope| {
+            for writer in 0..4 {
+                let (ctx, table) = (&ctx, &table);
+                scope.spawn(move || {
+                    for i in 0..25 {
+                        let row = HashMap::from([
+                            ("id".to_string(), (writer * 25 + i).to_string()),
+                            ("name".to_string(), "John".to_string()),
+                        ]);
+                        ctx.append_to_table(table, row, current_timestamp(), None)
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        // The node crashes: the memtables are lost, and only the segments and SSTables are left.
+        let mut ids = Vec::new();
+        for segment in read_dir(node.join(COMMITLOG_DIR)).unwrap() {
+            for mutation in read_segment(&segment.unwrap().path()).unwrap() {
+                if let MutationKind::Upsert(row, _) = mutation.kind {
+                    ids.push(row["id"].parse::<i32>().unwrap());
+                }
+            }
+        }
+        std::mem::forget(ctx);
+        let ctx = initialize_context(node).unwrap();
+        ctx.read_table(&table, &mut |row| {
+            ids.push(row["id"].parse().unwrap());
+            Ok(())
+        })
+        .unwrap();
+        drop(ctx);
+
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_write_batch_is_applied_and_logged_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let node = dir.path();
+        let keyspace = node.join("ks");
+        let table = keyspace.join("users");
+        let options = CommitLogOptions {
+            sync_mode: SyncMode::Batch,
+            sync_period_ms: 0,
+            segment_size: 1 << 20,
+        };
+        let mut ctx = initialize_context_with_commitlog(node, options).unwrap();
+        ctx.create_keyspace(
+            &keyspace,
+            &Options::new(true, "SimpleStrategy".to_string(), 1),
+        )
+        .unwrap();
+        let schema = Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        );
+        ctx.create_table(&table, &schema).unwrap();
+        let ids = |ctx: &Context| {
+            let mut ids = Vec::new();
+            ctx.read_table(&table, &mut |row| {
+                ids.push(row["id"].parse::<i32>().unwrap());
+                Ok(())
+            })
+            .unwrap();
+            ids.sort();
+            ids
+        };
+
+        let mut batch = ctx.write_batch(&table).unwrap();
+        for id in ["1", "2"] {
+            let row = HashMap::from([
+                ("id".to_string(), id.to_string()),
+                ("name".to_string(), "John".to_string()),
+            ]);
+            batch
+                .append_to_table(&row, current_timestamp(), None)
+                .unwrap();
+        }
+        let unknown = HashMap::from([("id".to_string(), "3".to_string())]);
+        assert!(batch
+            .delete_columns(&unknown, &["age".to_string()], current_timestamp())
+            .is_err());
+        // Nothing is written until the batch is applied.
+        assert!(ids(&ctx).is_empty());
+        batch.apply().unwrap();
+        assert_eq!(ids(&ctx), vec![1, 2]);
+
+        let segments: Vec<PathBuf> = read_dir(node.join(COMMITLOG_DIR))
+            .unwrap()
+            .map(|segment| segment.unwrap().path())
+            .collect();
+        assert_eq!(segments.len(), 1);
+        let records = read_segment(&segments[0]).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(matches!(&records[0].kind, MutationKind::Batch(mutations) if mutations.len() == 2));
+
+        // The node crashes, and the batch is replayed from its record by the next run of the node.
+        std::mem::forget(ctx);
+        let segment = node.join(COMMITLOG_DIR).join("CommitLog-0-0.log");
+        std::fs::rename(&segments[0], segment).unwrap();
+        let ctx = initialize_context(node).unwrap();
+        assert_eq!(ids(&ctx), vec![1, 2]);
+    }
+}
@@ -1,14 +1,35 @@
+mod commitlog;
 mod context;
 mod models;
+mod storage;
+
+pub use commitlog::CommitLogOptions;
+pub use commitlog::SyncMode;
 
 pub use context::initialize_context;
+pub use context::initialize_context_with_commitlog;
 pub use context::Context;
+pub use context::WriteBatch;
 
 pub use models::primary_key::PrimaryKey;
 
+pub use storage::cell::current_timestamp;
+pub use storage::cell::Cell;
+pub use storage::key::get_partition_token;
+pub use storage::key::get_token;
+pub use storage::merkle::MerkleTree;
+pub use storage::merkle::TokenRange;
+pub use storage::stored_row::get_live_rows;
+pub use storage::stored_row::get_repairs;
+pub use storage::stored_row::reconcile;
+pub use storage::stored_row::StoredRow;
+
 pub use models::schema::Schema;
 pub use models::schema::SchemaType;
 
+pub use models::table_options::CompactionOptions;
+pub use models::table_options::TableOptions;
+
 pub use models::keyspace::use_keyspace;
 pub use models::keyspace::Options;
 pub use models::keyspace::Replication;
@@ -1,19 +1,107 @@
 use std::{
+    collections::HashMap,
     fs::{create_dir_all, remove_dir_all, File},
-    io::{Read, Write},
+    io::{ErrorKind, Read, Write},
     path::Path,
 };
 
 use serde::{Deserialize, Serialize};
-use shared::{map_io_error, not_found_error};
+use shared::{io_error, map_io_error, not_found_error};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The replication strategy of a keyspace, set with `WITH replication = {...}`.
+///
+/// With `SimpleStrategy` the replicas are the nodes that follow the owner of the token in the ring.
+/// With `NetworkTopologyStrategy` each datacenter holds its own number of replicas, placed on different
+/// racks when possible, and `replication_factor` is the total among every datacenter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Replication {
     pub class: String,
     pub replication_factor: i32,
+    /// The replication factor of each datacenter, for `NetworkTopologyStrategy`.
+    #[serde(default)]
+    pub datacenters: HashMap<String, i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+const SIMPLE_STRATEGY: &str = "SimpleStrategy";
+const NETWORK_TOPOLOGY_STRATEGY: &str = "NetworkTopologyStrategy";
+/// The replicas of each token of the keyspaces created before their options were kept.
+const LEGACY_REPLICATION_FACTOR: i32 = 3;
+
+impl Replication {
+    /// Creates the replication of a keyspace from the entries of the `replication` map.
+    ///
+    /// The `class` may be given with or without the `org.apache.cassandra.locator.` package.
+    /// `SimpleStrategy` takes a `replication_factor`, and `NetworkTopologyStrategy` the replication factor
+    /// of each datacenter, keyed by its name.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if the class is missing or unknown, or if a replication factor is missing or
+    ///   is not a positive number.
+    pub fn new(map: &HashMap<String, String>) -> std::io::Result<Self> {
+        let class = map
+            .get("class")
+            .ok_or(io_error!("Missing replication class"))?;
+        let class = class
+            .strip_prefix("org.apache.cassandra.locator.")
+            .unwrap_or(class);
+        match class {
+            SIMPLE_STRATEGY => {
+                let factor = map
+                    .get("replication_factor")
+                    .ok_or(io_error!("Missing replication_factor"))?;
+                if let Some(option) = map
+                    .keys()
+                    .find(|key| !["class", "replication_factor"].contains(&key.as_str()))
+                {
+                    return Err(io_error!(format!(
+                        "Unknown option '{option}' for class '{class}'"
+                    )));
+                }
+                Ok(Replication {
+                    class: class.to_string(),
+                    replication_factor: parse_replication_factor(factor)?,
+                    datacenters: HashMap::new(),
+                })
+            }
+            NETWORK_TOPOLOGY_STRATEGY => {
+                let datacenters = map
+                    .iter()
+                    .filter(|(key, _)| key.as_str() != "class")
+                    .map(|(datacenter, factor)| {
+                        Ok((datacenter.clone(), parse_replication_factor(factor)?))
+                    })
+                    .collect::<std::io::Result<HashMap<_, _>>>()?;
+                if datacenters.is_empty() {
+                    return Err(io_error!(format!(
+                        "Missing the replication factor of the datacenters for class '{class}'"
+                    )));
+                }
+                Ok(Replication {
+                    class: class.to_string(),
+                    replication_factor: datacenters.values().sum(),
+                    datacenters,
+                })
+            }
+            _ => Err(io_error!(format!("Unknown replication class '{class}'"))),
+        }
+    }
+
+    pub fn is_network_topology(&self) -> bool {
+        self.class == NETWORK_TOPOLOGY_STRATEGY
+    }
+}
+
+fn parse_replication_factor(factor: &str) -> std::io::Result<i32> {
+    factor
+        .parse()
+        .ok()
+        .filter(|factor| *factor > 0)
+        .ok_or(io_error!(format!("Invalid replication_factor '{factor}'")))
+}
+
+/// Options of a keyspace, set with the `WITH` clause of `CREATE KEYSPACE` and `ALTER KEYSPACE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Options {
     pub durable_writes: bool,
     pub replication: Replication,
@@ -26,6 +114,7 @@ impl Options {
             replication: Replication {
                 class,
                 replication_factor,
+                datacenters: HashMap::new(),
             },
         }
     }
@@ -51,6 +140,15 @@ pub(crate) fn create_keyspace(keyspace: &Path, options: &Options) -> std::io::Re
     options.write(&mut opt_file)
 }
 
+/// Replaces the options of an existing keyspace.
+pub(crate) fn alter_keyspace(keyspace: &Path, options: &Options) -> std::io::Result<()> {
+    if !keyspace.exists() {
+        return Err(not_found_error!("Keyspace does not exist"));
+    }
+    let mut opt_file = File::create(keyspace.join("options.json"))?;
+    options.write(&mut opt_file)
+}
+
 pub(crate) fn drop_keyspace(keyspace: &Path) -> std::io::Result<()> {
     if !keyspace.exists() {
         return Err(not_found_error!("Keyspace does not exist"));
@@ -71,6 +169,28 @@ pub(crate) fn get_keyspace_options(keyspace: &Path) -> std::io::Result<Options>
     Options::read(&mut opt_file)
 }
 
+/// Returns the options of a keyspace, or the ones of the keyspaces created before their options were kept
+/// if it has no options file: durable writes, and the owner of each token and the two nodes that follow it
+/// as replicas.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the keyspace does not exist, or if its options file is present but cannot be
+///   read.
+pub(crate) fn get_keyspace_options_or_legacy(keyspace: &Path) -> std::io::Result<Options> {
+    if !keyspace.exists() {
+        return Err(not_found_error!("Keyspace does not exist"));
+    }
+    match get_keyspace_options(keyspace) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Options::new(
+            true,
+            SIMPLE_STRATEGY.to_string(),
+            LEGACY_REPLICATION_FACTOR,
+        )),
+        res => res,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +211,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_options_bincode_serde() {
+        let options = Options::new(true, "SimpleStrategy".to_string(), 1);
+        let bytes = bincode::serialize(&options).unwrap();
+        assert_eq!(options, bincode::deserialize(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_keyspaces_without_options_keep_the_legacy_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = get_keyspace_options_or_legacy(dir.path()).unwrap();
+        assert!(options.durable_writes);
+        assert_eq!(options.replication.class, SIMPLE_STRATEGY);
+        assert_eq!(options.replication.replication_factor, 3);
+
+        std::fs::write(dir.path().join("options.json"), "{").unwrap();
+        assert!(get_keyspace_options_or_legacy(dir.path()).is_err());
+        assert!(get_keyspace_options_or_legacy(&dir.path().join("missing")).is_err());
+    }
+
     #[test]
     fn test_create_keyspace() {
         let keyspace = PathBuf::from("test_keyspace");
@@ -107,4 +247,57 @@ mod tests {
         );
         assert!(!keyspace.exists());
     }
+
+    #[test]
+    fn test_replication_from_map() {
+        let map = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let replication = Replication::new(&map(&[
+            ("class", "org.apache.cassandra.locator.SimpleStrategy"),
+            ("replication_factor", "3"),
+        ]))
+        .unwrap();
+        assert_eq!(replication.class, "SimpleStrategy");
+        assert_eq!(replication.replication_factor, 3);
+
+        assert!(Replication::new(&map(&[("replication_factor", "3")])).is_err());
+        assert!(Replication::new(&map(&[("class", "SimpleStrategy")])).is_err());
+        assert!(Replication::new(&map(&[
+            ("class", "SimpleStrategy"),
+            ("replication_factor", "0")
+        ]))
+        .is_err());
+        assert!(Replication::new(&map(&[
+            ("class", "UnknownStrategy"),
+            ("replication_factor", "3")
+        ]))
+        .is_err());
+        assert!(Replication::new(&map(&[
+            ("class", "SimpleStrategy"),
+            ("replication_factor", "3"),
+            ("dc1", "3")
+        ]))
+        .is_err());
+
+        let replication = Replication::new(&map(&[
+            ("class", "NetworkTopologyStrategy"),
+            ("us-east-1a", "3"),
+            ("us-east-1b", "2"),
+        ]))
+        .unwrap();
+        assert!(replication.is_network_topology());
+        assert_eq!(replication.replication_factor, 5);
+        assert_eq!(replication.datacenters["us-east-1b"], 2);
+
+        assert!(Replication::new(&map(&[("class", "NetworkTopologyStrategy")])).is_err());
+        assert!(Replication::new(&map(&[
+            ("class", "NetworkTopologyStrategy"),
+            ("dc1", "none")
+        ]))
+        .is_err());
+    }
 }
@@ -1,4 +1,5 @@
 pub mod keyspace;
 pub mod primary_key;
 pub mod schema;
+pub mod table_options;
 pub mod tables;
@@ -8,7 +8,12 @@ use chrono::DateTime;
 use serde::{Deserialize, Serialize};
 use shared::{io_error, map_io_error};
 
-use super::primary_key::PrimaryKey;
+use crate::storage::{cell::Cell, key::Key};
+
+use super::{primary_key::PrimaryKey, table_options::TableOptions};
+
+/// A function that parses a value in form of bytes into its string representation.
+pub type ParseFn = fn(&[u8]) -> std::io::Result<String>;
 
 /// Represents the data types of the columns of the table.
 /// The data types are used to parse the data from the table.
@@ -54,7 +59,7 @@ impl SchemaType {
         }
     }
 
-    fn get_parse_function(&self) -> fn(&[u8]) -> std::io::Result<String> {
+    fn get_parse_function(&self) -> ParseFn {
         match self {
             SchemaType::Boolean => {
                 fn parse_boolean(bytes: &[u8]) -> std::io::Result<String> {
@@ -160,10 +165,38 @@ impl SchemaType {
 /// The schema contains the columns and the primary key.  
 /// Each column has a name and a data type and is used to parse the data from the table.  
 /// The primary key contains the partition key and the clustering key.
+/// The options are the ones set with the `WITH` clause when the table was created.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schema {
     columns: HashMap<String, SchemaType>,
     primary_key: PrimaryKey,
+    #[serde(default, with = "options_json")]
+    options: Box<TableOptions>,
+}
+
+/// Serializes the table options as their JSON, as they are written to the schema file. The compaction
+/// options are tagged by their class, which formats that are not self-describing, like the bincode of
+/// the internode messages, cannot read.
+mod options_json {
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    use super::TableOptions;
+
+    pub(super) fn serialize<S: Serializer>(
+        options: &TableOptions,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&options.to_json().map_err(ser::Error::custom)?)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<TableOptions>, D::Error> {
+        let json = String::deserialize(deserializer)?;
+        TableOptions::from_json(&json)
+            .map(Box::new)
+            .map_err(de::Error::custom)
+    }
 }
 
 impl Schema {
@@ -172,6 +205,7 @@ impl Schema {
         Schema {
             columns,
             primary_key,
+            options: Box::default(),
         }
     }
 
@@ -183,10 +217,7 @@ impl Schema {
     /// This function returns a string representation of the data from the bytes.
     ///
     /// Useful for bound variables in the query.
-    pub fn get_parse_function(
-        &self,
-        column_name: &str,
-    ) -> Option<fn(&[u8]) -> std::io::Result<String>> {
+    pub fn get_parse_function(&self, column_name: &str) -> Option<ParseFn> {
         self.columns
             .get(column_name)
             .map(|schema_type| schema_type.get_parse_function())
@@ -200,6 +231,14 @@ impl Schema {
         &self.primary_key
     }
 
+    pub fn get_options(&self) -> &TableOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: TableOptions) {
+        *self.options = options;
+    }
+
     pub fn get_schema_type(&self, column: &str) -> Option<&SchemaType> {
         self.columns.get(column)
     }
@@ -211,6 +250,34 @@ impl Schema {
             .and_then(|schema_type| schema_type.check_type(value))
     }
 
+    /// Returns the values of the live cells of a row, `NULL` for the columns of the table without one.
+    /// The primary key columns take their value from the key of the row.
+    ///
+    /// For each cell with a TTL, the row also has a `TTL(<col>)` entry with the seconds left until it
+    /// expires at `now`.
+    pub(crate) fn to_row(
+        &self,
+        key: &Key,
+        cells: &HashMap<String, Cell>,
+        now: i64,
+    ) -> HashMap<String, String> {
+        let mut row: HashMap<String, String> = key.values(self).unwrap_or_default();
+        for col in self.get_columns() {
+            match cells.get(&col) {
+                Some(cell) => {
+                    if let Some(expiration) = cell.expiration {
+                        row.insert(format!("TTL({col})"), (expiration - now).to_string());
+                    }
+                    row.insert(col, cell.value.clone());
+                }
+                None => {
+                    row.entry(col).or_insert("NULL".to_string());
+                }
+            }
+        }
+        row
+    }
+
     /// Reads the schema from the specified reader.
     ///
     /// ** The reader **must not** be a buffered reader. **
@@ -220,10 +287,12 @@ impl Schema {
     /// - For each column, the line must have the column name and the data type separated by a space.
     /// - The `PARTITION_KEY` line must have the keyword `PARTITION_KEY` followed by the partition key columns separated by spaces.
     /// - The `CLUSTERING_KEY` line must have the keyword `CLUSTERING_KEY` followed by the clustering key columns separated by spaces.
+    /// - The optional `OPTIONS` line must have the keyword `OPTIONS` followed by the table options in JSON.
     pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
         let mut columns = HashMap::new();
         let mut partition_key = Vec::new();
         let mut clustering_key = Vec::new();
+        let mut options = Box::default();
 
         let reader = BufReader::new(reader);
         for line in reader.lines() {
@@ -246,6 +315,10 @@ impl Schema {
                     .iter()
                     .map(std::string::ToString::to_string)
                     .collect();
+            } else if parts[0] == "OPTIONS" {
+                options = Box::new(TableOptions::from_json(
+                    line.trim_start()["OPTIONS".len()..].trim(),
+                )?);
             } else {
                 columns.insert(parts[0].to_string(), SchemaType::new(parts[1])?);
             }
@@ -256,6 +329,7 @@ impl Schema {
         Ok(Schema {
             columns,
             primary_key: PrimaryKey::new(partition_key, clustering_key),
+            options,
         })
     }
 
@@ -268,6 +342,7 @@ impl Schema {
     /// - For each column, the line has the column name and the data type separated by a space.
     /// - The `PARTITION_KEY` line has the keyword `PARTITION_KEY` followed by the partition key columns separated by spaces.
     /// - The `CLUSTERING_KEY` line has the keyword `CLUSTERING_KEY` followed by the clustering key columns separated by spaces.
+    /// - The `OPTIONS` line has the keyword `OPTIONS` followed by the table options in JSON.
     pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
         let mut writer = BufWriter::new(writer);
         for (column, schema_type) in &self.columns {
@@ -280,7 +355,8 @@ impl Schema {
         writer.write_fmt(format_args!(
             "CLUSTERING_KEY {}\n",
             self.primary_key.get_clustering_key().join(" ")
-        ))
+        ))?;
+        writer.write_fmt(format_args!("OPTIONS {}\n", self.options.to_json()?))
     }
 }
 
@@ -294,10 +370,18 @@ mod tests {
         let mut columns = HashMap::new();
         columns.insert("id".to_string(), SchemaType::Int);
         columns.insert("name".to_string(), SchemaType::Text);
-        let schema = Schema::new(
+        let mut schema = Schema::new(
             columns,
             PrimaryKey::new(vec!["id".to_string()], vec!["name".to_string()]),
         );
+        schema.set_options(TableOptions {
+            compaction: crate::CompactionOptions::Leveled {
+                sstable_size_in_mb: 10,
+                fanout_size: 10,
+            },
+            gc_grace_seconds: 0,
+            default_time_to_live: 0,
+        });
         let mut buffer = Cursor::new(Vec::new());
         schema.write(&mut buffer).unwrap();
         buffer.set_position(0);
@@ -322,6 +406,12 @@ mod tests {
             schema.get_schema_type("name").unwrap().to_string(),
             read_schema.get_schema_type("name").unwrap().to_string()
         );
+        assert_eq!(schema.get_options(), read_schema.get_options());
+
+        // Schemas are also sent to other nodes with bincode.
+        let bytes = bincode::serialize(&schema).unwrap();
+        let read_schema: Schema = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(schema.get_options(), read_schema.get_options());
     }
 
     #[test]
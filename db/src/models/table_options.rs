@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use shared::{io_error, map_io_error};
+
+/// Options of a table, set with the `WITH` clause of `CREATE TABLE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableOptions {
+    pub compaction: CompactionOptions,
+    /// Seconds during which the tombstones of the table are kept before compaction can purge them.
+    /// A replica that misses a deletion for longer than this may bring the deleted data back.
+    pub gc_grace_seconds: u64,
+    /// Seconds after which the cells written without a TTL expire. 0 means they do not expire.
+    pub default_time_to_live: u64,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            compaction: CompactionOptions::default(),
+            gc_grace_seconds: 864000,
+            default_time_to_live: 0,
+        }
+    }
+}
+
+/// The compaction strategy of a table and its sub-options.
+///
+/// Supported classes:
+/// - `SizeTieredCompactionStrategy`: merges SSTables of similar size once there are enough of them.
+/// - `LeveledCompactionStrategy`: keeps the SSTables in levels of non-overlapping SSTables, each level
+///   `fanout_size` times bigger than the previous one.
+/// - `TimeWindowCompactionStrategy`: merges the SSTables written in the same time window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "class")]
+pub enum CompactionOptions {
+    SizeTiered {
+        min_threshold: usize,
+        max_threshold: usize,
+        bucket_low: f64,
+        bucket_high: f64,
+        /// Size in bytes under which all the SSTables are considered of the same size.
+        min_sstable_size: u64,
+    },
+    Leveled {
+        sstable_size_in_mb: u64,
+        fanout_size: u64,
+    },
+    TimeWindow {
+        /// One of `MINUTES`, `HOURS` or `DAYS`.
+        compaction_window_unit: String,
+        compaction_window_size: u64,
+    },
+}
+
+impl Default for CompactionOptions {
+    fn default() -> Self {
+        CompactionOptions::SizeTiered {
+            min_threshold: 4,
+            max_threshold: 32,
+            bucket_low: 0.5,
+            bucket_high: 1.5,
+            min_sstable_size: 50 * 1024 * 1024,
+        }
+    }
+}
+
+impl CompactionOptions {
+    /// Builds the compaction options from the map of a `compaction = {...}` option.
+    /// The `class` entry is mandatory, the sub-options that are not present take their default value.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if the class is unknown, or if a sub-option is unknown for the class or has an invalid value.
+    pub fn new(options: &HashMap<String, String>) -> std::io::Result<Self> {
+        let class = options
+            .get("class")
+            .ok_or(io_error!("Compaction options must have a 'class'"))?;
+        let mut compaction = match class.rsplit('.').next().unwrap_or_default() {
+            "SizeTieredCompactionStrategy" => CompactionOptions::default(),
+            "LeveledCompactionStrategy" => CompactionOptions::Leveled {
+                sstable_size_in_mb: 160,
+                fanout_size: 10,
+            },
+            "TimeWindowCompactionStrategy" => CompactionOptions::TimeWindow {
+                compaction_window_unit: "DAYS".to_string(),
+                compaction_window_size: 1,
+            },
+            _ => return Err(io_error!(format!("Unknown compaction class '{class}'"))),
+        };
+        for (option, value) in options {
+            compaction.set(option, value)?;
+        }
+        compaction.validate()?;
+        Ok(compaction)
+    }
+
+    fn set(&mut self, option: &str, value: &str) -> std::io::Result<()> {
+        match (self, option) {
+            (_, "class") => return Ok(()),
+            (CompactionOptions::SizeTiered { min_threshold, .. }, "min_threshold") => {
+                *min_threshold = parse(option, value)?;
+            }
+            (CompactionOptions::SizeTiered { max_threshold, .. }, "max_threshold") => {
+                *max_threshold = parse(option, value)?;
+            }
+            (CompactionOptions::SizeTiered { bucket_low, .. }, "bucket_low") => {
+                *bucket_low = parse(option, value)?;
+            }
+            (CompactionOptions::SizeTiered { bucket_high, .. }, "bucket_high") => {
+                *bucket_high = parse(option, value)?;
+            }
+            (
+                CompactionOptions::SizeTiered {
+                    min_sstable_size, ..
+                },
+                "min_sstable_size",
+            ) => {
+                *min_sstable_size = parse(option, value)?;
+            }
+            (
+                CompactionOptions::Leveled {
+                    sstable_size_in_mb, ..
+                },
+                "sstable_size_in_mb",
+            ) => {
+                *sstable_size_in_mb = parse(option, value)?;
+            }
+            (CompactionOptions::Leveled { fanout_size, .. }, "fanout_size") => {
+                *fanout_size = parse(option, value)?;
+            }
+            (
+                CompactionOptions::TimeWindow {
+                    compaction_window_unit,
+                    ..
+                },
+                "compaction_window_unit",
+            ) => {
+                if !["MINUTES", "HOURS", "DAYS"].contains(&value) {
+                    return Err(io_error!(format!(
+                        "Invalid compaction_window_unit '{value}'"
+                    )));
+                }
+                *compaction_window_unit = value.to_string();
+            }
+            (
+                CompactionOptions::TimeWindow {
+                    compaction_window_size,
+                    ..
+                },
+                "compaction_window_size",
+            ) => {
+                *compaction_window_size = parse(option, value)?;
+            }
+            _ => return Err(io_error!(format!("Unknown compaction option '{option}'"))),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> std::io::Result<()> {
+        let valid = match self {
+            CompactionOptions::SizeTiered {
+                min_threshold,
+                max_threshold,
+                bucket_low,
+                bucket_high,
+                ..
+            } => *min_threshold >= 2 && max_threshold >= min_threshold && bucket_low < bucket_high,
+            CompactionOptions::Leveled {
+                sstable_size_in_mb,
+                fanout_size,
+            } => *sstable_size_in_mb > 0 && *fanout_size > 1,
+            CompactionOptions::TimeWindow {
+                compaction_window_size,
+                ..
+            } => *compaction_window_size > 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(io_error!("Invalid compaction options"))
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(option: &str, value: &str) -> std::io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| io_error!(format!("Invalid value '{value}' for '{option}'")))
+}
+
+impl TableOptions {
+    pub(crate) fn to_json(&self) -> std::io::Result<String> {
+        serde_json::to_string(self).map_err(map_io_error!("Failed to write table options"))
+    }
+
+    pub(crate) fn from_json(json: &str) -> std::io::Result<Self> {
+        serde_json::from_str(json).map_err(map_io_error!("Failed to read table options"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compaction_options() {
+        let options = CompactionOptions::new(&HashMap::from([
+            ("class".to_string(), "LeveledCompactionStrategy".to_string()),
+            ("sstable_size_in_mb".to_string(), "10".to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(
+            options,
+            CompactionOptions::Leveled {
+                sstable_size_in_mb: 10,
+                fanout_size: 10
+            }
+        );
+        assert!(CompactionOptions::new(&HashMap::from([
+            ("class".to_string(), "LeveledCompactionStrategy".to_string()),
+            ("min_threshold".to_string(), "4".to_string()),
+        ]))
+        .is_err());
+        assert!(CompactionOptions::new(&HashMap::from([(
+            "class".to_string(),
+            "UnknownStrategy".to_string()
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_table_options_json() {
+        let options = TableOptions {
+            compaction: CompactionOptions::TimeWindow {
+                compaction_window_unit: "HOURS".to_string(),
+                compaction_window_size: 2,
+            },
+            gc_grace_seconds: 3600,
+            default_time_to_live: 60,
+        };
+        let json = options.to_json().unwrap();
+        assert_eq!(TableOptions::from_json(&json).unwrap(), options);
+        assert_eq!(
+            TableOptions::from_json("{}").unwrap(),
+            TableOptions::default()
+        );
+    }
+}
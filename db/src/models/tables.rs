@@ -1,37 +1,186 @@
 use std::{
     collections::HashMap,
-    fs::{create_dir_all, read_dir, remove_dir_all, rename, File, OpenOptions},
-    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    fs::{create_dir_all, read_dir, remove_dir_all, File},
+    io::Write,
+    ops::Bound,
     path::Path,
-    sync::RwLock,
+    sync::{Arc, RwLock},
 };
 
 use shared::{io_error, not_found_error};
 
-use crate::context::get_file_name;
+use crate::{
+    commitlog::{
+        mutation::{primary_key_values, Mutation, MutationKind},
+        CommitLog,
+    },
+    context::get_file_name,
+    storage::{
+        cell::current_time, compaction::Compactor, key::Key, stored_row::StoredRow, Entry,
+        TableStore,
+    },
+};
+
+use super::{
+    keyspace::{get_keyspace_options_or_legacy, Options},
+    schema::Schema,
+};
 
-use super::schema::Schema;
+/// A function that receives a row of the table and returns the columns to write, or `None` if the row must be deleted.
+pub type UpdateVisitor<'a> =
+    dyn FnMut(HashMap<String, String>) -> std::io::Result<Option<HashMap<String, String>>> + 'a;
 
 /// Represents the tables in a keyspace.
-/// The tables contain their schema and are this is used to create, drop, and read them.
+/// The tables contain their schema and storage and this is used to create, drop, read and write them.
 #[derive(Debug)]
 pub(crate) struct Tables {
-    tables: RwLock<HashMap<String, RwLock<Schema>>>,
+    tables: RwLock<HashMap<String, Table>>,
+    /// The options of the keyspace: whether its mutations go through the commit log and its replication.
+    options: Options,
+}
+
+/// A table of the keyspace: its schema and the storage of its rows.
+/// The storage is shared with the compaction thread.
+#[derive(Debug)]
+struct Table {
+    schema: Schema,
+    store: Arc<RwLock<TableStore>>,
+}
+
+impl Table {
+    fn open(dir: &Path, schema: Schema, compactor: &Compactor) -> std::io::Result<Self> {
+        let store = Arc::new(RwLock::new(TableStore::open(dir, &schema)?));
+        compactor.register(&store);
+        Ok(Table { schema, store })
+    }
+
+    /// Applies the mutations to the storage of the table, all of them under the same lock so the readers
+    /// of the table see all of them or none.
+    fn apply(&self, mutations: &[Mutation]) -> std::io::Result<()> {
+        let mut store = self.store.write().unwrap();
+        self.apply_to(&mut store, mutations)
+    }
+
+    fn apply_to(&self, store: &mut TableStore, mutations: &[Mutation]) -> std::io::Result<()> {
+        for mutation in mutations {
+            match &mutation.kind {
+                MutationKind::Upsert(row, expiration) => {
+                    let key = Key::new(&self.schema, row)?;
+                    let entry = Entry::row(row, mutation.timestamp).with_expiration(*expiration);
+                    store.write(key, entry)?;
+                }
+                MutationKind::Delete(primary_key) => {
+                    let key = Key::new(&self.schema, primary_key)?;
+                    store.write(key, Entry::deleted(mutation.timestamp))?;
+                }
+                MutationKind::Write(key, entry) => store.write(key.clone(), entry.clone())?,
+                MutationKind::Batch(mutations) => self.apply_to(store, mutations)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the local time at which the cells written with the TTL expire, taking the
+    /// `default_time_to_live` of the table if it is `None`. A TTL of 0 means the cells do not expire.
+    fn expiration(&self, ttl: Option<u64>) -> Option<i64> {
+        match ttl.unwrap_or(self.schema.get_options().default_time_to_live) {
+            0 => None,
+            ttl => Some(current_time() + ttl as i64),
+        }
+    }
+
+    /// Returns the key and the entry that delete the rows selected by the values of their primary key.
+    /// The partition key columns must have a value, and the clustering columns with a value must be the
+    /// first ones. The bounds restrict the values of the next clustering column.
+    fn deletion(
+        &self,
+        key: &HashMap<String, String>,
+        bounds: (Bound<String>, Bound<String>),
+        timestamp: i64,
+    ) -> std::io::Result<(Key, Entry)> {
+        let schema = &self.schema;
+        let primary_key = schema.get_primary_key();
+        if primary_key
+            .get_partition_key()
+            .iter()
+            .any(|col| !key.contains_key(col))
+        {
+            return Err(io_error!("All partition key columns must be restricted"));
+        }
+        let clustering = primary_key.get_clustering_key();
+        let prefix_len = clustering
+            .iter()
+            .take_while(|col| key.contains_key(*col))
+            .count();
+        if clustering[prefix_len..]
+            .iter()
+            .any(|col| key.contains_key(col))
+        {
+            return Err(io_error!(
+                "Clustering columns must be restricted in the order of the primary key"
+            ));
+        }
+        let (start, end) = bounds;
+        if matches!((&start, &end), (Bound::Unbounded, Bound::Unbounded)) {
+            // Deletes a row, or the whole partition without a restricted clustering column.
+            if prefix_len == clustering.len() || prefix_len == 0 {
+                return Ok((
+                    Key::prefix(schema, key, prefix_len)?,
+                    Entry::deleted(timestamp),
+                ));
+            }
+        }
+
+        let prefix = Key::prefix(schema, key, prefix_len)?;
+        let encode_bound = |bound: Bound<String>| -> std::io::Result<Bound<Vec<u8>>> {
+            let encode = |value: String| -> std::io::Result<Vec<u8>> {
+                let col = clustering.get(prefix_len).ok_or(io_error!(
+                    "Range deletions must restrict a clustering column"
+                ))?;
+                let mut row = key.clone();
+                row.insert(col.clone(), value);
+                Ok(Key::prefix(schema, &row, prefix_len + 1)?
+                    .as_bytes()
+                    .to_vec())
+            };
+            Ok(match bound {
+                Bound::Included(value) => Bound::Included(encode(value)?),
+                Bound::Excluded(value) => Bound::Excluded(encode(value)?),
+                Bound::Unbounded => Bound::Included(prefix.as_bytes().to_vec()),
+            })
+        };
+        let entry = Entry::deleted_range(encode_bound(start)?, encode_bound(end)?, timestamp);
+        Ok((Key::prefix(schema, key, 0)?, entry))
+    }
 }
 
 impl Tables {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(options: Options) -> Self {
         Tables {
             tables: RwLock::new(HashMap::new()),
+            options,
         }
     }
 
-    /// Reads the schema of the tables in the keyspace.
-    /// Should be called when the server starts to load the schema of the tables on startup.
+    pub(crate) fn is_durable(&self) -> bool {
+        self.options.durable_writes
+    }
+
+    pub(crate) fn get_options(&self) -> &Options {
+        &self.options
+    }
+
+    pub(crate) fn set_options(&mut self, options: Options) {
+        self.options = options;
+    }
+
+    /// Reads the schema of the tables in the keyspace and opens their storage.
+    /// Should be called when the server starts to load the tables on startup.
     ///
     /// # Arguments
     ///
     /// * `keyspace` - A reference to the path of the keyspace.
+    /// * `compactor` - The compaction thread the tables are registered to.
     ///
     /// # Returns
     ///
@@ -39,27 +188,38 @@ impl Tables {
     ///
     /// # Errors
     ///
-    /// * Returns an error if the keyspace does not exist or if there is an error reading the schema of the tables.
-    pub(crate) fn get_tables_schema(keyspace: &Path) -> std::io::Result<Self> {
-        let mut tables: HashMap<String, RwLock<Schema>> = HashMap::new();
+    /// * Returns an error if the keyspace does not exist or if there is an error reading the schema or the storage of the tables.
+    pub(crate) fn get_tables_schema(
+        keyspace: &Path,
+        compactor: &Compactor,
+    ) -> std::io::Result<Self> {
+        let mut tables: HashMap<String, Table> = HashMap::new();
         for entry in read_dir(keyspace)? {
             let table = entry?;
             if !table.file_type()?.is_dir() {
                 continue;
             }
             let mut schema_file = File::open(table.path().join("table.schema"))?;
+            let schema = Schema::read(&mut schema_file)?;
 
             tables.insert(
                 get_file_name(&table.path(), "Invalid table name".to_string())?,
-                RwLock::new(Schema::read(&mut schema_file)?),
+                Table::open(&table.path(), schema, compactor)?,
             );
         }
+        let options = get_keyspace_options_or_legacy(keyspace)?;
         Ok(Tables {
             tables: RwLock::new(tables),
+            options,
         })
     }
 
-    pub(crate) fn create_table(&mut self, table: &Path, schema: Schema) -> std::io::Result<()> {
+    pub(crate) fn create_table(
+        &mut self,
+        table: &Path,
+        schema: Schema,
+        compactor: &Compactor,
+    ) -> std::io::Result<()> {
         let table_str = get_file_name(table, "Invalid table name".to_string())?;
         let read_guard = self.tables.read().unwrap();
         if read_guard.contains_key(&table_str) {
@@ -75,14 +235,8 @@ impl Tables {
         schema.write(&mut schema_file)?;
         schema_file.flush()?;
 
-        let mut table_file = File::create(table.join("table.csv"))?;
-        table_file.write_fmt(format_args!("{}\n", schema.get_columns().join(",")))?;
-        table_file.flush()?;
-
-        self.tables
-            .write()
-            .unwrap()
-            .insert(table_str, RwLock::new(schema));
+        let new_table = Table::open(table, schema, compactor)?;
+        self.tables.write().unwrap().insert(table_str, new_table);
         Ok(())
     }
 
@@ -92,6 +246,7 @@ impl Tables {
         let write_guard = binding
             .get(&table_str)
             .ok_or(not_found_error!("Table does not exist"))?
+            .store
             .write()
             .unwrap();
         remove_dir_all(table)?;
@@ -106,12 +261,16 @@ impl Tables {
             .ok_or(not_found_error!("Table does not exist"))
     }
 
+    pub(crate) fn get_table_names(&self) -> Vec<String> {
+        self.tables.read().unwrap().keys().cloned().collect()
+    }
+
     pub(crate) fn get_table_schema(&self, table: &str) -> std::io::Result<Schema> {
         self.tables
             .read()
             .unwrap()
             .get(table)
-            .map(|schema| schema.read().unwrap().clone())
+            .map(|table| table.schema.clone())
             .ok_or(not_found_error!("Table does not exist"))
     }
 
@@ -121,150 +280,422 @@ impl Tables {
         visitor: &mut dyn FnMut(HashMap<String, String>) -> std::io::Result<()>,
     ) -> std::io::Result<()> {
         let table_str = get_file_name(table, "Invalid table name".to_string())?;
-        let read_guard = self.tables.read().unwrap();
-        let __read_guard = read_guard
+        let binding = self.tables.read().unwrap();
+        let table = binding
             .get(&table_str)
-            .ok_or(not_found_error!("Table not found"))?
-            .read()
-            .unwrap();
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .from_path(table.join("table.csv"))?;
+            .ok_or(not_found_error!("Table not found"))?;
+        let read_guard = table.store.read().unwrap();
+        let now = current_time();
+        read_guard.scan(&mut |key, cells| visitor(table.schema.to_row(key, &cells, now)))
+    }
 
-        let headers = reader.headers()?.clone();
+    /// Reads only the rows of the partition, using the partition index of the SSTables.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `partition_key` - The values of the partition key columns.
+    /// * `visitor` - A function that takes each row of the partition.
+    pub(crate) fn read_partition(
+        &self,
+        table: &Path,
+        partition_key: &HashMap<String, String>,
+        visitor: &mut dyn FnMut(HashMap<String, String>) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
+        let key = Key::new(&table.schema, partition_key)?;
+        let read_guard = table.store.read().unwrap();
+        let now = current_time();
+        read_guard.scan_partition(key.partition(), &mut |key, cells| {
+            visitor(table.schema.to_row(key, &cells, now))
+        })
+    }
 
-        for result in reader.records() {
-            let record = result?;
-            let map = map_string_record_to_hashmap(&record, &headers);
-            visitor(map)?;
+    /// Reads the stored rows of the partition, with their tombstones.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `partition_key` - The values of the partition key columns.
+    /// * `visitor` - A function that takes each stored row of the partition, in key order.
+    pub(crate) fn read_stored_rows(
+        &self,
+        table: &Path,
+        partition_key: &HashMap<String, String>,
+        visitor: &mut dyn FnMut(StoredRow) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
+        let key = Key::new(&table.schema, partition_key)?;
+        let read_guard = table.store.read().unwrap();
+        for result in read_guard.entries(Some(key.partition()))? {
+            let (key, entry) = result?;
+            visitor(StoredRow { key, entry })?;
         }
-
         Ok(())
     }
 
-    pub(crate) fn append_to_table(
+    /// Reads the stored rows of every partition of the table, with their tombstones.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `since` - If given, only the data this node received after this time is read.
+    /// * `visitor` - A function that takes each stored row of the table, in key order.
+    pub(crate) fn scan_stored_rows(
         &self,
         table: &Path,
-        data: &HashMap<String, String>,
+        since: Option<i64>,
+        visitor: &mut dyn FnMut(StoredRow) -> std::io::Result<()>,
     ) -> std::io::Result<()> {
-        let table_file = &table.join("table.csv");
-        ensure_newline_at_end(table_file)?;
         let table_str = get_file_name(table, "Invalid table name".to_string())?;
         let binding = self.tables.read().unwrap();
-        let read_guard = binding
+        let table = binding
             .get(&table_str)
             .ok_or(not_found_error!("Table not found"))?;
-        let table_read_guard = read_guard.read().unwrap();
-        let cols = get_columns(table_file)?;
-
-        let mut row = vec![String::new(); cols.len()];
-        for (idx, col) in cols.iter().enumerate() {
-            let value = data
-                .get(col)
-                .map_or("NULL".to_string(), ToString::to_string);
-            if value != "NULL" {
-                table_read_guard.check_type(col, &value)?;
-            }
-            row[idx] = value;
+        let read_guard = table.store.read().unwrap();
+        let entries = match since {
+            Some(since) => read_guard.unrepaired_entries(since)?,
+            None => read_guard.entries(None)?,
+        };
+        for result in entries {
+            let (key, entry) = result?;
+            visitor(StoredRow { key, entry })?;
         }
-        drop(table_read_guard);
+        Ok(())
+    }
 
-        let _table_write_guard = read_guard.write().unwrap();
-        let mut file = OpenOptions::new().append(true).open(table_file)?;
-        writeln!(file, "{}", row.join(","))?;
-        file.flush()
+    /// Returns the mutations that merge the stored rows into the table, such as the ones written by a read
+    /// repair.
+    pub(crate) fn stored_rows_mutations(
+        &self,
+        table: &Path,
+        rows: Vec<StoredRow>,
+    ) -> std::io::Result<Vec<Mutation>> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        if !self.tables.read().unwrap().contains_key(&table_str) {
+            return Err(not_found_error!("Table not found"));
+        }
+        let mutations = rows
+            .into_iter()
+            .map(|row| {
+                new_mutation(
+                    table,
+                    row.entry.timestamp(),
+                    MutationKind::Write(row.key, row.entry),
+                )
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        Ok(mutations)
     }
 
-    pub(crate) fn update_table(
+    /// Returns the mutation that writes a tombstone deleting the rows selected by the values of their
+    /// primary key at the specified time.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir.
+    /// * `key` - The values of the partition key columns and of the first clustering columns.
+    /// * `bounds` - The bounds of the values of the next clustering column, for range deletions.
+    /// * `timestamp` - The time of the deletion, in microseconds since the epoch.
+    pub(crate) fn delete_rows_mutations(
         &self,
         table: &Path,
-        visitor: &mut dyn FnMut(
-            HashMap<String, String>,
-        ) -> std::io::Result<Option<HashMap<String, String>>>,
-    ) -> std::io::Result<()> {
-        let output_file = table.join("table.tmp");
+        key: &HashMap<String, String>,
+        bounds: (Bound<String>, Bound<String>),
+        timestamp: i64,
+    ) -> std::io::Result<Vec<Mutation>> {
         let table_str = get_file_name(table, "Invalid table name".to_string())?;
-        let table_file = table.join("table.csv");
         let binding = self.tables.read().unwrap();
-        let read_guard = binding
+        let table_ref = binding
             .get(&table_str)
             .ok_or(not_found_error!("Table not found"))?;
-        let table_read_guard = read_guard.read().unwrap();
-        let cols = get_columns(&table_file)?;
+        let (key, entry) = table_ref.deletion(key, bounds, timestamp)?;
+        let mutations = vec![new_mutation(
+            table,
+            timestamp,
+            MutationKind::Write(key, entry),
+        )?];
+        Ok(mutations)
+    }
 
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .from_path(&table_file)?;
-        let headers = reader.headers()?.clone();
-        let mut writer = csv::Writer::from_path(&output_file)?;
+    /// Returns the mutation that writes tombstones deleting the columns of a row at the specified time.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if a primary key column has no value, or if a column is unknown or part of the primary key.
+    pub(crate) fn delete_columns_mutations(
+        &self,
+        table: &Path,
+        primary_key: &HashMap<String, String>,
+        columns: &[String],
+        timestamp: i64,
+    ) -> std::io::Result<Vec<Mutation>> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table_ref = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
+        let schema = &table_ref.schema;
+        let key_cols = primary_key_values(schema, &HashMap::new());
+        if key_cols.keys().any(|col| !primary_key.contains_key(col)) {
+            return Err(io_error!(
+                "Deleting columns requires all the primary key columns"
+            ));
+        }
+        for col in columns {
+            if schema.get_schema_type(col).is_none() {
+                return Err(io_error!(format!("Unknown column '{col}'")));
+            }
+            if key_cols.contains_key(col) {
+                return Err(io_error!(format!(
+                    "Primary key column '{col}' can not be deleted"
+                )));
+            }
+        }
+        let key = Key::new(schema, primary_key)?;
+        let entry = Entry::deleted_cells(columns, timestamp);
+        let mutations = vec![new_mutation(
+            table,
+            timestamp,
+            MutationKind::Write(key, entry),
+        )?];
+        Ok(mutations)
+    }
 
-        writer.write_record(&cols)?;
+    /// Returns the mutation that writes the columns of the row to the table at the specified time. The
+    /// columns of the row with the same primary key that are not given keep their value.
+    ///
+    /// The cells expire after `ttl` seconds, or after the `default_time_to_live` of the table if it is
+    /// `None`. A TTL of 0 means the cells do not expire.
+    pub(crate) fn append_mutations(
+        &self,
+        table: &Path,
+        data: &HashMap<String, String>,
+        timestamp: i64,
+        ttl: Option<u64>,
+    ) -> std::io::Result<Vec<Mutation>> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table_ref = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
 
-        for result in reader.records() {
-            let record = result?;
-            let map = map_string_record_to_hashmap(&record, &headers);
-            let Some(updated_row) = visitor(map.clone())? else {
+        let mut row = HashMap::new();
+        for col in table_ref.schema.get_columns() {
+            let Some(value) = data.get(&col) else {
                 continue;
             };
-            let mut row = vec![String::new(); cols.len()];
-            for (idx, col) in cols.iter().enumerate() {
-                let value = updated_row
-                    .get(col)
-                    .map(ToString::to_string)
-                    .unwrap_or(map.get(col).map_or("NULL".to_string(), ToString::to_string));
-                if value != "NULL" {
-                    table_read_guard.check_type(col, &value)?;
-                }
-                row[idx] = value;
+            if value != "NULL" {
+                table_ref.schema.check_type(&col, value)?;
             }
-            writer.write_record(&row)?;
+            row.insert(col, value.to_string());
         }
-        writer.flush()?;
-        drop(table_read_guard);
-        let _table_write_guard = read_guard.write().unwrap();
-        rename(output_file, table_file)
+        let expiration = table_ref.expiration(ttl);
+        let mutation = new_mutation(table, timestamp, MutationKind::Upsert(row, expiration))?;
+        let mutations = vec![mutation];
+        Ok(mutations)
     }
-}
 
-fn ensure_newline_at_end(path: &Path) -> std::io::Result<()> {
-    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
-    let end_pos = file.seek(SeekFrom::End(0))?;
-    if end_pos == 0 {
-        return Ok(());
+    /// Returns the mutation that writes the columns of the row with the primary key at the specified time,
+    /// without reading it. The columns of the row that are not given keep their value.
+    ///
+    /// Only the written cells take the TTL, as they do when the rows are updated with `update_table`.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if a primary key column has no value, or if a column is unknown, part of the
+    ///   primary key or has a value of another type.
+    pub(crate) fn update_row_mutations(
+        &self,
+        table: &Path,
+        primary_key: &HashMap<String, String>,
+        columns: &HashMap<String, String>,
+        timestamp: i64,
+        ttl: Option<u64>,
+    ) -> std::io::Result<Vec<Mutation>> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table_ref = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
+        let schema = &table_ref.schema;
+        let key_cols = primary_key_values(schema, &HashMap::new());
+        if key_cols.keys().any(|col| !primary_key.contains_key(col)) {
+            return Err(io_error!(
+                "Updating a row requires all the primary key columns"
+            ));
+        }
+        for (col, value) in columns {
+            if schema.get_schema_type(col).is_none() {
+                return Err(io_error!(format!("Unknown column '{col}'")));
+            }
+            if key_cols.contains_key(col) {
+                return Err(io_error!(format!(
+                    "Primary key column '{col}' can not be updated"
+                )));
+            }
+            if value != "NULL" {
+                schema.check_type(col, value)?;
+            }
+        }
+        let key = Key::new(schema, primary_key)?;
+        let entry = Entry::row(columns, timestamp).with_expiration(table_ref.expiration(ttl));
+        let mutations = vec![new_mutation(
+            table,
+            timestamp,
+            MutationKind::Write(key, entry),
+        )?];
+        Ok(mutations)
+    }
+
+    /// Returns the mutations that write the columns returned by the visitor for each row of the table at
+    /// the specified time. If the primary key of a row changes, the row is moved to the new primary key.
+    ///
+    /// The written cells expire after `ttl` seconds, or after the `default_time_to_live` of the table if
+    /// it is `None`. A TTL of 0 means the cells do not expire.
+    pub(crate) fn update_table_mutations(
+        &self,
+        table: &Path,
+        timestamp: i64,
+        ttl: Option<u64>,
+        visitor: &mut UpdateVisitor,
+    ) -> std::io::Result<Vec<Mutation>> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table_ref = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
+
+        let read_guard = table_ref.store.read().unwrap();
+        let schema = &table_ref.schema;
+        let expiration = table_ref.expiration(ttl);
+        let now = current_time();
+        let mut mutations = Vec::new();
+        read_guard.scan(&mut |key, cells| {
+            let mut row = schema.to_row(key, &cells, now);
+            row.retain(|col, _| schema.get_schema_type(col).is_some());
+            let old_key = primary_key_values(schema, &row);
+            let Some(updated_row) = visitor(row.clone())? else {
+                let mutation = new_mutation(table, timestamp, MutationKind::Delete(old_key))?;
+                mutations.push(mutation);
+                return Ok(());
+            };
+            let mut changes = HashMap::new();
+            for col in schema.get_columns() {
+                let Some(value) = updated_row.get(&col) else {
+                    continue;
+                };
+                if value != "NULL" {
+                    schema.check_type(&col, value)?;
+                }
+                changes.insert(col, value.to_string());
+            }
+            if changes.is_empty() {
+                return Ok(());
+            }
+            let mut new_row = row;
+            new_row.extend(changes.clone());
+            let new_key = primary_key_values(schema, &new_row);
+            if new_key == old_key {
+                // Only the written cells take the TTL, the primary key of the row stays.
+                changes.retain(|col, _| !new_key.contains_key(col));
+                let entry = Entry::row(&changes, timestamp).with_expiration(expiration);
+                let kind = MutationKind::Write(key.clone(), entry);
+                mutations.push(new_mutation(table, timestamp, kind)?);
+                return Ok(());
+            }
+            // The row is moved, so every cell is written again under the new key.
+            let mutation = new_mutation(table, timestamp, MutationKind::Delete(old_key))?;
+            mutations.push(mutation);
+            mutations.push(new_mutation(
+                table,
+                timestamp,
+                MutationKind::Upsert(new_row, expiration),
+            )?);
+            Ok(())
+        })?;
+        Ok(mutations)
     }
-    file.seek(SeekFrom::End(-1))?;
-    let mut last_byte = [0; 1];
-    file.read_exact(&mut last_byte)?;
-    if last_byte[0] != b'\n' {
-        file.seek(SeekFrom::End(0))?;
-        file.write_all(b"\n")?;
-        file.flush()?;
+
+    /// Writes the mutations to the table as one, under the same lock, so the readers of the table see all of
+    /// them or none.
+    ///
+    /// If a commit log is given, the mutations are logged as a single record before they are written, so a
+    /// crash while they are logged does not replay only some of them.
+    pub(crate) fn write(
+        &self,
+        table: &Path,
+        mutations: Vec<Mutation>,
+        commitlog: Option<&CommitLog>,
+    ) -> std::io::Result<()> {
+        let table_str = get_file_name(table, "Invalid table name".to_string())?;
+        let binding = self.tables.read().unwrap();
+        let table_ref = binding
+            .get(&table_str)
+            .ok_or(not_found_error!("Table not found"))?;
+        let mutations = match mutations.len() {
+            0 => return Ok(()),
+            1 => mutations,
+            _ => {
+                let timestamp = mutations.iter().map(|m| m.timestamp).max().unwrap_or(0);
+                vec![new_mutation(
+                    table,
+                    timestamp,
+                    MutationKind::Batch(mutations),
+                )?]
+            }
+        };
+        let _logged = commitlog
+            .map(|commitlog| commitlog.append(&mutations))
+            .transpose()?;
+        table_ref.apply(&mutations)
     }
 
-    Ok(())
-}
+    /// Applies the mutations replayed from the commit log to the table.
+    /// Mutations of tables that no longer exist are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table.
+    /// * `mutations` - The mutations of the table, in the order they were logged.
+    pub(crate) fn replay_mutations(
+        &self,
+        table: &str,
+        mutations: &[Mutation],
+    ) -> std::io::Result<()> {
+        let binding = self.tables.read().unwrap();
+        let Some(table) = binding.get(table) else {
+            return Ok(());
+        };
+        table.apply(mutations)
+    }
 
-fn get_columns(path: &Path) -> std::io::Result<Vec<String>> {
-    let Ok(file) = File::open(path) else {
-        return Err(not_found_error!("Table does not exist"));
-    };
-    let reader = BufReader::new(file).lines().next();
-    match reader {
-        Some(Ok(line)) => Ok(line
-            .split(',')
-            .map(std::string::ToString::to_string)
-            .collect()),
-        _ => Err(io_error!("Error while reading the table.")),
+    /// Flushes the memtables of all the tables of the keyspace to SSTables.
+    pub(crate) fn flush(&self) -> std::io::Result<()> {
+        for table in self.tables.read().unwrap().values() {
+            table.store.write().unwrap().flush()?;
+        }
+        Ok(())
     }
 }
 
-fn map_string_record_to_hashmap(
-    record: &csv::StringRecord,
-    headers: &csv::StringRecord,
-) -> HashMap<String, String> {
-    record
-        .iter()
-        .zip(headers.iter())
-        .map(|(value, header)| (header.to_string(), value.to_string()))
-        .collect()
+/// Creates a mutation for the table, taking the keyspace from the parent dir of the table.
+fn new_mutation(table: &Path, timestamp: i64, kind: MutationKind) -> std::io::Result<Mutation> {
+    Ok(Mutation {
+        keyspace: get_file_name(
+            table.parent().ok_or(io_error!("Invalid table path"))?,
+            "Invalid keyspace path".to_string(),
+        )?,
+        table: get_file_name(table, "Invalid table name".to_string())?,
+        timestamp,
+        kind,
+    })
 }
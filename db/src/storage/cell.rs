@@ -0,0 +1,95 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// The value of a column of a row and the time it was written.
+///
+/// When a cell is written more than once, on a node or on different replicas, the last write wins.
+/// Deleting a column writes a tombstone cell, which wins over the writes of the cell until then.
+/// Cells written with a TTL expire on their own, and are then handled like tombstones.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cell {
+    pub value: String,
+    /// Write time in microseconds since the epoch.
+    pub timestamp: i64,
+    /// Local time in seconds since the epoch at which the cell was deleted, if it is a tombstone.
+    pub deletion_time: Option<i64>,
+    /// Local time in seconds since the epoch at which the cell expires, if it was written with a TTL.
+    pub expiration: Option<i64>,
+}
+
+impl Cell {
+    pub fn new(value: String, timestamp: i64) -> Self {
+        Cell {
+            value,
+            timestamp,
+            deletion_time: None,
+            expiration: None,
+        }
+    }
+
+    /// Returns the tombstone that deletes the cell at the specified time.
+    pub fn tombstone(timestamp: i64) -> Self {
+        Cell {
+            value: "NULL".to_string(),
+            timestamp,
+            deletion_time: Some(current_time()),
+            expiration: None,
+        }
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.deletion_time.is_some()
+    }
+
+    /// Whether the cell is neither a tombstone nor expired at `now`, in seconds since the epoch.
+    pub fn is_live(&self, now: i64) -> bool {
+        !self.is_tombstone() && self.expiration.is_none_or(|expiration| now < expiration)
+    }
+
+    /// Returns the local time at which the cell stopped being live, if it is a tombstone or has a TTL.
+    /// Once `gc_grace_seconds` have passed since then, the cell can be purged.
+    pub(crate) fn local_deletion_time(&self) -> Option<i64> {
+        self.deletion_time.or(self.expiration)
+    }
+
+    /// Whether this cell wins over another write of the same cell: the one with the greater timestamp
+    /// wins, and on a tie a tombstone, and then the greater value, so every replica keeps the same one.
+    pub fn wins_over(&self, other: &Cell) -> bool {
+        (self.timestamp, self.is_tombstone(), &self.value)
+            > (other.timestamp, other.is_tombstone(), &other.value)
+    }
+}
+
+/// A deletion of a row, a range of rows or a partition.
+/// It hides the cells written until its timestamp, and is purged `gc_grace_seconds` after its deletion time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub(crate) struct Tombstone {
+    /// Time of the deletion in microseconds since the epoch, compared with the timestamps of the cells.
+    pub(crate) timestamp: i64,
+    /// Local time in seconds since the epoch at which the deletion was written.
+    pub(crate) deletion_time: i64,
+}
+
+impl Tombstone {
+    pub(crate) fn new(timestamp: i64) -> Self {
+        Tombstone {
+            timestamp,
+            deletion_time: current_time(),
+        }
+    }
+}
+
+/// Returns the current time in microseconds since the epoch, the timestamp of the writes that do not
+/// have one.
+pub fn current_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_micros() as i64)
+        .unwrap_or_default()
+}
+
+/// Returns the current time in seconds since the epoch, the deletion time of the tombstones.
+pub(crate) fn current_time() -> i64 {
+    current_timestamp() / 1_000_000
+}
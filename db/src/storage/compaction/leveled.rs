@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use crate::storage::sstable::SSTable;
+
+use super::{CompactionStrategy, CompactionTask};
+
+/// Number of SSTables in level 0 from which they are compacted into level 1.
+const LEVEL_0_THRESHOLD: usize = 4;
+
+/// Keeps the SSTables in levels. Level 0 has the flushed SSTables, and every other level has
+/// SSTables of around `sstable_size` bytes that do not overlap each other, so a partition is in a
+/// single SSTable of the level.
+///
+/// Each level may hold `fanout_size` times the bytes of the previous one. When it holds more, one of
+/// its SSTables is merged into the overlapping SSTables of the next level.
+#[derive(Debug)]
+pub(super) struct Leveled {
+    pub(super) sstable_size: u64,
+    pub(super) fanout_size: u64,
+}
+
+impl Leveled {
+    fn max_level_size(&self, level: u32) -> u64 {
+        self.sstable_size
+            .saturating_mul(self.fanout_size.saturating_pow(level))
+    }
+
+    /// Returns a task that merges the inputs with the overlapping SSTables of the next level.
+    fn task(
+        &self,
+        mut inputs: Vec<Arc<SSTable>>,
+        sstables: &[Arc<SSTable>],
+        level: u32,
+    ) -> CompactionTask {
+        let overlapping: Vec<Arc<SSTable>> = sstables
+            .iter()
+            .filter(|sstable| sstable.metadata().level == level + 1)
+            .filter(|sstable| inputs.iter().any(|input| input.overlaps(sstable)))
+            .cloned()
+            .collect();
+        inputs.extend(overlapping);
+        CompactionTask {
+            inputs,
+            level: level + 1,
+            max_sstable_size: Some(self.sstable_size),
+        }
+    }
+}
+
+impl CompactionStrategy for Leveled {
+    fn next_task(&self, sstables: &[Arc<SSTable>]) -> Option<CompactionTask> {
+        let level_0: Vec<Arc<SSTable>> = sstables
+            .iter()
+            .filter(|sstable| sstable.metadata().level == 0)
+            .cloned()
+            .collect();
+        if level_0.len() >= LEVEL_0_THRESHOLD {
+            return Some(self.task(level_0, sstables, 0));
+        }
+
+        let max_level = sstables
+            .iter()
+            .map(|sstable| sstable.metadata().level)
+            .max()?;
+        for level in 1..=max_level {
+            let in_level = sstables
+                .iter()
+                .filter(|sstable| sstable.metadata().level == level);
+            let size: u64 = in_level.clone().map(|sstable| sstable.size()).sum();
+            if size <= self.max_level_size(level) {
+                continue;
+            }
+            let oldest = in_level.min_by_key(|sstable| sstable.metadata().timestamp)?;
+            return Some(self.task(vec![oldest.clone()], sstables, level));
+        }
+        None
+    }
+}
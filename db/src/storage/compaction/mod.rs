@@ -0,0 +1,606 @@
+mod leveled;
+mod size_tiered;
+mod time_window;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, RwLock, Weak,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::CompactionOptions;
+
+use super::{
+    entry::PartitionDeletions,
+    key::Key,
+    sstable::{Metadata, SSTable, SSTableWriter},
+    Entry, EntryResult, MergeIter, TableStore,
+};
+
+use leveled::Leveled;
+use size_tiered::SizeTiered;
+use time_window::TimeWindow;
+
+/// Time between two checks of the tables that need to be compacted.
+const COMPACTION_CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// Decides which SSTables of a table are compacted together.
+pub(crate) trait CompactionStrategy: Debug + Send + Sync {
+    /// Returns the next compaction to run for the SSTables of the table, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `sstables` - The SSTables of the table, from the newest to the oldest.
+    fn next_task(&self, sstables: &[Arc<SSTable>]) -> Option<CompactionTask>;
+}
+
+/// A set of SSTables to merge into new ones.
+#[derive(Debug)]
+pub(crate) struct CompactionTask {
+    pub(crate) inputs: Vec<Arc<SSTable>>,
+    /// Level of the SSTables written by the compaction.
+    pub(crate) level: u32,
+    /// Size in bytes from which the output is split into a new SSTable, at a partition boundary.
+    pub(crate) max_sstable_size: Option<u64>,
+}
+
+/// Returns the strategy of the compaction options of a table.
+pub(crate) fn new_strategy(options: &CompactionOptions) -> Box<dyn CompactionStrategy> {
+    match options {
+        CompactionOptions::SizeTiered {
+            min_threshold,
+            max_threshold,
+            bucket_low,
+            bucket_high,
+            min_sstable_size,
+        } => Box::new(SizeTiered {
+            min_threshold: *min_threshold,
+            max_threshold: *max_threshold,
+            bucket_low: *bucket_low,
+            bucket_high: *bucket_high,
+            min_sstable_size: *min_sstable_size,
+        }),
+        CompactionOptions::Leveled {
+            sstable_size_in_mb,
+            fanout_size,
+        } => Box::new(Leveled {
+            sstable_size: sstable_size_in_mb * 1024 * 1024,
+            fanout_size: *fanout_size,
+        }),
+        CompactionOptions::TimeWindow {
+            compaction_window_unit,
+            compaction_window_size,
+        } => {
+            let unit: i64 = match compaction_window_unit.as_str() {
+                "MINUTES" => 60,
+                "HOURS" => 60 * 60,
+                _ => 24 * 60 * 60,
+            };
+            Box::new(TimeWindow {
+                window: unit * *compaction_window_size as i64 * 1_000_000,
+            })
+        }
+    }
+}
+
+/// A compaction ready to run without holding the lock of the table.
+pub(crate) struct Compaction {
+    dir: PathBuf,
+    task: CompactionTask,
+    /// The SSTables outside the compaction, which may have cells hidden by its tombstones.
+    others: Vec<Arc<SSTable>>,
+    legacy: Arc<BTreeMap<Key, Entry>>,
+    /// The partitions of the memtable when the compaction was picked, which may have cells hidden by its
+    /// tombstones once flushed.
+    memtable: BTreeSet<Vec<u8>>,
+    generations: Arc<AtomicU64>,
+    /// Time in seconds since the epoch until which the tombstones may be purged.
+    gc_before: i64,
+}
+
+impl Compaction {
+    pub(crate) fn new(
+        store: &TableStore,
+        mut task: CompactionTask,
+        generations: Arc<AtomicU64>,
+    ) -> Self {
+        task.inputs.sort_by_key(|sstable| precedence(sstable));
+        let others = store
+            .sstables
+            .iter()
+            .filter(|sstable| {
+                !task
+                    .inputs
+                    .iter()
+                    .any(|input| input.generation() == sstable.generation())
+            })
+            .cloned()
+            .collect();
+        Compaction {
+            dir: store.dir.clone(),
+            task,
+            others,
+            legacy: store.legacy.clone(),
+            memtable: store.memtable.partitions(),
+            generations,
+            gc_before: store.gc_before(),
+        }
+    }
+
+    pub(crate) fn inputs(&self) -> &[Arc<SSTable>] {
+        &self.task.inputs
+    }
+
+    /// Merges the input SSTables into new ones, keeping only the last write of each cell and
+    /// dropping the cells hidden by tombstones.
+    /// The tombstones and the cells expired more than `gc_grace_seconds` ago are purged, unless they may
+    /// hide cells of the memtable or of the SSTables outside the compaction.
+    pub(crate) fn run(&self) -> std::io::Result<Vec<SSTable>> {
+        let mut sources: Vec<Box<dyn Iterator<Item = EntryResult> + '_>> = Vec::new();
+        for sstable in &self.task.inputs {
+            sources.push(Box::new(sstable.iter()?));
+        }
+        let metadata = Metadata {
+            level: self.task.level,
+            timestamp: max_timestamp(&self.task.inputs),
+            received_at: self
+                .task
+                .inputs
+                .iter()
+                .map(|sstable| sstable.metadata().received_at)
+                .max()
+                .unwrap_or_default(),
+            ancestors: self
+                .task
+                .inputs
+                .iter()
+                .map(|sstable| sstable.generation())
+                .collect(),
+        };
+
+        let mut outputs = Vec::new();
+        let mut writer: Option<SSTableWriter> = None;
+        let mut last_partition = Vec::new();
+        let mut deletions = PartitionDeletions::default();
+        for result in MergeIter::new(sources) {
+            let (key, mut entry) = result?;
+            deletions.apply(&key, &mut entry);
+            if !self.is_hiding(&key) {
+                entry.purge(self.gc_before);
+            }
+            if entry.is_empty() {
+                continue;
+            }
+            if let Some(current) = writer.take() {
+                let is_full = self
+                    .task
+                    .max_sstable_size
+                    .is_some_and(|max| current.size() >= max);
+                if is_full && key.partition() != last_partition.as_slice() {
+                    outputs.push(current.finish(metadata.clone())?);
+                } else {
+                    writer = Some(current);
+                }
+            }
+            let current = match writer.as_mut() {
+                Some(current) => current,
+                None => writer.insert(SSTableWriter::create(
+                    &self.dir,
+                    self.generations.fetch_add(1, Ordering::SeqCst),
+                )?),
+            };
+            current.append(&key, &entry)?;
+            last_partition = key.partition().to_vec();
+        }
+        if let Some(current) = writer {
+            outputs.push(current.finish(metadata)?);
+        }
+        Ok(outputs)
+    }
+
+    /// Whether a tombstone of the key may hide an entry of its partition outside the compaction.
+    fn is_hiding(&self, key: &Key) -> bool {
+        let partition = key.partition();
+        self.memtable.contains(partition)
+            || self
+                .others
+                .iter()
+                .any(|sstable| sstable.contains_partition(partition))
+            || self
+                .legacy
+                .range(Key::partition_start(partition)..)
+                .next()
+                .is_some_and(|(key, _)| key.partition() == partition)
+    }
+}
+
+/// Orders the SSTables by level, then from the newest to the oldest.
+pub(crate) fn precedence(sstable: &SSTable) -> (u32, std::cmp::Reverse<(i64, u64)>) {
+    let metadata = sstable.metadata();
+    (
+        metadata.level,
+        std::cmp::Reverse((metadata.timestamp, sstable.generation())),
+    )
+}
+
+fn max_timestamp(sstables: &[Arc<SSTable>]) -> i64 {
+    sstables
+        .iter()
+        .map(|sstable| sstable.metadata().timestamp)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Runs the next compaction of the table, if there is one.
+///
+/// The lock of the table is only held to pick the SSTables and to replace them with the new ones, so
+/// reads and writes are not blocked while the SSTables are merged.
+///
+/// # Returns
+///
+/// * Returns whether a compaction was run.
+pub(crate) fn compact(store: &RwLock<TableStore>) -> std::io::Result<bool> {
+    let Some(compaction) = store
+        .read()
+        .map_err(|_| shared::io_error!("Table poisoned"))?
+        .next_compaction()
+    else {
+        return Ok(false);
+    };
+    let outputs = compaction.run()?;
+    store
+        .write()
+        .map_err(|_| shared::io_error!("Table poisoned"))?
+        .replace(compaction.inputs(), outputs);
+    for sstable in compaction.inputs() {
+        SSTable::remove(&compaction.dir, sstable.generation())?;
+    }
+    Ok(true)
+}
+
+enum Message {
+    Register(Weak<RwLock<TableStore>>),
+    Stop,
+}
+
+/// Background thread that compacts the SSTables of the tables of the node.
+#[derive(Debug)]
+pub(crate) struct Compactor {
+    sender: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Compactor {
+    pub(crate) fn start() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || run_compactions(&receiver));
+        Compactor {
+            sender,
+            thread: Some(thread),
+        }
+    }
+
+    /// Adds the table to the ones checked by the compaction thread, until it is dropped.
+    pub(crate) fn register(&self, store: &Arc<RwLock<TableStore>>) {
+        let _ = self.sender.send(Message::Register(Arc::downgrade(store)));
+    }
+}
+
+impl Drop for Compactor {
+    /// Waits for the running compaction to finish.
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_compactions(receiver: &Receiver<Message>) {
+    let mut stores: Vec<Weak<RwLock<TableStore>>> = Vec::new();
+    loop {
+        match receiver.recv_timeout(COMPACTION_CHECK_PERIOD) {
+            Ok(Message::Register(store)) => stores.push(store),
+            Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        stores.retain(|store| store.strong_count() > 0);
+        for store in stores.clone() {
+            loop {
+                for message in receiver.try_iter() {
+                    match message {
+                        Message::Register(store) => stores.push(store),
+                        Message::Stop => return,
+                    }
+                }
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                // A failed compaction is retried on the next check.
+                if !compact(&store).unwrap_or(false) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs::{create_dir_all, remove_dir_all},
+    };
+
+    use super::*;
+    use crate::{PrimaryKey, Schema, SchemaType, TableOptions};
+
+    fn schema(compaction: CompactionOptions) -> Schema {
+        let mut schema = Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        );
+        schema.set_options(TableOptions {
+            compaction,
+            ..Default::default()
+        });
+        schema
+    }
+
+    fn row(id: usize, name: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("id".to_string(), id.to_string()),
+            ("name".to_string(), name.to_string()),
+        ])
+    }
+
+    fn rows(store: &TableStore) -> Vec<(String, String)> {
+        let mut rows = Vec::new();
+        store
+            .scan(&mut |_, row| {
+                rows.push((row["id"].value.clone(), row["name"].value.clone()));
+                Ok(())
+            })
+            .unwrap();
+        rows.sort();
+        rows
+    }
+
+    /// Writes each batch of rows to its own SSTable, deleting the ids of the `deleted` batch.
+    /// Each batch is written after the previous one.
+    fn write_sstables(store: &mut TableStore, schema: &Schema, batches: &[(&[usize], &str)]) {
+        for (ids, name) in batches {
+            let timestamp = store
+                .memtable
+                .timestamp()
+                .max(max_timestamp(&store.sstables))
+                + 1;
+            for id in ids.iter() {
+                let row = row(*id, name);
+                let entry = if *name == "deleted" {
+                    Entry::deleted(timestamp)
+                } else {
+                    Entry::row(&row, timestamp)
+                };
+                store.write(Key::new(schema, &row).unwrap(), entry).unwrap();
+            }
+            store.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_size_tiered_compaction() {
+        let dir = PathBuf::from("test_size_tiered_compaction");
+        create_dir_all(&dir).unwrap();
+        let schema = schema(CompactionOptions::default());
+        let store = Arc::new(RwLock::new(TableStore::open(&dir, &schema).unwrap()));
+        write_sstables(
+            &mut store.write().unwrap(),
+            &schema,
+            &[
+                (&[1, 2, 3], "old"),
+                (&[2], "new"),
+                (&[3], "deleted"),
+                (&[4], "new"),
+            ],
+        );
+        let before = rows(&store.read().unwrap());
+        let compacted = compact(&store).unwrap();
+        let after = rows(&store.read().unwrap());
+        let generations = SSTable::get_generations(&dir).unwrap();
+        let reopened = rows(&TableStore::open(&dir, &schema).unwrap());
+        remove_dir_all(&dir).unwrap();
+
+        let expected = vec![
+            ("1".to_string(), "old".to_string()),
+            ("2".to_string(), "new".to_string()),
+            ("4".to_string(), "new".to_string()),
+        ];
+        assert!(compacted);
+        assert_eq!(before, expected);
+        assert_eq!(after, expected);
+        assert_eq!(reopened, expected);
+        assert_eq!(generations, vec![5]);
+    }
+
+    #[test]
+    fn test_compaction_purges_tombstones_after_gc_grace() {
+        let mut entries = Vec::new();
+        for gc_grace_seconds in [864000, 0] {
+            let dir = PathBuf::from(format!("test_compaction_gc_grace_{gc_grace_seconds}"));
+            create_dir_all(&dir).unwrap();
+            let mut schema = schema(CompactionOptions::default());
+            schema.set_options(TableOptions {
+                gc_grace_seconds,
+                ..Default::default()
+            });
+            let store = Arc::new(RwLock::new(TableStore::open(&dir, &schema).unwrap()));
+            write_sstables(
+                &mut store.write().unwrap(),
+                &schema,
+                &[
+                    (&[1], "old"),
+                    (&[2], "old"),
+                    (&[1], "deleted"),
+                    (&[3], "old"),
+                ],
+            );
+            assert!(compact(&store).unwrap());
+            let rows = rows(&store.read().unwrap());
+            entries.push(store.read().unwrap().sstables[0].iter().unwrap().count());
+            remove_dir_all(&dir).unwrap();
+
+            assert_eq!(
+                rows,
+                vec![
+                    ("2".to_string(), "old".to_string()),
+                    ("3".to_string(), "old".to_string()),
+                ]
+            );
+        }
+        // The tombstone of the deleted row is only purged once gc_grace_seconds have passed.
+        assert_eq!(entries, vec![3, 2]);
+    }
+
+    #[test]
+    fn test_compaction_keeps_tombstones_hiding_the_memtable() {
+        let dir = PathBuf::from("test_compaction_keeps_tombstones_hiding_the_memtable");
+        create_dir_all(&dir).unwrap();
+        let mut schema = schema(CompactionOptions::default());
+        schema.set_options(TableOptions {
+            gc_grace_seconds: 0,
+            ..Default::default()
+        });
+        let store = Arc::new(RwLock::new(TableStore::open(&dir, &schema).unwrap()));
+        write_sstables(
+            &mut store.write().unwrap(),
+            &schema,
+            &[
+                (&[1], "old"),
+                (&[2], "old"),
+                (&[1], "deleted"),
+                (&[3], "old"),
+            ],
+        );
+        // A write older than the tombstone, such as a hint, is still in the memtable.
+        let row = row(1, "hinted");
+        store
+            .write()
+            .unwrap()
+            .write(Key::new(&schema, &row).unwrap(), Entry::row(&row, 1))
+            .unwrap();
+        assert!(compact(&store).unwrap());
+        let entries = store.read().unwrap().sstables[0].iter().unwrap().count();
+        store.write().unwrap().flush().unwrap();
+        let rows = rows(&store.read().unwrap());
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(entries, 3);
+        assert_eq!(
+            rows,
+            vec![
+                ("2".to_string(), "old".to_string()),
+                ("3".to_string(), "old".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_leveled_compaction() {
+        let dir = PathBuf::from("test_leveled_compaction");
+        create_dir_all(&dir).unwrap();
+        let schema = schema(CompactionOptions::Leveled {
+            sstable_size_in_mb: 1,
+            fanout_size: 10,
+        });
+        let store = Arc::new(RwLock::new(TableStore::open(&dir, &schema).unwrap()));
+        write_sstables(
+            &mut store.write().unwrap(),
+            &schema,
+            &[(&[1, 2], "a"), (&[2, 3], "b"), (&[3, 4], "c"), (&[5], "d")],
+        );
+        let compacted = compact(&store).unwrap();
+        // Newer writes in level 0 hide the cells of level 1.
+        write_sstables(&mut store.write().unwrap(), &schema, &[(&[1], "e")]);
+        let rows = rows(&store.read().unwrap());
+        let levels: Vec<u32> = store
+            .read()
+            .unwrap()
+            .sstables
+            .iter()
+            .map(|sstable| sstable.metadata().level)
+            .collect();
+        remove_dir_all(&dir).unwrap();
+
+        assert!(compacted);
+        assert_eq!(levels, vec![0, 1]);
+        assert_eq!(
+            rows,
+            vec![
+                ("1".to_string(), "e".to_string()),
+                ("2".to_string(), "b".to_string()),
+                ("3".to_string(), "c".to_string()),
+                ("4".to_string(), "c".to_string()),
+                ("5".to_string(), "d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_time_window_compaction() {
+        let dir = PathBuf::from("test_time_window_compaction");
+        create_dir_all(&dir).unwrap();
+        let schema = schema(CompactionOptions::TimeWindow {
+            compaction_window_unit: "HOURS".to_string(),
+            compaction_window_size: 1,
+        });
+        let now = crate::storage::cell::current_timestamp();
+        let hour = 60 * 60 * 1_000_000;
+        let window_start = now - now.rem_euclid(hour);
+        // Two SSTables of a past window and two of the current one.
+        for (generation, timestamp) in [
+            (1, window_start - hour),
+            (2, window_start - 1),
+            (3, window_start),
+            (4, now),
+        ] {
+            let row = row(generation as usize, "name");
+            let key = Key::new(&schema, &row).unwrap();
+            let metadata = Metadata {
+                level: 0,
+                timestamp,
+                received_at: timestamp,
+                ancestors: Vec::new(),
+            };
+            SSTable::write(
+                &dir,
+                generation,
+                metadata,
+                [(&key, &Entry::row(&row, timestamp))].into_iter(),
+            )
+            .unwrap();
+        }
+        let store = Arc::new(RwLock::new(TableStore::open(&dir, &schema).unwrap()));
+        let first = compact(&store).unwrap();
+        let generations = SSTable::get_generations(&dir).unwrap();
+        // The SSTables of the current window are too few for the size-tiered strategy.
+        let second = compact(&store).unwrap();
+        let rows = rows(&store.read().unwrap());
+        remove_dir_all(&dir).unwrap();
+
+        assert!(first);
+        assert!(!second);
+        assert_eq!(generations, vec![3, 4, 5]);
+        assert_eq!(rows.len(), 4);
+    }
+}
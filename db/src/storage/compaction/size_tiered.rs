@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use crate::storage::sstable::SSTable;
+
+use super::{CompactionStrategy, CompactionTask};
+
+/// Merges SSTables of similar size once there are at least `min_threshold` of them.
+///
+/// Only SSTables written one after the other are compacted together, so the compacted SSTable can
+/// take the place of the newest one when the entries of the SSTables are merged on reads.
+#[derive(Debug)]
+pub(super) struct SizeTiered {
+    pub(super) min_threshold: usize,
+    pub(super) max_threshold: usize,
+    pub(super) bucket_low: f64,
+    pub(super) bucket_high: f64,
+    pub(super) min_sstable_size: u64,
+}
+
+impl SizeTiered {
+    fn is_similar(&self, average: f64, size: u64) -> bool {
+        let small = self.min_sstable_size as f64;
+        (average < small && (size as f64) < small)
+            || (size as f64 >= average * self.bucket_low
+                && size as f64 <= average * self.bucket_high)
+    }
+}
+
+impl CompactionStrategy for SizeTiered {
+    fn next_task(&self, sstables: &[Arc<SSTable>]) -> Option<CompactionTask> {
+        let mut sstables: Vec<&Arc<SSTable>> = sstables
+            .iter()
+            .filter(|sstable| sstable.metadata().level == 0)
+            .collect();
+        sstables.sort_by_key(|sstable| (sstable.metadata().timestamp, sstable.generation()));
+
+        let mut bucket: Vec<&Arc<SSTable>> = Vec::new();
+        let mut total = 0;
+        for sstable in sstables {
+            let average = if bucket.is_empty() {
+                sstable.size() as f64
+            } else {
+                total as f64 / bucket.len() as f64
+            };
+            if !self.is_similar(average, sstable.size()) {
+                if bucket.len() >= self.min_threshold {
+                    break;
+                }
+                bucket.clear();
+                total = 0;
+            }
+            bucket.push(sstable);
+            total += sstable.size();
+            if bucket.len() == self.max_threshold {
+                break;
+            }
+        }
+        if bucket.len() < self.min_threshold {
+            return None;
+        }
+        Some(CompactionTask {
+            inputs: bucket.into_iter().cloned().collect(),
+            level: 0,
+            max_sstable_size: None,
+        })
+    }
+}
@@ -0,0 +1,43 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::storage::sstable::SSTable;
+
+use super::{size_tiered::SizeTiered, CompactionStrategy, CompactionTask};
+
+/// Groups the SSTables in windows of time by the write time of their newest data.
+///
+/// The SSTables of the current window are compacted with the size-tiered strategy, and the ones of
+/// every past window are merged into a single SSTable.
+#[derive(Debug)]
+pub(super) struct TimeWindow {
+    /// Size of the windows in microseconds.
+    pub(super) window: i64,
+}
+
+impl CompactionStrategy for TimeWindow {
+    fn next_task(&self, sstables: &[Arc<SSTable>]) -> Option<CompactionTask> {
+        let mut windows: BTreeMap<i64, Vec<Arc<SSTable>>> = BTreeMap::new();
+        for sstable in sstables {
+            windows
+                .entry(sstable.metadata().timestamp.div_euclid(self.window))
+                .or_default()
+                .push(sstable.clone());
+        }
+        let (_, current) = windows.pop_last()?;
+        if let Some((_, inputs)) = windows.into_iter().find(|(_, window)| window.len() > 1) {
+            return Some(CompactionTask {
+                inputs,
+                level: 0,
+                max_sstable_size: None,
+            });
+        }
+        SizeTiered {
+            min_threshold: 4,
+            max_threshold: 32,
+            bucket_low: 0.5,
+            bucket_high: 1.5,
+            min_sstable_size: 50 * 1024 * 1024,
+        }
+        .next_task(&current)
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::{hash_map, HashMap},
+    ops::Bound,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cell::{Cell, Tombstone},
+    key::Key,
+};
+
+/// The value stored for a key: the cells written to the row and its tombstones.
+///
+/// The entry of the key of a partition (see `Key::is_partition`) also holds the deletions of the whole
+/// partition and of ranges of its rows, which hide the cells of the rows that follow it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) cells: HashMap<String, Cell>,
+    /// Last deletion of the row, which hides the cells written until then.
+    pub(crate) deletion: Option<Tombstone>,
+    /// Deletions of ranges of rows of the partition.
+    pub(crate) ranges: Vec<RangeTombstone>,
+}
+
+/// A deletion of the rows of a partition whose keys are between two bounds.
+///
+/// The bounds are encoded prefixes of keys: a bound includes or excludes every key that starts with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RangeTombstone {
+    pub(crate) start: Bound<Vec<u8>>,
+    pub(crate) end: Bound<Vec<u8>>,
+    pub(crate) tombstone: Tombstone,
+}
+
+impl RangeTombstone {
+    /// Whether the key of a row is in the range.
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        let after_start = match &self.start {
+            Bound::Included(start) => key >= start.as_slice(),
+            Bound::Excluded(start) => key > start.as_slice() && !key.starts_with(start),
+            Bound::Unbounded => true,
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => key <= end.as_slice() || key.starts_with(end),
+            Bound::Excluded(end) => key < end.as_slice(),
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+
+    /// Whether both tombstones are the same deletion, which may have been written at a different local
+    /// time on each replica.
+    pub(crate) fn is_same(&self, other: &RangeTombstone) -> bool {
+        self.start == other.start
+            && self.end == other.end
+            && self.tombstone.timestamp == other.tombstone.timestamp
+    }
+}
+
+impl Entry {
+    /// Returns the entry that writes the values of the row at the specified time.
+    pub(crate) fn row(row: &HashMap<String, String>, timestamp: i64) -> Self {
+        Entry {
+            cells: row
+                .iter()
+                .map(|(col, value)| (col.clone(), Cell::new(value.clone(), timestamp)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the time at which the cells of the entry expire, for writes with a TTL.
+    pub(crate) fn with_expiration(mut self, expiration: Option<i64>) -> Self {
+        for cell in self.cells.values_mut() {
+            cell.expiration = expiration;
+        }
+        self
+    }
+
+    /// Returns the entry that deletes the row at the specified time.
+    pub(crate) fn deleted(timestamp: i64) -> Self {
+        Entry {
+            deletion: Some(Tombstone::new(timestamp)),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the entry that deletes the columns of the row at the specified time.
+    pub(crate) fn deleted_cells(columns: &[String], timestamp: i64) -> Self {
+        Entry {
+            cells: columns
+                .iter()
+                .map(|col| (col.clone(), Cell::tombstone(timestamp)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the entry of a partition that deletes a range of its rows at the specified time.
+    pub(crate) fn deleted_range(
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+        timestamp: i64,
+    ) -> Self {
+        Entry {
+            ranges: vec![RangeTombstone {
+                start,
+                end,
+                tombstone: Tombstone::new(timestamp),
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Merges another entry of the same key into this one, keeping the last write of each cell and
+    /// every tombstone.
+    pub(crate) fn merge(&mut self, other: Entry) {
+        self.deletion = self.deletion.max(other.deletion);
+        for (col, cell) in other.cells {
+            match self.cells.entry(col) {
+                hash_map::Entry::Occupied(mut current) => {
+                    if cell.wins_over(current.get()) {
+                        current.insert(cell);
+                    }
+                }
+                hash_map::Entry::Vacant(current) => {
+                    current.insert(cell);
+                }
+            }
+        }
+        for range in other.ranges {
+            if !self.ranges.iter().any(|current| current.is_same(&range)) {
+                self.ranges.push(range);
+            }
+        }
+        if let Some(deletion) = self.deletion {
+            self.cells
+                .retain(|_, cell| cell.timestamp > deletion.timestamp);
+        }
+    }
+
+    /// Whether the entry neither has cells nor tombstones.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.deletion.is_none() && self.ranges.is_empty()
+    }
+
+    /// Whether the row has a live cell at `now`, in seconds since the epoch.
+    pub(crate) fn is_live(&self, now: i64) -> bool {
+        self.cells.values().any(|cell| cell.is_live(now))
+    }
+
+    /// Returns the cells of the row that are live at `now`, in seconds since the epoch.
+    pub(crate) fn live_cells(self, now: i64) -> HashMap<String, Cell> {
+        self.cells
+            .into_iter()
+            .filter(|(_, cell)| cell.is_live(now))
+            .collect()
+    }
+
+    /// Returns the time of the newest write of the entry.
+    pub(crate) fn timestamp(&self) -> i64 {
+        self.cells
+            .values()
+            .map(|cell| cell.timestamp)
+            .chain(self.deletion.map(|deletion| deletion.timestamp))
+            .chain(self.ranges.iter().map(|range| range.tombstone.timestamp))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Removes the tombstones deleted and the cells expired until `gc_before`, in seconds since the epoch.
+    pub(crate) fn purge(&mut self, gc_before: i64) {
+        let is_purgeable = |deletion_time: i64| deletion_time <= gc_before;
+        self.cells
+            .retain(|_, cell| !cell.local_deletion_time().is_some_and(is_purgeable));
+        if self
+            .deletion
+            .is_some_and(|deletion| is_purgeable(deletion.deletion_time))
+        {
+            self.deletion = None;
+        }
+        self.ranges
+            .retain(|range| !is_purgeable(range.tombstone.deletion_time));
+    }
+}
+
+/// The deletions of the partition being read, which hide the cells of its rows written until then.
+///
+/// Entries must be applied in key order, so the entry of a partition is applied before its rows.
+#[derive(Debug, Default)]
+pub(crate) struct PartitionDeletions {
+    partition: Vec<u8>,
+    deletion: Option<Tombstone>,
+    ranges: Vec<RangeTombstone>,
+}
+
+impl PartitionDeletions {
+    /// Removes the cells of the entry hidden by the deletions of its partition.
+    /// If the entry is the one of the partition, its deletions are applied to the following rows.
+    pub(crate) fn apply(&mut self, key: &Key, entry: &mut Entry) {
+        if key.partition() != self.partition.as_slice() {
+            *self = PartitionDeletions {
+                partition: key.partition().to_vec(),
+                ..Default::default()
+            };
+        }
+        entry
+            .cells
+            .retain(|_, cell| !self.hides(key, cell.timestamp));
+        if key.is_partition() {
+            self.deletion = entry.deletion;
+            self.ranges = entry.ranges.clone();
+        }
+    }
+
+    fn hides(&self, key: &Key, timestamp: i64) -> bool {
+        self.deletion
+            .is_some_and(|deletion| timestamp <= deletion.timestamp)
+            || self.ranges.iter().any(|range| {
+                timestamp <= range.tombstone.timestamp && range.contains(key.as_bytes())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_tombstone_bounds() {
+        let range = RangeTombstone {
+            start: Bound::Excluded(vec![1, 2]),
+            end: Bound::Included(vec![1, 4]),
+            tombstone: Tombstone::new(1),
+        };
+        assert!(!range.contains(&[1, 1, 9]));
+        assert!(!range.contains(&[1, 2, 9]));
+        assert!(range.contains(&[1, 3]));
+        assert!(range.contains(&[1, 4, 9]));
+        assert!(!range.contains(&[1, 5]));
+    }
+
+    #[test]
+    fn test_merge_keeps_tombstones() {
+        let mut entry = Entry::row(&HashMap::from([("a".to_string(), "1".to_string())]), 5);
+        entry.merge(Entry::deleted_cells(&["a".to_string()], 5));
+        assert!(!entry.is_live(0));
+
+        entry.merge(Entry::row(
+            &HashMap::from([("a".to_string(), "2".to_string())]),
+            6,
+        ));
+        assert!(entry.is_live(0));
+
+        entry.merge(Entry::deleted(6));
+        assert!(entry.cells.is_empty());
+        assert!(!entry.is_empty());
+
+        entry.purge(i64::MAX);
+        assert!(entry.is_empty());
+    }
+
+    #[test]
+    fn test_expired_cells() {
+        let row = HashMap::from([("a".to_string(), "1".to_string())]);
+        let mut entry = Entry::row(&row, 1).with_expiration(Some(100));
+        assert!(entry.is_live(99));
+        assert!(!entry.is_live(100));
+        assert!(entry.clone().live_cells(100).is_empty());
+
+        entry.purge(99);
+        assert!(!entry.is_empty());
+        entry.purge(100);
+        assert!(entry.is_empty());
+    }
+}
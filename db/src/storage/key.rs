@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use murmur3::murmur3_x64_128;
+use serde::{Deserialize, Serialize};
+use shared::{io_error, map_io_error};
+
+use crate::{Schema, SchemaType};
+
+/// The primary key of a row, encoded so that comparing the bytes orders rows the way they are stored:
+/// first by the token of the partition, then by the partition key and then by the clustering key.
+///
+/// The encoding of the partition (token and partition key columns) is a prefix of the key, so all the
+/// rows of a partition are contiguous.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub(crate) struct Key {
+    bytes: Vec<u8>,
+    partition_len: usize,
+}
+
+impl Key {
+    /// Encodes the primary key of the row. Missing primary key columns are encoded as `NULL`.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if a value does not match the type of its column.
+    pub(crate) fn new(schema: &Schema, row: &HashMap<String, String>) -> std::io::Result<Self> {
+        Key::prefix(schema, row, usize::MAX)
+    }
+
+    /// Encodes the partition key of the row and its first `clustering` clustering columns.
+    /// The prefix sorts before the keys of all the rows that start with it.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if a value does not match the type of its column.
+    pub(crate) fn prefix(
+        schema: &Schema,
+        row: &HashMap<String, String>,
+        clustering: usize,
+    ) -> std::io::Result<Self> {
+        let primary_key = schema.get_primary_key();
+        let partition_key = primary_key.get_partition_key();
+        let values = partition_key
+            .iter()
+            .map(|col| row.get(col).map_or("NULL", String::as_str))
+            .collect::<Vec<_>>();
+
+        let mut bytes = ((get_partition_token(&values)? as u64) ^ (1 << 63))
+            .to_be_bytes()
+            .to_vec();
+        for col in partition_key {
+            encode_value(&mut bytes, schema, col, row.get(col))?;
+        }
+        let partition_len = bytes.len();
+        for col in primary_key.get_clustering_key().iter().take(clustering) {
+            encode_value(&mut bytes, schema, col, row.get(col))?;
+        }
+        Ok(Key {
+            bytes,
+            partition_len,
+        })
+    }
+
+    /// Returns the smallest key of the encoded partition.
+    pub(crate) fn partition_start(partition: &[u8]) -> Self {
+        Key {
+            bytes: partition.to_vec(),
+            partition_len: 0,
+        }
+    }
+
+    /// Returns the token of the partition of the key.
+    pub(crate) fn token(&self) -> i64 {
+        let mut token = [0u8; 8];
+        token.copy_from_slice(&self.bytes[..8]);
+        (u64::from_be_bytes(token) ^ (1 << 63)) as i64
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the encoded partition of the key.
+    pub(crate) fn partition(&self) -> &[u8] {
+        &self.bytes[..self.partition_len]
+    }
+
+    /// Decodes the values of the primary key columns encoded in the key.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if the key was not encoded with the primary key of the schema.
+    pub(crate) fn values(&self, schema: &Schema) -> std::io::Result<HashMap<String, String>> {
+        let primary_key = schema.get_primary_key();
+        let mut bytes = self.bytes.get(8..).unwrap_or_default();
+        let mut values = HashMap::new();
+        for col in primary_key
+            .get_partition_key()
+            .iter()
+            .chain(primary_key.get_clustering_key())
+        {
+            if bytes.is_empty() {
+                break;
+            }
+            let (value, rest) = decode_value(bytes, schema.get_schema_type(col))?;
+            values.insert(col.clone(), value);
+            bytes = rest;
+        }
+        Ok(values)
+    }
+
+    /// Whether the key is the one of the partition itself, which holds the deletions of the partition.
+    pub(crate) fn is_partition(&self) -> bool {
+        self.bytes.len() == self.partition_len
+    }
+}
+
+/// Returns the token of a partition key value, which decides the position of the partition in the ring.
+pub fn get_token(value: &str) -> std::io::Result<i64> {
+    murmur3_x64_128(&mut value.as_bytes(), 0)
+        .map(|hash| hash as i64)
+        .map_err(map_io_error!("Failed to hash partition key"))
+}
+
+/// Returns the token of a partition, given the values of its partition key columns in the order of the
+/// schema. A single value is hashed as is, and a composite partition key as each of its values prefixed
+/// with its length and followed by a `0` byte, so the coordinators and the storage place it alike.
+pub fn get_partition_token<S: AsRef<str>>(values: &[S]) -> std::io::Result<i64> {
+    if let [value] = values {
+        return get_token(value.as_ref());
+    }
+    let mut composite = Vec::new();
+    for value in values {
+        let value = value.as_ref().as_bytes();
+        let length =
+            u16::try_from(value.len()).map_err(|_| io_error!("Partition key value too long"))?;
+        composite.extend(length.to_be_bytes());
+        composite.extend(value);
+        composite.push(0);
+    }
+    murmur3_x64_128(&mut composite.as_slice(), 0)
+        .map(|hash| hash as i64)
+        .map_err(map_io_error!("Failed to hash partition key"))
+}
+
+fn encode_value(
+    bytes: &mut Vec<u8>,
+    schema: &Schema,
+    col: &str,
+    value: Option<&String>,
+) -> std::io::Result<()> {
+    let value = match value {
+        Some(value) if value != "NULL" => value,
+        _ => {
+            bytes.push(0);
+            return Ok(());
+        }
+    };
+    bytes.push(1);
+    schema.check_type(col, value)?;
+    match schema.get_schema_type(col) {
+        Some(SchemaType::Boolean) => bytes.push(u8::from(value == "true")),
+        Some(SchemaType::Int) => {
+            let value = value
+                .parse::<i32>()
+                .map_err(map_io_error!("Invalid int value"))?;
+            bytes.extend(((value as u32) ^ (1 << 31)).to_be_bytes());
+        }
+        Some(SchemaType::Float) => {
+            let bits = value
+                .parse::<f32>()
+                .map_err(map_io_error!("Invalid float value"))?
+                .to_bits();
+            let bits = if bits >> 31 == 1 {
+                !bits
+            } else {
+                bits ^ (1 << 31)
+            };
+            bytes.extend(bits.to_be_bytes());
+        }
+        Some(SchemaType::Text) | Some(SchemaType::Timestamp) | None => {
+            for byte in value.bytes() {
+                bytes.push(byte);
+                if byte == 0 {
+                    bytes.push(0xFF);
+                }
+            }
+            bytes.extend([0, 0]);
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a value encoded by `encode_value`, returning it with the bytes that follow it.
+fn decode_value<'a>(
+    bytes: &'a [u8],
+    schema_type: Option<&SchemaType>,
+) -> std::io::Result<(String, &'a [u8])> {
+    let invalid = || io_error!("Invalid encoded key");
+    let (&marker, bytes) = bytes.split_first().ok_or_else(invalid)?;
+    if marker == 0 {
+        return Ok(("NULL".to_string(), bytes));
+    }
+    let fixed = |len: usize| -> std::io::Result<([u8; 4], &'a [u8])> {
+        let value = bytes.get(..len).ok_or_else(invalid)?;
+        let mut buffer = [0; 4];
+        buffer[4 - len..].copy_from_slice(value);
+        Ok((buffer, &bytes[len..]))
+    };
+    match schema_type {
+        Some(SchemaType::Boolean) => {
+            let (value, rest) = fixed(1)?;
+            Ok(((value[3] == 1).to_string(), rest))
+        }
+        Some(SchemaType::Int) => {
+            let (value, rest) = fixed(4)?;
+            let value = (u32::from_be_bytes(value) ^ (1 << 31)) as i32;
+            Ok((value.to_string(), rest))
+        }
+        Some(SchemaType::Float) => {
+            let (value, rest) = fixed(4)?;
+            let bits = u32::from_be_bytes(value);
+            let bits = if bits >> 31 == 1 {
+                bits ^ (1 << 31)
+            } else {
+                !bits
+            };
+            Ok((f32::from_bits(bits).to_string(), rest))
+        }
+        Some(SchemaType::Text) | Some(SchemaType::Timestamp) | None => {
+            let mut value = Vec::new();
+            let mut idx = 0;
+            loop {
+                match (bytes.get(idx), bytes.get(idx + 1)) {
+                    (Some(0), Some(0)) => break,
+                    (Some(0), Some(0xFF)) => {
+                        value.push(0);
+                        idx += 2;
+                    }
+                    (Some(&byte), _) if byte != 0 => {
+                        value.push(byte);
+                        idx += 1;
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            let value = String::from_utf8(value).map_err(|_| invalid())?;
+            Ok((value, &bytes[idx + 2..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrimaryKey;
+
+    fn schema() -> Schema {
+        Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Text),
+                ("age".to_string(), SchemaType::Int),
+                ("score".to_string(), SchemaType::Float),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(
+                vec!["id".to_string()],
+                vec!["age".to_string(), "score".to_string(), "name".to_string()],
+            ),
+        )
+    }
+
+    fn key(age: &str, score: &str, name: &str) -> Key {
+        let row = HashMap::from([
+            ("id".to_string(), "partition".to_string()),
+            ("age".to_string(), age.to_string()),
+            ("score".to_string(), score.to_string()),
+            ("name".to_string(), name.to_string()),
+        ]);
+        Key::new(&schema(), &row).unwrap()
+    }
+
+    #[test]
+    fn test_composite_partition_key_token() {
+        let schema = Schema::new(
+            HashMap::from([
+                ("a".to_string(), SchemaType::Int),
+                ("b".to_string(), SchemaType::Text),
+                ("c".to_string(), SchemaType::Int),
+            ]),
+            PrimaryKey::new(
+                vec!["a".to_string(), "b".to_string()],
+                vec!["c".to_string()],
+            ),
+        );
+        let row = HashMap::from([
+            ("c".to_string(), "3".to_string()),
+            ("b".to_string(), "two".to_string()),
+            ("a".to_string(), "1".to_string()),
+        ]);
+        let token = Key::new(&schema, &row).unwrap().token();
+        assert_eq!(token, get_partition_token(&["1", "two"]).unwrap());
+        assert_ne!(token, get_partition_token(&["two", "1"]).unwrap());
+        assert_ne!(token, get_token("1").unwrap());
+        assert_eq!(
+            get_partition_token(&["1"]).unwrap(),
+            get_token("1").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_key_clustering_order() {
+        assert!(key("-5", "0", "a") < key("3", "0", "a"));
+        assert!(key("3", "-1.5", "a") < key("3", "-0.5", "a"));
+        assert!(key("3", "-0.5", "a") < key("3", "2.5", "a"));
+        assert!(key("3", "2.5", "a") < key("3", "2.5", "ab"));
+        assert!(key("3", "2.5", "ab") < key("3", "2.5", "b"));
+        assert!(key("NULL", "0", "a") < key("-5", "0", "a"));
+    }
+
+    #[test]
+    fn test_key_partition_prefix() {
+        let first = key("1", "0", "a");
+        let second = key("2", "1", "b");
+        assert_eq!(first.partition(), second.partition());
+        assert!(first.bytes.starts_with(first.partition()));
+    }
+
+    #[test]
+    fn test_key_values() {
+        let key = key("-5", "2.5", "a\0b");
+        let values = key.values(&schema()).unwrap();
+        assert_eq!(values["id"], "partition");
+        assert_eq!(values["age"], "-5");
+        assert_eq!(values["score"], "2.5");
+        assert_eq!(values["name"], "a\0b");
+        let partition = Key::prefix(&schema(), &values, 0).unwrap();
+        assert_eq!(partition.values(&schema()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_key_invalid_value() {
+        let row = HashMap::from([
+            ("id".to_string(), "partition".to_string()),
+            ("age".to_string(), "old".to_string()),
+        ]);
+        assert!(Key::new(&schema(), &row).is_err());
+    }
+}
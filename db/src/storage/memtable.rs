@@ -0,0 +1,84 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{key::Key, Entry};
+
+/// In-memory sorted buffer of the latest writes of a table, until it is flushed to an SSTable.
+#[derive(Debug, Default)]
+pub(crate) struct Memtable {
+    entries: BTreeMap<Key, Entry>,
+    /// Approximate size in bytes of the entries.
+    size: usize,
+    /// Time of the newest write of the entries.
+    timestamp: i64,
+}
+
+impl Memtable {
+    /// Writes the entry of the key, merging it with the previous one.
+    pub(crate) fn put(&mut self, key: Key, entry: Entry) {
+        self.timestamp = self.timestamp.max(entry.timestamp());
+        match self.entries.get_mut(&key) {
+            Some(current) => {
+                self.size -= entry_size(&key, current);
+                current.merge(entry);
+                self.size += entry_size(&key, current);
+            }
+            None => {
+                self.size += entry_size(&key, &entry);
+                self.entries.insert(key, entry);
+            }
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Time of the newest write of the entries.
+    pub(crate) fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entries in key order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Key, &Entry)> {
+        self.entries.iter()
+    }
+
+    /// Returns the entries of the partition in key order.
+    pub(crate) fn iter_partition<'a>(
+        &'a self,
+        partition: &'a [u8],
+    ) -> impl Iterator<Item = (&'a Key, &'a Entry)> {
+        self.entries
+            .range(Key::partition_start(partition)..)
+            .take_while(move |(key, _)| key.partition() == partition)
+    }
+
+    /// Returns the partitions with entries in the memtable.
+    pub(crate) fn partitions(&self) -> BTreeSet<Vec<u8>> {
+        self.entries
+            .keys()
+            .map(|key| key.partition().to_vec())
+            .collect()
+    }
+
+    /// Empties the memtable, returning its entries in key order.
+    pub(crate) fn take(&mut self) -> BTreeMap<Key, Entry> {
+        self.size = 0;
+        self.timestamp = 0;
+        std::mem::take(&mut self.entries)
+    }
+}
+
+fn entry_size(key: &Key, entry: &Entry) -> usize {
+    key.as_bytes().len()
+        + size_of::<Option<i64>>()
+        + entry
+            .cells
+            .iter()
+            .map(|(col, cell)| col.len() + cell.value.len() + size_of::<i64>())
+            .sum::<usize>()
+}
@@ -0,0 +1,279 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Bound,
+};
+
+use serde::{Deserialize, Serialize};
+use shared::io_error;
+
+use super::stored_row::StoredRow;
+
+/// The tokens from `start` to `end`, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl TokenRange {
+    pub fn new(start: i64, end: i64) -> Self {
+        TokenRange { start, end }
+    }
+
+    pub fn contains(&self, token: i64) -> bool {
+        self.start <= token && token <= self.end
+    }
+
+    /// Returns the tokens of both ranges, if they have any in common.
+    pub fn intersection(&self, other: &TokenRange) -> Option<TokenRange> {
+        let range = TokenRange::new(self.start.max(other.start), self.end.min(other.end));
+        (range.start <= range.end).then_some(range)
+    }
+
+    /// Number of tokens of the range, which does not fit in an `i64` for the whole ring.
+    fn width(&self) -> i128 {
+        self.end as i128 - self.start as i128 + 1
+    }
+}
+
+/// A Merkle tree of the rows of a table whose tokens are in a range, used to find the rows that differ
+/// between replicas without sending them.
+///
+/// The range is split in `2^depth` sub-ranges of the same width, and each leaf hashes the rows of one of
+/// them. Each inner node hashes its children, so two trees are compared from the root, only descending
+/// into the nodes whose hashes differ.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerkleTree {
+    range: TokenRange,
+    depth: u32,
+    leaves: Vec<u64>,
+}
+
+impl MerkleTree {
+    /// Creates the tree of a range without rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The tokens of the rows of the tree.
+    /// * `depth` - The depth of the tree, which has `2^depth` leaves.
+    pub fn new(range: TokenRange, depth: u32) -> Self {
+        MerkleTree {
+            range,
+            depth,
+            leaves: vec![0; 1 << depth],
+        }
+    }
+
+    pub fn get_range(&self) -> TokenRange {
+        self.range
+    }
+
+    /// Adds a row to the leaf of its token, if the token is in the range of the tree.
+    ///
+    /// # Returns
+    ///
+    /// * Returns whether the row was added.
+    pub fn add(&mut self, row: &StoredRow) -> bool {
+        let token = row.token();
+        if !self.range.contains(token) {
+            return false;
+        }
+        let leaf = (token as i128 - self.range.start as i128) * self.leaves.len() as i128
+            / self.range.width();
+        // The rows of a leaf are combined in any order, as the replicas may not store them the same way.
+        self.leaves[leaf as usize] ^= digest(row);
+        true
+    }
+
+    /// Compares the tree with the one built by another replica over the same range.
+    ///
+    /// # Returns
+    ///
+    /// * Returns the sub-ranges whose rows differ between both trees, merging the adjacent ones.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if the trees do not have the same range or depth.
+    pub fn difference(&self, other: &MerkleTree) -> std::io::Result<Vec<TokenRange>> {
+        if self.range != other.range || self.depth != other.depth {
+            return Err(io_error!("Cannot compare Merkle trees of different ranges"));
+        }
+        let (levels, other_levels) = (self.levels(), other.levels());
+        let mut leaves = Vec::new();
+        let mut pending = vec![(0, 0)];
+        while let Some((level, index)) = pending.pop() {
+            if levels[level][index] == other_levels[level][index] {
+                continue;
+            }
+            if level == self.depth as usize {
+                leaves.push(index);
+            } else {
+                pending.push((level + 1, 2 * index + 1));
+                pending.push((level + 1, 2 * index));
+            }
+        }
+
+        let mut ranges: Vec<TokenRange> = Vec::new();
+        for range in leaves.into_iter().filter_map(|leaf| self.leaf_range(leaf)) {
+            match ranges.last_mut() {
+                Some(last) if last.end as i128 + 1 == range.start as i128 => last.end = range.end,
+                _ => ranges.push(range),
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Returns the hashes of the nodes of the tree by level, from the root to the leaves.
+    fn levels(&self) -> Vec<Vec<u64>> {
+        let mut levels = vec![self.leaves.clone()];
+        while levels[0].len() > 1 {
+            let level = levels[0]
+                .chunks(2)
+                .map(|children| {
+                    let mut hasher = DefaultHasher::new();
+                    children.hash(&mut hasher);
+                    hasher.finish()
+                })
+                .collect();
+            levels.insert(0, level);
+        }
+        levels
+    }
+
+    /// Returns the tokens of a leaf, or `None` if the range is narrower than the leaves and it has none.
+    fn leaf_range(&self, leaf: usize) -> Option<TokenRange> {
+        let (width, leaves) = (self.range.width(), self.leaves.len() as i128);
+        // The first token of a leaf is the first one whose position in the range maps to it.
+        let first = |leaf: i128| self.range.start as i128 + (leaf * width + leaves - 1) / leaves;
+        let (start, end) = (first(leaf as i128), first(leaf as i128 + 1) - 1);
+        (start <= end).then(|| TokenRange::new(start as i64, end as i64))
+    }
+}
+
+/// Hashes the key of a row with its cells and tombstones.
+///
+/// The times at which the cells were deleted or expire are local to each replica, so they are left out.
+fn digest(row: &StoredRow) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    row.key.as_bytes().hash(&mut hasher);
+    let mut cells: Vec<_> = row.entry.cells.iter().collect();
+    cells.sort_by_key(|(col, _)| *col);
+    for (col, cell) in cells {
+        (col, &cell.value, cell.timestamp, cell.is_tombstone()).hash(&mut hasher);
+    }
+    row.entry
+        .deletion
+        .map(|deletion| deletion.timestamp)
+        .hash(&mut hasher);
+    let mut ranges: Vec<_> = row
+        .entry
+        .ranges
+        .iter()
+        .map(|range| {
+            let bound = |bound: &Bound<Vec<u8>>| match bound {
+                Bound::Included(key) => (0, key.clone()),
+                Bound::Excluded(key) => (1, key.clone()),
+                Bound::Unbounded => (2, Vec::new()),
+            };
+            (
+                bound(&range.start),
+                bound(&range.end),
+                range.tombstone.timestamp,
+            )
+        })
+        .collect();
+    ranges.sort();
+    ranges.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        storage::{entry::Entry, key::Key},
+        PrimaryKey, Schema, SchemaType,
+    };
+
+    fn written(id: &str, name: &str, timestamp: i64) -> StoredRow {
+        let schema = Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        );
+        let row = HashMap::from([
+            ("id".to_string(), id.to_string()),
+            ("name".to_string(), name.to_string()),
+        ]);
+        StoredRow {
+            key: Key::new(&schema, &row).unwrap(),
+            entry: Entry::row(&row, timestamp),
+        }
+    }
+
+    #[test]
+    fn test_difference_of_trees() {
+        let range = TokenRange::new(i64::MIN, i64::MAX);
+        let rows: Vec<StoredRow> = (0..100)
+            .map(|id| written(&id.to_string(), "John", 1))
+            .collect();
+        let mut first = MerkleTree::new(range, 8);
+        let mut second = MerkleTree::new(range, 8);
+        for row in &rows {
+            assert!(first.add(row));
+        }
+        // The second replica adds the rows in another order.
+        for row in rows.iter().rev() {
+            assert!(second.add(row));
+        }
+        assert!(first.difference(&second).unwrap().is_empty());
+
+        let missing = &rows[10];
+        let mut third = MerkleTree::new(range, 8);
+        let updated = written("20", "Jane", 2);
+        for row in &rows {
+            if row == missing {
+                continue;
+            }
+            third.add(if row.key == updated.key {
+                &updated
+            } else {
+                row
+            });
+        }
+        let ranges = first.difference(&third).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert!(ranges.iter().any(|range| range.contains(missing.token())));
+        assert!(ranges.iter().any(|range| range.contains(updated.token())));
+    }
+
+    #[test]
+    fn test_leaf_ranges_cover_the_range() {
+        let tree = MerkleTree::new(TokenRange::new(-10, 10), 3);
+        let ranges: Vec<TokenRange> = (0..8).filter_map(|leaf| tree.leaf_range(leaf)).collect();
+        assert_eq!(ranges.first().unwrap().start, -10);
+        assert_eq!(ranges.last().unwrap().end, 10);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end + 1, pair[1].start);
+        }
+
+        // Narrower ranges than the leaves leave some of them without tokens.
+        let tree = MerkleTree::new(TokenRange::new(0, 2), 3);
+        assert_eq!((0..8).filter_map(|leaf| tree.leaf_range(leaf)).count(), 3);
+    }
+
+    #[test]
+    fn test_rows_out_of_range() {
+        let row = written("1", "John", 1);
+        let mut tree = MerkleTree::new(TokenRange::new(row.token(), row.token()), 4);
+        assert!(tree.add(&row));
+        assert!(!tree.add(&written("2", "Jane", 1)));
+        assert!(tree
+            .difference(&MerkleTree::new(TokenRange::new(0, 1), 4))
+            .is_err());
+    }
+}
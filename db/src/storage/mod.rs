@@ -0,0 +1,467 @@
+pub(crate) mod cell;
+pub(crate) mod compaction;
+pub(crate) mod entry;
+pub(crate) mod key;
+pub(crate) mod memtable;
+pub(crate) mod merkle;
+pub(crate) mod sstable;
+pub(crate) mod stored_row;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::Schema;
+
+use cell::{current_time, current_timestamp, Cell};
+use compaction::{new_strategy, precedence, Compaction, CompactionStrategy};
+pub(crate) use entry::Entry;
+use entry::PartitionDeletions;
+use key::Key;
+use memtable::Memtable;
+use sstable::{Metadata, SSTable};
+
+/// Size in bytes from which the memtable of a table is flushed to a new SSTable.
+const MEMTABLE_FLUSH_SIZE: usize = 4 * 1024 * 1024;
+
+type EntryResult = std::io::Result<(Key, Entry)>;
+
+/// A function that receives the key and the live cells of each row read.
+pub(crate) type RowVisitor<'a> = dyn FnMut(&Key, HashMap<String, Cell>) -> std::io::Result<()> + 'a;
+
+/// Log-structured storage of the rows of a table.
+///
+/// Writes go to the memtable, which is flushed to a new immutable SSTable when it grows too big.
+/// Reads merge the entries of each key in the memtable and the SSTables, keeping the last write of
+/// each cell, and skip the cells hidden by tombstones.
+/// The SSTables are merged in the background following the compaction strategy of the table.
+///
+/// The `table.csv` file of tables written before the SSTables is still read as the oldest data of the
+/// table, with a timestamp of 0, but is never written.
+#[derive(Debug)]
+pub(crate) struct TableStore {
+    dir: PathBuf,
+    memtable: Memtable,
+    /// The SSTables of the table, by level and from the newest to the oldest.
+    sstables: Vec<Arc<SSTable>>,
+    /// The rows of the `table.csv` file, if any.
+    legacy: Arc<BTreeMap<Key, Entry>>,
+    /// The generation of the next SSTable written.
+    generations: Arc<AtomicU64>,
+    strategy: Box<dyn CompactionStrategy>,
+    /// Seconds during which the tombstones are kept, so they can reach the replicas that missed them.
+    gc_grace_seconds: i64,
+}
+
+impl TableStore {
+    /// Opens the storage of the table dir, loading the index of its SSTables.
+    /// The SSTables left behind by a compaction that did not finish removing them are removed.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if the SSTables or the `table.csv` file can not be read.
+    pub(crate) fn open(dir: &Path, schema: &Schema) -> std::io::Result<Self> {
+        let mut sstables = SSTable::get_generations(dir)?
+            .into_iter()
+            .map(|generation| SSTable::open(dir, generation).map(Arc::new))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let next_generation = sstables
+            .last()
+            .map_or(1, |sstable| sstable.generation() + 1);
+        let compacted: Vec<u64> = sstables
+            .iter()
+            .flat_map(|sstable| sstable.metadata().ancestors.clone())
+            .collect();
+        for generation in &compacted {
+            SSTable::remove(dir, *generation)?;
+        }
+        sstables.retain(|sstable| !compacted.contains(&sstable.generation()));
+        sstables.sort_by_key(|sstable| precedence(sstable));
+
+        let mut legacy = BTreeMap::new();
+        let legacy_file = dir.join("table.csv");
+        if legacy_file.exists() {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(true)
+                .from_path(legacy_file)?;
+            let headers = reader.headers()?.clone();
+            for record in reader.records() {
+                let row: HashMap<String, String> = headers
+                    .iter()
+                    .zip(record?.iter())
+                    .map(|(col, value)| (col.to_string(), value.to_string()))
+                    .collect();
+                legacy.insert(Key::new(schema, &row)?, Entry::row(&row, 0));
+            }
+        }
+
+        Ok(TableStore {
+            dir: dir.to_path_buf(),
+            memtable: Memtable::default(),
+            sstables,
+            legacy: Arc::new(legacy),
+            generations: Arc::new(AtomicU64::new(next_generation)),
+            strategy: new_strategy(&schema.get_options().compaction),
+            gc_grace_seconds: schema.get_options().gc_grace_seconds as i64,
+        })
+    }
+
+    /// Writes the entry of the key, merging it with the previous writes of the key, and flushes the
+    /// memtable if it grows too big.
+    pub(crate) fn write(&mut self, key: Key, entry: Entry) -> std::io::Result<()> {
+        self.memtable.put(key, entry);
+        if self.memtable.size() >= MEMTABLE_FLUSH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the memtable to a new SSTable and empties it.
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let metadata = Metadata {
+            level: 0,
+            timestamp: self.memtable.timestamp(),
+            received_at: current_timestamp(),
+            ancestors: Vec::new(),
+        };
+        let generation = self.generations.fetch_add(1, Ordering::SeqCst);
+        let sstable = SSTable::write(&self.dir, generation, metadata, self.memtable.iter())?;
+        self.memtable.take();
+        self.sstables.insert(0, Arc::new(sstable));
+        Ok(())
+    }
+
+    /// Returns the next compaction of the SSTables, according to the strategy of the table.
+    pub(crate) fn next_compaction(&self) -> Option<Compaction> {
+        let task = self.strategy.next_task(&self.sstables)?;
+        Some(Compaction::new(self, task, self.generations.clone()))
+    }
+
+    /// Replaces the compacted SSTables with the ones written by the compaction.
+    pub(crate) fn replace(&mut self, compacted: &[Arc<SSTable>], outputs: Vec<SSTable>) {
+        self.sstables.retain(|sstable| {
+            !compacted
+                .iter()
+                .any(|input| input.generation() == sstable.generation())
+        });
+        self.sstables.extend(outputs.into_iter().map(Arc::new));
+        self.sstables.sort_by_key(|sstable| precedence(sstable));
+    }
+
+    /// Returns the time, in seconds since the epoch, until which the tombstones can be purged.
+    pub(crate) fn gc_before(&self) -> i64 {
+        current_time() - self.gc_grace_seconds
+    }
+
+    /// Calls the visitor with the key and the live cells of every row of the table, in key order.
+    pub(crate) fn scan(&self, visitor: &mut RowVisitor) -> std::io::Result<()> {
+        visit_rows(self.entries(None)?, visitor)
+    }
+
+    /// Calls the visitor with the key and the live cells of every row of the partition, in key order.
+    pub(crate) fn scan_partition(
+        &self,
+        partition: &[u8],
+        visitor: &mut RowVisitor,
+    ) -> std::io::Result<()> {
+        visit_rows(self.entries(Some(partition))?, visitor)
+    }
+
+    /// Returns the merged entries of the table, or only the ones of a partition, in key order.
+    /// The entries keep their tombstones, which are not applied to the cells of the other keys.
+    pub(crate) fn entries<'a>(
+        &'a self,
+        partition: Option<&'a [u8]>,
+    ) -> std::io::Result<MergeIter<'a>> {
+        let mut sources: Vec<Box<dyn Iterator<Item = EntryResult> + '_>> = Vec::new();
+        match partition {
+            Some(partition) => {
+                sources.push(Box::new(
+                    self.memtable
+                        .iter_partition(partition)
+                        .map(|(key, entry)| Ok((key.clone(), entry.clone()))),
+                ));
+                for sstable in &self.sstables {
+                    sources.push(Box::new(sstable.iter_partition(partition)?));
+                }
+                sources.push(Box::new(
+                    self.legacy
+                        .range(Key::partition_start(partition)..)
+                        .take_while(move |(key, _)| key.partition() == partition)
+                        .map(|(key, entry)| Ok((key.clone(), entry.clone()))),
+                ));
+            }
+            None => {
+                sources.push(Box::new(
+                    self.memtable
+                        .iter()
+                        .map(|(key, entry)| Ok((key.clone(), entry.clone()))),
+                ));
+                for sstable in &self.sstables {
+                    sources.push(Box::new(sstable.iter()?));
+                }
+                sources.push(Box::new(
+                    self.legacy
+                        .iter()
+                        .map(|(key, entry)| Ok((key.clone(), entry.clone()))),
+                ));
+            }
+        }
+        Ok(MergeIter::new(sources))
+    }
+
+    /// Returns the merged entries of the data received by this node after `since`, in key order: the
+    /// memtable, the SSTables received after it, and the `table.csv` rows, which have no receive time.
+    pub(crate) fn unrepaired_entries(&self, since: i64) -> std::io::Result<MergeIter<'_>> {
+        let mut sources: Vec<Box<dyn Iterator<Item = EntryResult> + '_>> = vec![Box::new(
+            self.memtable
+                .iter()
+                .map(|(key, entry)| Ok((key.clone(), entry.clone()))),
+        )];
+        for sstable in &self.sstables {
+            if sstable.metadata().received_at > since {
+                sources.push(Box::new(sstable.iter()?));
+            }
+        }
+        sources.push(Box::new(
+            self.legacy
+                .iter()
+                .map(|(key, entry)| Ok((key.clone(), entry.clone()))),
+        ));
+        Ok(MergeIter::new(sources))
+    }
+}
+
+/// Calls the visitor with the live cells of the rows that were neither deleted nor expired.
+fn visit_rows(
+    entries: impl Iterator<Item = EntryResult>,
+    visitor: &mut RowVisitor,
+) -> std::io::Result<()> {
+    let now = current_time();
+    let mut deletions = PartitionDeletions::default();
+    for result in entries {
+        let (key, mut entry) = result?;
+        deletions.apply(&key, &mut entry);
+        if entry.is_live(now) {
+            visitor(&key, entry.live_cells(now))?;
+        }
+    }
+    Ok(())
+}
+
+/// Merges sorted sources of entries into a single sorted one.
+/// When a key is present in many sources, their entries are merged into one.
+pub(crate) struct MergeIter<'a> {
+    sources: Vec<Peekable<Box<dyn Iterator<Item = EntryResult> + 'a>>>,
+}
+
+impl<'a> MergeIter<'a> {
+    pub(crate) fn new(sources: Vec<Box<dyn Iterator<Item = EntryResult> + 'a>>) -> Self {
+        MergeIter {
+            sources: sources.into_iter().map(Iterator::peekable).collect(),
+        }
+    }
+}
+
+impl Iterator for MergeIter<'_> {
+    type Item = EntryResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut min: Option<Key> = None;
+        for source in self.sources.iter_mut() {
+            match source.peek() {
+                Some(Ok((key, _))) if min.as_ref().is_none_or(|min| key < min) => {
+                    min = Some(key.clone());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) => return source.next(),
+                None => {}
+            }
+        }
+        let min = min?;
+        let mut merged: Option<Entry> = None;
+        for source in self.sources.iter_mut() {
+            if matches!(source.peek(), Some(Ok((key, _))) if key == &min) {
+                if let Some(Ok((_, entry))) = source.next() {
+                    match merged.as_mut() {
+                        Some(merged) => merged.merge(entry),
+                        None => merged = Some(entry),
+                    }
+                }
+            }
+        }
+        merged.map(|entry| Ok((min, entry)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+    use crate::{PrimaryKey, SchemaType};
+
+    fn schema() -> Schema {
+        Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        )
+    }
+
+    fn row(id: &str, name: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("id".to_string(), id.to_string()),
+            ("name".to_string(), name.to_string()),
+        ])
+    }
+
+    fn names(store: &TableStore) -> Vec<String> {
+        let mut names = Vec::new();
+        store
+            .scan(&mut |_, row| {
+                names.push(row["name"].value.clone());
+                Ok(())
+            })
+            .unwrap();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_store_merges_memtable_and_sstables() {
+        let dir = PathBuf::from("test_store_merge");
+        create_dir_all(&dir).unwrap();
+        let schema = schema();
+        let mut store = TableStore::open(&dir, &schema).unwrap();
+        for (id, name) in [("1", "John"), ("2", "Jane"), ("3", "Jim")] {
+            let row = row(id, name);
+            store
+                .write(Key::new(&schema, &row).unwrap(), Entry::row(&row, 1))
+                .unwrap();
+        }
+        store.flush().unwrap();
+
+        let updated = row("1", "Johnny");
+        store
+            .write(
+                Key::new(&schema, &updated).unwrap(),
+                Entry::row(&updated, 2),
+            )
+            .unwrap();
+        store
+            .write(Key::new(&schema, &row("2", "")).unwrap(), Entry::deleted(2))
+            .unwrap();
+        let before_flush = names(&store);
+        store.flush().unwrap();
+        let after_flush = names(&store);
+
+        let reopened = TableStore::open(&dir, &schema).unwrap();
+        let after_reopen = names(&reopened);
+        let mut partition = Vec::new();
+        let key = Key::new(&schema, &row("3", "")).unwrap();
+        reopened
+            .scan_partition(key.partition(), &mut |_, row| {
+                partition.push(row["name"].value.clone());
+                Ok(())
+            })
+            .unwrap();
+        let sstables = SSTable::get_generations(&dir).unwrap();
+        remove_dir_all(&dir).unwrap();
+
+        let expected = vec!["Jim".to_string(), "Johnny".to_string()];
+        assert_eq!(before_flush, expected);
+        assert_eq!(after_flush, expected);
+        assert_eq!(after_reopen, expected);
+        assert_eq!(partition, vec!["Jim".to_string()]);
+        assert_eq!(sstables, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_store_keeps_last_write_of_each_cell() {
+        let dir = PathBuf::from("test_store_last_write");
+        create_dir_all(&dir).unwrap();
+        let schema = schema();
+        let mut store = TableStore::open(&dir, &schema).unwrap();
+        let key = Key::new(&schema, &row("1", "")).unwrap();
+        store
+            .write(key.clone(), Entry::row(&row("1", "John"), 10))
+            .unwrap();
+        store.flush().unwrap();
+        // Older writes and deletions, flushed after the newer one, do not hide it.
+        store
+            .write(key.clone(), Entry::row(&row("1", "Johnny"), 5))
+            .unwrap();
+        store.write(key.clone(), Entry::deleted(8)).unwrap();
+        store.flush().unwrap();
+        let older_writes = names(&store);
+
+        store.write(key.clone(), Entry::deleted(10)).unwrap();
+        let deleted = names(&store);
+        store.write(key, Entry::row(&row("1", "Jim"), 11)).unwrap();
+        let rewritten = names(&store);
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(older_writes, vec!["John".to_string()]);
+        assert!(deleted.is_empty());
+        assert_eq!(rewritten, vec!["Jim".to_string()]);
+    }
+
+    #[test]
+    fn test_unrepaired_entries_follow_the_receive_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = schema();
+        let mut store = TableStore::open(dir.path(), &schema).unwrap();
+        let repaired = row("1", "John");
+        store
+            .write(
+                Key::new(&schema, &repaired).unwrap(),
+                Entry::row(&repaired, 10),
+            )
+            .unwrap();
+        store.flush().unwrap();
+        let since = current_timestamp();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+
+        // A write that arrives late keeps its older client timestamp, but was not repaired yet.
+        let late = row("2", "Jane");
+        store
+            .write(Key::new(&schema, &late).unwrap(), Entry::row(&late, 5))
+            .unwrap();
+        store.flush().unwrap();
+        let pending = row("3", "Jim");
+        store
+            .write(
+                Key::new(&schema, &pending).unwrap(),
+                Entry::row(&pending, 1),
+            )
+            .unwrap();
+
+        let keys: Vec<Key> = store
+            .unrepaired_entries(since)
+            .unwrap()
+            .map(|result| result.unwrap().0)
+            .collect();
+        let all = store.entries(None).unwrap().count();
+
+        assert_eq!(
+            keys,
+            vec![
+                Key::new(&schema, &pending).unwrap(),
+                Key::new(&schema, &late).unwrap()
+            ]
+        );
+        assert_eq!(all, 3);
+    }
+}
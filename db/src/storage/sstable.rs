@@ -0,0 +1,286 @@
+use std::{
+    fs::{read_dir, remove_file, rename, File},
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+use super::{key::Key, Entry};
+
+/// An immutable sorted file of entries of a table, written when a memtable is flushed or by a compaction.
+///
+/// Each SSTable is made of two files in the table dir:
+/// - `sstable-<generation>.data`: the entries in key order. Each one is the length of the payload as
+///   a big endian `u32` followed by the payload, the key and the entry serialized with `bincode`.
+/// - `sstable-<generation>.index`: the metadata of the SSTable and the partition index, with the offset in
+///   the data file of the first entry of each partition. It is written last, so an SSTable without index
+///   is incomplete and ignored.
+#[derive(Debug)]
+pub(crate) struct SSTable {
+    generation: u64,
+    data: PathBuf,
+    size: u64,
+    metadata: Metadata,
+    index: Vec<(Vec<u8>, u64)>,
+}
+
+/// Metadata of an SSTable, stored in its index file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Metadata {
+    /// Level of the SSTable. Flushed SSTables are in level 0, and only the leveled compaction
+    /// strategy moves them to the next levels.
+    pub(crate) level: u32,
+    /// Write time of the newest cell or deletion of the SSTable, in microseconds since the epoch.
+    pub(crate) timestamp: i64,
+    /// Local time, in microseconds since the epoch, when the newest data of the SSTable reached this
+    /// node, so incremental repairs skip the SSTables that were already repaired.
+    pub(crate) received_at: i64,
+    /// Generations of the SSTables this one was compacted from, that are left behind if the node
+    /// crashed before removing them.
+    pub(crate) ancestors: Vec<u64>,
+}
+
+impl SSTable {
+    /// Writes the entries, that must be in key order, to a new SSTable and flushes it to disk.
+    pub(crate) fn write<'a>(
+        dir: &Path,
+        generation: u64,
+        metadata: Metadata,
+        entries: impl Iterator<Item = (&'a Key, &'a Entry)>,
+    ) -> std::io::Result<Self> {
+        let mut writer = SSTableWriter::create(dir, generation)?;
+        for (key, entry) in entries {
+            writer.append(key, entry)?;
+        }
+        writer.finish(metadata)
+    }
+
+    /// Opens the SSTable of the specified generation, loading its metadata and partition index.
+    pub(crate) fn open(dir: &Path, generation: u64) -> std::io::Result<Self> {
+        let index_file = BufReader::new(File::open(index_path(dir, generation))?);
+        let (metadata, index) = bincode::deserialize_from(index_file)
+            .map_err(map_io_error!("Failed to read SSTable index"))?;
+        let data = data_path(dir, generation);
+        Ok(SSTable {
+            generation,
+            size: data.metadata()?.len(),
+            data,
+            metadata,
+            index,
+        })
+    }
+
+    /// Returns the generations of the complete SSTables in the table dir, in ascending order.
+    pub(crate) fn get_generations(dir: &Path) -> std::io::Result<Vec<u64>> {
+        let mut generations = Vec::new();
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if let Some(generation) = name
+                .strip_prefix("sstable-")
+                .and_then(|name| name.strip_suffix(".index"))
+                .and_then(|generation| generation.parse().ok())
+            {
+                generations.push(generation);
+            }
+        }
+        generations.sort();
+        Ok(generations)
+    }
+
+    /// Removes the files of the SSTable of the specified generation, the index first.
+    pub(crate) fn remove(dir: &Path, generation: u64) -> std::io::Result<()> {
+        for path in [index_path(dir, generation), data_path(dir, generation)] {
+            match remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Size in bytes of the data file.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the encoded first and last partitions of the SSTable, or `None` if it is empty.
+    pub(crate) fn partition_range(&self) -> Option<(&[u8], &[u8])> {
+        let first = self.index.first()?;
+        let last = self.index.last()?;
+        Some((&first.0, &last.0))
+    }
+
+    /// Whether the partitions of both SSTables overlap.
+    pub(crate) fn overlaps(&self, other: &SSTable) -> bool {
+        match (self.partition_range(), other.partition_range()) {
+            (Some((first, last)), Some((other_first, other_last))) => {
+                first <= other_last && other_first <= last
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the SSTable has entries of the partition.
+    pub(crate) fn contains_partition(&self, partition: &[u8]) -> bool {
+        self.index
+            .binary_search_by(|(other, _)| other.as_slice().cmp(partition))
+            .is_ok()
+    }
+
+    /// Returns all the entries of the SSTable in key order.
+    pub(crate) fn iter(&self) -> std::io::Result<SSTableIter> {
+        Ok(SSTableIter {
+            reader: BufReader::new(File::open(&self.data)?),
+            partition: None,
+        })
+    }
+
+    /// Returns the entries of the partition in key order, using the index to seek to it.
+    pub(crate) fn iter_partition(&self, partition: &[u8]) -> std::io::Result<SSTableIter> {
+        let mut reader = BufReader::new(File::open(&self.data)?);
+        match self
+            .index
+            .binary_search_by(|(other, _)| other.as_slice().cmp(partition))
+        {
+            Ok(idx) => {
+                reader.seek(SeekFrom::Start(self.index[idx].1))?;
+            }
+            // The partition is not in this SSTable.
+            Err(_) => {
+                reader.seek(SeekFrom::End(0))?;
+            }
+        }
+        Ok(SSTableIter {
+            reader,
+            partition: Some(partition.to_vec()),
+        })
+    }
+}
+
+/// Writes the entries of a new SSTable, that must be appended in key order.
+/// The SSTable is not visible until it is finished.
+pub(crate) struct SSTableWriter {
+    dir: PathBuf,
+    generation: u64,
+    writer: BufWriter<File>,
+    index: Vec<(Vec<u8>, u64)>,
+    offset: u64,
+}
+
+impl SSTableWriter {
+    pub(crate) fn create(dir: &Path, generation: u64) -> std::io::Result<Self> {
+        let data_tmp = data_path(dir, generation).with_extension("data.tmp");
+        Ok(SSTableWriter {
+            dir: dir.to_path_buf(),
+            generation,
+            writer: BufWriter::new(File::create(data_tmp)?),
+            index: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    pub(crate) fn append(&mut self, key: &Key, entry: &Entry) -> std::io::Result<()> {
+        if self
+            .index
+            .last()
+            .is_none_or(|(partition, _)| partition != key.partition())
+        {
+            self.index.push((key.partition().to_vec(), self.offset));
+        }
+        let payload = bincode::serialize(&(key, entry))
+            .map_err(map_io_error!("Failed to serialize SSTable entry"))?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.offset += 4 + payload.len() as u64;
+        Ok(())
+    }
+
+    /// Size in bytes of the entries written so far.
+    pub(crate) fn size(&self) -> u64 {
+        self.offset
+    }
+
+    /// Flushes the data to disk and writes the index, making the SSTable complete.
+    pub(crate) fn finish(self, metadata: Metadata) -> std::io::Result<SSTable> {
+        let data = data_path(&self.dir, self.generation);
+        let data_tmp = data.with_extension("data.tmp");
+        self.writer
+            .into_inner()
+            .map_err(map_io_error!("Failed to write SSTable"))?
+            .sync_all()?;
+
+        let index_path = index_path(&self.dir, self.generation);
+        let index_tmp = index_path.with_extension("index.tmp");
+        let mut index_file = File::create(&index_tmp)?;
+        bincode::serialize_into(BufWriter::new(&mut index_file), &(&metadata, &self.index))
+            .map_err(map_io_error!("Failed to write SSTable index"))?;
+        index_file.sync_all()?;
+
+        rename(data_tmp, &data)?;
+        rename(index_tmp, index_path)?;
+        File::open(&self.dir)?.sync_all()?;
+        Ok(SSTable {
+            generation: self.generation,
+            data,
+            size: self.offset,
+            metadata,
+            index: self.index,
+        })
+    }
+}
+
+/// Iterator over the entries of an SSTable.
+pub(crate) struct SSTableIter {
+    reader: BufReader<File>,
+    /// If set, the iteration stops at the first entry outside this partition.
+    partition: Option<Vec<u8>>,
+}
+
+impl Iterator for SSTableIter {
+    type Item = std::io::Result<(Key, Entry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut length = [0u8; 4];
+        match self.reader.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        }
+        let mut payload = vec![0u8; u32::from_be_bytes(length) as usize];
+        if let Err(e) = self.reader.read_exact(&mut payload) {
+            return Some(Err(e));
+        }
+        let (key, entry): (Key, Entry) = match bincode::deserialize(&payload) {
+            Ok(entry) => entry,
+            Err(_) => return Some(Err(shared::io_error!("Corrupted SSTable entry"))),
+        };
+        if let Some(partition) = &self.partition {
+            if key.partition() != partition.as_slice() {
+                return None;
+            }
+        }
+        Some(Ok((key, entry)))
+    }
+}
+
+fn data_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("sstable-{generation}.data"))
+}
+
+fn index_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("sstable-{generation}.index"))
+}
@@ -0,0 +1,213 @@
+use std::collections::{btree_map, BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Schema;
+
+use super::{cell::current_time, entry::PartitionDeletions, key::Key, Entry};
+
+/// A row as stored in a table: its encoded primary key with its cells and tombstones.
+///
+/// Unlike the rows read with `Context::read_table`, stored rows keep the deletions of cells, rows and
+/// partitions, so the rows of a partition read from different replicas can be reconciled with
+/// `reconcile` and the replicas that missed a write or a deletion repaired with `get_repairs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredRow {
+    pub(crate) key: Key,
+    pub(crate) entry: Entry,
+}
+
+impl StoredRow {
+    /// Returns the token of the partition of the row, which decides the nodes that hold it.
+    pub fn token(&self) -> i64 {
+        self.key.token()
+    }
+
+    /// Returns the time of the newest write of the row, in microseconds since the epoch.
+    pub fn timestamp(&self) -> i64 {
+        self.entry.timestamp()
+    }
+
+    /// Whether the stored row is the entry of its partition, which holds the deletions of the whole
+    /// partition and of ranges of its rows instead of cells.
+    pub fn is_partition(&self) -> bool {
+        self.key.is_partition()
+    }
+
+    /// Returns the values of the primary key and of the cells of the row that are not tombstones,
+    /// `NULL` for the columns without one. The deletions of its partition and the expirations are not
+    /// applied, so a row has the same values in a replica that has its newest writes as once reconciled.
+    pub fn values(&self, schema: &Schema) -> HashMap<String, String> {
+        let cells = self
+            .entry
+            .cells
+            .iter()
+            .filter(|(_, cell)| cell.deletion_time.is_none())
+            .map(|(col, cell)| (col.clone(), cell.clone()))
+            .collect();
+        let now = current_time();
+        schema
+            .to_row(&self.key, &cells, now)
+            .into_iter()
+            .filter(|(col, _)| !col.starts_with("TTL("))
+            .collect()
+    }
+}
+
+/// Merges the stored rows read from the replicas, keeping the last write of each cell and every
+/// tombstone.
+///
+/// # Returns
+///
+/// * Returns the merged rows in key order.
+pub fn reconcile(responses: Vec<Vec<StoredRow>>) -> Vec<StoredRow> {
+    let mut merged: BTreeMap<Key, Entry> = BTreeMap::new();
+    for row in responses.into_iter().flatten() {
+        match merged.entry(row.key) {
+            btree_map::Entry::Occupied(mut current) => current.get_mut().merge(row.entry),
+            btree_map::Entry::Vacant(current) => {
+                current.insert(row.entry);
+            }
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(key, entry)| StoredRow { key, entry })
+        .collect()
+}
+
+/// Returns the values of the rows that were neither deleted nor expired, `NULL` for the columns without
+/// a value.
+///
+/// # Arguments
+///
+/// * `rows` - Stored rows in key order, such as the ones returned by `reconcile`.
+/// * `schema` - The schema of the table of the rows.
+pub fn get_live_rows(rows: &[StoredRow], schema: &Schema) -> Vec<HashMap<String, String>> {
+    let now = current_time();
+    let mut deletions = PartitionDeletions::default();
+    rows.iter()
+        .filter_map(|row| {
+            let mut entry = row.entry.clone();
+            deletions.apply(&row.key, &mut entry);
+            entry
+                .is_live(now)
+                .then(|| schema.to_row(&row.key, &entry.live_cells(now), now))
+        })
+        .collect()
+}
+
+/// Returns what a replica is missing of the reconciled rows: the cells it has an older write of and the
+/// tombstones it does not have.
+///
+/// # Arguments
+///
+/// * `replica` - The stored rows read from the replica.
+/// * `merged` - The stored rows reconciled from all the replicas.
+pub fn get_repairs(replica: &[StoredRow], merged: &[StoredRow]) -> Vec<StoredRow> {
+    let current: HashMap<&Key, &Entry> = replica.iter().map(|row| (&row.key, &row.entry)).collect();
+    merged
+        .iter()
+        .filter_map(|row| {
+            let current = current.get(&row.key);
+            let entry = Entry {
+                cells: row
+                    .entry
+                    .cells
+                    .iter()
+                    .filter(|(col, cell)| {
+                        current
+                            .and_then(|current| current.cells.get(*col))
+                            .is_none_or(|current| cell.wins_over(current))
+                    })
+                    .map(|(col, cell)| (col.clone(), cell.clone()))
+                    .collect(),
+                deletion: row.entry.deletion.filter(|deletion| {
+                    current.is_none_or(|current| {
+                        current
+                            .deletion
+                            .is_none_or(|current| current.timestamp < deletion.timestamp)
+                    })
+                }),
+                ranges: row
+                    .entry
+                    .ranges
+                    .iter()
+                    .filter(|range| {
+                        current.is_none_or(|current| {
+                            !current.ranges.iter().any(|current| current.is_same(range))
+                        })
+                    })
+                    .cloned()
+                    .collect(),
+            };
+            (!entry.is_empty()).then(|| StoredRow {
+                key: row.key.clone(),
+                entry,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_token, PrimaryKey, SchemaType};
+
+    fn schema() -> Schema {
+        Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], vec![]),
+        )
+    }
+
+    fn stored_row(id: &str, entry: Entry) -> StoredRow {
+        let row = HashMap::from([("id".to_string(), id.to_string())]);
+        StoredRow {
+            key: Key::new(&schema(), &row).unwrap(),
+            entry,
+        }
+    }
+
+    fn written(id: &str, name: &str, timestamp: i64) -> StoredRow {
+        let row = HashMap::from([
+            ("id".to_string(), id.to_string()),
+            ("name".to_string(), name.to_string()),
+        ]);
+        stored_row(id, Entry::row(&row, timestamp))
+    }
+
+    #[test]
+    fn test_reconcile_hides_deleted_rows() {
+        let first = vec![written("1", "John", 1), written("2", "Jane", 1)];
+        let second = vec![
+            written("1", "Johnny", 2),
+            stored_row("2", Entry::deleted(2)),
+        ];
+        let merged = reconcile(vec![first.clone(), second.clone()]);
+
+        let rows = get_live_rows(&merged, &schema());
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["name"], "Johnny");
+
+        // The first replica misses the new name and the deletion, the second one is up to date.
+        let repairs = get_repairs(&first, &merged);
+        assert_eq!(repairs.len(), 2);
+        assert!(repairs.iter().any(|row| row
+            .entry
+            .cells
+            .get("name")
+            .is_some_and(|cell| cell.value == "Johnny")));
+        assert!(repairs.iter().any(|row| row.entry.deletion.is_some()));
+        assert!(get_repairs(&second, &merged).is_empty());
+    }
+
+    #[test]
+    fn test_token_of_stored_row() {
+        let row = written("1", "John", 1);
+        assert_eq!(row.token(), get_token("1").unwrap());
+    }
+}
@@ -1,6 +1,35 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::{copy, create_dir_all, read_dir},
+    path::{Path, PathBuf},
+};
 
-use db::{initialize_context, Options, PrimaryKey, Schema, SchemaType};
+use db::{current_timestamp, initialize_context, Options, PrimaryKey, Schema, SchemaType};
+
+/// Copies the node fixture to a scratch dir, so tests that write to it leave the fixture unchanged.
+fn copy_node(fixture: &str, name: &str) -> PathBuf {
+    fn copy_dir(from: &Path, to: &Path) {
+        create_dir_all(to).unwrap();
+        for entry in read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_dir(&path, &target);
+            } else {
+                copy(&path, &target).unwrap();
+            }
+        }
+    }
+    let node = std::env::temp_dir().join(format!("cassandrust-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&node);
+    copy_dir(Path::new(fixture), &node);
+    node
+}
+
+/// Sorts the rows by the specified column, since rows are read in the order they are stored.
+fn sort_rows(rows: &mut [HashMap<String, String>], col: &str) {
+    rows.sort_by(|a, b| a.get(col).cmp(&b.get(col)));
+}
 
 #[test]
 fn test_initialize_context() {
@@ -34,6 +63,13 @@ fn test_create_delete_keyspace() {
         opt_cmp.replication.replication_factor
     );
 
+    let altered = Options::new(false, "SimpleStrategy".to_string(), 1);
+    ctx.alter_keyspace(&ks, &altered).unwrap();
+    assert_eq!(ctx.get_keyspace_options(&ks).unwrap(), altered);
+    assert!(ctx
+        .alter_keyspace(&node.join("ks_test_missing"), &altered)
+        .is_err());
+
     ctx.drop_keyspace(&ks).unwrap();
     assert!(!ks.exists());
     assert!(!ctx.is_a_keyspace("ks_test_create"));
@@ -83,6 +119,7 @@ fn test_select() {
         Ok(())
     })
     .unwrap();
+    sort_rows(&mut rows, "id");
 
     assert_eq!(rows.len(), 2);
     let mut row = &rows[0];
@@ -99,16 +136,17 @@ fn test_select() {
 
 #[test]
 fn test_insert_and_delete() {
-    let node = PathBuf::from("tests/node_test");
+    let node = copy_node("tests/node_test", "insert_and_delete");
     let table = node.join("ks_test/table_test_insert");
-    let mut ctx = initialize_context(&node).unwrap();
+    let ctx = initialize_context(&node).unwrap();
 
     let mut new_row = HashMap::new();
     new_row.insert("name".to_string(), "tablet".to_string());
     new_row.insert("price".to_string(), "150".to_string());
     new_row.insert("quantity".to_string(), "3".to_string());
 
-    ctx.append_to_table(&table, new_row).unwrap();
+    ctx.append_to_table(&table, new_row, current_timestamp(), None)
+        .unwrap();
 
     let mut rows = Vec::new();
     ctx.read_table(&table, &mut |row| {
@@ -116,16 +154,17 @@ fn test_insert_and_delete() {
         Ok(())
     })
     .unwrap();
+    sort_rows(&mut rows, "name");
 
     assert_eq!(rows.len(), 3);
     let mut row = &rows[0];
-    assert_eq!(row.get("name").unwrap(), "phone");
-    assert_eq!(row.get("price").unwrap(), "99.99");
+    assert_eq!(row.get("name").unwrap(), "laptop");
+    assert_eq!(row.get("price").unwrap(), "999.99");
     assert_eq!(row.get("quantity").unwrap(), "1");
 
     row = &rows[1];
-    assert_eq!(row.get("name").unwrap(), "laptop");
-    assert_eq!(row.get("price").unwrap(), "999.99");
+    assert_eq!(row.get("name").unwrap(), "phone");
+    assert_eq!(row.get("price").unwrap(), "99.99");
     assert_eq!(row.get("quantity").unwrap(), "1");
 
     row = &rows[2];
@@ -133,7 +172,7 @@ fn test_insert_and_delete() {
     assert_eq!(row.get("price").unwrap(), "150");
     assert_eq!(row.get("quantity").unwrap(), "3");
 
-    ctx.update_table(&table, &mut |row| {
+    ctx.update_table(&table, current_timestamp(), None, &mut |row| {
         if row.get("name").unwrap() == "tablet" {
             Ok(None)
         } else {
@@ -148,26 +187,30 @@ fn test_insert_and_delete() {
         Ok(())
     })
     .unwrap();
+    sort_rows(&mut new_rows, "name");
 
     assert_eq!(new_rows.len(), 2);
     let mut row = &new_rows[0];
-    assert_eq!(row.get("name").unwrap(), "phone");
-    assert_eq!(row.get("price").unwrap(), "99.99");
+    assert_eq!(row.get("name").unwrap(), "laptop");
+    assert_eq!(row.get("price").unwrap(), "999.99");
     assert_eq!(row.get("quantity").unwrap(), "1");
 
     row = &new_rows[1];
-    assert_eq!(row.get("name").unwrap(), "laptop");
-    assert_eq!(row.get("price").unwrap(), "999.99");
+    assert_eq!(row.get("name").unwrap(), "phone");
+    assert_eq!(row.get("price").unwrap(), "99.99");
     assert_eq!(row.get("quantity").unwrap(), "1");
+
+    drop(ctx);
+    std::fs::remove_dir_all(&node).unwrap();
 }
 
 #[test]
 fn test_update() {
-    let node = PathBuf::from("tests/node_test");
+    let node = copy_node("tests/node_test", "update");
     let table = node.join("ks_test/table_test_update");
-    let mut ctx = initialize_context(&node).unwrap();
+    let ctx = initialize_context(&node).unwrap();
 
-    ctx.update_table(&table, &mut |row| {
+    ctx.update_table(&table, current_timestamp(), None, &mut |row| {
         if row.get("name").unwrap() == "phone" {
             let mut new_row = row.clone();
             new_row.insert("price".to_string(), "199.99".to_string());
@@ -184,28 +227,21 @@ fn test_update() {
         Ok(())
     })
     .unwrap();
+    sort_rows(&mut rows, "name");
 
     assert_eq!(rows.len(), 2);
     let mut row = &rows[0];
-    assert_eq!(row.get("name").unwrap(), "phone");
-    assert_eq!(row.get("price").unwrap(), "199.99");
+    assert_eq!(row.get("name").unwrap(), "laptop");
+    assert_eq!(row.get("price").unwrap(), "999.99");
     assert_eq!(row.get("quantity").unwrap(), "1");
 
     row = &rows[1];
-    assert_eq!(row.get("name").unwrap(), "laptop");
-    assert_eq!(row.get("price").unwrap(), "999.99");
+    assert_eq!(row.get("name").unwrap(), "phone");
+    assert_eq!(row.get("price").unwrap(), "199.99");
     assert_eq!(row.get("quantity").unwrap(), "1");
 
-    ctx.update_table(&table, &mut |row| {
-        if row.get("name").unwrap() == "phone" {
-            let mut new_row = row.clone();
-            new_row.insert("price".to_string(), "99.99".to_string());
-            Ok(Some(new_row))
-        } else {
-            Ok(Some(row.clone()))
-        }
-    })
-    .unwrap();
+    drop(ctx);
+    std::fs::remove_dir_all(&node).unwrap();
 }
 
 #[test]
@@ -231,3 +267,48 @@ fn test_create_table_already_exist() {
     let schema = Schema::new(cols, prim_key);
     assert!(ctx.create_table(&table, &schema).is_err());
 }
+
+#[test]
+fn test_stream_table() {
+    let node = copy_node("tests/node_test", "stream_table");
+    let table = node.join("ks_test/table_test_insert");
+    let mut ctx = initialize_context(&node).unwrap();
+    assert!(ctx.get_keyspaces().contains(&"ks_test".to_string()));
+    assert!(ctx
+        .get_tables("ks_test")
+        .unwrap()
+        .contains(&"table_test_insert".to_string()));
+
+    let mut stored_rows = Vec::new();
+    ctx.scan_stored_rows(&table, None, &mut |row| {
+        stored_rows.push(row);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(stored_rows.len(), 2);
+
+    // The rows streamed to another keyspace keep their values.
+    let schema = ctx
+        .get_table_schema("ks_test", "table_test_insert")
+        .unwrap();
+    let streamed = node.join("ks_streamed/table_test_insert");
+    let opt = Options::new(true, "SimpleStrategy".to_string(), 1);
+    ctx.create_keyspace(&node.join("ks_streamed"), &opt)
+        .unwrap();
+    ctx.create_table(&streamed, &schema).unwrap();
+    ctx.write_stored_rows(&streamed, stored_rows).unwrap();
+
+    let mut rows = Vec::new();
+    ctx.read_table(&streamed, &mut |row| {
+        rows.push(row);
+        Ok(())
+    })
+    .unwrap();
+    sort_rows(&mut rows, "name");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get("name").unwrap(), "laptop");
+    assert_eq!(rows[1].get("name").unwrap(), "phone");
+
+    drop(ctx);
+    std::fs::remove_dir_all(&node).unwrap();
+}
@@ -0,0 +1,58 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+use crate::query::Query;
+
+/// The statements of a batch that a replica applies together, with their tables qualified with their
+/// keyspaces and their write times set by the coordinator.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Batch {
+    pub queries: Vec<Query>,
+}
+
+/// A logged batch, kept by the nodes that hold its batchlog until the coordinator applies it, so they
+/// replay it if the coordinator dies before.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchLogEntry {
+    pub id: String,
+    /// When the batch was written to the batchlog, in microseconds since the epoch.
+    pub written_at: i64,
+    pub queries: Vec<Query>,
+}
+
+/// A change to the batchlog of a node.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum BatchLog {
+    /// Keeps a batch before its coordinator applies it.
+    Store(BatchLogEntry),
+    /// Removes a batch, given its id, once its coordinator applied it.
+    Remove(String),
+}
+
+impl Batch {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let batch: Batch = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize Batch struct"))?;
+        Ok(batch)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize Batch struct"))
+    }
+}
+
+impl BatchLog {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let batch_log: BatchLog = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize BatchLog struct"))?;
+        Ok(batch_log)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize BatchLog struct"))
+    }
+}
@@ -0,0 +1,24 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+/// The answer of a node that could not carry out a request, so the node that sent it does not wait for
+/// a result that never comes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Error {
+    pub message: String,
+}
+
+impl Error {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let error: Error = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize Error struct"))?;
+        Ok(error)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize Error struct"))
+    }
+}
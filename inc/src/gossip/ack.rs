@@ -3,12 +3,16 @@ use std::io::{Read, Write};
 use serde::{Deserialize, Serialize};
 use shared::map_io_error;
 
-use super::peer::Peer;
+use super::{digest::GossipDigest, peer::Peer};
 
+/// The answer to a `Syn`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Ack {
-    pub heartbeat: u64,
-    pub update_peers: Vec<Peer>,
+    /// The nodes the sender of the `Syn` knows a newer state of, with the version the answering node
+    /// knows of them.
+    pub digests: Vec<GossipDigest>,
+    /// The states the sender of the `Syn` is missing, with only what is newer than what it knows.
+    pub states: Vec<Peer>,
 }
 
 impl Ack {
@@ -0,0 +1,24 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+use super::peer::Peer;
+
+/// The answer to an `Ack`, with the states its sender asked for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ack2 {
+    pub states: Vec<Peer>,
+}
+
+impl Ack2 {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let ack2: Ack2 = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize Ack2 struct"))?;
+        Ok(ack2)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self).map_err(map_io_error!("Cannot serialize Ack2 struct"))
+    }
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+use super::peer::EndpointState;
+
+/// A summary of what a node knows of the state of another node: the generation of its heartbeat and the
+/// highest version of its state.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GossipDigest {
+    pub ip: String,
+    pub generation: i64,
+    pub max_version: u64,
+}
+
+impl GossipDigest {
+    pub fn new(ip: &str, state: &EndpointState) -> Self {
+        GossipDigest {
+            ip: ip.to_string(),
+            generation: state.heartbeat.generation,
+            max_version: state.max_version(),
+        }
+    }
+}
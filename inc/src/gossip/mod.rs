@@ -1,3 +1,5 @@
 pub mod ack;
+pub mod ack2;
+pub mod digest;
 pub mod peer;
 pub mod syn;
@@ -1,9 +1,206 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
 use serde::{Deserialize, Serialize};
+use shared::io_error;
 
+/// A node of the cluster, as it is known through gossip.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Peer {
     pub ip: String,
     pub port: u16,
-    pub last_heartbeat: u64,
+    /// Whether this node considers the peer up. It is not gossiped, as each node tells it on its own.
+    #[serde(skip)]
     pub alive: bool,
+    pub state: EndpointState,
+}
+
+/// The heartbeat of a node.
+///
+/// The generation is the time the node started, so the state of a restarted node replaces the one of
+/// its previous run. The version goes up while the node runs.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct HeartBeatState {
+    pub generation: i64,
+    pub version: u64,
+}
+
+/// What a node announces about itself through gossip.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ApplicationState {
+    /// The `Status` of the node in the ring.
+    Status,
+    /// The tokens the node owns, separated by commas.
+    Tokens,
+    /// The version of the schema of the node.
+    Schema,
+    Dc,
+    Rack,
+    /// The bytes of data the node holds.
+    Load,
+    /// The version of the node software.
+    ReleaseVersion,
+}
+
+/// The value of an application state, with the version of the node when it was set.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VersionedValue {
+    pub value: String,
+    pub version: u64,
+}
+
+/// The state of a node: its heartbeat and its application states.
+///
+/// The heartbeat and the application states share the versions of the node, so the highest of them tells
+/// what another node is missing of the state.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct EndpointState {
+    pub heartbeat: HeartBeatState,
+    pub application_states: BTreeMap<ApplicationState, VersionedValue>,
+}
+
+impl EndpointState {
+    /// Creates the state of a node started at the generation, with no application states yet.
+    pub fn new(generation: i64) -> Self {
+        EndpointState {
+            heartbeat: HeartBeatState {
+                generation,
+                version: 0,
+            },
+            application_states: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the highest version of the heartbeat and of the application states.
+    pub fn max_version(&self) -> u64 {
+        self.application_states
+            .values()
+            .map(|value| value.version)
+            .fold(self.heartbeat.version, u64::max)
+    }
+
+    /// Moves the heartbeat to the next version.
+    pub fn beat(&mut self) {
+        self.heartbeat.version = self.max_version() + 1;
+    }
+
+    /// Sets an application state with the next version. Setting it to the value it has does nothing, so
+    /// it is not gossiped again.
+    pub fn set(&mut self, state: ApplicationState, value: String) {
+        if self.get(state) == Some(&value[..]) {
+            return;
+        }
+        let version = self.max_version() + 1;
+        self.application_states
+            .insert(state, VersionedValue { value, version });
+    }
+
+    pub fn get(&self, state: ApplicationState) -> Option<&str> {
+        self.application_states
+            .get(&state)
+            .map(|value| &value.value[..])
+    }
+
+    /// Returns the heartbeat and the application states set after the version.
+    pub fn delta(&self, after: u64) -> EndpointState {
+        EndpointState {
+            heartbeat: self.heartbeat,
+            application_states: self
+                .application_states
+                .iter()
+                .filter(|(_, value)| value.version > after)
+                .map(|(state, value)| (*state, value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Takes what is newer of a state gossiped by another node. The state of a newer generation replaces
+    /// this one, and the states of an older one are ignored.
+    ///
+    /// Returns whether the heartbeat went forward, which means the node is running.
+    pub fn apply(&mut self, remote: &EndpointState) -> bool {
+        if remote.heartbeat.generation > self.heartbeat.generation {
+            *self = remote.clone();
+            return true;
+        }
+        if remote.heartbeat.generation < self.heartbeat.generation {
+            return false;
+        }
+        for (state, value) in &remote.application_states {
+            if self
+                .application_states
+                .get(state)
+                .is_none_or(|local| local.version < value.version)
+            {
+                self.application_states.insert(*state, value.clone());
+            }
+        }
+        if remote.heartbeat.version > self.heartbeat.version {
+            self.heartbeat.version = remote.heartbeat.version;
+            return true;
+        }
+        false
+    }
+
+    /// Returns the status of the node in the ring, if it is known.
+    pub fn status(&self) -> Option<Status> {
+        self.get(ApplicationState::Status)?.parse().ok()
+    }
+
+    /// Returns the tokens the node owns, if they are known.
+    pub fn tokens(&self) -> Option<Vec<i64>> {
+        self.get(ApplicationState::Tokens)?
+            .split(',')
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse().ok())
+            .collect()
+    }
+
+    pub fn datacenter(&self) -> Option<&str> {
+        self.get(ApplicationState::Dc)
+    }
+
+    pub fn rack(&self) -> Option<&str> {
+        self.get(ApplicationState::Rack)
+    }
+}
+
+/// The status of a node in the ring.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// The node is streaming the data of its tokens. It receives the writes of its tokens, but is not
+    /// read from until it is `Normal`.
+    Joining,
+    /// The node owns its tokens.
+    Normal,
+    /// The node is streaming the data of its tokens to the nodes that are going to own them. It is still
+    /// read from, and its writes also go to those nodes.
+    Leaving,
+    /// The node left the ring, decommissioned or removed while it was down.
+    Left,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Status::Joining => "JOINING",
+            Status::Normal => "NORMAL",
+            Status::Leaving => "LEAVING",
+            Status::Left => "LEFT",
+        };
+        write!(f, "{status}")
+    }
+}
+
+impl FromStr for Status {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "JOINING" => Ok(Status::Joining),
+            "NORMAL" => Ok(Status::Normal),
+            "LEAVING" => Ok(Status::Leaving),
+            "LEFT" => Ok(Status::Left),
+            _ => Err(io_error!(format!("Invalid status: {s}"))),
+        }
+    }
 }
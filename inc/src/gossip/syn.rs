@@ -3,15 +3,13 @@ use std::io::{Read, Write};
 use serde::{Deserialize, Serialize};
 use shared::map_io_error;
 
-use super::peer::Peer;
+use super::digest::GossipDigest;
 
+/// The first message of a gossip round, with the digests of every node the sender knows, itself included.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Syn {
     pub sender: String,
-    pub ip: String,
-    pub port: u16,
-    pub heartbeat: u64,
-    pub known_peers: Vec<Peer>,
+    pub digests: Vec<GossipDigest>,
 }
 
 impl Syn {
@@ -1,16 +1,28 @@
+pub mod batch;
+pub mod error;
 pub mod gossip;
 pub mod hinted;
+pub mod operation;
 pub mod query;
+pub mod repair;
 pub mod result;
+pub mod schema;
+pub mod stream;
 
 use std::io::{Read, Write};
 
-use gossip::{ack::Ack, syn::Syn};
+use batch::{Batch, BatchLog};
+use error::Error;
+use gossip::{ack::Ack, ack2::Ack2, syn::Syn};
 use hinted::Hinted;
-use query::Query;
+use operation::{Operation, OperationResult};
+use query::{PageQuery, Query};
+use repair::{MerkleTreeRequest, MerkleTreeResponse, RangeRequest, RangeResponse};
 use result::Result;
+use schema::{SchemaRequest, SchemaResponse};
 use serde::{Deserialize, Serialize};
 use shared::io_error;
+use stream::{StreamRequest, StreamResponse};
 
 #[derive(Debug)]
 pub enum FrameType {
@@ -19,6 +31,21 @@ pub enum FrameType {
     Syn = 0x03,
     Ack = 0x04,
     Hinted = 0x05,
+    StreamRequest = 0x06,
+    StreamResponse = 0x07,
+    Operation = 0x08,
+    OperationResult = 0x09,
+    MerkleTreeRequest = 0x0A,
+    MerkleTreeResponse = 0x0B,
+    RangeRequest = 0x0C,
+    RangeResponse = 0x0D,
+    Ack2 = 0x0E,
+    SchemaRequest = 0x0F,
+    SchemaResponse = 0x10,
+    Batch = 0x11,
+    BatchLog = 0x12,
+    Error = 0x13,
+    PageQuery = 0x14,
 }
 
 impl FrameType {
@@ -31,6 +58,21 @@ impl FrameType {
             0x03 => Ok(FrameType::Syn),
             0x04 => Ok(FrameType::Ack),
             0x05 => Ok(FrameType::Hinted),
+            0x06 => Ok(FrameType::StreamRequest),
+            0x07 => Ok(FrameType::StreamResponse),
+            0x08 => Ok(FrameType::Operation),
+            0x09 => Ok(FrameType::OperationResult),
+            0x0A => Ok(FrameType::MerkleTreeRequest),
+            0x0B => Ok(FrameType::MerkleTreeResponse),
+            0x0C => Ok(FrameType::RangeRequest),
+            0x0D => Ok(FrameType::RangeResponse),
+            0x0E => Ok(FrameType::Ack2),
+            0x0F => Ok(FrameType::SchemaRequest),
+            0x10 => Ok(FrameType::SchemaResponse),
+            0x11 => Ok(FrameType::Batch),
+            0x12 => Ok(FrameType::BatchLog),
+            0x13 => Ok(FrameType::Error),
+            0x14 => Ok(FrameType::PageQuery),
             _ => Err(io_error!("Invalid frame type")),
         }
     }
@@ -42,6 +84,21 @@ impl FrameType {
             FrameType::Syn => writer.write_all(&[0x03u8]),
             FrameType::Ack => writer.write_all(&[0x04u8]),
             FrameType::Hinted => writer.write_all(&[0x05u8]),
+            FrameType::StreamRequest => writer.write_all(&[0x06u8]),
+            FrameType::StreamResponse => writer.write_all(&[0x07u8]),
+            FrameType::Operation => writer.write_all(&[0x08u8]),
+            FrameType::OperationResult => writer.write_all(&[0x09u8]),
+            FrameType::MerkleTreeRequest => writer.write_all(&[0x0Au8]),
+            FrameType::MerkleTreeResponse => writer.write_all(&[0x0Bu8]),
+            FrameType::RangeRequest => writer.write_all(&[0x0Cu8]),
+            FrameType::RangeResponse => writer.write_all(&[0x0Du8]),
+            FrameType::Ack2 => writer.write_all(&[0x0Eu8]),
+            FrameType::SchemaRequest => writer.write_all(&[0x0Fu8]),
+            FrameType::SchemaResponse => writer.write_all(&[0x10u8]),
+            FrameType::Batch => writer.write_all(&[0x11u8]),
+            FrameType::BatchLog => writer.write_all(&[0x12u8]),
+            FrameType::Error => writer.write_all(&[0x13u8]),
+            FrameType::PageQuery => writer.write_all(&[0x14u8]),
         }
     }
 }
@@ -53,6 +110,21 @@ pub enum Body {
     Syn(Syn),
     Ack(Ack),
     Hinted(Hinted),
+    StreamRequest(StreamRequest),
+    StreamResponse(StreamResponse),
+    Operation(Operation),
+    OperationResult(OperationResult),
+    MerkleTreeRequest(MerkleTreeRequest),
+    MerkleTreeResponse(MerkleTreeResponse),
+    RangeRequest(RangeRequest),
+    RangeResponse(RangeResponse),
+    Ack2(Ack2),
+    SchemaRequest(SchemaRequest),
+    SchemaResponse(SchemaResponse),
+    Batch(Batch),
+    BatchLog(BatchLog),
+    Error(Error),
+    PageQuery(PageQuery),
 }
 
 pub fn read_inc_frame<R: Read>(reader: &mut R) -> std::io::Result<(FrameType, Body)> {
@@ -78,6 +150,72 @@ pub fn read_inc_frame<R: Read>(reader: &mut R) -> std::io::Result<(FrameType, Bo
             let hinted = Hinted::read(reader)?;
             Ok((FrameType::Hinted, Body::Hinted(hinted)))
         }
+        FrameType::StreamRequest => {
+            let request = StreamRequest::read(reader)?;
+            Ok((FrameType::StreamRequest, Body::StreamRequest(request)))
+        }
+        FrameType::StreamResponse => {
+            let response = StreamResponse::read(reader)?;
+            Ok((FrameType::StreamResponse, Body::StreamResponse(response)))
+        }
+        FrameType::Operation => {
+            let operation = Operation::read(reader)?;
+            Ok((FrameType::Operation, Body::Operation(operation)))
+        }
+        FrameType::OperationResult => {
+            let result = OperationResult::read(reader)?;
+            Ok((FrameType::OperationResult, Body::OperationResult(result)))
+        }
+        FrameType::MerkleTreeRequest => {
+            let request = MerkleTreeRequest::read(reader)?;
+            Ok((
+                FrameType::MerkleTreeRequest,
+                Body::MerkleTreeRequest(request),
+            ))
+        }
+        FrameType::MerkleTreeResponse => {
+            let response = MerkleTreeResponse::read(reader)?;
+            Ok((
+                FrameType::MerkleTreeResponse,
+                Body::MerkleTreeResponse(response),
+            ))
+        }
+        FrameType::RangeRequest => {
+            let request = RangeRequest::read(reader)?;
+            Ok((FrameType::RangeRequest, Body::RangeRequest(request)))
+        }
+        FrameType::RangeResponse => {
+            let response = RangeResponse::read(reader)?;
+            Ok((FrameType::RangeResponse, Body::RangeResponse(response)))
+        }
+        FrameType::Ack2 => {
+            let ack2 = Ack2::read(reader)?;
+            Ok((FrameType::Ack2, Body::Ack2(ack2)))
+        }
+        FrameType::SchemaRequest => {
+            let request = SchemaRequest::read(reader)?;
+            Ok((FrameType::SchemaRequest, Body::SchemaRequest(request)))
+        }
+        FrameType::SchemaResponse => {
+            let response = SchemaResponse::read(reader)?;
+            Ok((FrameType::SchemaResponse, Body::SchemaResponse(response)))
+        }
+        FrameType::Batch => {
+            let batch = Batch::read(reader)?;
+            Ok((FrameType::Batch, Body::Batch(batch)))
+        }
+        FrameType::BatchLog => {
+            let batch_log = BatchLog::read(reader)?;
+            Ok((FrameType::BatchLog, Body::BatchLog(batch_log)))
+        }
+        FrameType::Error => {
+            let error = Error::read(reader)?;
+            Ok((FrameType::Error, Body::Error(error)))
+        }
+        FrameType::PageQuery => {
+            let query = PageQuery::read(reader)?;
+            Ok((FrameType::PageQuery, Body::PageQuery(query)))
+        }
     }
 }
 
@@ -103,6 +241,51 @@ pub fn write_inc_frame<W: Write>(
         (FrameType::Hinted, Body::Hinted(hinted)) => {
             hinted.write(writer)?;
         }
+        (FrameType::StreamRequest, Body::StreamRequest(request)) => {
+            request.write(writer)?;
+        }
+        (FrameType::StreamResponse, Body::StreamResponse(response)) => {
+            response.write(writer)?;
+        }
+        (FrameType::Operation, Body::Operation(operation)) => {
+            operation.write(writer)?;
+        }
+        (FrameType::OperationResult, Body::OperationResult(result)) => {
+            result.write(writer)?;
+        }
+        (FrameType::MerkleTreeRequest, Body::MerkleTreeRequest(request)) => {
+            request.write(writer)?;
+        }
+        (FrameType::MerkleTreeResponse, Body::MerkleTreeResponse(response)) => {
+            response.write(writer)?;
+        }
+        (FrameType::RangeRequest, Body::RangeRequest(request)) => {
+            request.write(writer)?;
+        }
+        (FrameType::RangeResponse, Body::RangeResponse(response)) => {
+            response.write(writer)?;
+        }
+        (FrameType::Ack2, Body::Ack2(ack2)) => {
+            ack2.write(writer)?;
+        }
+        (FrameType::SchemaRequest, Body::SchemaRequest(request)) => {
+            request.write(writer)?;
+        }
+        (FrameType::SchemaResponse, Body::SchemaResponse(response)) => {
+            response.write(writer)?;
+        }
+        (FrameType::Batch, Body::Batch(batch)) => {
+            batch.write(writer)?;
+        }
+        (FrameType::BatchLog, Body::BatchLog(batch_log)) => {
+            batch_log.write(writer)?;
+        }
+        (FrameType::Error, Body::Error(error)) => {
+            error.write(writer)?;
+        }
+        (FrameType::PageQuery, Body::PageQuery(query)) => {
+            query.write(writer)?;
+        }
         _ => return Err(io_error!("Invalid frame type")),
     }
     writer.flush()
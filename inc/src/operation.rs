@@ -0,0 +1,66 @@
+use std::io::{Read, Write};
+
+use db::TokenRange;
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+use crate::gossip::peer::Peer;
+
+/// An operation that changes the members of the ring, sent to the node that carries it out.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Operation {
+    /// Streams the data of the node to the nodes that are going to own its tokens, and leaves the ring.
+    Decommission,
+    /// Removes a dead node from the ring, given its address. The nodes that hold its data stream it to
+    /// the nodes that replace it as replicas.
+    RemoveNode(String),
+    /// Streams the data this node holds of a removed node to the nodes that replace it as replicas.
+    Restream(Peer),
+    /// Repairs the data of the ranges this node is a replica of, comparing the Merkle trees of the replicas.
+    Repair(RepairOptions),
+}
+
+/// What a `Repair` operation repairs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepairOptions {
+    /// The keyspace to repair, or `None` to repair every keyspace.
+    pub keyspace: Option<String>,
+    /// The table of the keyspace to repair, or `None` to repair all of them.
+    pub table: Option<String>,
+    /// The tokens to repair, or `None` to repair every range this node is a replica of.
+    pub range: Option<TokenRange>,
+    /// Whether to repair every row, instead of only the data received since the last repair.
+    pub full: bool,
+}
+
+/// The outcome of an `Operation`, sent once it is finished.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OperationResult {
+    pub error: Option<String>,
+}
+
+impl Operation {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let operation: Operation = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize Operation struct"))?;
+        Ok(operation)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize Operation struct"))
+    }
+}
+
+impl OperationResult {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let result: OperationResult = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize OperationResult struct"))?;
+        Ok(result)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize OperationResult struct"))
+    }
+}
@@ -4,7 +4,7 @@ use query::Query as Cql_Query;
 use serde::{Deserialize, Serialize};
 use shared::map_io_error;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Query {
     pub query: Cql_Query,
     pub table: String,
@@ -22,3 +22,27 @@ impl Query {
             .map_err(map_io_error!("Cannot serialize Query struct"))
     }
 }
+
+/// A `SELECT` that reads a page of a partition from a replica: only the stored rows after the position of
+/// the last row of the previous page, up to `page_size` of them, as `Query::read_stored_page` does.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PageQuery {
+    pub query: Cql_Query,
+    pub table: String,
+    /// The position of the last row of the previous page, or `None` for the first page.
+    pub start: Option<Vec<String>>,
+    pub page_size: usize,
+}
+
+impl PageQuery {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let query: PageQuery = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize PageQuery struct"))?;
+        Ok(query)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize PageQuery struct"))
+    }
+}
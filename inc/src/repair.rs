@@ -0,0 +1,92 @@
+use std::io::{Read, Write};
+
+use db::{MerkleTree, StoredRow, TokenRange};
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+/// Asks a replica for the Merkle trees of its rows of ranges of tokens of a table.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MerkleTreeRequest {
+    /// The name of the table, qualified with its keyspace as `<keyspace>.<table>`.
+    pub table: String,
+    pub ranges: Vec<TokenRange>,
+    /// The depth of the trees.
+    pub depth: u32,
+    /// For incremental repairs, the time of the last repair in microseconds since the epoch. Only the
+    /// data the replica received after it is hashed.
+    pub since: Option<i64>,
+}
+
+/// The Merkle trees of a `MerkleTreeRequest`, one for each of its ranges.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MerkleTreeResponse {
+    pub trees: Vec<MerkleTree>,
+}
+
+/// Asks a replica for its stored rows of ranges of tokens of a table, whose Merkle trees did not match
+/// the ones of the other replicas.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RangeRequest {
+    /// The name of the table, qualified with its keyspace as `<keyspace>.<table>`.
+    pub table: String,
+    pub ranges: Vec<TokenRange>,
+}
+
+/// The stored rows of a `RangeRequest`, with their write times and tombstones.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RangeResponse {
+    pub rows: Vec<StoredRow>,
+}
+
+impl MerkleTreeRequest {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let request: MerkleTreeRequest = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize MerkleTreeRequest struct"))?;
+        Ok(request)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize MerkleTreeRequest struct"))
+    }
+}
+
+impl MerkleTreeResponse {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let response: MerkleTreeResponse = bincode::deserialize_from(reader).map_err(
+            map_io_error!("Cannot deserialize MerkleTreeResponse struct"),
+        )?;
+        Ok(response)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize MerkleTreeResponse struct"))
+    }
+}
+
+impl RangeRequest {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let request: RangeRequest = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize RangeRequest struct"))?;
+        Ok(request)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize RangeRequest struct"))
+    }
+}
+
+impl RangeResponse {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let response: RangeResponse = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize RangeResponse struct"))?;
+        Ok(response)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize RangeResponse struct"))
+    }
+}
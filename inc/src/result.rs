@@ -1,11 +1,14 @@
 use std::io::{Read, Write};
 
+use db::StoredRow;
 use serde::{Deserialize, Serialize};
 use shared::map_io_error;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Result {
-    pub rows: Option<Vec<Vec<String>>>,
+    /// The stored rows of the partition read by a `SELECT`, with their write times and tombstones, so
+    /// the coordinator can reconcile the responses of the replicas.
+    pub rows: Option<Vec<StoredRow>>,
 }
 
 impl Result {
@@ -0,0 +1,43 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+use crate::query::Query;
+
+/// Asks a node for the schema changes it applied, when the schema versions of both nodes differ.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaRequest;
+
+/// The answer to a `SchemaRequest`: every DDL query the node applied, with the keyspace or table it was
+/// applied to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaResponse {
+    pub changes: Vec<Query>,
+}
+
+impl SchemaRequest {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let request: SchemaRequest = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize SchemaRequest struct"))?;
+        Ok(request)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize SchemaRequest struct"))
+    }
+}
+
+impl SchemaResponse {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let response: SchemaResponse = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize SchemaResponse struct"))?;
+        Ok(response)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize SchemaResponse struct"))
+    }
+}
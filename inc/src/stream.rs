@@ -0,0 +1,65 @@
+use std::io::{Read, Write};
+
+use db::{Options, Schema, StoredRow};
+use serde::{Deserialize, Serialize};
+use shared::map_io_error;
+
+/// A request of a joining node to a node of the ring.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum StreamRequest {
+    /// Asks for the keyspaces and tables of the node.
+    Schema,
+    /// Asks for the stored rows of a table whose tokens are in one of the ranges, given by their first and
+    /// last tokens.
+    Rows {
+        /// The name of the table, qualified with its keyspace as `<keyspace>.<table>`.
+        table: String,
+        ranges: Vec<(i64, i64)>,
+    },
+}
+
+/// The answer to a `StreamRequest`.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum StreamResponse {
+    Schema(Vec<StreamedKeyspace>),
+    Rows(Vec<StoredRow>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamedKeyspace {
+    pub name: String,
+    pub options: Options,
+    pub tables: Vec<StreamedTable>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamedTable {
+    pub name: String,
+    pub schema: Schema,
+}
+
+impl StreamRequest {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let request: StreamRequest = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize StreamRequest struct"))?;
+        Ok(request)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize StreamRequest struct"))
+    }
+}
+
+impl StreamResponse {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let response: StreamResponse = bincode::deserialize_from(reader)
+            .map_err(map_io_error!("Cannot deserialize StreamResponse struct"))?;
+        Ok(response)
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        bincode::serialize_into(writer, self)
+            .map_err(map_io_error!("Cannot serialize StreamResponse struct"))
+    }
+}
@@ -2,19 +2,26 @@ use std::{collections::HashMap, io::Read};
 
 use crate::native_protocol::{
     header::{Header, Opcode},
-    models::query::QueryMsg,
+    models::{execute::ExecuteMsg, query::QueryMsg},
     native::{Body, Frame},
     requests::request::Request,
 };
 
 use shared::io_error;
 
+pub use crate::native_protocol::models::batch::{BatchMsg, BatchQuery, BatchStatement};
 pub use crate::native_protocol::models::consistency::ConsistencyLevel;
+pub use crate::native_protocol::models::query_parameters::{BoundValue, QueryParameters};
 
 pub const STARTUP: Opcode = Opcode::Startup;
+pub const OPTIONS: Opcode = Opcode::Options;
 pub const QUERY: Opcode = Opcode::Query;
+pub const PREPARE: Opcode = Opcode::Prepare;
+pub const EXECUTE: Opcode = Opcode::Execute;
+pub const BATCH: Opcode = Opcode::Batch;
 pub const READY: Opcode = Opcode::Ready;
 pub const RESULT: Opcode = Opcode::ResultOP;
+pub const SUPPORTED: Opcode = Opcode::Supported;
 
 pub fn create_request(
     opcode: Opcode,
@@ -25,19 +32,57 @@ pub fn create_request(
     let header = Header::new(0x04, 0x00, stream, opcode.clone())?;
     let req = match opcode {
         QUERY => {
-            let query = QueryMsg::new(query.unwrap().to_owned(), consistency_level.unwrap(), 0x00)?;
-            Request::Query(query)
+            let query = QueryMsg::new(
+                query.unwrap().to_owned(),
+                QueryParameters::new(consistency_level.unwrap()),
+            )?;
+            Request::Query(Box::new(query))
         }
         STARTUP => Request::Startup(HashMap::from([(
             "CQL_VERSION".to_string(),
             "3.0.0".to_string(),
         )])),
+        OPTIONS => Request::Options,
+        PREPARE => Request::Prepare(query.unwrap().to_owned()),
         _ => return Err(io_error!(format!("Invalid opcode: {opcode}"))),
     };
     let body = Body::Request(req);
     Ok(Frame::new(header, body))
 }
 
+/// Creates a `QUERY` request, which runs the query with the parameters, binding their values to the
+/// markers of the query.
+pub fn create_query_request(
+    stream: u16,
+    query: &str,
+    parameters: QueryParameters,
+) -> std::io::Result<Frame> {
+    let header = Header::new(0x04, 0x00, stream, Opcode::Query)?;
+    let body = Body::Request(Request::Query(Box::new(QueryMsg::new(
+        query.to_owned(),
+        parameters,
+    )?)));
+    Ok(Frame::new(header, body))
+}
+
+/// Creates an `EXECUTE` request, which runs the query prepared with the id with the parameters.
+pub fn create_execute_request(
+    stream: u16,
+    id: Vec<u8>,
+    parameters: QueryParameters,
+) -> std::io::Result<Frame> {
+    let header = Header::new(0x04, 0x00, stream, Opcode::Execute)?;
+    let body = Body::Request(Request::Execute(ExecuteMsg { id, parameters }));
+    Ok(Frame::new(header, body))
+}
+
+/// Creates a `BATCH` request, which applies the statements of the batch together.
+pub fn create_batch_request(stream: u16, batch: BatchMsg) -> std::io::Result<Frame> {
+    let header = Header::new(0x04, 0x00, stream, Opcode::Batch)?;
+    let body = Body::Request(Request::Batch(batch));
+    Ok(Frame::new(header, body))
+}
+
 pub fn read_response<R: Read>(reader: &mut R) -> std::io::Result<Frame> {
     Frame::read(reader)
 }
@@ -32,6 +32,7 @@ pub enum Opcode {
     ResultOP = 0x08,
     Prepare = 0x09,
     Execute = 0x0A,
+    Batch = 0x0D,
     AuthChallenge = 0x0E,
     AuthResponse = 0x0F,
     AuthSuccess = 0x10,
@@ -50,6 +51,7 @@ impl Display for Opcode {
             Opcode::ResultOP => "Result",
             Opcode::Prepare => "Prepare",
             Opcode::Execute => "Execute",
+            Opcode::Batch => "Batch",
             Opcode::AuthChallenge => "AuthChallenge",
             Opcode::AuthResponse => "AuthResponse",
             Opcode::AuthSuccess => "AuthSuccess",
@@ -71,6 +73,7 @@ impl Opcode {
             0x08 => Ok(Opcode::ResultOP),
             0x09 => Ok(Opcode::Prepare),
             0x0A => Ok(Opcode::Execute),
+            0x0D => Ok(Opcode::Batch),
             0x0E => Ok(Opcode::AuthChallenge),
             0x0F => Ok(Opcode::AuthResponse),
             0x10 => Ok(Opcode::AuthSuccess),
@@ -93,6 +96,7 @@ impl Opcode {
             Opcode::ResultOP => [0x08],
             Opcode::Prepare => [0x09],
             Opcode::Execute => [0x0A],
+            Opcode::Batch => [0x0D],
             Opcode::AuthChallenge => [0x0E],
             Opcode::AuthResponse => [0x0F],
             Opcode::AuthSuccess => [0x10],
@@ -118,7 +122,7 @@ impl Header {
                 "Invalid version: expected 0x04 or 0x84, got {version}"
             )));
         }
-        let reqs = vec![
+        let reqs = [
             Opcode::Startup,
             Opcode::AuthResponse,
             Opcode::Options,
@@ -126,7 +130,7 @@ impl Header {
             Opcode::Prepare,
             Opcode::Execute,
         ];
-        let resp = vec![
+        let resp = [
             Opcode::Error,
             Opcode::Ready,
             Opcode::Authenticate,
@@ -170,7 +174,7 @@ impl Header {
 
         let opcode = Opcode::new(buffer[4])?;
 
-        let reqs = vec![
+        let reqs = [
             Opcode::Startup,
             Opcode::AuthResponse,
             Opcode::Options,
@@ -178,7 +182,7 @@ impl Header {
             Opcode::Prepare,
             Opcode::Execute,
         ];
-        let resp = vec![
+        let resp = [
             Opcode::Error,
             Opcode::Ready,
             Opcode::Authenticate,
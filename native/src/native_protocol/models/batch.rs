@@ -0,0 +1,47 @@
+use query::BatchType;
+
+use super::{consistency::ConsistencyLevel, query_parameters::BoundValue};
+
+/// The query of a statement of a `BATCH` message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchQuery {
+    /// A query, with bind markers in place of the values of the statement.
+    Query(String),
+    /// The id of a prepared query, as returned in the result of the `PREPARE`.
+    Prepared(Vec<u8>),
+}
+
+/// A statement of a `BATCH` message: a query and the values bound to its markers, in their order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchStatement {
+    pub query: BatchQuery,
+    pub values: Vec<BoundValue>,
+}
+
+/// A `BATCH` message: several writes applied together, run with the consistency level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchMsg {
+    pub batch_type: BatchType,
+    pub statements: Vec<BatchStatement>,
+    pub consistency: ConsistencyLevel,
+    pub serial_consistency: Option<ConsistencyLevel>,
+    /// The write time of the statements that do not set one, in microseconds since the epoch.
+    pub timestamp: Option<i64>,
+}
+
+impl BatchMsg {
+    /// Creates a batch of the statements, run with the consistency level.
+    pub fn new(
+        batch_type: BatchType,
+        statements: Vec<BatchStatement>,
+        consistency: ConsistencyLevel,
+    ) -> Self {
+        BatchMsg {
+            batch_type,
+            statements,
+            consistency,
+            serial_consistency: None,
+            timestamp: None,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use shared::io_error;
 
@@ -11,6 +11,11 @@ pub enum ConsistencyLevel {
     Three = 0x0003,
     Quorum = 0x0004,
     All = 0x0005,
+    LocalQuorum = 0x0006,
+    EachQuorum = 0x0007,
+    Serial = 0x0008,
+    LocalSerial = 0x0009,
+    LocalOne = 0x000A,
 }
 
 impl Display for ConsistencyLevel {
@@ -22,6 +27,32 @@ impl Display for ConsistencyLevel {
             ConsistencyLevel::Three => write!(f, "THREE"),
             ConsistencyLevel::Quorum => write!(f, "QUORUM"),
             ConsistencyLevel::All => write!(f, "ALL"),
+            ConsistencyLevel::LocalQuorum => write!(f, "LOCAL_QUORUM"),
+            ConsistencyLevel::EachQuorum => write!(f, "EACH_QUORUM"),
+            ConsistencyLevel::Serial => write!(f, "SERIAL"),
+            ConsistencyLevel::LocalSerial => write!(f, "LOCAL_SERIAL"),
+            ConsistencyLevel::LocalOne => write!(f, "LOCAL_ONE"),
+        }
+    }
+}
+
+impl FromStr for ConsistencyLevel {
+    type Err = std::io::Error;
+
+    fn from_str(consistency: &str) -> std::io::Result<Self> {
+        match consistency {
+            "ANY" => Ok(ConsistencyLevel::Any),
+            "ONE" => Ok(ConsistencyLevel::One),
+            "TWO" => Ok(ConsistencyLevel::Two),
+            "THREE" => Ok(ConsistencyLevel::Three),
+            "QUORUM" => Ok(ConsistencyLevel::Quorum),
+            "ALL" => Ok(ConsistencyLevel::All),
+            "LOCAL_QUORUM" => Ok(ConsistencyLevel::LocalQuorum),
+            "EACH_QUORUM" => Ok(ConsistencyLevel::EachQuorum),
+            "SERIAL" => Ok(ConsistencyLevel::Serial),
+            "LOCAL_SERIAL" => Ok(ConsistencyLevel::LocalSerial),
+            "LOCAL_ONE" => Ok(ConsistencyLevel::LocalOne),
+            _ => Err(io_error!("Invalid Consistency Level")),
         }
     }
 }
@@ -35,18 +66,11 @@ impl ConsistencyLevel {
             0x0003 => Ok(ConsistencyLevel::Three),
             0x0004 => Ok(ConsistencyLevel::Quorum),
             0x0005 => Ok(ConsistencyLevel::All),
-            _ => Err(io_error!("Invalid Consistency Level")),
-        }
-    }
-
-    pub fn from_str(consistency: &str) -> std::io::Result<Self> {
-        match consistency {
-            "ANY" => Ok(ConsistencyLevel::Any),
-            "ONE" => Ok(ConsistencyLevel::One),
-            "TWO" => Ok(ConsistencyLevel::Two),
-            "THREE" => Ok(ConsistencyLevel::Three),
-            "QUORUM" => Ok(ConsistencyLevel::Quorum),
-            "ALL" => Ok(ConsistencyLevel::All),
+            0x0006 => Ok(ConsistencyLevel::LocalQuorum),
+            0x0007 => Ok(ConsistencyLevel::EachQuorum),
+            0x0008 => Ok(ConsistencyLevel::Serial),
+            0x0009 => Ok(ConsistencyLevel::LocalSerial),
+            0x000A => Ok(ConsistencyLevel::LocalOne),
             _ => Err(io_error!("Invalid Consistency Level")),
         }
     }
@@ -59,6 +83,106 @@ impl ConsistencyLevel {
             ConsistencyLevel::Three => 0x0003,
             ConsistencyLevel::Quorum => 0x0004,
             ConsistencyLevel::All => 0x0005,
+            ConsistencyLevel::LocalQuorum => 0x0006,
+            ConsistencyLevel::EachQuorum => 0x0007,
+            ConsistencyLevel::Serial => 0x0008,
+            ConsistencyLevel::LocalSerial => 0x0009,
+            ConsistencyLevel::LocalOne => 0x000A,
+        }
+    }
+
+    /// Returns how many replicas must respond for the consistency level to be met.
+    ///
+    /// The levels that only count the replicas of the datacenter of the coordinator (see `is_local`) and
+    /// `EACH_QUORUM` require that many acks of the replicas of each datacenter they count.
+    ///
+    /// # Arguments
+    ///
+    /// * `replication_factor` - The number of replicas of the keyspace being queried, or of the
+    ///   datacenter whose acks are counted.
+    pub fn required_acks(&self, replication_factor: usize) -> usize {
+        match self {
+            ConsistencyLevel::Any => 0,
+            ConsistencyLevel::One | ConsistencyLevel::LocalOne => 1,
+            ConsistencyLevel::Two => 2,
+            ConsistencyLevel::Three => 3,
+            ConsistencyLevel::Quorum
+            | ConsistencyLevel::LocalQuorum
+            | ConsistencyLevel::EachQuorum
+            | ConsistencyLevel::Serial
+            | ConsistencyLevel::LocalSerial => replication_factor / 2 + 1,
+            ConsistencyLevel::All => replication_factor,
+        }
+    }
+
+    /// Whether the consistency level only counts the replicas of the datacenter of the coordinator.
+    ///
+    /// Keyspaces with `SimpleStrategy` place their replicas regardless of the datacenters, so these levels
+    /// count every replica of them, as `ONE`, `QUORUM` and `SERIAL` do.
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            ConsistencyLevel::LocalOne
+                | ConsistencyLevel::LocalQuorum
+                | ConsistencyLevel::LocalSerial
+        )
+    }
+
+    /// Whether the consistency level can only be used by writes, as `ANY`, which a hint alone meets, and
+    /// `EACH_QUORUM`.
+    pub fn is_write_only(&self) -> bool {
+        matches!(self, ConsistencyLevel::Any | ConsistencyLevel::EachQuorum)
+    }
+
+    /// Whether the consistency level is one of the serial ones, used by lightweight transactions.
+    pub fn is_serial(&self) -> bool {
+        matches!(
+            self,
+            ConsistencyLevel::Serial | ConsistencyLevel::LocalSerial
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_acks() {
+        assert_eq!(ConsistencyLevel::One.required_acks(1), 1);
+        assert_eq!(ConsistencyLevel::Quorum.required_acks(1), 1);
+        assert_eq!(ConsistencyLevel::All.required_acks(1), 1);
+        assert_eq!(ConsistencyLevel::Two.required_acks(1), 2);
+
+        assert_eq!(ConsistencyLevel::Quorum.required_acks(3), 2);
+        assert_eq!(ConsistencyLevel::All.required_acks(3), 3);
+
+        assert_eq!(ConsistencyLevel::Any.required_acks(5), 0);
+        assert_eq!(ConsistencyLevel::Quorum.required_acks(5), 3);
+        assert_eq!(ConsistencyLevel::All.required_acks(5), 5);
+
+        assert_eq!(ConsistencyLevel::LocalOne.required_acks(3), 1);
+        assert_eq!(ConsistencyLevel::LocalQuorum.required_acks(3), 2);
+        assert_eq!(ConsistencyLevel::EachQuorum.required_acks(2), 2);
+    }
+
+    #[test]
+    fn test_datacenter_levels_codes() {
+        for code in 0x0006..=0x000A {
+            let consistency = ConsistencyLevel::from_u16(code).unwrap();
+            assert_eq!(consistency.to_u16(), code);
+            assert_eq!(
+                consistency.to_string().parse::<ConsistencyLevel>().unwrap(),
+                consistency
+            );
         }
+        assert!(ConsistencyLevel::LocalQuorum.is_local());
+        assert!(!ConsistencyLevel::EachQuorum.is_local());
+        assert!(ConsistencyLevel::LocalSerial.is_serial());
+        assert!(ConsistencyLevel::EachQuorum.is_write_only());
+        assert!(ConsistencyLevel::Any.is_write_only());
+        assert!(!ConsistencyLevel::One.is_write_only());
+        assert!("LOCAL_TWO".parse::<ConsistencyLevel>().is_err());
+        assert!(ConsistencyLevel::from_u16(0x000B).is_err());
     }
 }
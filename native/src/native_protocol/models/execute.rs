@@ -0,0 +1,8 @@
+use super::query_parameters::QueryParameters;
+
+/// An `EXECUTE` message: the id of a prepared query, and the parameters to run it with.
+#[derive(Debug)]
+pub struct ExecuteMsg {
+    pub id: Vec<u8>,
+    pub parameters: QueryParameters,
+}
@@ -1,2 +1,5 @@
+pub mod batch;
 pub mod consistency;
+pub mod execute;
 pub mod query;
+pub mod query_parameters;
@@ -0,0 +1,347 @@
+use std::io::{Read, Write};
+
+use shared::io_error;
+
+use crate::native_protocol::parsers::string::{read_string, write_string};
+
+use super::consistency::ConsistencyLevel;
+
+/// A byte representing the possible flags of the parameters of a `QUERY` or `EXECUTE` message
+#[derive(Debug)]
+pub(crate) enum QueryFlagsMask {
+    Values = 0x01,
+    SkipMetadata = 0x02,
+    PageSize = 0x04,
+    WithPagingState = 0x08,
+    WithSerialConsistency = 0x10,
+    WithDefaultTimestamp = 0x20,
+    WithNamesForValues = 0x40,
+}
+
+/// A value bound to a query, with the name of its bind marker if it is sent with one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundValue {
+    pub name: Option<String>,
+    /// The serialized value, or `None` for a `NULL` value.
+    pub value: Option<Vec<u8>>,
+}
+
+/// The parameters of a `QUERY` or `EXECUTE` message. The format is:
+/// - consistency: u16 = consistency level (see `models/consistency.rs`)
+/// - flags: u8 = flags (see `QueryFlagsMask`)
+///
+/// Depending on the flags, the parameters may contain additional fields:
+/// - VALUES: <n><value_1>...<value_n>, where a value is an i32 length followed by the bytes, and a
+///   negative length is a `NULL` value
+/// - WITH_NAMES_FOR_VALUES: VALUES but with a string name before each value
+/// - PAGE_SIZE: <page_size> (4 bytes)
+/// - PAGING_STATE: <paging_state> (<n><byte_1>...<byte_n>)
+/// - SERIAL_CONSISTENCY: <serial_consistency> (2 bytes)
+/// - DEFAULT_TIMESTAMP: <timestamp> (8 bytes)
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryParameters {
+    pub consistency: ConsistencyLevel,
+    pub skip_metadata: bool,
+    pub values: Vec<BoundValue>,
+    pub page_size: Option<i32>,
+    pub paging_state: Option<Vec<u8>>,
+    pub serial_consistency: Option<ConsistencyLevel>,
+    pub timestamp: Option<i64>,
+}
+
+impl QueryParameters {
+    /// Creates the parameters of a query without values, run with the consistency level.
+    pub fn new(consistency: ConsistencyLevel) -> Self {
+        QueryParameters {
+            consistency,
+            skip_metadata: false,
+            values: Vec::new(),
+            page_size: None,
+            paging_state: None,
+            serial_consistency: None,
+            timestamp: None,
+        }
+    }
+
+    /// Returns the flags of the parameters, from the fields that are set.
+    pub fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if !self.values.is_empty() {
+            flags |= QueryFlagsMask::Values as u8;
+            if self.values.iter().all(|value| value.name.is_some()) {
+                flags |= QueryFlagsMask::WithNamesForValues as u8;
+            }
+        }
+        if self.skip_metadata {
+            flags |= QueryFlagsMask::SkipMetadata as u8;
+        }
+        if self.page_size.is_some() {
+            flags |= QueryFlagsMask::PageSize as u8;
+        }
+        if self.paging_state.is_some() {
+            flags |= QueryFlagsMask::WithPagingState as u8;
+        }
+        if self.serial_consistency.is_some() {
+            flags |= QueryFlagsMask::WithSerialConsistency as u8;
+        }
+        if self.timestamp.is_some() {
+            flags |= QueryFlagsMask::WithDefaultTimestamp as u8;
+        }
+        flags
+    }
+
+    /// Reads the parameters from the reader.
+    ///
+    /// # Returns
+    /// A tuple containing the parameters and the number of bytes read from the reader.
+    pub(crate) fn read<R: Read>(reader: &mut R) -> std::io::Result<(Self, u32)> {
+        let mut short_buffer = [0u8; 2];
+        let mut int_buffer = [0u8; 4];
+        reader.read_exact(&mut short_buffer)?;
+        let consistency = ConsistencyLevel::from_u16(u16::from_be_bytes(short_buffer))?;
+
+        let mut flags_buffer = [0u8; 1];
+        reader.read_exact(&mut flags_buffer)?;
+        let flags = flags_buffer[0];
+        let has = |mask: QueryFlagsMask| flags & mask as u8 != 0;
+        let mut bytes_read = 3;
+
+        let mut parameters = QueryParameters::new(consistency);
+        parameters.skip_metadata = has(QueryFlagsMask::SkipMetadata);
+        if has(QueryFlagsMask::Values) {
+            let (values, read) = read_values(reader, has(QueryFlagsMask::WithNamesForValues))?;
+            parameters.values = values;
+            bytes_read += read;
+        }
+        if has(QueryFlagsMask::PageSize) {
+            reader.read_exact(&mut int_buffer)?;
+            bytes_read += 4;
+            parameters.page_size = Some(i32::from_be_bytes(int_buffer));
+        }
+        if has(QueryFlagsMask::WithPagingState) {
+            reader.read_exact(&mut int_buffer)?;
+            let length = i32::from_be_bytes(int_buffer);
+            if length < 0 {
+                return Err(io_error!("Invalid paging state length"));
+            }
+            let paging_state = read_bytes(reader, length as u32)?;
+            bytes_read += 4 + length as u32;
+            parameters.paging_state = Some(paging_state);
+        }
+        if has(QueryFlagsMask::WithSerialConsistency) {
+            reader.read_exact(&mut short_buffer)?;
+            bytes_read += 2;
+            parameters.serial_consistency = Some(ConsistencyLevel::from_u16(u16::from_be_bytes(
+                short_buffer,
+            ))?);
+        }
+        if has(QueryFlagsMask::WithDefaultTimestamp) {
+            let mut long_buffer = [0u8; 8];
+            reader.read_exact(&mut long_buffer)?;
+            bytes_read += 8;
+            parameters.timestamp = Some(i64::from_be_bytes(long_buffer));
+        }
+        Ok((parameters, bytes_read))
+    }
+
+    /// Writes the parameters to the writer. The values are only written with their names if all of them
+    /// have one.
+    ///
+    /// # Returns
+    /// The number of bytes written to the writer.
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<u32> {
+        let flags = self.flags();
+        writer.write_all(&(self.consistency.clone() as u16).to_be_bytes())?;
+        writer.write_all(&[flags])?;
+        let mut written = 3;
+        if !self.values.is_empty() {
+            let named = flags & QueryFlagsMask::WithNamesForValues as u8 != 0;
+            written += write_values(writer, &self.values, named)?;
+        }
+        if let Some(page_size) = self.page_size {
+            writer.write_all(&page_size.to_be_bytes())?;
+            written += 4;
+        }
+        if let Some(paging_state) = &self.paging_state {
+            writer.write_all(&(paging_state.len() as i32).to_be_bytes())?;
+            writer.write_all(paging_state)?;
+            written += 4 + paging_state.len() as u32;
+        }
+        if let Some(serial_consistency) = &self.serial_consistency {
+            writer.write_all(&(serial_consistency.clone() as u16).to_be_bytes())?;
+            written += 2;
+        }
+        if let Some(timestamp) = self.timestamp {
+            writer.write_all(&timestamp.to_be_bytes())?;
+            written += 8;
+        }
+        Ok(written)
+    }
+}
+
+/// Reads the values bound to a query. The format is:
+/// - n: u16 = number of values
+/// - n values, each a string name if they are named, and an i32 length followed by the bytes, where a
+///   negative length is a `NULL` value
+///
+/// # Returns
+/// A tuple containing the values and the number of bytes read from the reader.
+pub(crate) fn read_values<R: Read>(
+    reader: &mut R,
+    named: bool,
+) -> std::io::Result<(Vec<BoundValue>, u32)> {
+    let mut short_buffer = [0u8; 2];
+    let mut int_buffer = [0u8; 4];
+    reader.read_exact(&mut short_buffer)?;
+    let mut bytes_read = 2;
+    let mut values = Vec::new();
+    for _ in 0..u16::from_be_bytes(short_buffer) {
+        let name = if named {
+            let (name, read) = read_string(reader)?;
+            bytes_read += read;
+            Some(name)
+        } else {
+            None
+        };
+        reader.read_exact(&mut int_buffer)?;
+        bytes_read += 4;
+        // A negative length is a `NULL` value, or a value that is not set, taken as `NULL` too.
+        let length = i32::from_be_bytes(int_buffer);
+        let value = if length < 0 {
+            None
+        } else {
+            let value = read_bytes(reader, length as u32)?;
+            bytes_read += length as u32;
+            Some(value)
+        };
+        values.push(BoundValue { name, value });
+    }
+    Ok((values, bytes_read))
+}
+
+/// Reads the given number of bytes. The buffer grows as the bytes are read, so a length sent by a client
+/// that is larger than its frame is an error rather than an allocation of that size.
+fn read_bytes<R: Read>(reader: &mut R, length: u32) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length as usize {
+        return Err(io_error!("Length exceeds the frame body"));
+    }
+    Ok(bytes)
+}
+
+/// Writes the values bound to a query, in the format read by `read_values`.
+///
+/// # Returns
+/// The number of bytes written to the writer.
+pub(crate) fn write_values<W: Write>(
+    writer: &mut W,
+    values: &[BoundValue],
+    named: bool,
+) -> std::io::Result<u32> {
+    writer.write_all(&(values.len() as u16).to_be_bytes())?;
+    let mut written = 2;
+    for value in values {
+        if named {
+            written += write_string(writer, value.name.as_deref().unwrap_or_default())?;
+        }
+        match &value.value {
+            Some(bytes) => {
+                writer.write_all(&(bytes.len() as i32).to_be_bytes())?;
+                writer.write_all(bytes)?;
+                written += 4 + bytes.len() as u32;
+            }
+            None => {
+                writer.write_all(&(-1i32).to_be_bytes())?;
+                written += 4;
+            }
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_read_and_write_parameters_without_values() {
+        let parameters = QueryParameters::new(ConsistencyLevel::Quorum);
+        let mut buffer = Vec::new();
+        assert_eq!(parameters.write(&mut buffer).unwrap(), 3);
+        assert_eq!(buffer, vec![0x00, 0x04, 0x00]);
+
+        let (read, bytes_read) = QueryParameters::read(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(read, parameters);
+        assert_eq!(bytes_read, 3);
+    }
+
+    #[test]
+    fn test_read_and_write_parameters_with_every_field() {
+        let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+        parameters.skip_metadata = true;
+        parameters.values = vec![
+            BoundValue {
+                name: Some("id".to_string()),
+                value: Some(vec![0x00, 0x00, 0x00, 0x01]),
+            },
+            BoundValue {
+                name: Some("name".to_string()),
+                value: None,
+            },
+        ];
+        parameters.page_size = Some(100);
+        parameters.paging_state = Some(vec![0x01, 0x02]);
+        parameters.serial_consistency = Some(ConsistencyLevel::LocalSerial);
+        parameters.timestamp = Some(1700000000000000);
+        assert_eq!(parameters.flags(), 0x7F);
+
+        let mut buffer = Vec::new();
+        let written = parameters.write(&mut buffer).unwrap();
+        assert_eq!(written, buffer.len() as u32);
+
+        let (read, bytes_read) = QueryParameters::read(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(read, parameters);
+        assert_eq!(bytes_read, written);
+    }
+
+    #[test]
+    fn test_lengths_beyond_the_body_are_an_error() {
+        let paging_state = vec![
+            0x00, 0x01, // consistency
+            0x08, // flags: paging state
+            0x7F, 0xFF, 0xFF, 0xFF, // length
+            0x01,
+        ];
+        assert!(QueryParameters::read(&mut Cursor::new(paging_state)).is_err());
+
+        let value = vec![
+            0x00, 0x01, // consistency
+            0x01, // flags: values
+            0x00, 0x01, // one value
+            0x7F, 0xFF, 0xFF, 0xFF, // length
+            0x01,
+        ];
+        assert!(QueryParameters::read(&mut Cursor::new(value)).is_err());
+    }
+
+    #[test]
+    fn test_read_unset_value_as_null() {
+        let buffer = vec![
+            0x00, 0x01, // consistency
+            0x01, // flags: values
+            0x00, 0x01, // one value
+            0xFF, 0xFF, 0xFF, 0xFE, // not set
+        ];
+        let (read, _) = QueryParameters::read(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(
+            read.values,
+            vec![BoundValue {
+                name: None,
+                value: None
+            }]
+        );
+    }
+}
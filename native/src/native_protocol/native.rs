@@ -1,14 +1,18 @@
-use std::io::{Read, Write};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
 
-use query::Query;
+use query::{BindMarker, Query};
 use shared::io_error;
 
 use crate::client::ConsistencyLevel;
 
 use super::{
     header::{Header, Opcode},
+    models::{batch::BatchMsg, execute::ExecuteMsg, query_parameters::QueryParameters},
     requests::request::Request,
-    responses::response::Response,
+    responses::{response::Response, result_op::Prepared},
 };
 
 #[derive(Debug)]
@@ -39,6 +43,30 @@ impl Body {
         }
     }
 
+    /// Returns the id and the parameters of the prepared query to run, if the body is an `EXECUTE` request.
+    pub fn get_execute(&self) -> Option<&ExecuteMsg> {
+        match self {
+            Body::Request(request) => request.get_execute(),
+            Body::Response(_) => None,
+        }
+    }
+
+    /// Returns the statements of the batch to apply and how to apply them, if the body is a `BATCH`
+    /// request.
+    pub fn get_batch(&self) -> Option<&BatchMsg> {
+        match self {
+            Body::Request(request) => request.get_batch(),
+            Body::Response(_) => None,
+        }
+    }
+
+    pub fn get_startup_options(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            Body::Request(request) => request.get_startup_options(),
+            Body::Response(_) => None,
+        }
+    }
+
     pub fn get_rows(&self) -> Option<Vec<Vec<String>>> {
         match self {
             Body::Request(_) => None,
@@ -46,6 +74,32 @@ impl Body {
         }
     }
 
+    /// Returns the paging state to request the next page of rows with, if the body is a page of rows and
+    /// there are more.
+    pub fn get_paging_state(&self) -> Option<&[u8]> {
+        match self {
+            Body::Request(_) => None,
+            Body::Response(response) => response.get_paging_state(),
+        }
+    }
+
+    /// Returns the id and the metadata of a prepared query, if the body is the result of a `PREPARE`.
+    pub fn get_prepared(&self) -> Option<&Prepared> {
+        match self {
+            Body::Request(_) => None,
+            Body::Response(response) => response.get_prepared(),
+        }
+    }
+
+    /// Returns the values the server supports for each option of a `STARTUP` message, if the body is a
+    /// `SUPPORTED` response.
+    pub fn get_supported_options(&self) -> Option<&HashMap<String, Vec<String>>> {
+        match self {
+            Body::Request(_) => None,
+            Body::Response(response) => response.get_supported_options(),
+        }
+    }
+
     pub fn get_error(&self) -> Option<&str> {
         match self {
             Body::Request(_) => None,
@@ -59,6 +113,24 @@ impl Body {
             Body::Response(_) => None,
         }
     }
+
+    /// Returns the consistency, flags and values of the query, if the body is a `QUERY` or an `EXECUTE`
+    /// request.
+    pub fn get_parameters(&self) -> Option<&QueryParameters> {
+        match self {
+            Body::Request(request) => request.get_parameters(),
+            Body::Response(_) => None,
+        }
+    }
+
+    /// Returns the bind markers of the query, which the values of its parameters are bound to, if the body
+    /// is a `QUERY` request.
+    pub fn get_bind_markers(&self) -> Option<&[BindMarker]> {
+        match self {
+            Body::Request(request) => request.get_bind_markers(),
+            Body::Response(_) => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -74,6 +146,14 @@ impl Frame {
 
     pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
         let header = Header::read_header(reader)?;
+        Self::read_body(reader, header)
+    }
+
+    /// Reads the body of a frame whose header has already been read.
+    ///
+    /// The whole body is consumed from the reader even if it cannot be processed,
+    /// so the next frame of the connection can still be read.
+    pub fn read_body<R: Read>(reader: &mut R, header: Header) -> std::io::Result<Self> {
         let mut length_buffer = [0u8; 4];
         reader.read_exact(&mut length_buffer)?;
         let length = u32::from_be_bytes(length_buffer);
@@ -82,13 +162,19 @@ impl Frame {
             return Err(io_error!("Frame body too large"));
         }
         let body: Body = match header.opcode {
-            Opcode::Startup | Opcode::Query => {
-                Body::Request(Request::read(reader, &header.opcode, length)?)
-            }
-            Opcode::Error | Opcode::Ready | Opcode::ResultOP => {
+            Opcode::Startup
+            | Opcode::Options
+            | Opcode::Query
+            | Opcode::Prepare
+            | Opcode::Execute
+            | Opcode::Batch => Body::Request(Request::read(reader, &header.opcode, length)?),
+            Opcode::Error | Opcode::Ready | Opcode::ResultOP | Opcode::Supported => {
                 Body::Response(Response::read(reader, &header.opcode, length)?)
             }
-            _ => return Err(io_error!(format!("Invalid opcode: {}", header.opcode))),
+            _ => {
+                std::io::copy(&mut reader.take(length as u64), &mut std::io::sink())?;
+                return Err(io_error!(format!("Invalid opcode: {}", header.opcode)));
+            }
         };
         Ok(Frame { header, body })
     }
@@ -110,7 +196,11 @@ impl Frame {
 mod tests {
     use std::{collections::HashMap, io::Cursor};
 
-    use crate::native_protocol::models::query::QueryMsg;
+    use crate::native_protocol::models::{
+        consistency::ConsistencyLevel,
+        query::QueryMsg,
+        query_parameters::{BoundValue, QueryParameters},
+    };
 
     use super::*;
 
@@ -144,14 +234,13 @@ mod tests {
     fn test_read_and_write_frame_query() {
         let frame = Frame::new(
             Header::new(0x04, 0x00, 1234, Opcode::Query).unwrap(),
-            Body::Request(Request::Query(
+            Body::Request(Request::Query(Box::new(
                 QueryMsg::new(
                     "SELECT * FROM table WHERE id = 1".to_string(),
-                    crate::native_protocol::models::consistency::ConsistencyLevel::Three,
-                    0,
+                    QueryParameters::new(ConsistencyLevel::Three),
                 )
                 .unwrap(),
-            )),
+            ))),
         );
 
         let mut buffer = Vec::new();
@@ -165,11 +254,8 @@ mod tests {
         assert_eq!(result.header.opcode, Opcode::Query);
         if let Body::Request(Request::Query(query_msg)) = result.body {
             assert_eq!(query_msg.query_str, "SELECT * FROM table WHERE id = 1");
-            assert_eq!(
-                query_msg.consistency,
-                crate::native_protocol::models::consistency::ConsistencyLevel::Three
-            );
-            assert_eq!(query_msg.flags, 0);
+            assert_eq!(query_msg.parameters.consistency, ConsistencyLevel::Three);
+            assert!(query_msg.markers.is_empty());
             assert_eq!(
                 query_msg.query.get_keys(),
                 vec![("id".to_string(), "1".to_string())]
@@ -179,6 +265,145 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_read_and_write_frame_query_with_values() {
+        let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+        parameters.values = vec![
+            BoundValue {
+                name: Some("name".to_string()),
+                value: Some(b"a, 'b' WHERE".to_vec()),
+            },
+            BoundValue {
+                name: Some("id".to_string()),
+                value: Some(vec![0x00, 0x00, 0x00, 0x01]),
+            },
+        ];
+        let mut buffer = Vec::new();
+        Frame::new(
+            Header::new(0x04, 0x00, 1, Opcode::Query).unwrap(),
+            Body::Request(Request::Query(Box::new(
+                QueryMsg::new(
+                    "UPDATE users SET name = :name WHERE id = :id".to_string(),
+                    parameters.clone(),
+                )
+                .unwrap(),
+            ))),
+        )
+        .write(&mut buffer)
+        .unwrap();
+
+        let frame = Frame::read(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(frame.body.get_parameters(), Some(&parameters));
+        let markers = frame.body.get_bind_markers().unwrap();
+        assert_eq!(
+            markers
+                .iter()
+                .map(|marker| (marker.name.as_deref(), marker.column.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(Some("name"), "name"), (Some("id"), "id")]
+        );
+    }
+
+    #[test]
+    fn test_read_and_write_frame_options_and_supported() {
+        let mut buffer = Vec::new();
+        Frame::new(
+            Header::new(0x04, 0x00, 1, Opcode::Options).unwrap(),
+            Body::Request(Request::Options),
+        )
+        .write(&mut buffer)
+        .unwrap();
+        Frame::new(
+            Header::new(0x84, 0x00, 1, Opcode::Supported).unwrap(),
+            Body::Response(Response::Supported(HashMap::from([(
+                "CQL_VERSION".to_string(),
+                vec!["3.0.0".to_string()],
+            )]))),
+        )
+        .write(&mut buffer)
+        .unwrap();
+
+        let mut cursor = Cursor::new(buffer);
+        let options = Frame::read(&mut cursor).unwrap();
+        assert_eq!(options.header.opcode, Opcode::Options);
+        assert!(matches!(options.body, Body::Request(Request::Options)));
+        let supported = Frame::read(&mut cursor).unwrap();
+        assert_eq!(supported.header.opcode, Opcode::Supported);
+        assert_eq!(
+            supported.body.get_supported_options(),
+            Some(&HashMap::from([(
+                "CQL_VERSION".to_string(),
+                vec!["3.0.0".to_string()]
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_read_and_write_frame_prepare_and_execute() {
+        let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+        parameters.values.push(BoundValue {
+            name: None,
+            value: Some(1i32.to_be_bytes().to_vec()),
+        });
+        let mut buffer = Vec::new();
+        Frame::new(
+            Header::new(0x04, 0x00, 1, Opcode::Prepare).unwrap(),
+            Body::Request(Request::Prepare(
+                "SELECT * FROM table WHERE id = ?".to_string(),
+            )),
+        )
+        .write(&mut buffer)
+        .unwrap();
+        Frame::new(
+            Header::new(0x04, 0x00, 2, Opcode::Execute).unwrap(),
+            Body::Request(Request::Execute(ExecuteMsg {
+                id: vec![0x01; 16],
+                parameters: parameters.clone(),
+            })),
+        )
+        .write(&mut buffer)
+        .unwrap();
+
+        let mut cursor = Cursor::new(buffer);
+        let prepare = Frame::read(&mut cursor).unwrap();
+        assert_eq!(prepare.header.opcode, Opcode::Prepare);
+        assert_eq!(
+            prepare.body.get_query_str(),
+            Some("SELECT * FROM table WHERE id = ?".to_string())
+        );
+        let execute = Frame::read(&mut cursor).unwrap();
+        assert_eq!(execute.header.opcode, Opcode::Execute);
+        let execute = execute.body.get_execute().unwrap();
+        assert_eq!(execute.id, vec![0x01; 16]);
+        assert_eq!(execute.parameters, parameters);
+    }
+
+    #[test]
+    fn test_read_unsupported_frame_consumes_body() {
+        let mut buffer = Vec::new();
+        Header::new(0x04, 0x00, 1, Opcode::AuthResponse)
+            .unwrap()
+            .write_header(&mut buffer)
+            .unwrap();
+        buffer.extend(3u32.to_be_bytes());
+        buffer.extend([0x01, 0x02, 0x03]);
+        Frame::new(
+            Header::new(0x04, 0x00, 2, Opcode::Startup).unwrap(),
+            Body::Request(Request::Startup(HashMap::from([(
+                "CQL_VERSION".to_string(),
+                "3.0.0".to_string(),
+            )]))),
+        )
+        .write(&mut buffer)
+        .unwrap();
+
+        let mut cursor = Cursor::new(buffer);
+        assert!(Frame::read(&mut cursor).is_err());
+        let result = Frame::read(&mut cursor).unwrap();
+        assert_eq!(result.header.stream, 2);
+        assert_eq!(result.header.opcode, Opcode::Startup);
+    }
+
     // #[test]
     // fn test_read_frame_invalid_body() {
     //     let frame = new_frame(
@@ -17,7 +17,7 @@ pub fn read_long_string<R: Read>(reader: &mut R) -> std::io::Result<(String, u32
     Ok((
         String::from_utf8(string_bytes)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
-        4 + length as u32,
+        4 + length,
     ))
 }
 
@@ -1,4 +1,6 @@
 pub mod bytes;
 pub mod long_string;
+pub mod short_bytes;
 pub mod string;
 pub mod string_map;
+pub mod string_multimap;
@@ -0,0 +1,57 @@
+use std::io::{Read, Write};
+
+/// Read short bytes from a reader. The format is:
+/// - n: u16 = number of bytes
+/// - n bytes
+///
+/// # Returns
+/// A tuple containing the bytes and the number of bytes read from the reader.
+pub fn read_short_bytes<R: Read>(reader: &mut R) -> std::io::Result<(Vec<u8>, u32)> {
+    let mut buffer = [0u8; 2];
+    reader.read_exact(&mut buffer)?;
+    let length = u16::from_be_bytes(buffer);
+
+    let mut bytes = vec![0u8; length as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok((bytes, 2 + length as u32))
+}
+
+/// Write short bytes to a writer. The format in which is written is:
+/// - n: u16 = number of bytes *(2 bytes)*
+/// - *n bytes*
+///
+/// # Returns
+/// The number of bytes written to the writer.
+pub fn write_short_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> std::io::Result<u32> {
+    writer.write_all(&(bytes.len() as u16).to_be_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(2 + bytes.len() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_short_bytes() {
+        let mut input = std::io::Cursor::new(&[0x00, 0x03, 0x01, 0x02, 0x03]);
+        let (bytes, read) = read_short_bytes(&mut input).unwrap();
+        assert_eq!(bytes, vec![0x01, 0x02, 0x03]);
+        assert_eq!(read, 5);
+    }
+
+    #[test]
+    fn test_write_short_bytes() {
+        let mut output = Vec::new();
+        let written = write_short_bytes(&mut output, &[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(output, vec![0x00, 0x03, 0x01, 0x02, 0x03]);
+        assert_eq!(written, 5);
+    }
+
+    #[test]
+    fn test_read_short_bytes_too_short() {
+        let mut input = std::io::Cursor::new(&[0x00, 0x03, 0x01]);
+        assert!(read_short_bytes(&mut input).is_err());
+    }
+}
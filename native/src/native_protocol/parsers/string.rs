@@ -84,7 +84,7 @@ mod tests {
 
         let mut input = std::io::Cursor::new(input_data);
         let (string, read) = read_string(&mut input).unwrap();
-        assert_eq!(read, 0xFFFF as u32 + 2);
+        assert_eq!(read, 0xFFFF_u32 + 2);
         assert_eq!(string.len(), 0xFFFF);
     }
 
@@ -94,6 +94,6 @@ mod tests {
         let mut output = Vec::new();
         let written = write_string(&mut output, &string).unwrap();
         assert_eq!(output.len(), 0xFFFF + 2);
-        assert_eq!(written, 0xFFFF as u32 + 2);
+        assert_eq!(written, 0xFFFF_u32 + 2);
     }
 }
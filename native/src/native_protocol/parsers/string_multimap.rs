@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use super::string::{read_string, write_string};
+
+/// Read a multimap of strings from a reader. The format is:
+/// - n: u16 = number of key-value pairs
+/// - For each key-value pair:
+///     - key: string
+///     - value: string list
+///         - m: u16 = number of strings
+///         - m strings
+///
+/// # Returns
+/// A tuple containing the multimap and the number of bytes read from the reader.
+pub fn read_string_multimap<R: Read>(
+    reader: &mut R,
+) -> std::io::Result<(HashMap<String, Vec<String>>, u32)> {
+    let mut buffer = [0u8; 2];
+    reader.read_exact(&mut buffer)?;
+    let length = u16::from_be_bytes(buffer);
+    let mut bytes_read = 2u32;
+    let mut map = HashMap::new();
+    for _ in 0..length {
+        let (key, read) = read_string(reader)?;
+        bytes_read += read;
+        reader.read_exact(&mut buffer)?;
+        bytes_read += 2;
+        let mut values = Vec::new();
+        for _ in 0..u16::from_be_bytes(buffer) {
+            let (value, read) = read_string(reader)?;
+            bytes_read += read;
+            values.push(value);
+        }
+        map.insert(key, values);
+    }
+    Ok((map, bytes_read))
+}
+
+/// Write a multimap of strings to a writer. The format in which is written is:
+/// - n: u16 = number of key-value pairs *(2 bytes)*
+/// - For each key-value pair:
+///     - key: string *(see `write_string`)*
+///     - value: string list
+///         - m: u16 = number of strings *(2 bytes)*
+///         - m strings *(see `write_string`)*
+///
+/// # Returns
+/// The number of bytes written to the writer.
+pub fn write_string_multimap<W: Write>(
+    writer: &mut W,
+    map: &HashMap<String, Vec<String>>,
+) -> std::io::Result<u32> {
+    writer.write_all(&(map.len() as u16).to_be_bytes())?;
+    let mut bytes_written = 2u32;
+    for (key, values) in map {
+        bytes_written += write_string(writer, key)?;
+        writer.write_all(&(values.len() as u16).to_be_bytes())?;
+        bytes_written += 2;
+        for value in values {
+            bytes_written += write_string(writer, value)?;
+        }
+    }
+    Ok(bytes_written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_string_multimap() {
+        let mut input = std::io::Cursor::new(&[
+            0x00, 0x01, // n = 1
+            0x00, 0x03, b'f', b'o', b'o', // key = "foo"
+            0x00, 0x02, // m = 2
+            0x00, 0x03, b'b', b'a', b'r', // "bar"
+            0x00, 0x03, b'b', b'a', b'z', // "baz"
+        ]);
+        let (map, read) = read_string_multimap(&mut input).unwrap();
+        assert_eq!(read, 19);
+        assert_eq!(
+            map,
+            HashMap::from([(
+                "foo".to_string(),
+                vec!["bar".to_string(), "baz".to_string()]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_write_string_multimap_empty_list() {
+        let map = HashMap::from([("foo".to_string(), Vec::new())]);
+        let mut output = Vec::new();
+        let written = write_string_multimap(&mut output, &map).unwrap();
+        assert_eq!(written, 9);
+        assert_eq!(
+            output,
+            vec![0x00, 0x01, 0x00, 0x03, b'f', b'o', b'o', 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_read_and_write_string_multimap() {
+        let map = HashMap::from([
+            ("CQL_VERSION".to_string(), vec!["3.0.0".to_string()]),
+            ("COMPRESSION".to_string(), Vec::new()),
+        ]);
+        let mut output = Vec::new();
+        let written = write_string_multimap(&mut output, &map).unwrap();
+
+        let mut input = std::io::Cursor::new(&output);
+        let (read_map, read) = read_string_multimap(&mut input).unwrap();
+
+        assert_eq!(written, read);
+        assert_eq!(map, read_map);
+    }
+}
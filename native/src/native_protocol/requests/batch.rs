@@ -0,0 +1,171 @@
+use std::io::{Cursor, Read, Write};
+
+use query::BatchType;
+use shared::io_error;
+
+use crate::native_protocol::{
+    models::{
+        batch::{BatchMsg, BatchQuery, BatchStatement},
+        consistency::ConsistencyLevel,
+        query_parameters::{read_values, write_values, QueryFlagsMask},
+    },
+    parsers::{
+        long_string::{read_long_string, write_long_string},
+        short_bytes::{read_short_bytes, write_short_bytes},
+    },
+};
+
+/// Reads a batch body from the provided reader.
+///
+/// The format of the body is:
+/// - type: u8 = 0 for a logged batch, 1 for an unlogged one and 2 for a counter one
+/// - n: u16 = number of statements
+/// - n statements, each:
+///   - kind: u8 = 0 if it is a query, 1 if it is the id of a prepared one
+///   - query: [long_string] or id: [short bytes]
+///   - values: the values bound to the statement (see `models/query_parameters.rs`), without names
+/// - consistency: u16 = consistency level (see `models/consistency.rs`)
+/// - flags: u8 = flags (see `QueryFlagsMask`), where only `WithSerialConsistency` and
+///   `WithDefaultTimestamp` apply to a batch
+/// - serial_consistency: u16, if the flag is set
+/// - timestamp: i64, if the flag is set
+pub(crate) fn read_batch<R: Read>(reader: &mut R, length: u32) -> std::io::Result<BatchMsg> {
+    let mut buffer = vec![0; length as usize];
+    reader.read_exact(&mut buffer)?;
+    let mut cursor = Cursor::new(buffer);
+    let mut byte_buffer = [0u8; 1];
+    let mut short_buffer = [0u8; 2];
+
+    cursor.read_exact(&mut byte_buffer)?;
+    let batch_type = match byte_buffer[0] {
+        0 => BatchType::Logged,
+        1 => BatchType::Unlogged,
+        2 => BatchType::Counter,
+        batch_type => return Err(io_error!(format!("Invalid batch type: {batch_type}"))),
+    };
+    cursor.read_exact(&mut short_buffer)?;
+    let mut statements = Vec::new();
+    for _ in 0..u16::from_be_bytes(short_buffer) {
+        cursor.read_exact(&mut byte_buffer)?;
+        let query = match byte_buffer[0] {
+            0 => BatchQuery::Query(read_long_string(&mut cursor)?.0),
+            1 => BatchQuery::Prepared(read_short_bytes(&mut cursor)?.0),
+            kind => return Err(io_error!(format!("Invalid batch statement kind: {kind}"))),
+        };
+        let (values, _) = read_values(&mut cursor, false)?;
+        statements.push(BatchStatement { query, values });
+    }
+
+    cursor.read_exact(&mut short_buffer)?;
+    let consistency = ConsistencyLevel::from_u16(u16::from_be_bytes(short_buffer))?;
+    let mut batch = BatchMsg::new(batch_type, statements, consistency);
+    cursor.read_exact(&mut byte_buffer)?;
+    let flags = byte_buffer[0];
+    if flags & QueryFlagsMask::WithSerialConsistency as u8 != 0 {
+        cursor.read_exact(&mut short_buffer)?;
+        batch.serial_consistency = Some(ConsistencyLevel::from_u16(u16::from_be_bytes(
+            short_buffer,
+        ))?);
+    }
+    if flags & QueryFlagsMask::WithDefaultTimestamp as u8 != 0 {
+        let mut long_buffer = [0u8; 8];
+        cursor.read_exact(&mut long_buffer)?;
+        batch.timestamp = Some(i64::from_be_bytes(long_buffer));
+    }
+    if cursor.position() != length as u64 {
+        return Err(io_error!("Body length does not match the frame length"));
+    }
+    Ok(batch)
+}
+
+pub(crate) fn write_batch<W: Write>(writer: &mut W, batch: &BatchMsg) -> std::io::Result<u32> {
+    let batch_type: u8 = match batch.batch_type {
+        BatchType::Logged => 0,
+        BatchType::Unlogged => 1,
+        BatchType::Counter => 2,
+    };
+    writer.write_all(&[batch_type])?;
+    writer.write_all(&(batch.statements.len() as u16).to_be_bytes())?;
+    let mut written = 3;
+    for statement in &batch.statements {
+        written += 1 + match &statement.query {
+            BatchQuery::Query(query) => {
+                writer.write_all(&[0])?;
+                write_long_string(writer, query)?
+            }
+            BatchQuery::Prepared(id) => {
+                writer.write_all(&[1])?;
+                write_short_bytes(writer, id)?
+            }
+        };
+        written += write_values(writer, &statement.values, false)?;
+    }
+
+    let mut flags = 0;
+    if batch.serial_consistency.is_some() {
+        flags |= QueryFlagsMask::WithSerialConsistency as u8;
+    }
+    if batch.timestamp.is_some() {
+        flags |= QueryFlagsMask::WithDefaultTimestamp as u8;
+    }
+    writer.write_all(&(batch.consistency.clone() as u16).to_be_bytes())?;
+    writer.write_all(&[flags])?;
+    written += 3;
+    if let Some(serial_consistency) = &batch.serial_consistency {
+        writer.write_all(&(serial_consistency.clone() as u16).to_be_bytes())?;
+        written += 2;
+    }
+    if let Some(timestamp) = batch.timestamp {
+        writer.write_all(&timestamp.to_be_bytes())?;
+        written += 8;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::native_protocol::models::query_parameters::BoundValue;
+
+    use super::*;
+
+    #[test]
+    fn test_read_and_write_batch() {
+        let mut batch = BatchMsg::new(
+            BatchType::Unlogged,
+            vec![
+                BatchStatement {
+                    query: BatchQuery::Query("INSERT INTO t (id) VALUES (?)".to_string()),
+                    values: vec![BoundValue {
+                        name: None,
+                        value: Some(vec![0x00, 0x00, 0x00, 0x01]),
+                    }],
+                },
+                BatchStatement {
+                    query: BatchQuery::Prepared(vec![0xAB; 16]),
+                    values: vec![BoundValue {
+                        name: None,
+                        value: None,
+                    }],
+                },
+            ],
+            ConsistencyLevel::Quorum,
+        );
+        batch.timestamp = Some(1700000000000000);
+
+        let mut buffer = Vec::new();
+        let written = write_batch(&mut buffer, &batch).unwrap();
+        assert_eq!(written, buffer.len() as u32);
+        let read = read_batch(&mut Cursor::new(buffer), written).unwrap();
+        assert_eq!(read, batch);
+    }
+
+    #[test]
+    fn test_read_invalid_batch() {
+        // A batch of type 3, which does not exist.
+        let buffer = vec![0x03, 0x00, 0x00, 0x00, 0x01, 0x00];
+        assert!(read_batch(&mut Cursor::new(buffer), 6).is_err());
+        // A batch longer than the frame.
+        let buffer = vec![0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00];
+        assert!(read_batch(&mut Cursor::new(buffer), 7).is_err());
+    }
+}
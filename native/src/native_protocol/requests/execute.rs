@@ -0,0 +1,62 @@
+use std::io::{Cursor, Read, Write};
+
+use shared::io_error;
+
+use crate::native_protocol::{
+    models::{execute::ExecuteMsg, query_parameters::QueryParameters},
+    parsers::short_bytes::{read_short_bytes, write_short_bytes},
+};
+
+/// Reads an execute body from the provided reader.
+///
+/// The format of the body is:
+/// - id: [short bytes] = the id of the prepared query, as returned in the result of the `PREPARE`
+/// - parameters: the consistency, flags and values of the query (see `models/query_parameters.rs`)
+pub(crate) fn read_execute<R: Read>(reader: &mut R, length: u32) -> std::io::Result<ExecuteMsg> {
+    let mut buffer = vec![0; length as usize];
+    reader.read_exact(&mut buffer)?;
+    let mut cursor = Cursor::new(buffer);
+
+    let (id, read) = read_short_bytes(&mut cursor)?;
+    let (parameters, read_parameters) = QueryParameters::read(&mut cursor)?;
+    if read + read_parameters != length {
+        return Err(io_error!("Body length does not match the frame length"));
+    }
+    Ok(ExecuteMsg { id, parameters })
+}
+
+pub(crate) fn write_execute<W: Write>(
+    writer: &mut W,
+    execute: &ExecuteMsg,
+) -> std::io::Result<u32> {
+    let written = write_short_bytes(writer, &execute.id)?;
+    Ok(written + execute.parameters.write(writer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::native_protocol::models::{
+        consistency::ConsistencyLevel, query_parameters::BoundValue,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_read_and_write_execute() {
+        let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+        parameters.values.push(BoundValue {
+            name: None,
+            value: Some(vec![0x00, 0x00, 0x00, 0x01]),
+        });
+        let execute = ExecuteMsg {
+            id: vec![0xAB; 16],
+            parameters,
+        };
+
+        let mut buffer = Vec::new();
+        let written = write_execute(&mut buffer, &execute).unwrap();
+        let read = read_execute(&mut Cursor::new(buffer), written).unwrap();
+        assert_eq!(read.id, execute.id);
+        assert_eq!(read.parameters, execute.parameters);
+    }
+}
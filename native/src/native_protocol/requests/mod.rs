@@ -1,3 +1,7 @@
+pub mod batch;
+pub mod execute;
+pub mod options;
+pub mod prepare;
 pub mod query;
 pub mod request;
 pub mod startup;
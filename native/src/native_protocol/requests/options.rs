@@ -0,0 +1,48 @@
+use std::{collections::HashMap, io::Read};
+
+use shared::io_error;
+
+/// Reads an options body from the provided reader.
+///
+/// The body of an `OPTIONS` request is empty. A body that is not is consumed anyway, so the next
+/// frame of the connection can still be read.
+pub(crate) fn read_options<R: Read>(reader: &mut R, length: u32) -> std::io::Result<()> {
+    if length != 0 {
+        std::io::copy(&mut reader.take(length as u64), &mut std::io::sink())?;
+        return Err(io_error!("The body of an OPTIONS request must be empty"));
+    }
+    Ok(())
+}
+
+/// Returns the values the server supports for each option of a `STARTUP` message, as sent in the
+/// `SUPPORTED` answer to an `OPTIONS` request:
+/// - CQL_VERSION: "3.0.0"
+/// - COMPRESSION: none, the frames are never compressed
+/// - PROTOCOL_VERSIONS: "4/v4"
+pub(crate) fn supported_options() -> HashMap<String, Vec<String>> {
+    HashMap::from([
+        ("CQL_VERSION".to_string(), vec!["3.0.0".to_string()]),
+        ("COMPRESSION".to_string(), Vec::new()),
+        ("PROTOCOL_VERSIONS".to_string(), vec!["4/v4".to_string()]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_read_options() {
+        let mut input = Cursor::new(Vec::new());
+        assert!(read_options(&mut input, 0).is_ok());
+    }
+
+    #[test]
+    fn test_read_options_with_body_consumes_it() {
+        let mut input = Cursor::new(vec![0x01, 0x02, 0x03]);
+        assert!(read_options(&mut input, 3).is_err());
+        assert_eq!(input.position(), 3);
+    }
+}
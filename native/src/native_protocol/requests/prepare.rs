@@ -0,0 +1,44 @@
+use std::io::{Cursor, Read, Write};
+
+use shared::io_error;
+
+use crate::native_protocol::parsers::long_string::{read_long_string, write_long_string};
+
+/// Reads a prepare body from the provided reader.
+///
+/// The format of the body is:
+/// - query: [long_string] (see `parsers/long_string.rs`), with bind markers in place of its values
+pub(crate) fn read_prepare<R: Read>(reader: &mut R, length: u32) -> std::io::Result<String> {
+    let mut buffer = vec![0; length as usize];
+    reader.read_exact(&mut buffer)?;
+    let (query_string, read) = read_long_string(&mut Cursor::new(buffer))?;
+    if read != length {
+        return Err(io_error!("Body length does not match the frame length"));
+    }
+    Ok(query_string)
+}
+
+pub(crate) fn write_prepare<W: Write>(writer: &mut W, query_str: &str) -> std::io::Result<u32> {
+    write_long_string(writer, query_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_write_prepare() {
+        let mut buffer = Vec::new();
+        let written = write_prepare(&mut buffer, "SELECT * FROM t WHERE id = ?").unwrap();
+        let query = read_prepare(&mut Cursor::new(buffer), written).unwrap();
+        assert_eq!(query, "SELECT * FROM t WHERE id = ?");
+    }
+
+    #[test]
+    fn test_read_prepare_with_trailing_bytes() {
+        let mut buffer = Vec::new();
+        let written = write_prepare(&mut buffer, "SELECT * FROM t").unwrap();
+        buffer.push(0x00);
+        assert!(read_prepare(&mut Cursor::new(buffer), written + 1).is_err());
+    }
+}
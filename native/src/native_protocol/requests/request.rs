@@ -4,14 +4,17 @@ use std::{
 };
 
 use db::{Context, SchemaType};
-use query::Query;
-use shared::{get_keyspace, get_keyspace_name, io_error};
+use query::{BindMarker, Query};
+use shared::{io_error, resolve_table, set_keyspace};
 
 use crate::{
     client::ConsistencyLevel,
     native_protocol::{
         header::Opcode,
-        models::query::QueryMsg,
+        models::{
+            batch::BatchMsg, execute::ExecuteMsg, query::QueryMsg,
+            query_parameters::QueryParameters,
+        },
         responses::{
             response::Response,
             result_op::{ColumnSpec, DataTypeFlags, ResultOP, RowMetadata, Rows},
@@ -20,14 +23,23 @@ use crate::{
 };
 
 use super::{
+    batch::{read_batch, write_batch},
+    execute::{read_execute, write_execute},
+    options::{read_options, supported_options},
+    prepare::{read_prepare, write_prepare},
     query::{read_query, write_query},
     startup::{read_startup, write_startup},
 };
 
 #[derive(Debug)]
 pub enum Request {
-    Query(QueryMsg),
+    Query(Box<QueryMsg>),
     Startup(HashMap<String, String>),
+    Options,
+    /// Prepare (query). The query has bind markers in place of its values.
+    Prepare(String),
+    Execute(ExecuteMsg),
+    Batch(BatchMsg),
 }
 
 impl Request {
@@ -35,12 +47,19 @@ impl Request {
         match opcode {
             Opcode::Query => {
                 let query = read_query(reader, length)?;
-                Ok(Request::Query(query))
+                Ok(Request::Query(Box::new(query)))
             }
             Opcode::Startup => {
                 let startup = read_startup(reader, length)?;
                 Ok(Request::Startup(startup))
             }
+            Opcode::Options => {
+                read_options(reader, length)?;
+                Ok(Request::Options)
+            }
+            Opcode::Prepare => Ok(Request::Prepare(read_prepare(reader, length)?)),
+            Opcode::Execute => Ok(Request::Execute(read_execute(reader, length)?)),
+            Opcode::Batch => Ok(Request::Batch(read_batch(reader, length)?)),
             _ => Err(io_error!(format!("Invalid opcode: {opcode}"))),
         }
     }
@@ -57,16 +76,32 @@ impl Request {
                 }
                 Ok(Response::Ready)
             }
+            Request::Options => Ok(Response::Supported(supported_options())),
+            // The prepared queries are kept by the server, which processes these requests itself.
+            Request::Prepare(_) | Request::Execute(_) => Err(io_error!(
+                "Prepared queries are only processed by the server"
+            )),
+            Request::Batch(_) => Err(io_error!("Batches are only processed by the server")),
+            Request::Query(query) if query.query.is_keyspace_query() => {
+                query.query.process(&ctx.node_dir.join(&query.table), ctx)?;
+                if query.query.is_use() {
+                    set_keyspace(query.table.clone());
+                    return Ok(Response::ResultOp(ResultOP::SetKeyspace(
+                        query.table.clone(),
+                    )));
+                }
+                Ok(Response::ResultOp(ResultOP::Void))
+            }
             Request::Query(query) => {
-                let table = get_keyspace().join(&query.table);
+                let (keyspace, table_name) = resolve_table(&query.table)?;
+                let table = ctx.node_dir.join(&keyspace).join(&table_name);
                 let rows = query.query.process(&table, ctx)?;
-                if rows.is_some() {
-                    let rows = rows.unwrap();
+                if let Some(rows) = rows {
                     let cols = query.query.get_cols();
                     let mut column_specs = Vec::new();
                     for col in cols {
                         match ctx
-                            .get_table_schema(&get_keyspace_name().unwrap(), &query.table)?
+                            .get_table_schema(&keyspace, &table_name)?
                             .get_schema_type(&col)
                             .unwrap()
                         {
@@ -90,7 +125,7 @@ impl Request {
                     let metadata = RowMetadata::new(
                         0x0001,
                         rows[0].len() as i32,
-                        Some((get_keyspace_name()?, query.table.clone())),
+                        Some((keyspace, table_name)),
                         Some(column_specs),
                     )?;
                     let rows = Rows::new(metadata, rows.len() as i32, rows);
@@ -111,6 +146,28 @@ impl Request {
     pub fn get_query_str(&self) -> Option<String> {
         match self {
             Request::Query(query) => Some(query.query_str.clone()),
+            Request::Prepare(query_str) => Some(query_str.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_execute(&self) -> Option<&ExecuteMsg> {
+        match self {
+            Request::Execute(execute) => Some(execute),
+            _ => None,
+        }
+    }
+
+    pub fn get_batch(&self) -> Option<&BatchMsg> {
+        match self {
+            Request::Batch(batch) => Some(batch),
+            _ => None,
+        }
+    }
+
+    pub fn get_startup_options(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            Request::Startup(options) => Some(options),
             _ => None,
         }
     }
@@ -128,19 +185,35 @@ impl Request {
 
     pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<u32> {
         match self {
-            Request::Query(query) => write_query(
-                writer,
-                query.query_str.to_owned(),
-                query.consistency.clone(),
-                query.flags,
-            ),
+            Request::Query(query) => write_query(writer, &query.query_str, &query.parameters),
             Request::Startup(startup) => write_startup(writer, startup),
+            Request::Options => Ok(0),
+            Request::Prepare(query_str) => write_prepare(writer, query_str),
+            Request::Execute(execute) => write_execute(writer, execute),
+            Request::Batch(batch) => write_batch(writer, batch),
         }
     }
 
     pub fn get_consistency(&self) -> Option<&ConsistencyLevel> {
         match self {
-            Request::Query(query) => Some(&query.consistency),
+            Request::Batch(batch) => Some(&batch.consistency),
+            _ => self
+                .get_parameters()
+                .map(|parameters| &parameters.consistency),
+        }
+    }
+
+    pub fn get_parameters(&self) -> Option<&QueryParameters> {
+        match self {
+            Request::Query(query) => Some(&query.parameters),
+            Request::Execute(execute) => Some(&execute.parameters),
+            _ => None,
+        }
+    }
+
+    pub fn get_bind_markers(&self) -> Option<&[BindMarker]> {
+        match self {
+            Request::Query(query) => Some(&query.markers),
             _ => None,
         }
     }
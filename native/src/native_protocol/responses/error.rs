@@ -7,7 +7,10 @@ use shared::{io_error, map_io_error};
 
 use crate::native_protocol::{
     models::consistency::ConsistencyLevel,
-    parsers::string::{read_string, write_string},
+    parsers::{
+        short_bytes::{read_short_bytes, write_short_bytes},
+        string::{read_string, write_string},
+    },
 };
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -163,6 +166,10 @@ impl Error {
             let (table, read) = read_string(reader)?;
             bytes_read += read;
             extras.insert("table".to_string(), table);
+        } else if code == ErrorCode::Unprepared as i32 {
+            let (id, read) = read_short_bytes(reader)?;
+            bytes_read += read;
+            extras.insert("id".to_string(), encode_hex(&id));
         }
 
         let error_code = ErrorCode::from_u16(code as u16)?;
@@ -189,7 +196,7 @@ impl Error {
                     .get("consistency")
                     .ok_or(io_error!("'consistency' not found"))?;
                 writer
-                    .write_all(&(ConsistencyLevel::from_str(consistency)? as u16).to_be_bytes())?;
+                    .write_all(&(consistency.parse::<ConsistencyLevel>()? as u16).to_be_bytes())?;
                 bytes_written += 2;
 
                 let required = self
@@ -226,7 +233,7 @@ impl Error {
                     .get("consistency")
                     .ok_or(io_error!("'consistency' not found"))?;
                 writer
-                    .write_all(&(ConsistencyLevel::from_str(consistency)? as u16).to_be_bytes())?;
+                    .write_all(&(consistency.parse::<ConsistencyLevel>()? as u16).to_be_bytes())?;
                 bytes_written += 2;
 
                 let received = self
@@ -306,6 +313,13 @@ impl Error {
                     .ok_or(io_error!("'table' key not found"))?;
                 bytes_written += write_string(writer, table)?;
             }
+            ErrorCode::Unprepared => {
+                let id = self
+                    .extras
+                    .get("id")
+                    .ok_or(io_error!("'id' key not found"))?;
+                bytes_written += write_short_bytes(writer, &decode_hex(id)?)?;
+            }
             _ => {}
         }
 
@@ -313,6 +327,25 @@ impl Error {
     }
 }
 
+/// Encodes bytes as a hexadecimal string, so they can be kept as an extra of an error.
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> std::io::Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(io_error!("Invalid hexadecimal string"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(io_error!("Invalid hexadecimal string"))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -486,7 +519,8 @@ mod tests {
 
     #[test]
     fn test_read_and_write_unprepared_error() {
-        let error = Error::new(ErrorCode::Unprepared, "Unprepared".to_string());
+        let mut error = Error::new(ErrorCode::Unprepared, "Unprepared".to_string());
+        error.add_extra("id".to_string(), "00ff1a".to_string());
 
         let mut buffer = Vec::new();
         error.write(&mut buffer).unwrap();
@@ -537,4 +571,14 @@ mod tests {
         let mut buffer = Vec::new();
         assert!(error.write(&mut buffer).is_err());
     }
+
+    #[test]
+    fn test_invalid_unprepared_error() {
+        let error = Error::new(ErrorCode::Unprepared, "Unprepared".to_string());
+        assert!(error.write(&mut Vec::new()).is_err());
+
+        let mut error = Error::new(ErrorCode::Unprepared, "Unprepared".to_string());
+        error.add_extra("id".to_string(), "0g".to_string());
+        assert!(error.write(&mut Vec::new()).is_err());
+    }
 }
@@ -1,16 +1,27 @@
-use std::io::{Read, Write};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
 
 use shared::io_error;
 
-use crate::native_protocol::header::Opcode;
+use crate::native_protocol::{
+    header::Opcode,
+    parsers::string_multimap::{read_string_multimap, write_string_multimap},
+};
 
-use super::{error::Error, result_op::ResultOP};
+use super::{
+    error::Error,
+    result_op::{Prepared, ResultOP},
+};
 
 #[derive(Debug)]
 pub enum Response {
     Ready,
     Error(Error),
     ResultOp(ResultOP),
+    /// The values the server supports for each option of a `STARTUP` message.
+    Supported(HashMap<String, Vec<String>>),
 }
 
 impl Response {
@@ -32,6 +43,13 @@ impl Response {
                 let result_op = ResultOP::read(reader, length)?;
                 Ok(Response::ResultOp(result_op))
             }
+            Opcode::Supported => {
+                let (options, read) = read_string_multimap(reader)?;
+                if length != read {
+                    return Err(io_error!("Invalid supported length"));
+                }
+                Ok(Response::Supported(options))
+            }
             _ => Err(io_error!(format!("Invalid opcode: {opcode}"))),
         }
     }
@@ -41,6 +59,7 @@ impl Response {
             Response::Ready => Ok(0),
             Response::Error(error) => error.write(writer),
             Response::ResultOp(result_op) => result_op.write(writer),
+            Response::Supported(options) => write_string_multimap(writer, options),
         }
     }
 
@@ -51,6 +70,27 @@ impl Response {
         }
     }
 
+    pub(crate) fn get_paging_state(&self) -> Option<&[u8]> {
+        match self {
+            Response::ResultOp(result_op) => result_op.paging_state(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_prepared(&self) -> Option<&Prepared> {
+        match self {
+            Response::ResultOp(ResultOP::Prepared(prepared)) => Some(prepared),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_supported_options(&self) -> Option<&HashMap<String, Vec<String>>> {
+        match self {
+            Response::Supported(options) => Some(options),
+            _ => None,
+        }
+    }
+
     pub(crate) fn get_error(&self) -> Option<&str> {
         match self {
             Response::Error(error) => Some(&error.message),
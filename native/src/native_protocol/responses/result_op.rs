@@ -3,6 +3,7 @@ use shared::io_error;
 
 use crate::native_protocol::parsers::{
     bytes::Bytes,
+    short_bytes::{read_short_bytes, write_short_bytes},
     string::{read_string, write_string},
 };
 
@@ -119,6 +120,8 @@ pub struct RowMetadata {
     /// `keyspace` and `table` names for the global table spec
     pub global_table_spec: Option<(String, String)>,
     pub column_specs: Option<Vec<ColumnSpec>>,
+    /// The position to resume the query from to get its next page of rows, if it has more.
+    pub paging_state: Option<Vec<u8>>,
 }
 
 impl RowMetadata {
@@ -134,11 +137,6 @@ impl RowMetadata {
         {
             return Err(io_error!("Global table spec is None but set to be present"));
         }
-        // 0x0002 => {
-        //     if let None = paging_state {
-        //         return Err("Paging state spec is None".to_string());
-        //     }
-        // }
         if flags & RowsMetadaFlagsMask::NoMetadata as i32 == RowsMetadaFlagsMask::NoMetadata as i32
             && global_table_spec.is_some()
         {
@@ -159,9 +157,20 @@ impl RowMetadata {
             columns_count,
             global_table_spec,
             column_specs,
+            paging_state: None,
         })
     }
 
+    /// Sets the position of the next page of rows, or clears it if it is the last page.
+    pub fn set_paging_state(&mut self, paging_state: Option<Vec<u8>>) {
+        if paging_state.is_some() {
+            self.flags |= RowsMetadaFlagsMask::HasMorePages as i32;
+        } else {
+            self.flags &= !(RowsMetadaFlagsMask::HasMorePages as i32);
+        }
+        self.paging_state = paging_state;
+    }
+
     fn read<R: Read>(reader: &mut R) -> std::io::Result<(Self, u32)> {
         let mut buf_flag = [0u8; 4];
         reader.read_exact(&mut buf_flag)?;
@@ -172,6 +181,14 @@ impl RowMetadata {
         let columns_count = i32::from_be_bytes(buf_columns_count);
         let mut bytes_read = 8;
 
+        let paging_state = if flags & RowsMetadaFlagsMask::HasMorePages as i32 != 0 {
+            let (paging_state, read) = Bytes::read(reader)?;
+            bytes_read += read;
+            Some(paging_state.bytes_data)
+        } else {
+            None
+        };
+
         let global_table_spec: Option<(String, String)>;
         let column_specs: Option<Vec<ColumnSpec>>;
 
@@ -207,6 +224,7 @@ impl RowMetadata {
                 columns_count,
                 global_table_spec,
                 column_specs,
+                paging_state,
             },
             bytes_read,
         ))
@@ -216,6 +234,13 @@ impl RowMetadata {
         writer.write_all(&self.flags.to_be_bytes())?;
         writer.write_all(&self.columns_count.to_be_bytes())?;
         let mut written = 8;
+        if self.flags & RowsMetadaFlagsMask::HasMorePages as i32 != 0 {
+            let paging_state = self
+                .paging_state
+                .as_ref()
+                .ok_or(io_error!("Has more pages but no paging state"))?;
+            written += Bytes::new(paging_state.clone()).write(writer)?;
+        }
         if self.flags & RowsMetadaFlagsMask::GlobalTablesSpec as i32
             == RowsMetadaFlagsMask::GlobalTablesSpec as i32
         {
@@ -245,6 +270,129 @@ impl RowMetadata {
     }
 }
 
+/// The metadata of the bind markers of a prepared query.
+#[derive(Debug, PartialEq)]
+pub struct PreparedMetadata {
+    /// The indexes of the bind markers of the partition key columns, in the order of the partition key,
+    /// or none if the query does not bind the whole partition key.
+    pub pk_indexes: Vec<u16>,
+    /// `keyspace` and `table` names for the global table spec, if the query is on a table
+    pub global_table_spec: Option<(String, String)>,
+    /// The column each bind marker is bound to, named after the marker.
+    pub column_specs: Vec<ColumnSpec>,
+}
+
+impl PreparedMetadata {
+    fn read<R: Read>(reader: &mut R) -> std::io::Result<(Self, u32)> {
+        let mut int_buffer = [0u8; 4];
+        reader.read_exact(&mut int_buffer)?;
+        let flags = i32::from_be_bytes(int_buffer);
+        reader.read_exact(&mut int_buffer)?;
+        let columns_count = i32::from_be_bytes(int_buffer);
+        reader.read_exact(&mut int_buffer)?;
+        let pk_count = i32::from_be_bytes(int_buffer);
+        let mut bytes_read = 12;
+
+        let mut pk_indexes = Vec::new();
+        let mut short_buffer = [0u8; 2];
+        for _ in 0..pk_count {
+            reader.read_exact(&mut short_buffer)?;
+            pk_indexes.push(u16::from_be_bytes(short_buffer));
+            bytes_read += 2;
+        }
+
+        let mut global_table_spec = None;
+        if flags & RowsMetadaFlagsMask::GlobalTablesSpec as i32
+            == RowsMetadaFlagsMask::GlobalTablesSpec as i32
+        {
+            let (keyspace, read_keyspace) = read_string(reader)?;
+            let (table, read_table) = read_string(reader)?;
+            bytes_read += read_keyspace + read_table;
+            global_table_spec = Some((keyspace, table));
+        } else if columns_count > 0 {
+            return Err(io_error!(
+                "Metadata is expected but there is no global table spec"
+            ));
+        }
+        let mut column_specs = Vec::new();
+        for _ in 0..columns_count {
+            let (column_spec, read_column_spec) = ColumnSpec::read(reader)?;
+            column_specs.push(column_spec);
+            bytes_read += read_column_spec;
+        }
+
+        Ok((
+            PreparedMetadata {
+                pk_indexes,
+                global_table_spec,
+                column_specs,
+            },
+            bytes_read,
+        ))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<u32> {
+        let flags = match self.global_table_spec {
+            Some(_) => RowsMetadaFlagsMask::GlobalTablesSpec as i32,
+            None if self.column_specs.is_empty() => 0,
+            None => {
+                return Err(io_error!(
+                    "Global table spec is None but there are bind markers"
+                ))
+            }
+        };
+        writer.write_all(&flags.to_be_bytes())?;
+        writer.write_all(&(self.column_specs.len() as i32).to_be_bytes())?;
+        writer.write_all(&(self.pk_indexes.len() as i32).to_be_bytes())?;
+        let mut written = 12;
+        for pk_index in &self.pk_indexes {
+            writer.write_all(&pk_index.to_be_bytes())?;
+            written += 2;
+        }
+        if let Some((keyspace, table)) = &self.global_table_spec {
+            written += write_string(writer, keyspace)?;
+            written += write_string(writer, table)?;
+        }
+        for column in &self.column_specs {
+            written += column.write(writer)?;
+        }
+        Ok(written)
+    }
+}
+
+/// The result of a `PREPARE`: the id to execute the query with, the metadata of its bind markers and
+/// the metadata of the rows it returns.
+#[derive(Debug)]
+pub struct Prepared {
+    pub id: Vec<u8>,
+    pub metadata: PreparedMetadata,
+    /// The metadata of the rows of a `SELECT`, or no metadata for the rest of the queries.
+    pub result_metadata: RowMetadata,
+}
+
+impl Prepared {
+    fn read<R: Read>(reader: &mut R) -> std::io::Result<(Self, u32)> {
+        let (id, read_id) = read_short_bytes(reader)?;
+        let (metadata, read_metadata) = PreparedMetadata::read(reader)?;
+        let (result_metadata, read_result_metadata) = RowMetadata::read(reader)?;
+        Ok((
+            Prepared {
+                id,
+                metadata,
+                result_metadata,
+            },
+            read_id + read_metadata + read_result_metadata,
+        ))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<u32> {
+        let mut written = write_short_bytes(writer, &self.id)?;
+        written += self.metadata.write(writer)?;
+        written += self.result_metadata.write(writer)?;
+        Ok(written)
+    }
+}
+
 type RowBytes = Vec<Bytes>;
 type Row = Vec<String>;
 
@@ -338,9 +486,10 @@ impl Rows {
 
 #[derive(Debug)]
 pub enum ResultOP {
-    Void, // Void = 0x0001
-    Rows(Rows), // Rows = 0x0002
-          // SetKeyspace = 0x0003
+    Void,                // Void = 0x0001
+    Rows(Rows),          // Rows = 0x0002
+    SetKeyspace(String), // SetKeyspace = 0x0003
+    Prepared(Prepared),  // Prepared = 0x0004
 }
 
 impl ResultOP {
@@ -358,7 +507,8 @@ impl ResultOP {
         }
     }
 
-    /// Reads the result from the reader. The result can be a void or rows.
+    /// Reads the result from the reader. The result can be a void, rows, the keyspace set by a `USE` or a
+    /// prepared query.
     ///
     /// # Arguments
     ///
@@ -398,6 +548,24 @@ impl ResultOP {
                     Ok(ResultOP::Rows(rows))
                 }
             }
+            0x0003 => {
+                let (keyspace, read_keyspace) = read_string(&mut reader)?;
+                bytes_read += read_keyspace;
+                if bytes_read != length {
+                    Err(io_error!("Body length is greater than the frame length"))
+                } else {
+                    Ok(ResultOP::SetKeyspace(keyspace))
+                }
+            }
+            0x0004 => {
+                let (prepared, read_prepared) = Prepared::read(&mut reader)?;
+                bytes_read += read_prepared;
+                if bytes_read != length {
+                    Err(io_error!("Body length is greater than the frame length"))
+                } else {
+                    Ok(ResultOP::Prepared(prepared))
+                }
+            }
             _ => Err(io_error!(format!("Invalid result kind: {kind}"))),
         }
     }
@@ -406,11 +574,23 @@ impl ResultOP {
         let kind: i32 = match self {
             ResultOP::Void => 0x0001,
             ResultOP::Rows(_) => 0x0002,
+            ResultOP::SetKeyspace(_) => 0x0003,
+            ResultOP::Prepared(_) => 0x0004,
         };
         writer.write_all(&kind.to_be_bytes())?;
         match self {
             ResultOP::Void => Ok(4),
             ResultOP::Rows(rows) => Ok(rows.write(writer)? + 4),
+            ResultOP::SetKeyspace(keyspace) => Ok(write_string(writer, keyspace)? + 4),
+            ResultOP::Prepared(prepared) => Ok(prepared.write(writer)? + 4),
+        }
+    }
+
+    /// Returns the position to resume the query from to get its next page of rows, if it has more.
+    pub fn paging_state(&self) -> Option<&[u8]> {
+        match self {
+            ResultOP::Rows(rows) => rows.metadata.paging_state.as_deref(),
+            _ => None,
         }
     }
 
@@ -711,6 +891,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_and_write_rows_with_paging_state() {
+        let mut row_metadata = RowMetadata::new(
+            0x01,
+            1,
+            Some(("key".to_string(), "table".to_string())),
+            Some(vec![ColumnSpec::new(
+                "name".to_string(),
+                DataTypeFlags::Varchar,
+            )]),
+        )
+        .unwrap();
+        row_metadata.set_paging_state(Some(vec![0x01, 0x02, 0x03]));
+        assert_eq!(row_metadata.flags, 0x03);
+        let rows = Rows::new(row_metadata, 1, vec![vec!["hello".to_string()]]);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let written = rows.write(&mut buffer).unwrap();
+        let (rows, read) = Rows::read(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(read, written);
+        assert_eq!(rows.metadata.paging_state, Some(vec![0x01, 0x02, 0x03]));
+        assert_eq!(
+            ResultOP::Rows(rows).paging_state(),
+            Some([0x01, 0x02, 0x03].as_slice())
+        );
+
+        let mut row_metadata = RowMetadata::new(0x04, 0, None, None).unwrap();
+        row_metadata.set_paging_state(Some(vec![0x01]));
+        row_metadata.set_paging_state(None);
+        assert_eq!(row_metadata.flags, 0x04);
+    }
+
     #[test]
     fn test_read_and_write_result() {
         let col_spec_1 = ColumnSpec::new("name".to_string(), DataTypeFlags::Varchar);
@@ -784,8 +996,23 @@ mod tests {
         let mut buffer = Cursor::new(vec![0x00, 0x00, 0x00, 0x01]);
         let result_op = ResultOP::read(&mut buffer, 4).unwrap();
         match result_op {
-            ResultOP::Rows(_) => panic!("Should be a Void result"),
             ResultOP::Void => {}
+            _ => panic!("Should be a Void result"),
+        }
+    }
+
+    #[test]
+    fn test_read_and_write_set_keyspace() {
+        let result_op = ResultOP::SetKeyspace("ks".to_string());
+        let mut buffer: Vec<u8> = Vec::new();
+        let written = result_op.write(&mut buffer).unwrap();
+        assert_eq!(written, 8);
+        assert_eq!(buffer, vec![0x0, 0x0, 0x0, 0x3, 0x0, 0x2, b'k', b's']);
+
+        let mut buffer = Cursor::new(buffer);
+        match ResultOP::read(&mut buffer, 8).unwrap() {
+            ResultOP::SetKeyspace(keyspace) => assert_eq!(keyspace, "ks"),
+            _ => panic!("Should be a SetKeyspace result"),
         }
     }
 
@@ -837,4 +1064,46 @@ mod tests {
         assert_eq!(rows[1][1], "30");
         assert_eq!(rows[1][2], "email");
     }
+
+    #[test]
+    fn test_read_and_write_prepared() {
+        let metadata = PreparedMetadata {
+            pk_indexes: vec![1],
+            global_table_spec: Some(("ks".to_string(), "users".to_string())),
+            column_specs: vec![
+                ColumnSpec::new("name".to_string(), DataTypeFlags::Varchar),
+                ColumnSpec::new("id".to_string(), DataTypeFlags::Int),
+            ],
+        };
+        let result_op = ResultOP::Prepared(Prepared {
+            id: vec![0x01; 16],
+            metadata,
+            result_metadata: RowMetadata::new(
+                RowsMetadaFlagsMask::NoMetadata as i32,
+                0,
+                None,
+                None,
+            )
+            .unwrap(),
+        });
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let written = result_op.write(&mut buffer).unwrap();
+        assert_eq!(written, buffer.len() as u32);
+
+        let mut buffer = Cursor::new(buffer);
+        match ResultOP::read(&mut buffer, written).unwrap() {
+            ResultOP::Prepared(prepared) => {
+                assert_eq!(prepared.id, vec![0x01; 16]);
+                assert_eq!(prepared.metadata.pk_indexes, vec![1]);
+                assert_eq!(prepared.metadata.column_specs[0].name, "name");
+                assert_eq!(
+                    prepared.metadata.column_specs[1].data_type,
+                    DataTypeFlags::Int
+                );
+                assert_eq!(prepared.result_metadata.columns_count, 0);
+            }
+            _ => panic!("Should be a Prepared result"),
+        }
+    }
 }
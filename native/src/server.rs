@@ -1,24 +1,42 @@
 use std::{collections::HashMap, io::Read};
 
 use crate::native_protocol::{
-    header::{Header, Opcode},
-    native::{Body, Frame},
-    responses::{error::Error, response::Response},
+    header::Header,
+    native::Body,
+    requests::options::supported_options,
+    responses::error::{encode_hex, Error},
 };
 
+pub use crate::native_protocol::header::Opcode;
+pub use crate::native_protocol::models::batch::{BatchMsg, BatchQuery, BatchStatement};
+pub use crate::native_protocol::models::query_parameters::{BoundValue, QueryParameters};
+pub use crate::native_protocol::native::Frame;
 pub use crate::native_protocol::responses::error::ErrorCode;
+pub use crate::native_protocol::responses::response::Response;
 pub use crate::native_protocol::responses::result_op::{
-    ColumnSpec, DataTypeFlags, ResultOP, RowMetadata, Rows, RowsMetadaFlagsMask,
+    ColumnSpec, DataTypeFlags, Prepared, PreparedMetadata, ResultOP, RowMetadata, Rows,
+    RowsMetadaFlagsMask,
 };
 
 pub const READY: Opcode = Opcode::Ready;
 pub const ERROR: Opcode = Opcode::Error;
 pub const RESULT: Opcode = Opcode::ResultOP;
+pub const SUPPORTED: Opcode = Opcode::Supported;
 
 pub fn read_request<R: Read>(stream: &mut R) -> std::io::Result<Frame> {
     Frame::read(stream)
 }
 
+/// Reads only the header of the next request, so the stream id is known even if the body turns out to be invalid.
+pub fn read_request_header<R: Read>(stream: &mut R) -> std::io::Result<Header> {
+    Header::read_header(stream)
+}
+
+/// Reads the body of a request whose header was read with `read_request_header`.
+pub fn read_request_body<R: Read>(stream: &mut R, header: Header) -> std::io::Result<Frame> {
+    Frame::read_body(stream, header)
+}
+
 pub fn create_error_response(
     code: ErrorCode,
     message: &str,
@@ -41,10 +59,44 @@ pub fn create_result_response(rows: Option<Rows>) -> Response {
     }
 }
 
+/// Creates the result of a `USE` query, with the keyspace set in the connection.
+pub fn create_set_keyspace_response(keyspace: &str) -> Response {
+    Response::ResultOp(ResultOP::SetKeyspace(keyspace.to_string()))
+}
+
+/// Creates the result of a `PREPARE`, with the id to execute the query with.
+pub fn create_prepared_response(
+    id: Vec<u8>,
+    metadata: PreparedMetadata,
+    result_metadata: RowMetadata,
+) -> Response {
+    Response::ResultOp(ResultOP::Prepared(Prepared {
+        id,
+        metadata,
+        result_metadata,
+    }))
+}
+
+/// Creates the error of an `EXECUTE` of an id that is not prepared, so the client prepares the query
+/// again.
+pub fn create_unprepared_response(id: &[u8]) -> Response {
+    create_error_response(
+        ErrorCode::Unprepared,
+        &format!("Prepared query with id {} not found", encode_hex(id)),
+        Some(HashMap::from([("id".to_string(), encode_hex(id))])),
+    )
+}
+
 pub fn create_ready_response() -> Response {
     Response::Ready
 }
 
+/// Creates the answer to an `OPTIONS` request, with the values the server supports for each option of a
+/// `STARTUP` message.
+pub fn create_supported_response() -> Response {
+    Response::Supported(supported_options())
+}
+
 pub fn create_response_frame(
     opcode: Opcode,
     stream_id: u16,
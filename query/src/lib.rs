@@ -2,5 +2,5 @@ mod models;
 mod parsers;
 mod utils;
 
-pub use models::query::Query;
-pub use parsers::query::process_query;
+pub use models::{batch::BatchType, bind_marker::BindMarker, query::Query};
+pub use parsers::query::{prepare_query, process_query};
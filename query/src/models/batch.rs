@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// The kind of a batch, which sets how its statements are applied.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum BatchType {
+    /// The statements are written to a batchlog before they are applied, so they are all applied even if
+    /// the coordinator dies while applying them.
+    Logged,
+    /// The statements are applied without a batchlog.
+    Unlogged,
+    /// A batch of counter updates, which the coordinators reject since there are no counter columns.
+    Counter,
+}
@@ -0,0 +1,108 @@
+use shared::io_error;
+
+/// Starts the placeholder that a bind marker of a query is parsed as, followed by the index of the
+/// marker. The control character cannot be written in a CQL literal, so no value is mistaken for one.
+const PLACEHOLDER_PREFIX: &str = "\u{1}?";
+
+/// A value of a prepared query that is bound when it is executed, written as `?` or as `:<name>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindMarker {
+    /// The name of the marker, if it was written as `:<name>`.
+    pub name: Option<String>,
+    /// The column the bound value is written to or compared with.
+    pub column: String,
+}
+
+/// Returns the placeholder of the bind marker with the index.
+pub(crate) fn placeholder(index: usize) -> String {
+    format!("{PLACEHOLDER_PREFIX}{index}")
+}
+
+/// Returns the index of the bind marker of the value, if it is the placeholder of one.
+pub(crate) fn get_placeholder_index(value: &str) -> Option<usize> {
+    value.strip_prefix(PLACEHOLDER_PREFIX)?.parse().ok()
+}
+
+/// Replaces the bind markers of a query outside of its string literals with placeholders, so they are
+/// parsed as values.
+///
+/// # Returns
+///
+/// * The query with the placeholders, and the name of each marker in the order they appear in.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the query mixes `?` markers with named ones.
+pub(crate) fn replace_bind_markers(query: &str) -> std::io::Result<(String, Vec<Option<String>>)> {
+    let mut replaced = String::with_capacity(query.len());
+    let mut names = Vec::new();
+    let mut quoted = false;
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                replaced.push(c);
+            }
+            '?' if !quoted => {
+                replaced += &placeholder(names.len());
+                names.push(None);
+            }
+            ':' if !quoted && chars.peek().is_some_and(|c| c.is_alphabetic() || *c == '_') => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                replaced += &placeholder(names.len());
+                names.push(Some(name));
+            }
+            _ => replaced.push(c),
+        }
+    }
+    let named = names.iter().filter(|name| name.is_some()).count();
+    if named != 0 && named != names.len() {
+        return Err(io_error!("Cannot mix '?' and named bind markers"));
+    }
+    Ok((replaced, names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markers_are_replaced_in_order() {
+        let (query, names) =
+            replace_bind_markers("SELECT * FROM t WHERE id = ? AND name = '?'").unwrap();
+        assert_eq!(
+            query,
+            format!(
+                "SELECT * FROM t WHERE id = {} AND name = '?'",
+                placeholder(0)
+            )
+        );
+        assert_eq!(names, vec![None]);
+
+        let (query, names) =
+            replace_bind_markers("INSERT INTO t (id, name) VALUES (:id,:user_name)").unwrap();
+        assert_eq!(
+            query,
+            format!(
+                "INSERT INTO t (id, name) VALUES ({},{})",
+                placeholder(0),
+                placeholder(1)
+            )
+        );
+        assert_eq!(
+            names,
+            vec![Some("id".to_string()), Some("user_name".to_string())]
+        );
+        assert_eq!(get_placeholder_index(&placeholder(1)), Some(1));
+        assert_eq!(get_placeholder_index("1"), None);
+    }
+
+    #[test]
+    fn test_mixed_markers_are_invalid() {
+        assert!(replace_bind_markers("SELECT * FROM t WHERE id = ? AND name = :name").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+pub mod batch;
+pub mod bind_marker;
 pub mod query;
 pub mod statement;
 pub mod where_clause;
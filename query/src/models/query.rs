@@ -1,10 +1,12 @@
-use std::{cmp::Ordering, collections::HashMap, path::Path};
+use std::{cmp::Ordering, collections::HashMap, ops::Bound, path::Path};
 
-use db::Context;
+use db::{current_timestamp, Context, Schema, StoredRow, WriteBatch};
 use serde::{Deserialize, Serialize};
-use shared::io_error;
+use shared::{io_error, not_found_error};
 
 use super::{
+    batch::BatchType,
+    bind_marker::get_placeholder_index,
     statement::{Cols, OrderMode, Statement},
     where_clause::WhereClause,
 };
@@ -15,11 +17,20 @@ pub const KEYWORDS: [&str; 18] = [
     "OR", "SET", "INTO", "ORDER", "BY", "ASC", "DESC", "NOT",
 ];
 
+/// The selected columns of the rows of a page, and the position of its last row if there are more pages.
+type Page = (Option<Vec<Cols>>, Option<Vec<String>>);
+
 /// Represents a parsed SQL query, containing a statement and an optional WHERE clause.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Query {
     statement: Statement,
     where_clause: Option<WhereClause>,
+    /// Write time of the query in microseconds since the epoch, set with `USING TIMESTAMP` or by
+    /// the coordinator. Queries without one are written at the time they are processed.
+    timestamp: Option<i64>,
+    /// Seconds after which the written cells expire, set with `USING TTL`. Writes without one take the
+    /// `default_time_to_live` of the table.
+    ttl: Option<u64>,
 }
 
 impl Query {
@@ -37,14 +48,59 @@ impl Query {
         Query {
             statement,
             where_clause,
+            timestamp: None,
+            ttl: None,
         }
     }
 
+    /// Creates a query that merges the stored rows that a replica is missing into the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The stored rows returned by `db::get_repairs` for the replica.
+    pub fn repair(rows: Vec<StoredRow>) -> Self {
+        Query {
+            statement: Statement::Repair(rows),
+            where_clause: None,
+            timestamp: None,
+            ttl: None,
+        }
+    }
+
+    /// Creates a batch of writes, applied together.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_type` - How the statements are applied.
+    /// * `statements` - The statements of the batch, with the names of their tables as in `process_query`.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if there are no statements, or if a statement is not an `INSERT`, an `UPDATE` or a `DELETE`.
+    pub fn batch(batch_type: BatchType, statements: Vec<(Query, String)>) -> std::io::Result<Self> {
+        if statements.is_empty() {
+            return Err(io_error!("A batch must have at least one statement"));
+        }
+        let is_write = |query: &Query| {
+            matches!(
+                query.statement,
+                Statement::Insert(_) | Statement::Update(_) | Statement::Delete(_)
+            )
+        };
+        if !statements.iter().all(|(query, _)| is_write(query)) {
+            return Err(io_error!(
+                "Only INSERT, UPDATE and DELETE queries can be batched"
+            ));
+        }
+        Ok(Query::new(Statement::Batch(batch_type, statements), None))
+    }
+
     /// Processes the query against a specified table.
     ///
     /// # Arguments
     ///
-    /// * `table` - The path of the table dir to execute the query against.
+    /// * `table` - The path of the table dir to execute the query against, or the path of the keyspace dir
+    ///   for the keyspace queries.
     /// * `ctx` - The context of the node.
     ///
     /// # Returns
@@ -63,7 +119,52 @@ impl Query {
         match &self.statement {
             Statement::CreateTable(schema) => ctx.create_table(table, schema).map(|_| None),
             Statement::DropTable => ctx.drop_table(table).map(|_| None),
-            _ => {
+            Statement::CreateKeyspace(options) => ctx.create_keyspace(table, options).map(|_| None),
+            Statement::AlterKeyspace(replication, durable_writes) => {
+                let mut options = ctx.get_keyspace_options(table)?;
+                if let Some(replication) = replication {
+                    options.replication = replication.clone();
+                }
+                if let Some(durable_writes) = durable_writes {
+                    options.durable_writes = *durable_writes;
+                }
+                ctx.alter_keyspace(table, &options).map(|_| None)
+            }
+            Statement::DropKeyspace => ctx.drop_keyspace(table).map(|_| None),
+            // The keyspace is set in the connection by the server, here it is only checked.
+            Statement::Use => {
+                let keyspace = table.file_name().and_then(|keyspace| keyspace.to_str());
+                if keyspace.is_some_and(|keyspace| ctx.is_a_keyspace(keyspace)) {
+                    Ok(None)
+                } else {
+                    Err(not_found_error!("Keyspace does not exist"))
+                }
+            }
+            _ => self.process_rows(table, ctx),
+        }
+    }
+
+    /// Processes a query that reads or writes the rows of a table: a `SELECT`, an `INSERT`, an `UPDATE`, a
+    /// `DELETE` or the rows of a repair. Each table guards its own data, so these queries only need shared
+    /// access to the context and run concurrently with each other.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir to execute the query against.
+    /// * `ctx` - The context of the node.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Cols>` if the query is successfully processed and the statement is `SELECT`.
+    /// * `None` if the query is successfully processed and the statement is not `SELECT`.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if the query changes the schema, or if an error occurs during processing.
+    pub fn process_rows(&self, table: &Path, ctx: &Context) -> std::io::Result<Option<Vec<Cols>>> {
+        match &self.statement {
+            Statement::Repair(rows) => ctx.write_stored_rows(table, rows.clone()).map(|_| None),
+            Statement::Select(_, _) => {
                 let ks = table
                     .parent()
                     .unwrap()
@@ -73,44 +174,383 @@ impl Query {
                     .unwrap();
                 let schema =
                     ctx.get_table_schema(ks, table.file_name().unwrap().to_str().unwrap())?;
-                match &self.statement {
-                    Statement::Select(to_print, order) => {
-                        let mut rows = Vec::new();
-                        ctx.read_table(table, &mut |row| {
-                            if self.where_clause.as_ref().unwrap().eval(&row, &schema)? {
-                                rows.push(row);
-                            }
-                            Ok(())
-                        })?;
-                        order_rows(&mut rows, order, to_print)
+                let mut rows = Vec::new();
+                ctx.read_table(table, &mut |row| {
+                    if self.matches(&row, &schema)? {
+                        rows.push(row);
                     }
-                    Statement::Insert(new_row) => {
-                        ctx.append_to_table(table, new_row.clone()).map(|_| None)
-                    }
-                    Statement::Update(new_rows) => ctx
-                        .update_table(table, &mut |mut row| {
-                            if self.where_clause.as_ref().unwrap().eval(&row, &schema)? {
-                                for (col, val) in new_rows.iter() {
-                                    row.insert(col.to_string(), val.to_string());
-                                }
-                                Ok(Some(row))
-                            } else {
-                                Ok(Some(row))
-                            }
-                        })
-                        .map(|_| None),
-                    Statement::Delete => ctx
-                        .update_table(table, &mut |row| {
-                            if self.where_clause.as_ref().unwrap().eval(&row, &schema)? {
-                                Ok(None)
-                            } else {
-                                Ok(Some(row.clone()))
-                            }
-                        })
-                        .map(|_| None),
-                    _ => panic!("Should not reach here"),
+                    Ok(())
+                })?;
+                self.order_rows(rows, &schema)
+            }
+            Statement::Insert(_) | Statement::Update(_) | Statement::Delete(_) => {
+                let mut batch = ctx.write_batch(table)?;
+                self.write_rows(&mut batch, ctx)?;
+                batch.apply().map(|_| None)
+            }
+            _ => Err(io_error!("The query does not process the rows of a table")),
+        }
+    }
+
+    /// Adds the writes of an `INSERT`, an `UPDATE` or a `DELETE` to a batch of writes to its table, so they
+    /// are applied together with the other writes of the batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The batch of writes to the table of the query.
+    /// * `ctx` - The context of the node, which the rows selected by the `WHERE` clause are read from.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if the query is not an `INSERT`, an `UPDATE` or a `DELETE`, or if a write is not valid.
+    pub fn write_rows(&self, batch: &mut WriteBatch, ctx: &Context) -> std::io::Result<()> {
+        let schema = get_table_schema(batch.table(), ctx)?;
+        let timestamp = self.timestamp.unwrap_or_else(current_timestamp);
+        match &self.statement {
+            Statement::Insert(new_row) => batch.append_to_table(new_row, timestamp, self.ttl),
+            Statement::Update(new_row) => self.update(batch, new_row, &schema, timestamp),
+            Statement::Delete(columns) => self.delete(batch, columns, &schema, timestamp, ctx),
+            _ => Err(io_error!(
+                "Only INSERT, UPDATE and DELETE queries write rows"
+            )),
+        }
+    }
+
+    /// Writes the new values of an `UPDATE` to the rows selected by the `WHERE` clause.
+    ///
+    /// If the clause selects a single row by its whole primary key, and the query does not change it, the
+    /// values are written to the row without reading the table. Otherwise each matching row is updated.
+    fn update(
+        &self,
+        batch: &mut WriteBatch,
+        new_row: &HashMap<String, String>,
+        schema: &Schema,
+        timestamp: i64,
+    ) -> std::io::Result<()> {
+        let where_clause = self.where_clause.as_ref().unwrap();
+        let primary_key = schema.get_primary_key();
+        let changes_key = primary_key
+            .get_partition_key()
+            .iter()
+            .chain(primary_key.get_clustering_key())
+            .any(|col| new_row.contains_key(col));
+        let restriction = where_clause
+            .get_key_restriction(schema)
+            .filter(|restriction| !changes_key && restriction.is_row(schema));
+        if let Some(restriction) = restriction {
+            return batch.update_row(&restriction.key, new_row, timestamp, self.ttl);
+        }
+
+        batch.update_table(timestamp, self.ttl, &mut |row| {
+            if where_clause.eval(&row, schema)? {
+                Ok(Some(new_row.clone()))
+            } else {
+                Ok(Some(HashMap::new()))
+            }
+        })
+    }
+
+    /// Deletes the rows, or the columns of the rows, selected by the `WHERE` clause with tombstones.
+    ///
+    /// If the clause only restricts the primary key, the tombstone is written without reading the table,
+    /// so it also hides the rows that the node has not received yet. Otherwise each matching row is deleted.
+    fn delete(
+        &self,
+        batch: &mut WriteBatch,
+        columns: &[String],
+        schema: &Schema,
+        timestamp: i64,
+        ctx: &Context,
+    ) -> std::io::Result<()> {
+        let where_clause = self.where_clause.as_ref().unwrap();
+        let restriction = where_clause
+            .get_key_restriction(schema)
+            .filter(|restriction| columns.is_empty() || restriction.is_row(schema));
+        if let Some(restriction) = restriction {
+            return if columns.is_empty() {
+                batch.delete_rows(&restriction.key, restriction.bounds, timestamp)
+            } else {
+                batch.delete_columns(&restriction.key, columns, timestamp)
+            };
+        }
+
+        let primary_key = schema.get_primary_key();
+        let key_cols: Vec<&String> = primary_key
+            .get_partition_key()
+            .iter()
+            .chain(primary_key.get_clustering_key())
+            .collect();
+        let mut keys = Vec::new();
+        ctx.read_table(batch.table(), &mut |row| {
+            if where_clause.eval(&row, schema)? {
+                let key: HashMap<String, String> = key_cols
+                    .iter()
+                    .map(|col| ((*col).clone(), row[*col].clone()))
+                    .collect();
+                keys.push(key);
+            }
+            Ok(())
+        })?;
+        for key in keys {
+            if columns.is_empty() {
+                batch.delete_rows(&key, (Bound::Unbounded, Bound::Unbounded), timestamp)?;
+            } else {
+                batch.delete_columns(&key, columns, timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the stored rows of the partition selected by a `SELECT` query, with their tombstones, so the
+    /// partitions read from different replicas can be reconciled before they are filtered with `select_rows`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir to execute the query against.
+    /// * `ctx` - The context of the node.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if the query is not a `SELECT`, if it does not restrict the partition key, or if an error
+    ///   occurs while reading the table.
+    pub fn read_stored_rows(&self, table: &Path, ctx: &Context) -> std::io::Result<Vec<StoredRow>> {
+        let Some(where_clause) = self.where_clause.as_ref().filter(|_| !self.is_not_select())
+        else {
+            return Err(io_error!("Only SELECT queries read stored rows"));
+        };
+        let schema = get_table_schema(table, ctx)?;
+        let partition_key = where_clause
+            .get_partition_key(&schema)
+            .ok_or(io_error!("All partition key columns must be restricted"))?;
+        let mut rows = Vec::new();
+        ctx.read_stored_rows(table, &partition_key, &mut |row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
+    /// Returns the selected columns of the rows that match the `WHERE` clause of a `SELECT` query, sorted
+    /// by its `ORDER BY` clause.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The rows to select from, such as the live rows of the reconciled stored rows.
+    /// * `schema` - The schema of the table of the rows.
+    ///
+    /// # Returns
+    ///
+    /// * `None` if no row matches.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if the query is not a `SELECT` or a selected column does not exist.
+    pub fn select_rows(
+        &self,
+        rows: Vec<HashMap<String, String>>,
+        schema: &Schema,
+    ) -> std::io::Result<Option<Vec<Cols>>> {
+        if self.is_not_select() {
+            return Err(io_error!("Only SELECT queries return rows"));
+        }
+        let mut selected = Vec::new();
+        for row in rows {
+            if self.matches(&row, schema)? {
+                selected.push(row);
+            }
+        }
+        self.order_rows(selected, schema)
+    }
+
+    /// Returns a page of the rows `select_rows` returns, resuming after the position of the last row of the
+    /// previous page. The rows are sorted by their positions: the value of the `ORDER BY` column, if there
+    /// is one, followed by the partition and clustering key, so the rows of a page never repeat or skip
+    /// the ones of the previous pages, even if some of them are written or deleted in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The rows to select from, such as the live rows of the reconciled stored rows.
+    /// * `schema` - The schema of the table of the rows.
+    /// * `start` - The position of the last row of the previous page, or `None` for the first page.
+    /// * `page_size` - The maximum number of rows of the page.
+    ///
+    /// # Returns
+    ///
+    /// * The selected columns of the rows of the page, `None` if there are none, and the position of its
+    ///   last row if there are more pages.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if the query is not a `SELECT`, a selected column does not exist, or the position does not
+    ///   have a value for each column of the positions of the table.
+    pub fn select_page(
+        &self,
+        rows: Vec<HashMap<String, String>>,
+        schema: &Schema,
+        start: Option<&[String]>,
+        page_size: usize,
+    ) -> std::io::Result<Page> {
+        if self.is_not_select() {
+            return Err(io_error!("Only SELECT queries return rows"));
+        }
+        self.check_page(schema, start, page_size)?;
+        let mut selected = Vec::new();
+        for row in rows {
+            let position = self.get_position(&row, schema);
+            let after_start =
+                start.is_none_or(|start| self.compare_positions(&position, start, schema).is_gt());
+            if after_start && self.matches(&row, schema)? {
+                selected.push((position, row));
+            }
+        }
+        selected.sort_by(|(a, _), (b, _)| self.compare_positions(a, b, schema));
+        let next = (selected.len() > page_size).then(|| selected[page_size - 1].0.clone());
+        let mut page: Vec<_> = selected
+            .into_iter()
+            .take(page_size)
+            .map(|(_, row)| row)
+            .collect();
+        let rows = order_rows(&mut page, &None, &self.get_selected_columns(schema))?;
+        Ok((rows, next))
+    }
+
+    /// Reads the stored rows of a page of the partition selected by a `SELECT` query: the first
+    /// `page_size` rows after the position `start`, sorted like the rows of `select_page`, and the
+    /// entry of the partition with its deletions. The rows are not filtered with the `WHERE` clause, as
+    /// a replica with an older write of a row would not return the newer one of the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The path of the table dir to execute the query against.
+    /// * `ctx` - The context of the node.
+    /// * `start` - The position of the last row of the previous page, or `None` for the first page.
+    /// * `page_size` - The maximum number of rows read.
+    ///
+    /// # Returns
+    ///
+    /// * The stored rows in key order, so they can be reconciled with the pages of the other replicas.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if `read_stored_rows` fails, or if the page size or the position are not valid.
+    pub fn read_stored_page(
+        &self,
+        table: &Path,
+        ctx: &Context,
+        start: Option<&[String]>,
+        page_size: usize,
+    ) -> std::io::Result<Vec<StoredRow>> {
+        let rows = self.read_stored_rows(table, ctx)?;
+        let schema = get_table_schema(table, ctx)?;
+        self.check_page(&schema, start, page_size)?;
+        let mut positions: Vec<(usize, Vec<String>)> = rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| !row.is_partition())
+            .map(|(i, row)| (i, self.get_position(&row.values(&schema), &schema)))
+            .filter(|(_, position)| {
+                start.is_none_or(|start| self.compare_positions(position, start, &schema).is_gt())
+            })
+            .collect();
+        positions.sort_by(|(_, a), (_, b)| self.compare_positions(a, b, &schema));
+        positions.truncate(page_size);
+        let mut page: Vec<usize> = positions.into_iter().map(|(i, _)| i).collect();
+        page.sort();
+        Ok(rows
+            .into_iter()
+            .enumerate()
+            .filter(|(i, row)| row.is_partition() || page.binary_search(i).is_ok())
+            .map(|(_, row)| row)
+            .collect())
+    }
+
+    /// Returns the position of a row in the pages of a `SELECT` query: the value of its `ORDER BY`
+    /// column, if there is one, followed by its partition and clustering key.
+    pub fn get_position(&self, row: &HashMap<String, String>, schema: &Schema) -> Vec<String> {
+        self.get_position_columns(schema)
+            .iter()
+            .map(|(col, _)| row.get(*col).cloned().unwrap_or_else(|| "NULL".to_string()))
+            .collect()
+    }
+
+    /// Compares two positions returned by `get_position`, with the types of their columns.
+    pub fn compare_positions(&self, a: &[String], b: &[String], schema: &Schema) -> Ordering {
+        self.get_position_columns(schema)
+            .iter()
+            .zip(a.iter().zip(b))
+            .map(|((col, desc), (a, b))| {
+                let ordering = compare_values(schema, col, a, b);
+                if *desc {
+                    ordering.reverse()
+                } else {
+                    ordering
                 }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Returns the columns of the positions of the rows of the table, with whether each one is sorted in
+    /// descending order.
+    fn get_position_columns<'a>(&'a self, schema: &'a Schema) -> Vec<(&'a String, bool)> {
+        let order = match &self.statement {
+            Statement::Select(_, order) => order.as_ref(),
+            _ => None,
+        };
+        let primary_key = schema.get_primary_key();
+        order
+            .map(|(col, mode)| (col, matches!(mode, OrderMode::Desc)))
+            .into_iter()
+            .chain(
+                primary_key
+                    .get_partition_key()
+                    .iter()
+                    .chain(primary_key.get_clustering_key())
+                    .map(|col| (col, false)),
+            )
+            .collect()
+    }
+
+    /// Checks that the page size is positive and that the position has a value for each column of the
+    /// positions of the table.
+    ///
+    /// # Errors
+    ///
+    /// * `Error` if the page size or the position are not valid.
+    pub fn check_page(
+        &self,
+        schema: &Schema,
+        start: Option<&[String]>,
+        page_size: usize,
+    ) -> std::io::Result<()> {
+        if page_size == 0 {
+            return Err(io_error!("The page size must be positive"));
+        }
+        let columns = self.get_position_columns(schema).len();
+        if start.is_some_and(|start| start.len() != columns) {
+            return Err(io_error!("Invalid paging state"));
+        }
+        Ok(())
+    }
+
+    /// Whether the row matches the `WHERE` clause of the query. Every row matches a query without one.
+    fn matches(&self, row: &HashMap<String, String>, schema: &Schema) -> std::io::Result<bool> {
+        match &self.where_clause {
+            Some(where_clause) => where_clause.eval(row, schema),
+            None => Ok(true),
+        }
+    }
+
+    fn order_rows(
+        &self,
+        mut rows: Vec<HashMap<String, String>>,
+        schema: &Schema,
+    ) -> std::io::Result<Option<Vec<Cols>>> {
+        match &self.statement {
+            Statement::Select(_, order) => {
+                order_rows(&mut rows, order, &self.get_selected_columns(schema))
             }
+            _ => Err(io_error!("Only SELECT queries return rows")),
         }
     }
 
@@ -129,12 +569,56 @@ impl Query {
     /// In all other cases, it returns an empty vector.
     pub fn get_keys(&self) -> Vec<(String, String)> {
         match &self.statement {
-            Statement::Select(_, _) => self.where_clause.as_ref().unwrap().get_keys(),
+            Statement::Select(_, _) => self
+                .where_clause
+                .as_ref()
+                .map(WhereClause::get_keys)
+                .unwrap_or_default(),
             Statement::Insert(row) => row.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
             Statement::Update(_) => self.where_clause.as_ref().unwrap().get_keys(),
-            Statement::Delete => self.where_clause.as_ref().unwrap().get_keys(),
+            Statement::Delete(_) => self.where_clause.as_ref().unwrap().get_keys(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the values of the query with their columns: the values written by an `INSERT` or an
+    /// `UPDATE`, and the ones compared in the `WHERE` clause.
+    pub(crate) fn get_values(&self) -> Vec<(String, String)> {
+        let mut values: Vec<(String, String)> = match &self.statement {
+            Statement::Insert(row) | Statement::Update(row) => {
+                row.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+            }
             _ => Vec::new(),
+        };
+        if let Some(where_clause) = &self.where_clause {
+            values.extend(where_clause.get_keys());
+        }
+        values
+    }
+
+    /// Replaces the bind markers of a query parsed with `prepare_query` with their values, given in the
+    /// order the markers appear in. A `NULL` value is given as `"NULL"`.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if there is no value for a bind marker.
+    pub fn bind(&mut self, values: &[String]) -> std::io::Result<()> {
+        let mut targets: Vec<&mut String> = match &mut self.statement {
+            Statement::Insert(row) | Statement::Update(row) => row.values_mut().collect(),
+            _ => Vec::new(),
+        };
+        if let Some(where_clause) = &mut self.where_clause {
+            targets.extend(where_clause.get_values_mut());
+        }
+        for target in targets {
+            if let Some(index) = get_placeholder_index(target) {
+                let Some(value) = values.get(index) else {
+                    return Err(io_error!(format!("No value for bind marker {}", index + 1)));
+                };
+                value.clone_into(target);
+            }
         }
+        Ok(())
     }
 
     pub fn get_cols(&self) -> Vec<String> {
@@ -144,44 +628,131 @@ impl Query {
         }
     }
 
+    /// Returns the columns selected by a `SELECT` query, with `*` replaced by every column of the table:
+    /// the partition key, then the clustering key, then the rest of them sorted by name.
+    pub fn get_selected_columns(&self, schema: &Schema) -> Vec<String> {
+        let cols = self.get_cols();
+        if cols != ["*"] {
+            return cols;
+        }
+        let primary_key = schema.get_primary_key();
+        let mut regular: Vec<String> = schema
+            .get_columns()
+            .into_iter()
+            .filter(|col| {
+                !primary_key.get_partition_key().contains(col)
+                    && !primary_key.get_clustering_key().contains(col)
+            })
+            .collect();
+        regular.sort();
+        primary_key
+            .get_partition_key()
+            .iter()
+            .chain(primary_key.get_clustering_key())
+            .cloned()
+            .chain(regular)
+            .collect()
+    }
+
     pub fn is_ddl(&self) -> bool {
         matches!(
             self.statement,
-            Statement::CreateTable(_) | Statement::DropTable
+            Statement::CreateTable(_)
+                | Statement::DropTable
+                | Statement::CreateKeyspace(_)
+                | Statement::AlterKeyspace(_, _)
+                | Statement::DropKeyspace
+        )
+    }
+
+    /// Whether the query is about a keyspace rather than a table, so it is processed against the path of the
+    /// keyspace dir.
+    pub fn is_keyspace_query(&self) -> bool {
+        matches!(
+            self.statement,
+            Statement::CreateKeyspace(_)
+                | Statement::AlterKeyspace(_, _)
+                | Statement::DropKeyspace
+                | Statement::Use
         )
     }
 
+    /// Whether the query is a `USE`, which changes the keyspace of the connection.
+    pub fn is_use(&self) -> bool {
+        matches!(self.statement, Statement::Use)
+    }
+
+    /// Whether the query is a batch of writes.
+    pub fn is_batch(&self) -> bool {
+        matches!(self.statement, Statement::Batch(_, _))
+    }
+
+    /// Returns the kind and the statements of a batch, with the names of their tables.
+    pub fn get_batch(&self) -> Option<(BatchType, &[(Query, String)])> {
+        match &self.statement {
+            Statement::Batch(batch_type, statements) => Some((*batch_type, statements)),
+            _ => None,
+        }
+    }
+
+    /// Whether the query is a `DELETE`, which may delete a whole partition or a range of its rows.
+    pub fn is_delete(&self) -> bool {
+        matches!(self.statement, Statement::Delete(_))
+    }
+
     pub fn is_not_select(&self) -> bool {
         !matches!(self.statement, Statement::Select(_, _))
     }
 
-    pub fn add_col(&mut self, col: &str, val: &str) {
-        match &mut self.statement {
-            Statement::Select(cols, _) => cols.push(col.to_string()),
-            Statement::Insert(row) | Statement::Update(row) => {
-                row.insert(col.to_string(), val.to_string());
-            }
-            _ => {}
-        }
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
     }
 
-    pub fn remove_col(&mut self, col: &str) {
-        match &mut self.statement {
-            Statement::Select(cols, _) => {
-                if let Some(idx) = cols.iter().position(|c| c == col) {
-                    cols.remove(idx);
-                }
-            }
-            Statement::Insert(row) | Statement::Update(row) => {
-                row.remove(col);
-            }
-            _ => {}
-        }
+    /// Sets the write time of the query, so all the replicas write it with the same timestamp.
+    pub fn set_timestamp(&mut self, timestamp: i64) {
+        self.timestamp = Some(timestamp);
+    }
+
+    pub fn ttl(&self) -> Option<u64> {
+        self.ttl
+    }
+
+    /// Sets the seconds after which the cells written by the query expire.
+    pub fn set_ttl(&mut self, ttl: u64) {
+        self.ttl = Some(ttl);
+    }
+}
+
+/// Returns the schema of the table of a table dir.
+fn get_table_schema(table: &Path, ctx: &Context) -> std::io::Result<Schema> {
+    let ks = table
+        .parent()
+        .and_then(|ks| ks.file_name())
+        .and_then(|ks| ks.to_str())
+        .ok_or(io_error!("Invalid table path"))?;
+    let table_name = table
+        .file_name()
+        .and_then(|table| table.to_str())
+        .ok_or(io_error!("Invalid table path"))?;
+    ctx.get_table_schema(ks, table_name)
+}
+
+/// Compares two values of a column with its type, where `NULL` is lower than every other value. Values
+/// that are not of the type of the column are compared as text.
+fn compare_values(schema: &Schema, col: &str, a: &str, b: &str) -> Ordering {
+    match (a, b) {
+        ("NULL", "NULL") => Ordering::Equal,
+        ("NULL", _) => Ordering::Less,
+        (_, "NULL") => Ordering::Greater,
+        _ => schema
+            .get_schema_type(col)
+            .and_then(|schema_type| schema_type.cmp(a, b).ok())
+            .unwrap_or_else(|| a.cmp(b)),
     }
 }
 
 fn order_rows(
-    rows: &mut Vec<HashMap<String, String>>,
+    rows: &mut [HashMap<String, String>],
     order: &Option<(String, OrderMode)>,
     to_print: &[String],
 ) -> std::io::Result<Option<Vec<Cols>>> {
@@ -189,8 +760,13 @@ fn order_rows(
         return Ok(None);
     }
     for col in to_print {
-        if !rows[0].contains_key(col) {
-            return Err(io_error!(format!("Column '{col}' does not exist")));
+        // The TTL of a cell without one is `NULL`, so only its column must exist.
+        let column = col
+            .strip_prefix("TTL(")
+            .and_then(|col| col.strip_suffix(')'))
+            .unwrap_or(col);
+        if !rows[0].contains_key(column) {
+            return Err(io_error!(format!("Column '{column}' does not exist")));
         }
     }
     if let Some((order_by, order_mode)) = order {
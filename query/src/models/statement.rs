@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 
-use db::{PrimaryKey, Schema};
+use db::{Options, PrimaryKey, Replication, Schema, StoredRow};
 use serde::{Deserialize, Serialize};
 use shared::io_error;
 
+use super::{batch::BatchType, query::Query};
+
 /// Represents the columns selected in a SQL query.
 pub(crate) type Cols = Vec<String>;
 /// Represents the optional ORDER BY clause in a SQL query.
@@ -25,10 +27,22 @@ pub(crate) enum Statement {
     Insert(HashMap<String, String>),
     /// Update (new row)
     Update(HashMap<String, String>),
-    Delete,
+    /// Delete (columns to delete, or none to delete the whole rows)
+    Delete(Vec<String>),
     ///Create table (columns<name, type>). Partition key is under the key "PARTITION_KEY", same for clustering key.
     CreateTable(Schema),
     DropTable,
+    /// Create keyspace (options)
+    CreateKeyspace(Options),
+    /// Alter keyspace (replication, durable writes). The options that are not given keep their value.
+    AlterKeyspace(Option<Replication>, Option<bool>),
+    DropKeyspace,
+    /// Use. Sets the keyspace of the tables that are not qualified with one in the next queries of the connection.
+    Use,
+    /// Repair (stored rows). Merges the rows that a replica is missing, found by a read repair.
+    Repair(Vec<StoredRow>),
+    /// Batch (kind, statements with the names of their tables). Applies several writes together.
+    Batch(BatchType, Vec<(Query, String)>),
 }
 
 impl Statement {
@@ -47,7 +61,7 @@ impl Statement {
             "SELECT" => Ok(Statement::Select(Vec::new(), None)),
             "INSERT" => Ok(Statement::Insert(HashMap::new())),
             "UPDATE" => Ok(Statement::Update(HashMap::new())),
-            "DELETE" => Ok(Statement::Delete),
+            "DELETE" => Ok(Statement::Delete(Vec::new())),
             "CREATE TABLE" => Ok(Statement::CreateTable(Schema::new(
                 HashMap::new(),
                 PrimaryKey::new(Vec::new(), Vec::new()),
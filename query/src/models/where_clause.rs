@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Bound};
 
 use db::Schema;
 use serde::{Deserialize, Serialize};
@@ -36,6 +36,26 @@ pub(crate) enum WhereClause {
     Tree(Box<WhereClause>, Operator, Box<WhereClause>),
 }
 
+/// The rows of a partition selected by a `WHERE` clause that only restricts their primary key: the
+/// values of the partition key and of the first clustering columns, and the bounds of the next one.
+#[derive(Debug, PartialEq)]
+pub(crate) struct KeyRestriction {
+    pub(crate) key: HashMap<String, String>,
+    pub(crate) bounds: (Bound<String>, Bound<String>),
+}
+
+impl KeyRestriction {
+    /// Whether the restriction selects a single row.
+    pub(crate) fn is_row(&self, schema: &Schema) -> bool {
+        let primary_key = schema.get_primary_key();
+        matches!(self.bounds, (Bound::Unbounded, Bound::Unbounded))
+            && primary_key
+                .get_clustering_key()
+                .iter()
+                .all(|col| self.key.contains_key(col))
+    }
+}
+
 impl WhereClause {
     /// Parses a vector of string parts into a `WhereClause` structure.
     ///
@@ -265,10 +285,10 @@ impl WhereClause {
         schema: &Schema,
     ) -> std::io::Result<bool> {
         let owned_value1 = val1.to_string();
-        let value1 = row.get(val1).unwrap_or_else(|| &owned_value1);
+        let value1 = row.get(val1).unwrap_or(&owned_value1);
 
         let owned_value2 = val2.to_string();
-        let value2 = row.get(val2).unwrap_or_else(|| &owned_value2);
+        let value2 = row.get(val2).unwrap_or(&owned_value2);
 
         if value1 == "NULL" || value2 == "NULL" {
             return Ok(value1 == value2);
@@ -288,6 +308,125 @@ impl WhereClause {
         }
     }
 
+    /// Returns the rows selected by the clause if it only restricts the primary key, so they can be
+    /// deleted without reading them.
+    ///
+    /// The clause must be a conjunction of an equality for each partition key column, equalities for the
+    /// first clustering columns and optionally bounds for the next clustering column.
+    pub(crate) fn get_key_restriction(&self, schema: &Schema) -> Option<KeyRestriction> {
+        let primary_key = schema.get_primary_key();
+        let partition_key = primary_key.get_partition_key();
+        let clustering_key = primary_key.get_clustering_key();
+        let mut key = HashMap::new();
+        let (mut start, mut end) = (Bound::Unbounded, Bound::Unbounded);
+        let mut range_col = None;
+        for comp in self.get_conjunction()? {
+            let (col, value) = match comp {
+                Comparator::Equal(col, value, false)
+                    if partition_key.contains(col) || clustering_key.contains(col) =>
+                {
+                    if key.insert(col.clone(), value.clone()).is_some() {
+                        return None;
+                    }
+                    continue;
+                }
+                Comparator::GreaterThan(col, value, false)
+                | Comparator::GreaterThanOrEqual(col, value, false)
+                | Comparator::LessThan(col, value, false)
+                | Comparator::LessThanOrEqual(col, value, false)
+                    if clustering_key.contains(col) =>
+                {
+                    (col, value.clone())
+                }
+                _ => return None,
+            };
+            if range_col
+                .replace(col)
+                .is_some_and(|range_col| range_col != col)
+            {
+                return None;
+            }
+            let bound = match comp {
+                Comparator::GreaterThan(..) | Comparator::GreaterThanOrEqual(..) => &mut start,
+                _ => &mut end,
+            };
+            if !matches!(bound, Bound::Unbounded) {
+                return None;
+            }
+            *bound = match comp {
+                Comparator::GreaterThan(..) | Comparator::LessThan(..) => Bound::Excluded(value),
+                _ => Bound::Included(value),
+            };
+        }
+        if partition_key.iter().any(|col| !key.contains_key(col)) {
+            return None;
+        }
+        let prefix_len = clustering_key
+            .iter()
+            .take_while(|col| key.contains_key(*col))
+            .count();
+        if clustering_key[prefix_len..]
+            .iter()
+            .any(|col| key.contains_key(col))
+            || range_col.is_some_and(|col| clustering_key.get(prefix_len) != Some(col))
+        {
+            return None;
+        }
+        Some(KeyRestriction {
+            key,
+            bounds: (start, end),
+        })
+    }
+
+    /// Returns the values of the partition key columns if the clause restricts them all to a single value.
+    pub(crate) fn get_partition_key(&self, schema: &Schema) -> Option<HashMap<String, String>> {
+        let conjunction = self.get_conjunction().unwrap_or_default();
+        schema
+            .get_primary_key()
+            .get_partition_key()
+            .iter()
+            .map(|col| {
+                conjunction.iter().find_map(|comp| match comp {
+                    Comparator::Equal(left, value, false) if left == col => {
+                        Some((col.clone(), value.clone()))
+                    }
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the comparisons of the clause if it only joins them with `AND`.
+    fn get_conjunction(&self) -> Option<Vec<&Comparator>> {
+        match self {
+            WhereClause::Comp(comp) => Some(vec![comp]),
+            WhereClause::Tree(left, Operator::And, right) => {
+                let mut comparators = left.get_conjunction()?;
+                comparators.extend(right.get_conjunction()?);
+                Some(comparators)
+            }
+            WhereClause::Tree(..) => None,
+        }
+    }
+
+    /// Returns the values compared by the clause, so they can be bound.
+    pub(crate) fn get_values_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            WhereClause::Comp(
+                Comparator::Equal(_, val, _)
+                | Comparator::GreaterThan(_, val, _)
+                | Comparator::LessThan(_, val, _)
+                | Comparator::GreaterThanOrEqual(_, val, _)
+                | Comparator::LessThanOrEqual(_, val, _),
+            ) => vec![val],
+            WhereClause::Tree(left, _, right) => {
+                let mut values = left.get_values_mut();
+                values.extend(right.get_values_mut());
+                values
+            }
+        }
+    }
+
     pub(crate) fn get_keys(&self) -> Vec<(String, String)> {
         match self {
             WhereClause::Comp(comp) => match comp {
@@ -331,6 +470,47 @@ mod tests {
         )
     }
 
+    fn where_clause(clause: &str) -> WhereClause {
+        let parts: Vec<String> = clause.split_whitespace().map(str::to_string).collect();
+        WhereClause::new(&parts).unwrap().0
+    }
+
+    #[test]
+    fn test_key_restriction() {
+        let schema = get_schema();
+        let restriction = where_clause("name = Alice AND age > 30 AND age <= 40")
+            .get_key_restriction(&schema)
+            .unwrap();
+        assert_eq!(
+            restriction,
+            KeyRestriction {
+                key: HashMap::from([("name".to_string(), "Alice".to_string())]),
+                bounds: (
+                    Bound::Excluded("30".to_string()),
+                    Bound::Included("40".to_string())
+                ),
+            }
+        );
+        assert!(!restriction.is_row(&schema));
+        assert!(where_clause("name = Alice AND age = 30")
+            .get_key_restriction(&schema)
+            .unwrap()
+            .is_row(&schema));
+
+        for clause in [
+            "age = 30",
+            "name = Alice AND salary = 10",
+            "name = Alice OR age = 30",
+            "name = Alice AND age > 30 AND age > 40",
+        ] {
+            assert_eq!(where_clause(clause).get_key_restriction(&schema), None);
+        }
+        assert_eq!(
+            where_clause("name = Alice AND salary = 10").get_partition_key(&schema),
+            Some(HashMap::from([("name".to_string(), "Alice".to_string())]))
+        );
+    }
+
     #[test]
     fn test_single_comparator() {
         let input = vec!["age".to_string(), ">".to_string(), "30".to_string()];
@@ -524,7 +704,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 
     #[test]
@@ -537,7 +717,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, false);
+        assert!(!result);
     }
 
     #[test]
@@ -550,7 +730,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 
     #[test]
@@ -585,7 +765,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 
     #[test]
@@ -607,7 +787,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 
     #[test]
@@ -625,7 +805,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 
     #[test]
@@ -638,7 +818,7 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 
     #[test]
@@ -662,6 +842,6 @@ mod tests {
         let schema = get_schema();
 
         let result = where_clause.eval(&row, &schema).expect("Evaluation failed");
-        assert_eq!(result, true);
+        assert!(result);
     }
 }
@@ -0,0 +1,136 @@
+use shared::io_error;
+
+use crate::{
+    models::{batch::BatchType, query::Query},
+    utils::tokens::get_timestamp_from_vec,
+};
+
+use super::query::process_query;
+
+/// Processes a `BEGIN [UNLOGGED | COUNTER] BATCH [USING TIMESTAMP <microseconds>] <statements> APPLY BATCH`
+/// query, where the statements are `INSERT`, `UPDATE` and `DELETE` queries separated by `;`.
+///
+/// # Returns
+///
+/// * `std::io::Result<(Query, String)>`: A tuple containing the parsed `Query` and an empty name, since
+///   each statement of the batch has its own table.
+///
+/// # Errors
+///
+/// * Returns an Error if there are syntax errors in the batch or in any of its statements, or if a
+///   statement is not an `INSERT`, an `UPDATE` or a `DELETE`.
+pub(crate) fn process_batch(query: &str) -> std::io::Result<(Query, String)> {
+    let invalid = || {
+        io_error!(
+            "BATCH query should look like: BEGIN [UNLOGGED | COUNTER] BATCH [USING TIMESTAMP <microseconds>] <statement>; ... APPLY BATCH"
+        )
+    };
+    let statements = split_statements(query);
+    let mut statements = statements
+        .iter()
+        .map(|statement| statement.split_whitespace().collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+        .collect::<Vec<_>>();
+
+    let first = statements.first_mut().ok_or_else(invalid)?;
+    let (batch_type, skip) = match first.as_slice() {
+        ["BEGIN", "BATCH", ..] => (BatchType::Logged, 2),
+        ["BEGIN", "UNLOGGED", "BATCH", ..] => (BatchType::Unlogged, 3),
+        ["BEGIN", "COUNTER", "BATCH", ..] => (BatchType::Counter, 3),
+        _ => return Err(invalid()),
+    };
+    first.drain(..skip);
+    let timestamp = if first.first() == Some(&"USING") {
+        let using: Vec<String> = first
+            .drain(..3.min(first.len()))
+            .map(String::from)
+            .collect();
+        Some(get_timestamp_from_vec(&using)?)
+    } else {
+        None
+    };
+
+    let last = statements.last_mut().ok_or_else(invalid)?;
+    if !last.ends_with(&["APPLY", "BATCH"]) {
+        return Err(invalid());
+    }
+    last.truncate(last.len() - 2);
+
+    let mut queries = Vec::new();
+    for words in statements.iter().filter(|words| !words.is_empty()) {
+        queries.push(process_query(&words.join(" "))?);
+    }
+    let mut batch = Query::batch(batch_type, queries)?;
+    if let Some(timestamp) = timestamp {
+        batch.set_timestamp(timestamp);
+    }
+    Ok((batch, String::new()))
+}
+
+/// Splits the statements of a batch on the `;` outside of its string literals.
+fn split_statements(query: &str) -> Vec<String> {
+    let mut statements = vec![String::new()];
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                statements.last_mut().unwrap().push(c);
+            }
+            ';' if !quoted => statements.push(String::new()),
+            _ => statements.last_mut().unwrap().push(c),
+        }
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_batch() {
+        let (query, name) = process_batch(
+            "BEGIN UNLOGGED BATCH USING TIMESTAMP 10 \
+             INSERT INTO users (id, name) VALUES (1, 'Sapo Pepe'); \
+             UPDATE ks.emails SET email = 'a@b.com' WHERE id = 1; \
+             DELETE FROM users WHERE id = 2 \
+             APPLY BATCH;",
+        )
+        .unwrap();
+        assert!(name.is_empty());
+        assert_eq!(query.timestamp(), Some(10));
+        let (batch_type, statements) = query.get_batch().unwrap();
+        assert_eq!(batch_type, BatchType::Unlogged);
+        let tables: Vec<&str> = statements.iter().map(|(_, table)| table.as_str()).collect();
+        assert_eq!(tables, ["users", "ks.emails", "users"]);
+        let mut keys = statements[0].0.get_keys();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                ("id".to_string(), "1".to_string()),
+                ("name".to_string(), "Sapo Pepe".to_string())
+            ]
+        );
+        assert!(statements[2].0.is_delete());
+
+        assert_eq!(
+            split_statements("INSERT INTO t (id) VALUES ('a;b'); APPLY BATCH"),
+            ["INSERT INTO t (id) VALUES ('a;b')", " APPLY BATCH"]
+        );
+    }
+
+    #[test]
+    fn test_process_invalid_batch() {
+        // Without APPLY BATCH.
+        assert!(process_batch("BEGIN BATCH INSERT INTO t (id) VALUES (1);").is_err());
+        // Without statements.
+        assert!(process_batch("BEGIN BATCH APPLY BATCH").is_err());
+        // Only writes can be batched.
+        assert!(process_batch("BEGIN BATCH SELECT * FROM t WHERE id = 1; APPLY BATCH").is_err());
+        assert!(
+            process_batch("BEGIN LOGGED BATCH INSERT INTO t (id) VALUES (1); APPLY BATCH").is_err()
+        );
+    }
+}
@@ -1,11 +1,15 @@
 use shared::io_error;
 
-use crate::models::{query::Query, statement::Statement, where_clause::WhereClause};
+use crate::{
+    models::{query::Query, statement::Statement, where_clause::WhereClause},
+    utils::tokens::{get_columns_from_vec, get_timestamp_from_vec},
+};
 
 /// Processes a `DELETE` query and prepares the `Query` and table name.
 ///
-/// This function parses the `DELETE` statement, including evaluating WHERE clauses,
-/// and constructs a `Query` object.
+/// This function parses the `DELETE` statement, including the optional columns to delete,
+/// an optional `USING TIMESTAMP` clause and evaluating WHERE clauses, and constructs a `Query` object.
+/// Without columns, the whole rows are deleted.
 ///
 /// # Arguments
 ///
@@ -20,15 +24,33 @@ use crate::models::{query::Query, statement::Statement, where_clause::WhereClaus
 ///
 /// * Returns an Error if there are syntax errors in the `DELETE` query.
 pub(crate) fn process_delete(parts: &[String]) -> std::io::Result<(Query, String)> {
-    if parts.len() < 6 || parts[0] != "FROM" || parts[2] != "WHERE" || parts[4] != "=" {
+    let Some(from) = parts.iter().position(|s| s == "FROM") else {
+        return Err(io_error!("No FROM keyword"));
+    };
+    let columns = get_columns_from_vec(&parts[..from])?;
+    if columns.iter().any(|col| col.is_empty() || col == "*") {
+        return Err(io_error!("Invalid column name"));
+    }
+    let parts = &parts[from..];
+    let mut timestamp = None;
+    let without_timestamp: Vec<String>;
+    let parts = if parts.get(2).is_some_and(|part| part == "USING") {
+        timestamp = Some(get_timestamp_from_vec(parts.get(2..5).unwrap_or_default())?);
+        without_timestamp = parts[..2].iter().chain(&parts[5..]).cloned().collect();
+        &without_timestamp
+    } else {
+        parts
+    };
+    if parts.len() < 6 || parts[2] != "WHERE" {
         return Err(io_error!(
-            "DELETE query should look like this: DELETE FROM <table> WHERE <cond>"
+            "DELETE query should look like this: DELETE [<col>, ...] FROM <table> [USING TIMESTAMP <microseconds>] WHERE <cond>"
         ));
     }
-    let statement = Statement::new("DELETE")?;
+    let statement = Statement::Delete(columns);
     let (where_clause, _) = WhereClause::new(&parts[3..])?;
-    Ok((
-        Query::new(statement, Some(where_clause)),
-        parts[1].to_owned(),
-    ))
+    let mut query = Query::new(statement, Some(where_clause));
+    if let Some(timestamp) = timestamp {
+        query.set_timestamp(timestamp);
+    }
+    Ok((query, parts[1].to_owned()))
 }
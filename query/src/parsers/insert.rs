@@ -2,13 +2,13 @@ use shared::io_error;
 
 use crate::{
     models::{query::Query, statement::Statement},
-    utils::tokens::get_columns_from_vec,
+    utils::tokens::{get_columns_from_vec, get_write_options_from_vec},
 };
 
 /// Processes an `INSERT` query and prepares the `Query` and table name.
 ///
-/// This function parses the `INSERT` statement, including columns and values,
-/// and constructs a `Query` object.
+/// This function parses the `INSERT` statement, including columns, values and
+/// an optional `USING TIMESTAMP` and `TTL` clause, and constructs a `Query` object.
 ///
 /// # Arguments
 ///
@@ -26,6 +26,13 @@ pub(crate) fn process_insert(tokens: &[String]) -> std::io::Result<(Query, Strin
     let Some(values) = tokens.iter().position(|s| s == "VALUES") else {
         return Err(io_error!("No VALUES keyword"));
     };
+    let (tokens, (timestamp, ttl)) = match tokens.iter().rposition(|s| s == "USING") {
+        Some(using) if using > values => (
+            &tokens[..using],
+            get_write_options_from_vec(&tokens[using..])?,
+        ),
+        _ => (tokens, (None, None)),
+    };
     if tokens.len() < 9
         || tokens[0] != "INTO"
         || tokens[2] != "("
@@ -34,7 +41,7 @@ pub(crate) fn process_insert(tokens: &[String]) -> std::io::Result<(Query, Strin
         || tokens.last() != Some(&")".to_string())
     {
         return Err(io_error!(
-            "INSERT query should follow this pattern: INSERT INTO <table> (col) VALUES (value)"
+            "INSERT query should follow this pattern: INSERT INTO <table> (col) VALUES (value) [USING TIMESTAMP <microseconds>] [AND TTL <seconds>]"
         ));
     }
     let mut statement = Statement::new("INSERT")?;
@@ -43,5 +50,12 @@ pub(crate) fn process_insert(tokens: &[String]) -> std::io::Result<(Query, Strin
     for i in 0..cols.len() {
         statement.add_row(cols[i].to_owned(), new_values[i].to_owned())?;
     }
-    Ok((Query::new(statement, None), tokens[1].to_owned()))
+    let mut query = Query::new(statement, None);
+    if let Some(timestamp) = timestamp {
+        query.set_timestamp(timestamp);
+    }
+    if let Some(ttl) = ttl {
+        query.set_ttl(ttl);
+    }
+    Ok((query, tokens[1].to_owned()))
 }
@@ -0,0 +1,101 @@
+use db::{Options, Replication};
+use shared::io_error;
+
+use crate::{
+    models::statement::Statement,
+    utils::tokens::{get_options_from_vec, parse_map},
+    Query,
+};
+
+/// Process a keyspace creation query.
+///
+/// # Arguments
+///
+/// * `tokens` - A slice of strings containing the tokens of the query.
+///
+/// The keyspace must set its `replication` with `WITH replication = {...}`, and may set `durable_writes`,
+/// which is `true` by default.
+///
+/// # Returns
+///
+/// * A `Query` object
+/// * A `String` containing the keyspace name
+///
+/// # Errors
+///
+/// * Returns an error if there is a syntax error, if the replication is missing or if an option is invalid.
+pub(crate) fn process_keyspace_creation(tokens: &[String]) -> std::io::Result<(Query, String)> {
+    if tokens.len() < 6 || tokens[0] != "KEYSPACE" || tokens[2] != "WITH" {
+        return Err(io_error!(
+            "CREATE KEYSPACE query should look like: CREATE KEYSPACE <keyspace> WITH replication = {'class': <class>, ...} [AND durable_writes = <bool>]"
+        ));
+    }
+    let (replication, durable_writes) = process_keyspace_options(&tokens[3..])?;
+    let replication = replication.ok_or(io_error!("Missing replication option"))?;
+    let options = Options {
+        durable_writes: durable_writes.unwrap_or(true),
+        replication,
+    };
+    let statement = Statement::CreateKeyspace(options);
+    Ok((Query::new(statement, None), tokens[1].to_owned()))
+}
+
+/// Process a keyspace alteration query, which changes the options it is given.
+///
+/// # Returns
+///
+/// * A `Query` object
+/// * A `String` containing the keyspace name
+///
+/// # Errors
+///
+/// * Returns an error if there is a syntax error or if an option is invalid.
+pub(crate) fn process_keyspace_alteration(tokens: &[String]) -> std::io::Result<(Query, String)> {
+    if tokens.len() < 6 || tokens[0] != "KEYSPACE" || tokens[2] != "WITH" {
+        return Err(io_error!(
+            "ALTER KEYSPACE query should look like: ALTER KEYSPACE <keyspace> WITH replication = {'class': <class>, ...} [AND durable_writes = <bool>]"
+        ));
+    }
+    let (replication, durable_writes) = process_keyspace_options(&tokens[3..])?;
+    let statement = Statement::AlterKeyspace(replication, durable_writes);
+    Ok((Query::new(statement, None), tokens[1].to_owned()))
+}
+
+pub(crate) fn process_keyspace_deletion(tokens: &[String]) -> std::io::Result<(Query, String)> {
+    if tokens.len() != 2 || tokens[0] != "KEYSPACE" {
+        return Err(io_error!(
+            "DROP KEYSPACE query should look like: DROP KEYSPACE <keyspace>"
+        ));
+    }
+    let statement = Statement::DropKeyspace;
+    Ok((Query::new(statement, None), tokens[1].to_owned()))
+}
+
+pub(crate) fn process_use(tokens: &[String]) -> std::io::Result<(Query, String)> {
+    if tokens.len() != 1 {
+        return Err(io_error!("USE query should look like: USE <keyspace>"));
+    }
+    let statement = Statement::Use;
+    Ok((Query::new(statement, None), tokens[0].to_owned()))
+}
+
+fn process_keyspace_options(
+    tokens: &[String],
+) -> std::io::Result<(Option<Replication>, Option<bool>)> {
+    let (mut replication, mut durable_writes) = (None, None);
+    for (option, value) in get_options_from_vec(tokens)? {
+        match option.as_str() {
+            "replication" => replication = Some(Replication::new(&parse_map(&value)?)?),
+            "durable_writes" => {
+                durable_writes = Some(
+                    value
+                        .to_lowercase()
+                        .parse()
+                        .map_err(|_| io_error!(format!("Invalid durable_writes '{value}'")))?,
+                );
+            }
+            _ => return Err(io_error!(format!("Unknown keyspace option '{option}'"))),
+        }
+    }
+    Ok((replication, durable_writes))
+}
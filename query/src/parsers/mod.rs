@@ -1,5 +1,7 @@
+mod batch;
 mod delete;
 mod insert;
+mod keyspace;
 pub mod query;
 mod select;
 mod table;
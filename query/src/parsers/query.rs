@@ -1,10 +1,21 @@
 use shared::io_error;
 
-use crate::{models::query::Query, utils::tokens::separate_parenthesis};
+use crate::{
+    models::{
+        bind_marker::{placeholder, replace_bind_markers, BindMarker},
+        query::Query,
+    },
+    utils::tokens::separate_parenthesis,
+};
 
 use super::{
+    batch::process_batch,
     delete::process_delete,
     insert::process_insert,
+    keyspace::{
+        process_keyspace_alteration, process_keyspace_creation, process_keyspace_deletion,
+        process_use,
+    },
     select::process_select,
     table::{process_table_creation, process_table_deletion},
     update::process_update,
@@ -13,8 +24,8 @@ use super::{
 /// Processes a raw SQL query string and determines the query type.
 ///
 /// This function splits the SQL query string into individual parts and identifies
-/// the query type (`SELECT`, `INSERT`, `UPDATE`, `DELETE`). It then delegates the
-/// parsing to the appropriate processing function.
+/// the query type (`SELECT`, `INSERT`, `UPDATE`, `DELETE`, the table and keyspace DDL and `USE`).
+/// It then delegates the parsing to the appropriate processing function.
 ///
 /// # Arguments
 ///
@@ -23,13 +34,18 @@ use super::{
 /// # Returns
 ///
 /// * `std::io::Result<(Query, String)>`: A tuple containing the parsed `Query`
-///   and the path to the table, or an Error if the query is invalid.
+///   and the name of the table, or of the keyspace for the keyspace queries, or an Error if the query is invalid.
+///   Table names may be qualified with their keyspace as `<keyspace>.<table>`.
 ///
 /// # Errors
 ///
 /// * Returns an Error if the query type is not recognized or
 ///   if there are errors in processing the query.
 pub fn process_query(query: &str) -> std::io::Result<(Query, String)> {
+    // The statements of a batch are separated by `;`, so they are split before the query is.
+    if query.split_whitespace().next() == Some("BEGIN") {
+        return process_batch(query);
+    }
     let query_vec = separate_parenthesis(
         &query
             .replace(';', "")
@@ -38,16 +54,23 @@ pub fn process_query(query: &str) -> std::io::Result<(Query, String)> {
             .filter(|s| !s.is_empty())
             .collect(),
     )?;
+    if query_vec.first().is_some_and(|keyword| keyword == "USE") {
+        return process_use(&query_vec[1..]);
+    }
     if query_vec.len() <= 2 {
         return Err(io_error!("Invalid syntax"));
     }
     let rest_of_query = query_vec[1..].to_vec();
+    let is_keyspace = rest_of_query[0] == "KEYSPACE";
     match query_vec[0].as_str() {
         "SELECT" => process_select(&rest_of_query),
         "INSERT" => process_insert(&rest_of_query),
         "UPDATE" => process_update(&rest_of_query),
         "DELETE" => process_delete(&rest_of_query),
+        "CREATE" if is_keyspace => process_keyspace_creation(&rest_of_query),
         "CREATE" => process_table_creation(&rest_of_query),
+        "ALTER" => process_keyspace_alteration(&rest_of_query),
+        "DROP" if is_keyspace => process_keyspace_deletion(&rest_of_query),
         "DROP" => process_table_deletion(&rest_of_query),
         query => Err(io_error!(format!(
             "Invalid query: cannot recognize query '{query}'",
@@ -55,6 +78,44 @@ pub fn process_query(query: &str) -> std::io::Result<(Query, String)> {
     }
 }
 
+/// Parses a query with bind markers, `?` or `:<name>`, in place of the values of its columns, so it
+/// can be executed many times with different values.
+///
+/// # Returns
+///
+/// * `std::io::Result<(Query, String, Vec<BindMarker>)>`: The parsed `Query`, to be bound with
+///   `Query::bind`, the name of the table as in `process_query`, and the bind markers in the order they
+///   appear in.
+///
+/// # Errors
+///
+/// * Returns an Error if the query is invalid, or if a bind marker is not the value of a column.
+pub fn prepare_query(query: &str) -> std::io::Result<(Query, String, Vec<BindMarker>)> {
+    let (replaced, names) = replace_bind_markers(query)?;
+    let (query, table) = process_query(&replaced)?;
+    if query.is_batch() && !names.is_empty() {
+        return Err(io_error!(
+            "BATCH queries cannot have bind markers, send a BATCH message with the values instead"
+        ));
+    }
+    let values = query.get_values();
+    let mut markers = Vec::with_capacity(names.len());
+    for (index, name) in names.into_iter().enumerate() {
+        let placeholder = placeholder(index);
+        let Some((column, _)) = values.iter().find(|(_, value)| *value == placeholder) else {
+            return Err(io_error!(format!(
+                "Bind marker {} is not the value of a column",
+                index + 1
+            )));
+        };
+        markers.push(BindMarker {
+            name,
+            column: column.to_owned(),
+        });
+    }
+    Ok((query, table, markers))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,6 +200,62 @@ mod tests {
         assert_eq!(path, "clients");
     }
 
+    #[test]
+    fn test_process_query_valid_delete_columns() {
+        let query_str = "DELETE email, age FROM clients USING TIMESTAMP 10 WHERE id = 1";
+        let (query, path) = process_query(query_str).unwrap();
+        assert_eq!(path, "clients");
+        assert!(query.is_delete());
+        assert!(process_query("DELETE * FROM clients WHERE id = 1").is_err());
+    }
+
+    #[test]
+    fn test_process_query_valid_using_timestamp() {
+        let queries = [
+            "INSERT INTO clients (id, name) VALUES (1, 'Pepe') USING TIMESTAMP 1700000000000000",
+            "UPDATE clients USING TIMESTAMP 1700000000000000 SET name = 'Pepe' WHERE id = 1",
+            "DELETE FROM clients USING TIMESTAMP 1700000000000000 WHERE id = 1",
+        ];
+        for query_str in queries {
+            let (query, path) = process_query(query_str).unwrap();
+            assert_eq!(path, "clients");
+            assert_eq!(query.timestamp(), Some(1700000000000000));
+        }
+        let (query, _) = process_query("DELETE FROM clients WHERE id = 1").unwrap();
+        assert_eq!(query.timestamp(), None);
+    }
+
+    #[test]
+    fn test_process_query_valid_using_ttl() {
+        let queries = [
+            "INSERT INTO clients (id, name) VALUES (1, 'Pepe') USING TTL 60",
+            "INSERT INTO clients (id, name) VALUES (1, 'Pepe') USING TIMESTAMP 10 AND TTL 60",
+            "UPDATE clients USING TTL 60 SET name = 'Pepe' WHERE id = 1",
+            "UPDATE clients USING TTL 60 AND TIMESTAMP 10 SET name = 'Pepe' WHERE id = 1",
+        ];
+        for query_str in queries {
+            let (query, path) = process_query(query_str).unwrap();
+            assert_eq!(path, "clients");
+            assert_eq!(query.ttl(), Some(60));
+        }
+        assert!(process_query("DELETE FROM clients USING TTL 60 WHERE id = 1").is_err());
+        assert!(
+            process_query("UPDATE clients USING TTL -1 SET name = 'Pepe' WHERE id = 1").is_err()
+        );
+    }
+
+    #[test]
+    fn test_process_query_invalid_using_timestamp() {
+        let queries = [
+            "INSERT INTO clients (id, name) VALUES (1, 'Pepe') USING TIMESTAMP now",
+            "UPDATE clients USING TIMESTAMP SET name = 'Pepe' WHERE id = 1",
+            "DELETE FROM clients USING 1700000000000000 WHERE id = 1",
+        ];
+        for query_str in queries {
+            assert!(process_query(query_str).is_err());
+        }
+    }
+
     #[test]
     fn test_process_query_invalid() {
         let result = process_query("INVALID QUERY");
@@ -248,4 +365,119 @@ mod tests {
         let (_, path) = result.unwrap();
         assert_eq!(path, "clients");
     }
+
+    #[test]
+    fn test_process_create_table_with_compaction() {
+        let query_str = "CREATE TABLE clients (id int, name text, PRIMARY KEY (id)) WITH compaction = {'class': 'LeveledCompactionStrategy', 'sstable_size_in_mb': 10} AND gc_grace_seconds = 3600 AND default_time_to_live = 60";
+        let result = process_query(query_str);
+        assert!(result.is_ok());
+
+        let (_, path) = result.unwrap();
+        assert_eq!(path, "clients");
+    }
+
+    #[test]
+    fn test_process_create_table_with_invalid_option() {
+        let query_str = "CREATE TABLE clients (id int, name text, PRIMARY KEY (id)) WITH compaction = {'class': 'UnknownStrategy'}";
+        assert!(process_query(query_str).is_err());
+        let query_str =
+            "CREATE TABLE clients (id int, name text, PRIMARY KEY (id)) WITH unknown = 10";
+        assert!(process_query(query_str).is_err());
+        let query_str =
+            "CREATE TABLE clients (id int, name text, PRIMARY KEY (id)) WITH gc_grace_seconds = -1";
+        assert!(process_query(query_str).is_err());
+        let query_str = "CREATE TABLE clients (id int, name text, PRIMARY KEY (id)) WITH default_time_to_live = ten";
+        assert!(process_query(query_str).is_err());
+    }
+
+    #[test]
+    fn test_process_keyspace_queries() {
+        let (query, keyspace) = process_query(
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 3} AND durable_writes = false;",
+        )
+        .unwrap();
+        assert_eq!(keyspace, "ks");
+        assert!(query.is_ddl() && query.is_keyspace_query());
+
+        let (query, keyspace) = process_query("ALTER KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}").unwrap();
+        assert_eq!(keyspace, "ks");
+        assert!(query.is_ddl());
+
+        let (query, keyspace) = process_query("DROP KEYSPACE ks").unwrap();
+        assert_eq!(keyspace, "ks");
+        assert!(query.is_ddl());
+
+        let (query, keyspace) = process_query("USE ks;").unwrap();
+        assert_eq!(keyspace, "ks");
+        assert!(query.is_use() && !query.is_ddl());
+    }
+
+    #[test]
+    fn test_process_invalid_keyspace_queries() {
+        let queries = [
+            "CREATE KEYSPACE ks",
+            "CREATE KEYSPACE ks WITH durable_writes = true",
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy'}",
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 3} AND durable_writes = maybe",
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 3} AND unknown = 1",
+            "ALTER TABLE clients WITH gc_grace_seconds = 10",
+            "DROP KEYSPACE",
+            "USE",
+            "USE ks other",
+        ];
+        for query_str in queries {
+            assert!(process_query(query_str).is_err(), "{query_str}");
+        }
+    }
+
+    #[test]
+    fn test_process_query_qualified_table() {
+        let (_, table) =
+            process_query("SELECT name FROM ks.clients WHERE id = 1 ORDER BY name").unwrap();
+        assert_eq!(table, "ks.clients");
+        let (_, table) =
+            process_query("INSERT INTO ks.clients (id, name) VALUES (1, 'Pepe')").unwrap();
+        assert_eq!(table, "ks.clients");
+    }
+
+    #[test]
+    fn test_prepare_query_markers() {
+        let (_, table, markers) =
+            prepare_query("INSERT INTO ks.clients (id, name) VALUES (?, ?)").unwrap();
+        assert_eq!(table, "ks.clients");
+        let columns: Vec<&str> = markers.iter().map(|m| m.column.as_str()).collect();
+        assert_eq!(columns, ["id", "name"]);
+
+        let (_, _, markers) =
+            prepare_query("UPDATE clients SET name = :name WHERE id = :id AND age > 3").unwrap();
+        assert_eq!(
+            markers,
+            [
+                BindMarker {
+                    name: Some("name".to_string()),
+                    column: "name".to_string()
+                },
+                BindMarker {
+                    name: Some("id".to_string()),
+                    column: "id".to_string()
+                },
+            ]
+        );
+
+        let (_, _, markers) = prepare_query("SELECT * FROM clients WHERE name = '?'").unwrap();
+        assert!(markers.is_empty());
+    }
+
+    #[test]
+    fn test_prepare_query_invalid_markers() {
+        let queries = [
+            "SELECT ? FROM clients WHERE id = 1",
+            "DELETE ? FROM clients WHERE id = 1",
+            "INSERT INTO clients (id, name) VALUES (1, 'Pepe') USING TTL ?",
+            "UPDATE clients SET name = ? WHERE id = :id",
+        ];
+        for query_str in queries {
+            assert!(prepare_query(query_str).is_err(), "{query_str}");
+        }
+    }
 }
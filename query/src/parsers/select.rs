@@ -12,7 +12,8 @@ use crate::{
 /// Processes a `SELECT` query and prepares the `Query` and table path.
 ///
 /// This function parses the `SELECT` statement, including columns,
-/// WHERE and ORDER BY clauses, and constructs a `Query` object.
+/// WHERE and ORDER BY clauses, and constructs a `Query` object. A `SELECT` without a WHERE clause
+/// selects every row, which only the virtual tables of the system keyspaces allow.
 ///
 /// # Arguments
 ///
@@ -30,7 +31,9 @@ pub(crate) fn process_select(parts: &[String]) -> std::io::Result<(Query, String
     let Some(from) = parts.iter().position(|s| s == "FROM") else {
         return Err(io_error!("No FROM keyword"));
     };
-    if from + 5 >= parts.len() || parts[from + 2] != "WHERE" {
+    if from + 1 >= parts.len()
+        || (parts.get(from + 2).is_some_and(|part| part == "WHERE") && from + 5 >= parts.len())
+    {
         return Err(io_error!("Invalid SELECT query"));
     }
     if parts[0] == "FROM"
@@ -44,7 +47,8 @@ pub(crate) fn process_select(parts: &[String]) -> std::io::Result<(Query, String
     let mut statement = Statement::new("SELECT")?;
 
     // First I process the columns
-    statement.add_cols_to_be_printed(get_columns_from_vec(&parts[..from])?)?;
+    let cols = get_columns_from_vec(&parts[..from])?;
+    statement.add_cols_to_be_printed(cols.into_iter().map(normalize_function).collect())?;
 
     // Then I process the rest of the query
     let mut where_clause = None;
@@ -82,3 +86,12 @@ pub(crate) fn process_select(parts: &[String]) -> std::io::Result<(Query, String
         parts[from + 1].to_owned(),
     ))
 }
+
+/// Writes the name of a function call in uppercase, so `ttl(email)` selects the `TTL(email)` value of
+/// the rows.
+fn normalize_function(col: String) -> String {
+    match col.get(..4) {
+        Some(name) if name.eq_ignore_ascii_case("TTL(") => format!("TTL({}", &col[4..]),
+        _ => col,
+    }
+}
@@ -1,9 +1,13 @@
 use std::collections::HashMap;
 
-use db::{PrimaryKey, Schema, SchemaType};
+use db::{CompactionOptions, PrimaryKey, Schema, SchemaType, TableOptions};
 use shared::io_error;
 
-use crate::{models::statement::Statement, Query};
+use crate::{
+    models::statement::Statement,
+    utils::tokens::{get_options_from_vec, parse_map},
+    Query,
+};
 
 /// Process a table creation query.
 ///
@@ -11,6 +15,8 @@ use crate::{models::statement::Statement, Query};
 ///
 /// * `tokens` - A slice of strings containing the tokens of the query.
 ///
+/// The table options may be set after the columns with `WITH <option> = <value> AND ...`.
+///
 /// # Returns
 ///
 /// * A `Query` object
@@ -18,8 +24,12 @@ use crate::{models::statement::Statement, Query};
 ///
 /// # Errors
 ///
-/// * Returns an error if the number of arguments is invalid or if there is a syntax error.
+/// * Returns an error if the number of arguments is invalid, if there is a syntax error or if an option is invalid.
 pub(crate) fn process_table_creation(tokens: &[String]) -> std::io::Result<(Query, String)> {
+    let (tokens, options) = match tokens.iter().position(|s| s == "WITH") {
+        Some(with) => (&tokens[..with], process_table_options(&tokens[with + 1..])?),
+        None => (tokens, TableOptions::default()),
+    };
     let primary = tokens.iter().position(|s| s == "PRIMARY");
     if tokens.len() < 11
     // 11 is the minimum number of tokens for a valid CREATE TABLE query in CQL
@@ -44,7 +54,7 @@ pub(crate) fn process_table_creation(tokens: &[String]) -> std::io::Result<(Quer
         }
         columns.insert(
             tokens[i].to_owned(),
-            SchemaType::new(&tokens[i + 1].trim_end_matches(','))?,
+            SchemaType::new(tokens[i + 1].trim_end_matches(','))?,
         );
         i += 2;
     }
@@ -56,11 +66,34 @@ pub(crate) fn process_table_creation(tokens: &[String]) -> std::io::Result<(Quer
         .collect::<Vec<String>>();
 
     let primary_key = PrimaryKey::new(partition_key, clustering_key);
-    let statement = Statement::CreateTable(Schema::new(columns, primary_key));
+    let mut schema = Schema::new(columns, primary_key);
+    schema.set_options(options);
+    let statement = Statement::CreateTable(schema);
 
     Ok((Query::new(statement, None), tokens[1].to_owned()))
 }
 
+fn process_table_options(tokens: &[String]) -> std::io::Result<TableOptions> {
+    let mut options = TableOptions::default();
+    for (option, value) in get_options_from_vec(tokens)? {
+        match option.as_str() {
+            "compaction" => options.compaction = CompactionOptions::new(&parse_map(&value)?)?,
+            "gc_grace_seconds" => {
+                options.gc_grace_seconds = value
+                    .parse()
+                    .map_err(|_| io_error!(format!("Invalid gc_grace_seconds '{value}'")))?;
+            }
+            "default_time_to_live" => {
+                options.default_time_to_live = value
+                    .parse()
+                    .map_err(|_| io_error!(format!("Invalid default_time_to_live '{value}'")))?;
+            }
+            _ => return Err(io_error!(format!("Unknown table option '{option}'"))),
+        }
+    }
+    Ok(options)
+}
+
 pub(crate) fn process_table_deletion(tokens: &[String]) -> std::io::Result<(Query, String)> {
     if tokens.len() != 2 || tokens[0] != "TABLE" {
         return Err(io_error!(
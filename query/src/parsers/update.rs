@@ -1,11 +1,14 @@
 use shared::io_error;
 
-use crate::models::{query::Query, statement::Statement, where_clause::WhereClause};
+use crate::{
+    models::{query::Query, statement::Statement, where_clause::WhereClause},
+    utils::tokens::get_write_options_from_vec,
+};
 
 /// Processes an `UPDATE` query and prepares the `Query` and table path.
 ///
-/// This function parses the `UPDATE` statement, including setting columns
-/// and evaluating WHERE clauses, and constructs a `Query` object.
+/// This function parses the `UPDATE` statement, including an optional `USING TIMESTAMP`
+/// and `TTL` clause, setting columns and evaluating WHERE clauses, and constructs a `Query` object.
 ///
 /// # Arguments
 ///
@@ -21,10 +24,23 @@ use crate::models::{query::Query, statement::Statement, where_clause::WhereClaus
 /// * Returns an Error if there are syntax errors in the `UPDATE` query.
 ///
 pub(crate) fn process_update(tokens: &[String]) -> std::io::Result<(Query, String)> {
+    let (mut timestamp, mut ttl) = (None, None);
+    let without_options: Vec<String>;
+    let tokens = if tokens.get(1).is_some_and(|token| token == "USING") {
+        let set = tokens
+            .iter()
+            .position(|token| token == "SET")
+            .ok_or(io_error!("No SET keyword"))?;
+        (timestamp, ttl) = get_write_options_from_vec(&tokens[1..set])?;
+        without_options = tokens[..1].iter().chain(&tokens[set..]).cloned().collect();
+        &without_options
+    } else {
+        tokens
+    };
     if tokens.len() < 9 || tokens[1] != "SET" || !tokens.contains(&"WHERE".to_string()) {
         // 9 is the minimum number of tokens for a valid UPDATE query in CQL
         return Err(io_error!(
-            "UPDATE query should look like: UPDATE <table> SET <col> = <val> WHERE <condition>"
+            "UPDATE query should look like: UPDATE <table> [USING TIMESTAMP <microseconds>] [AND TTL <seconds>] SET <col> = <val> WHERE <condition>"
         ));
     }
     let mut statement = Statement::new("UPDATE")?;
@@ -80,5 +96,12 @@ pub(crate) fn process_update(tokens: &[String]) -> std::io::Result<(Query, Strin
         return Err(io_error!("WHERE clause is missing"));
     }
     statement.add_row(col.trim().to_owned(), new_val.trim().to_string())?;
-    Ok((Query::new(statement, where_clause), tokens[0].to_owned()))
+    let mut query = Query::new(statement, where_clause);
+    if let Some(timestamp) = timestamp {
+        query.set_timestamp(timestamp);
+    }
+    if let Some(ttl) = ttl {
+        query.set_ttl(ttl);
+    }
+    Ok((query, tokens[0].to_owned()))
 }
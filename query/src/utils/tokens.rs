@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use shared::io_error;
 
 use crate::models::query::KEYWORDS;
@@ -25,7 +27,7 @@ use crate::models::query::KEYWORDS;
 ///
 /// # Example
 ///
-/// ```rust
+/// ```ignore
 /// #[cfg(test)]
 /// mod example {
 ///     use super::tokenize;
@@ -106,6 +108,7 @@ where
 /// This function processes a vector of strings, isolating any leading or trailing parentheses into
 /// separate elements in the returned vector. It's useful for cleaning up and organizing strings
 /// that include parentheses.
+/// Function calls of a single column, like `TTL(email)`, are kept as one element.
 ///
 /// # Arguments
 ///
@@ -122,7 +125,7 @@ where
 ///
 /// # Example
 ///
-/// ```rust
+/// ```ignore
 /// #[cfg(test)]
 /// mod tests {
 ///     use super::separate_parenthesis;
@@ -143,6 +146,10 @@ pub fn separate_parenthesis(parts: &Vec<String>) -> std::io::Result<Vec<String>>
     let mut open_parentheses = 0;
     let mut close_parentheses = 0;
     for part in parts {
+        if is_function_call(part) {
+            res.push(part.clone());
+            continue;
+        }
         let mut current = part.as_str();
 
         while let Some(stripped) = current.strip_prefix('(') {
@@ -173,6 +180,14 @@ pub fn separate_parenthesis(parts: &Vec<String>) -> std::io::Result<Vec<String>>
     Ok(res)
 }
 
+/// Whether the part is a call to a function of a column, like `TTL(email)`, optionally followed by a comma.
+fn is_function_call(part: &str) -> bool {
+    let part = part.strip_suffix(',').unwrap_or(part);
+    part.to_uppercase().starts_with("TTL(")
+        && part.ends_with(')')
+        && !part[4..part.len() - 1].contains(['(', ')'])
+}
+
 pub fn get_columns_from_vec(s: &[String]) -> std::io::Result<Vec<String>> {
     let mut res = Vec::new();
     let mut token = String::new();
@@ -212,6 +227,138 @@ pub fn get_columns_from_vec(s: &[String]) -> std::io::Result<Vec<String>> {
     Ok(res)
 }
 
+/// Parses the options of a `WITH` clause, like `compaction = {'class': 'LeveledCompactionStrategy'} AND gc_grace_seconds = 10`.
+///
+/// # Arguments
+///
+/// * `parts` - The parts of the clause after the `WITH` keyword.
+///
+/// # Returns
+///
+/// * A vector with the name and the value of each option, in the order they are given.
+///   Map values are returned as written, to be parsed with `parse_map`.
+///
+/// # Errors
+///
+/// * Returns an `Error` if an option is not of the form `<name> = <value>`.
+pub fn get_options_from_vec(parts: &[String]) -> std::io::Result<Vec<(String, String)>> {
+    let mut options = Vec::new();
+    let mut option = String::new();
+    let mut depth = 0;
+    for part in parts.iter().chain(std::iter::once(&"AND".to_string())) {
+        if depth == 0 && part.to_uppercase() == "AND" {
+            let (name, value) = option.split_once('=').ok_or(io_error!(
+                "Options should look like: <name> = <value> AND ..."
+            ))?;
+            let (name, value) = (name.trim(), value.trim());
+            if name.is_empty() || value.is_empty() {
+                return Err(io_error!(
+                    "Options should look like: <name> = <value> AND ..."
+                ));
+            }
+            options.push((name.to_owned(), value.trim_matches('\'').to_owned()));
+            option = String::new();
+            continue;
+        }
+        depth += part.matches('{').count() as i32 - part.matches('}').count() as i32;
+        option += &(" ".to_string() + part);
+    }
+    Ok(options)
+}
+
+/// Parses a map literal, like `{'class': 'SizeTieredCompactionStrategy', 'min_threshold': 4}`.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the value is not enclosed in braces or an entry is not of the form `<key>: <value>`.
+pub fn parse_map(value: &str) -> std::io::Result<HashMap<String, String>> {
+    let entries = value
+        .trim()
+        .strip_prefix('{')
+        .and_then(|value| value.strip_suffix('}'))
+        .ok_or(io_error!("Maps should look like: {'<key>': <value>, ...}"))?;
+    let mut map = HashMap::new();
+    for entry in entries.split(',').filter(|entry| !entry.trim().is_empty()) {
+        let (key, value) = entry
+            .split_once(':')
+            .ok_or(io_error!("Maps should look like: {'<key>': <value>, ...}"))?;
+        map.insert(
+            key.trim().trim_matches('\'').to_owned(),
+            value.trim().trim_matches('\'').to_owned(),
+        );
+    }
+    Ok(map)
+}
+
+/// Parses a `USING TIMESTAMP <microseconds>` clause, which sets the write time of a query.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the parts are not of the form `USING TIMESTAMP <microseconds>`.
+pub fn get_timestamp_from_vec(parts: &[String]) -> std::io::Result<i64> {
+    match get_write_options_from_vec(parts) {
+        Ok((Some(timestamp), None)) => Ok(timestamp),
+        _ => Err(io_error!(
+            "Timestamp should look like: USING TIMESTAMP <microseconds>"
+        )),
+    }
+}
+
+/// Parses the `USING` clause of a write, like `USING TIMESTAMP <microseconds> AND TTL <seconds>`.
+/// Each option may be given once, in any order.
+///
+/// # Returns
+///
+/// * The write time and the time to live of the written cells, if they are given.
+///
+/// # Errors
+///
+/// * Returns an `Error` if an option is unknown, repeated or has an invalid value.
+pub fn get_write_options_from_vec(parts: &[String]) -> std::io::Result<(Option<i64>, Option<u64>)> {
+    let invalid = || {
+        io_error!(
+            "USING clause should look like: USING TIMESTAMP <microseconds> [AND TTL <seconds>]"
+        )
+    };
+    let Some((_, mut options)) = parts.split_first().filter(|(using, _)| *using == "USING") else {
+        return Err(invalid());
+    };
+    let (mut timestamp, mut ttl) = (None, None);
+    while !options.is_empty() {
+        if timestamp.is_some() || ttl.is_some() {
+            match options.split_first() {
+                Some((and, rest)) if and == "AND" => options = rest,
+                _ => return Err(invalid()),
+            }
+        }
+        let [name, value, rest @ ..] = options else {
+            return Err(invalid());
+        };
+        options = rest;
+        match name.as_str() {
+            "TIMESTAMP" if timestamp.is_none() => {
+                timestamp = Some(
+                    value
+                        .parse()
+                        .map_err(|_| io_error!(format!("Invalid timestamp '{value}'")))?,
+                );
+            }
+            "TTL" if ttl.is_none() => {
+                ttl = Some(
+                    value
+                        .parse()
+                        .map_err(|_| io_error!(format!("Invalid TTL '{value}'")))?,
+                );
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if timestamp.is_none() && ttl.is_none() {
+        return Err(invalid());
+    }
+    Ok((timestamp, ttl))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -439,4 +586,54 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_get_options_from_vec() -> std::io::Result<()> {
+        let parts: Vec<String> = "compaction = {'class': 'LeveledCompactionStrategy', 'sstable_size_in_mb': 10} AND comment = 'users'"
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect();
+        let options = get_options_from_vec(&parts)?;
+        assert_eq!(options[0].0, "compaction");
+        assert_eq!(
+            parse_map(&options[0].1)?,
+            HashMap::from([
+                ("class".to_string(), "LeveledCompactionStrategy".to_string()),
+                ("sstable_size_in_mb".to_string(), "10".to_string()),
+            ])
+        );
+        assert_eq!(options[1], ("comment".to_string(), "users".to_string()));
+        assert!(get_options_from_vec(&["compaction".to_string()]).is_err());
+        assert!(parse_map("'class': 'LeveledCompactionStrategy'").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_timestamp_from_vec() {
+        let parts = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            get_timestamp_from_vec(&parts("USING TIMESTAMP 1700000000000000")).unwrap(),
+            1700000000000000
+        );
+        assert!(get_timestamp_from_vec(&parts("USING TIMESTAMP now")).is_err());
+        assert!(get_timestamp_from_vec(&parts("USING TTL 10")).is_err());
+        assert!(get_timestamp_from_vec(&parts("USING TIMESTAMP")).is_err());
+    }
+
+    #[test]
+    fn test_get_write_options_from_vec() {
+        let parts = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            get_write_options_from_vec(&parts("USING TTL 10")).unwrap(),
+            (None, Some(10))
+        );
+        assert_eq!(
+            get_write_options_from_vec(&parts("USING TTL 10 AND TIMESTAMP 5")).unwrap(),
+            (Some(5), Some(10))
+        );
+        assert!(get_write_options_from_vec(&parts("USING TTL -1")).is_err());
+        assert!(get_write_options_from_vec(&parts("USING TTL 10 AND TTL 5")).is_err());
+        assert!(get_write_options_from_vec(&parts("USING TTL 10 TIMESTAMP 5")).is_err());
+        assert!(get_write_options_from_vec(&parts("USING")).is_err());
+    }
 }
@@ -0,0 +1,44 @@
+use std::{
+    fs::{copy, create_dir_all, read_dir},
+    path::{Path, PathBuf},
+};
+
+use db::Context;
+
+/// Copies the node fixture to a scratch dir, so tests that write to it leave the fixture unchanged.
+pub fn copy_node(fixture: &str, name: &str) -> PathBuf {
+    fn copy_dir(from: &Path, to: &Path) {
+        create_dir_all(to).unwrap();
+        for entry in read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_dir(&path, &target);
+            } else {
+                copy(&path, &target).unwrap();
+            }
+        }
+    }
+    let node = std::env::temp_dir().join(format!("cassandrust-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&node);
+    copy_dir(Path::new(fixture), &node);
+    node
+}
+
+/// Reads the rows of the table as lines of comma separated values of the specified columns,
+/// sorted so they can be compared regardless of the order they are stored in.
+pub fn read_rows(ctx: &Context, table: &Path, cols: &[&str]) -> Vec<String> {
+    let mut rows = Vec::new();
+    ctx.read_table(table, &mut |row| {
+        rows.push(
+            cols.iter()
+                .map(|col| row[*col].clone())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        Ok(())
+    })
+    .unwrap();
+    rows.sort();
+    rows
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use db::{initialize_context, Options};
+use query::process_query;
+
+use common::{copy_node, read_rows};
+
+#[test]
+fn test_create_alter_drop_keyspace() {
+    let node = copy_node("tests/node_test", "keyspace");
+    let mut ctx = initialize_context(&node).unwrap();
+
+    // ! Test 1 - Create
+    let (mut query, keyspace_str) = process_query(
+        "CREATE KEYSPACE ks_test_create WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 2}",
+    )
+    .unwrap();
+    assert!(query.is_keyspace_query());
+    let keyspace = node.join(&keyspace_str);
+    assert!(query.process(&keyspace, &mut ctx).unwrap().is_none());
+    assert!(ctx.is_a_keyspace("ks_test_create"));
+    assert_eq!(
+        ctx.get_keyspace_options(&keyspace).unwrap(),
+        Options::new(true, "SimpleStrategy".to_string(), 2)
+    );
+    assert!(query.process(&keyspace, &mut ctx).is_err());
+
+    // ! Test 2 - Alter only the given options
+    (query, _) =
+        process_query("ALTER KEYSPACE ks_test_create WITH durable_writes = false").unwrap();
+    query.process(&keyspace, &mut ctx).unwrap();
+    assert_eq!(
+        ctx.get_keyspace_options(&keyspace).unwrap(),
+        Options::new(false, "SimpleStrategy".to_string(), 2)
+    );
+
+    // ! Test 3 - Use and create a table in the keyspace
+    (query, _) = process_query("USE ks_test_create").unwrap();
+    assert!(query.is_use());
+    query.process(&keyspace, &mut ctx).unwrap();
+    (query, _) = process_query("USE ks_test_missing").unwrap();
+    assert!(query
+        .process(&node.join("ks_test_missing"), &mut ctx)
+        .is_err());
+
+    let (mut query, table) =
+        process_query("CREATE TABLE ks_test_create.users (id int, name text, PRIMARY KEY (id))")
+            .unwrap();
+    assert_eq!(table, "ks_test_create.users");
+    query.process(&keyspace.join("users"), &mut ctx).unwrap();
+    assert!(ctx.get_table_schema("ks_test_create", "users").is_ok());
+
+    (query, _) =
+        process_query("INSERT INTO ks_test_create.users (id, name) VALUES (1, 'John')").unwrap();
+    query.process(&keyspace.join("users"), &mut ctx).unwrap();
+    assert_eq!(
+        read_rows(&ctx, &keyspace.join("users"), &["id", "name"]),
+        vec!["1,John"]
+    );
+
+    // ! Test 4 - Drop
+    (query, _) = process_query("DROP KEYSPACE ks_test_create").unwrap();
+    query.process(&keyspace, &mut ctx).unwrap();
+    assert!(!keyspace.exists());
+    assert!(!ctx.is_a_keyspace("ks_test_create"));
+
+    drop(ctx);
+    std::fs::remove_dir_all(&node).unwrap();
+}
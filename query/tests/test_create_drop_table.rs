@@ -16,10 +16,11 @@ fn test_create_drop_table() {
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    let updated = std::fs::read_to_string(table.join("table.csv"))
+    let updated = ctx
+        .get_table_schema("ks_test", "table_test_create")
         .unwrap()
-        .split(',')
-        .map(|s| s.trim().to_string())
+        .get_columns()
+        .into_iter()
         .collect::<HashSet<String>>();
     assert_eq!(
         updated,
@@ -1,26 +1,35 @@
-use std::path::Path;
+mod common;
 
 use db::initialize_context;
-use query::process_query;
+use query::{prepare_query, process_query};
 
-const ROOT: &str = "tests/node_test/ks_test";
+use common::{copy_node, read_rows};
+
+const COLS: [&str; 4] = ["id", "name", "email", "age"];
 
 // Do all tests in one function to avoid parallelism issues
 #[test]
 fn test_insert_and_delete_query() {
+    let node = copy_node("tests/node_test", "insert_and_delete");
+    let root = node.join("ks_test");
+
     // ! Test 1 - Insert
     let (mut query, table_str) = process_query(
         "INSERT INTO table_test_insert (id, name, email, age) VALUES (3, 'John Snow', 'snow@got.com', 40)"
     ).unwrap();
-    let mut ctx = initialize_context(Path::new("tests/node_test")).unwrap();
-    let table = Path::new(ROOT).join(table_str);
+    let mut ctx = initialize_context(&node).unwrap();
+    let table = root.join(table_str);
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    let mut updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    let mut updated = read_rows(&ctx, &table, &COLS);
     assert_eq!(
         updated,
-    "id,name,email,age\n1,John Doe,john@example.com,30\n2,Jane Smith,jane@example.com,20\n3,John Snow,snow@got.com,40\n"
+        vec![
+            "1,John Doe,john@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+            "3,John Snow,snow@got.com,40",
+        ]
     );
 
     // ! Test 2 - Insert
@@ -33,10 +42,15 @@ fn test_insert_and_delete_query() {
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    updated = read_rows(&ctx, &table, &COLS);
     assert_eq!(
         updated,
-    "id,name,email,age\n1,John Doe,john@example.com,30\n2,Jane Smith,jane@example.com,20\n3,John Snow,snow@got.com,40\n4,Fantastic Four,NULL,40\n"
+        vec![
+            "1,John Doe,john@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+            "3,John Snow,snow@got.com,40",
+            "4,Fantastic Four,NULL,40",
+        ]
     );
 
     // ! Test 3 - Insert
@@ -46,10 +60,16 @@ fn test_insert_and_delete_query() {
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    updated = read_rows(&ctx, &table, &COLS);
     assert_eq!(
         updated,
-    "id,name,email,age\n1,John Doe,john@example.com,30\n2,Jane Smith,jane@example.com,20\n3,John Snow,snow@got.com,40\n4,Fantastic Four,NULL,40\n5,Hi Five,NULL,NULL\n"
+        vec![
+            "1,John Doe,john@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+            "3,John Snow,snow@got.com,40",
+            "4,Fantastic Four,NULL,40",
+            "5,Hi Five,NULL,NULL",
+        ]
     );
 
     // ! Test 4 - Delete
@@ -57,10 +77,15 @@ fn test_insert_and_delete_query() {
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    updated = read_rows(&ctx, &table, &COLS);
     assert_eq!(
         updated,
-    "id,name,email,age\n1,John Doe,john@example.com,30\n2,Jane Smith,jane@example.com,20\n3,John Snow,snow@got.com,40\n5,Hi Five,NULL,NULL\n"
+        vec![
+            "1,John Doe,john@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+            "3,John Snow,snow@got.com,40",
+            "5,Hi Five,NULL,NULL",
+        ]
     );
 
     // ! Test 5 - Delete
@@ -68,19 +93,139 @@ fn test_insert_and_delete_query() {
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
-    assert_eq!(updated, 
-    "id,name,email,age\n1,John Doe,john@example.com,30\n2,Jane Smith,jane@example.com,20\n3,John Snow,snow@got.com,40\n"
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(
+        updated,
+        vec![
+            "1,John Doe,john@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+            "3,John Snow,snow@got.com,40",
+        ]
     );
 
     // ! Test 6 - Delete
-    (query, _) = process_query("DELETE FROM table_test_insert WHERE email = 'snow@got.com' AND age = 40").unwrap();
+    (query, _) =
+        process_query("DELETE FROM table_test_insert WHERE email = 'snow@got.com' AND age = 40")
+            .unwrap();
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
-    assert_eq!(updated, 
-    "id,name,email,age\n1,John Doe,john@example.com,30\n2,Jane Smith,jane@example.com,20\n"
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(
+        updated,
+        vec![
+            "1,John Doe,john@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+        ]
+    );
+
+    // ! Test 7 - Last write wins
+    (query, _) = process_query(
+        "INSERT INTO table_test_insert (id, name, email, age) VALUES (1, 'John Doe', 'new@example.com', 30) USING TIMESTAMP 20",
+    )
+    .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+    (query, _) = process_query(
+        "INSERT INTO table_test_insert (id, name, email, age) VALUES (1, 'John Doe', 'old@example.com', 30) USING TIMESTAMP 10",
+    )
+    .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+    (query, _) =
+        process_query("DELETE FROM table_test_insert USING TIMESTAMP 15 WHERE name = 'John Doe'")
+            .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(
+        updated,
+        vec![
+            "1,John Doe,new@example.com,30",
+            "2,Jane Smith,jane@example.com,20",
+        ]
+    );
+
+    (query, _) =
+        process_query("DELETE FROM table_test_insert USING TIMESTAMP 20 WHERE name = 'John Doe'")
+            .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(updated, vec!["2,Jane Smith,jane@example.com,20"]);
+
+    // ! Test 8 - Delete columns and ranges of rows
+    for insert in [
+        "INSERT INTO table_test_insert (id, name, email, age) VALUES (5, 'Jane Smith', 'five@example.com', 25)",
+        "INSERT INTO table_test_insert (id, name, email, age) VALUES (6, 'Jane Smith', 'six@example.com', 30)",
+    ] {
+        (query, _) = process_query(insert).unwrap();
+        query.process(&table, &mut ctx).unwrap();
+    }
+    (query, _) = process_query(
+        "DELETE email FROM table_test_insert WHERE name = 'Jane Smith' AND id = 2 AND age = 20",
+    )
+    .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+    (query, _) =
+        process_query("DELETE FROM table_test_insert WHERE name = 'Jane Smith' AND id > 4")
+            .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+    // Writes older than the tombstone stay hidden.
+    (query, _) = process_query(
+        "INSERT INTO table_test_insert (id, name, email, age) VALUES (5, 'Jane Smith', 'old@example.com', 25) USING TIMESTAMP 1",
+    )
+    .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(updated, vec!["2,Jane Smith,NULL,20"]);
+
+    (query, _) = process_query(
+        "DELETE id FROM table_test_insert WHERE name = 'Jane Smith' AND id = 2 AND age = 20",
+    )
+    .unwrap();
+    assert!(query.process(&table, &mut ctx).is_err());
+
+    // ! Test 9 - Prepared insert and delete with bound values
+    let (prepared, _, markers) =
+        prepare_query("INSERT INTO table_test_insert (id, name, email, age) VALUES (?, ?, ?, ?)")
+            .unwrap();
+    assert_eq!(markers.len(), 4);
+    for values in [
+        ["8", "Jane Smith", "O'Hara", "NULL"],
+        ["9", "Jane Smith", "nine@example.com", "45"],
+    ] {
+        query = prepared.clone();
+        query.bind(&values.map(str::to_string)).unwrap();
+        query.process(&table, &mut ctx).unwrap();
+    }
+    (query, _, _) =
+        prepare_query("DELETE FROM table_test_insert WHERE name = :name AND id = :id").unwrap();
+    query
+        .bind(&["Jane Smith".to_string(), "9".to_string()])
+        .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(
+        updated,
+        vec!["2,Jane Smith,NULL,20", "8,Jane Smith,O'Hara,NULL"]
     );
+    (query, _) =
+        process_query("DELETE FROM table_test_insert WHERE name = 'Jane Smith' AND id = 8")
+            .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+
+    // ! Test 10 - Rows inserted with a TTL expire
+    (query, _) = process_query(
+        "INSERT INTO table_test_insert (id, name, email, age) VALUES (7, 'Jane Smith', 'seven@example.com', 35) USING TTL 1",
+    )
+    .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(updated, vec!["2,Jane Smith,NULL,20"]);
 
+    drop(ctx);
+    std::fs::remove_dir_all(&node).unwrap();
 }
@@ -1,7 +1,10 @@
-use db::initialize_context;
+use db::{initialize_context, PrimaryKey, Schema, SchemaType};
 use query::process_query;
 
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
 const ROOT: &str = "tests/node_test/ks_test";
 
@@ -32,10 +35,10 @@ fn is_equal(original_row: Vec<Vec<String>>, expected: Vec<Vec<String>>) {
         .iter()
         .map(|row| row.iter().collect::<HashSet<_>>())
         .collect();
-    original_row.iter().enumerate().for_each(|(idx, row)| {
-        row.iter().for_each(|col| {
-            assert!(set[idx].contains(col));
-        });
+    // Rows are returned in partition token order, so match each row against any expected one.
+    original_row.iter().for_each(|row| {
+        let row = row.iter().collect::<HashSet<_>>();
+        assert!(set.contains(&row));
     });
 }
 
@@ -79,16 +82,18 @@ fn test_select_query_with_columns_in_different_order() {
         process_query("SELECT email, name FROM table_test_select WHERE all = true").unwrap();
     let mut ctx = initialize_context(Path::new("tests/node_test")).unwrap();
 
-    let output = query
+    let mut output = query
         .process(&Path::new(ROOT).join(table), &mut ctx)
+        .unwrap()
         .unwrap();
+    output.sort();
 
     assert_eq!(
         output,
-        Some(vec![
+        vec![
+            vec!["jane@example.com".to_string(), "Jane Smith".to_string()],
             vec!["john@example.com".to_string(), "John Doe".to_string()],
-            vec!["jane@example.com".to_string(), "Jane Smith".to_string()]
-        ])
+        ]
     );
 }
 
@@ -184,5 +189,100 @@ fn test_select_query_with_complex_where_clause() {
 
 #[test]
 fn test_select_query_without_where_clause() {
-    assert!(process_query("SELECT id, name, email, age, all FROM table_test_select").is_err());
+    let (mut query, table) =
+        process_query("SELECT id, name, email, age, all FROM table_test_select").unwrap();
+    let mut ctx = initialize_context(Path::new("tests/node_test")).unwrap();
+
+    let output = query
+        .process(&Path::new(ROOT).join(table), &mut ctx)
+        .unwrap()
+        .unwrap();
+
+    is_equal(output, get_rows());
+}
+
+#[test]
+fn test_select_all_columns() {
+    let (mut query, table) =
+        process_query("SELECT * FROM table_test_select WHERE age > 25").unwrap();
+    let mut ctx = initialize_context(Path::new("tests/node_test")).unwrap();
+
+    let output = query
+        .process(&Path::new(ROOT).join(table), &mut ctx)
+        .unwrap();
+
+    // The partition key, the clustering key, and then the rest of the columns by name.
+    assert_eq!(
+        output,
+        Some(vec![vec![
+            "John Doe".to_string(),
+            "1".to_string(),
+            "30".to_string(),
+            "true".to_string(),
+            "john@example.com".to_string(),
+        ]])
+    );
+}
+
+#[test]
+fn test_select_pages_resume_after_the_last_row() {
+    let schema = Schema::new(
+        HashMap::from([
+            ("id".to_string(), SchemaType::Int),
+            ("seq".to_string(), SchemaType::Int),
+            ("name".to_string(), SchemaType::Text),
+        ]),
+        PrimaryKey::new(vec!["id".to_string()], vec!["seq".to_string()]),
+    );
+    let rows = |seqs: &[i32]| -> Vec<HashMap<String, String>> {
+        seqs.iter()
+            .map(|seq| {
+                HashMap::from([
+                    ("id".to_string(), "1".to_string()),
+                    ("seq".to_string(), seq.to_string()),
+                    ("name".to_string(), format!("row {seq}")),
+                ])
+            })
+            .collect()
+    };
+    let (query, _) = process_query("SELECT seq FROM table_test WHERE id = 1").unwrap();
+
+    // The clustering key is compared as an int, so 10 comes after 9.
+    let (page, next) = query
+        .select_page(rows(&[10, 2, 9, 1]), &schema, None, 2)
+        .unwrap();
+    assert_eq!(
+        page,
+        Some(vec![vec!["1".to_string()], vec!["2".to_string()]])
+    );
+    assert_eq!(next, Some(vec!["1".to_string(), "2".to_string()]));
+
+    // The last row of the previous page was deleted, and a row was written before it.
+    let (page, next) = query
+        .select_page(rows(&[10, 0, 9, 1]), &schema, next.as_deref(), 2)
+        .unwrap();
+    assert_eq!(
+        page,
+        Some(vec![vec!["9".to_string()], vec!["10".to_string()]])
+    );
+    assert_eq!(next, None);
+
+    let (query, _) =
+        process_query("SELECT seq FROM table_test WHERE id = 1 ORDER BY name DESC").unwrap();
+    let (page, next) = query
+        .select_page(rows(&[1, 2, 3]), &schema, None, 2)
+        .unwrap();
+    assert_eq!(
+        page,
+        Some(vec![vec!["3".to_string()], vec!["2".to_string()]])
+    );
+    let (page, next) = query
+        .select_page(rows(&[1, 2, 3]), &schema, next.as_deref(), 2)
+        .unwrap();
+    assert_eq!(page, Some(vec![vec!["1".to_string()]]));
+    assert_eq!(next, None);
+
+    assert!(query
+        .select_page(rows(&[1]), &schema, Some(&["1".to_string()]), 2)
+        .is_err());
 }
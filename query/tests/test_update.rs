@@ -1,31 +1,39 @@
-use std::path::Path;
+mod common;
 
 use db::initialize_context;
 use query::process_query;
 use rand::Rng;
 
-const ROOT: &str = "tests/node_test/ks_test";
+use common::{copy_node, read_rows};
+
+const COLS: [&str; 4] = ["id", "name", "email", "age"];
 
 // Do all tests in one function to avoid parallelism issues
 #[test]
 fn test_update_query() {
+    let node = copy_node("tests/node_test", "update");
+    let root = node.join("ks_test");
+
     // ! Test 1
     let mut john_age = rand::thread_rng().gen_range(1..100);
     let (mut query, mut table_str) = process_query(&format!(
         "UPDATE table_test_update SET age = {john_age} WHERE id = 1"
     ))
     .unwrap();
-    let mut ctx = initialize_context(Path::new("tests/node_test")).unwrap();
+    let mut ctx = initialize_context(&node).unwrap();
 
-    let mut table = Path::new(ROOT).join(table_str);
+    let mut table = root.join(table_str);
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    let mut updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    let mut updated = read_rows(&ctx, &table, &COLS);
 
     assert_eq!(
         updated,
-        format!("id,name,email,age\n1,John Doe,john@example.com,{john_age}\n2,Jane Smith,jane@example.com,20\n")
+        vec![
+            format!("1,John Doe,john@example.com,{john_age}"),
+            "2,Jane Smith,jane@example.com,20".to_string(),
+        ]
     );
 
     // ! Test 2
@@ -34,15 +42,18 @@ fn test_update_query() {
     (query, table_str) =
         process_query(&format!("UPDATE table_test_update SET age = {jane_age} WHERE id = 2 AND name = 'Jane Smith' AND age = 20"))
             .unwrap();
-    table = Path::new(ROOT).join(table_str);
+    table = root.join(table_str);
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    updated = read_rows(&ctx, &table, &COLS);
 
     assert_eq!(
         updated,
-        format!("id,name,email,age\n1,John Doe,john@example.com,{john_age}\n2,Jane Smith,jane@example.com,{jane_age}\n")
+        vec![
+            format!("1,John Doe,john@example.com,{john_age}"),
+            format!("2,Jane Smith,jane@example.com,{jane_age}"),
+        ]
     );
 
     // ! Test 3
@@ -56,7 +67,7 @@ fn test_update_query() {
     // ! Test 5
 
     (query, table_str) = process_query("UPDATE invalid_table SET age = 25 WHERE id = 1").unwrap();
-    table = Path::new(ROOT).join(table_str);
+    table = root.join(table_str);
 
     assert!(query.process(&table, &mut ctx).is_err());
 
@@ -65,26 +76,80 @@ fn test_update_query() {
     john_age = rand::thread_rng().gen_range(1..100);
     (query, table_str) = process_query(&format!("UPDATE table_test_update SET age = {john_age}, name = 'Doe John' WHERE id = 1 AND name = 'John Doe'")).unwrap();
 
-    table = Path::new(ROOT).join(table_str);
+    table = root.join(table_str);
     let output = query.process(&table, &mut ctx).unwrap();
     assert!(output.is_none());
 
-    updated = std::fs::read_to_string(table.join("table.csv")).unwrap();
+    updated = read_rows(&ctx, &table, &COLS);
 
     assert_eq!(
         updated,
-        format!("id,name,email,age\n1,Doe John,john@example.com,{john_age}\n2,Jane Smith,jane@example.com,{jane_age}\n")
+        vec![
+            format!("1,Doe John,john@example.com,{john_age}"),
+            format!("2,Jane Smith,jane@example.com,{jane_age}"),
+        ]
     );
 
-    // ! Set back to original
-    (query, table_str) =
-        process_query("UPDATE table_test_update SET age = 30, name = 'John Doe' WHERE id = 1")
-            .unwrap();
-    table = Path::new(ROOT).join(table_str);
+    // ! Test 7 - Cells written with a TTL expire
+
+    (query, _) = process_query(
+        "UPDATE table_test_update USING TTL 100 SET email = 'jane@temp.com' WHERE id = 2",
+    )
+    .unwrap();
     query.process(&table, &mut ctx).unwrap();
-    (query, table_str) =
-        process_query("UPDATE table_test_update SET age = 20, name = 'Jane Smith' WHERE id = 2")
-            .unwrap();
-    table = Path::new(ROOT).join(table_str);
+    (query, _) = process_query(
+        "SELECT email, ttl(email), TTL(age) FROM table_test_update WHERE name = 'Jane Smith'",
+    )
+    .unwrap();
+    let selected = query.process(&table, &mut ctx).unwrap().unwrap();
+    assert_eq!(selected[0][0], "jane@temp.com");
+    assert!(matches!(selected[0][1].as_str(), "99" | "100"));
+    assert_eq!(selected[0][2], "NULL");
+
+    (query, _) = process_query(
+        "UPDATE table_test_update USING TTL 1 SET email = 'john@temp.com' WHERE id = 1",
+    )
+    .unwrap();
     query.process(&table, &mut ctx).unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(
+        updated,
+        vec![
+            format!("1,Doe John,NULL,{john_age}"),
+            format!("2,Jane Smith,jane@temp.com,{jane_age}"),
+        ]
+    );
+
+    // ! Test 8 - Updating a row by its primary key writes it without reading it, even if it is not stored
+
+    (query, _) = process_query(
+        "UPDATE table_test_update SET email = 'new@example.com' \
+         WHERE name = 'New Person' AND id = 3 AND age = 30",
+    )
+    .unwrap();
+    query.process(&table, &mut ctx).unwrap();
+
+    updated = read_rows(&ctx, &table, &COLS);
+    assert_eq!(
+        updated,
+        vec![
+            format!("1,Doe John,NULL,{john_age}"),
+            format!("2,Jane Smith,jane@temp.com,{jane_age}"),
+            "3,New Person,new@example.com,30".to_string(),
+        ]
+    );
+
+    // ! Test 9 - Primary key values of the wrong type are rejected
+
+    (query, _) = process_query(
+        "UPDATE table_test_update SET email = 'old@example.com' \
+         WHERE name = 'New Person' AND id = 3 AND age = 'old'",
+    )
+    .unwrap();
+    assert!(query.process(&table, &mut ctx).is_err());
+
+    drop(ctx);
+    std::fs::remove_dir_all(&node).unwrap();
 }
@@ -0,0 +1,703 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, read_dir, remove_file, File},
+    io::ErrorKind,
+    net::TcpStream,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+
+use db::{current_timestamp, Context};
+use inc::{
+    batch::{Batch, BatchLog, BatchLogEntry},
+    read_inc_frame, Body, FrameType,
+};
+use native::server::{
+    create_error_response, create_result_response, create_unprepared_response, BatchQuery,
+    ErrorCode, Frame, ERROR, RESULT,
+};
+use query::{prepare_query, BatchType, Query};
+use shared::{io_error, map_io_error, resolve_table};
+
+use crate::{
+    connections::{
+        client::{count_in_datacenter, get_ack_groups, get_query_key, get_schema, write_response},
+        gossip::{failure_detector::FailureDetector, manager::GossipManager},
+        hinted::add_hint,
+        node::{send_message, send_result},
+        prepared::{bind_values, PreparedStatements},
+        system::is_system_keyspace,
+    },
+    partitioner::{murmur3::Partitioner, node::Node},
+};
+
+/// The dir of the node that holds the batches it keeps in its batchlog, one file each.
+const BATCHLOG_DIR: &str = "batchlog";
+
+/// The nodes, other than the coordinator, that keep a logged batch until it is applied.
+const BATCHLOG_REPLICAS: usize = 2;
+
+/// How long a batch stays in the batchlog before it is replayed, in microseconds, so its coordinator has
+/// time to apply it and remove it first.
+const BATCHLOG_TIMEOUT: i64 = 30_000_000;
+
+/// How often the batchlog is checked for batches to replay.
+const REPLAY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The statements of a batch to the same partition, with the name of its table and the values of its
+/// partition key.
+type PartitionStatements<'a> = ((&'a str, Vec<String>), Vec<&'a Query>);
+
+/// The statements of a batch that go to the same replicas.
+#[derive(Debug)]
+struct ReplicaGroup {
+    keyspace: String,
+    nodes: Vec<Node>,
+    /// The nodes joining the ring as replicas of the statements, which do not count for the consistency
+    /// level.
+    pending: Vec<Node>,
+    queries: Vec<inc::query::Query>,
+}
+
+/// Returns the batch of a `BATCH` message, with the values of each statement bound to its markers. If a
+/// statement is not prepared or its values are not valid, the error is answered and `None` is returned.
+pub(crate) fn get_batch_query(
+    writer: &Mutex<TcpStream>,
+    frame: &Frame,
+    manager: &RwLock<GossipManager>,
+    prepared: &PreparedStatements,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<Option<Query>> {
+    let batch = frame.body.get_batch().unwrap();
+    println!("Received batch of {} statements", batch.statements.len());
+    let mut statements = Vec::with_capacity(batch.statements.len());
+    for statement in &batch.statements {
+        let (query, name, markers) = match &statement.query {
+            BatchQuery::Query(query_str) => match prepare_query(query_str) {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    let error = create_error_response(ErrorCode::SyntaxError, &e.to_string(), None);
+                    return write_response(writer, ERROR, frame.header.stream, error).map(|_| None);
+                }
+            },
+            BatchQuery::Prepared(id) => {
+                let Some(statement) = prepared.get(id) else {
+                    let error = create_unprepared_response(id);
+                    return write_response(writer, ERROR, frame.header.stream, error).map(|_| None);
+                };
+                (statement.query, statement.name, statement.markers)
+            }
+        };
+        if markers.is_empty() && statement.values.is_empty() {
+            statements.push((query, name));
+            continue;
+        }
+        let bound = resolve_table(&name)
+            .and_then(|(keyspace, table)| get_schema(&keyspace, &table, manager, ctx))
+            .and_then(|schema| bind_values(&query, &markers, &statement.values, Some(&schema)));
+        match bound {
+            Ok(query) => statements.push((query, name)),
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error).map(|_| None);
+            }
+        }
+    }
+    match Query::batch(batch.batch_type, statements) {
+        Ok(mut query) => {
+            if let Some(timestamp) = batch.timestamp {
+                query.set_timestamp(timestamp);
+            }
+            Ok(Some(query))
+        }
+        Err(e) => {
+            let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+            write_response(writer, ERROR, frame.header.stream, error).map(|_| None)
+        }
+    }
+}
+
+/// Applies the statements of a batch, sending each replica the statements it holds at once, and answers
+/// once the replicas of every statement acked it as the consistency level requires.
+///
+/// A logged batch is first kept in the batchlog of up to `BATCHLOG_REPLICAS` other nodes that are up, or of
+/// this node if there are none, which replay it if this node dies before applying it. Unlogged batches are
+/// applied right away, so they may be partially applied if this node dies. Counter batches are rejected,
+/// since there are no counter columns.
+pub(crate) fn handle_batch(
+    writer: &Mutex<TcpStream>,
+    frame: &Frame,
+    query: Query,
+    partitioner: &Partitioner,
+    manager: &RwLock<GossipManager>,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    let failure_detector = Arc::clone(&manager.read().unwrap().failure_detector);
+    let (batch_type, statements) = query.get_batch().unwrap();
+
+    let cl = frame.body.get_consistency().unwrap();
+    if cl.is_serial() {
+        let error = create_error_response(
+            ErrorCode::Invalid,
+            "You must use conditional updates for serializable writes",
+            None,
+        );
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    if batch_type == BatchType::Counter {
+        let error = create_error_response(
+            ErrorCode::Invalid,
+            "Counter batches are not supported, since there are no counter columns",
+            None,
+        );
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    // Every replica writes the statements with the same timestamp, so a replayed batch does not override
+    // the writes made after it.
+    let timestamp = query.timestamp().unwrap_or_else(current_timestamp);
+    let mut queries = Vec::with_capacity(statements.len());
+    for (statement, name) in statements {
+        // The replicas do not know the keyspace of the connection, so the tables are sent qualified with it.
+        let (keyspace, table) = match resolve_table(name) {
+            Ok(table) => table,
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        };
+        if is_system_keyspace(&keyspace) {
+            let error = create_error_response(
+                ErrorCode::Unauthorized,
+                &format!("Keyspace '{keyspace}' is read-only"),
+                None,
+            );
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+        let mut statement = statement.clone();
+        if statement.timestamp().is_none() {
+            statement.set_timestamp(timestamp);
+        }
+        queries.push(inc::query::Query {
+            query: statement,
+            table: format!("{keyspace}.{table}"),
+        });
+    }
+    let groups = match group_by_replicas(queries.clone(), partitioner, ctx) {
+        Ok(groups) => groups,
+        Err(e) => {
+            let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    };
+
+    // The replicas known to be down are not waited on, and their statements are hinted right away.
+    let mut ack_groups = Vec::with_capacity(groups.len());
+    for group in &groups {
+        let replication = ctx.read().unwrap().get_replication(&group.keyspace)?;
+        let required = get_ack_groups(
+            cl,
+            replication.replication_factor as usize,
+            &replication.datacenters,
+            partitioner,
+        );
+        let alive: Vec<Node> = group
+            .nodes
+            .iter()
+            .filter(|node| partitioner.is_me(node) || failure_detector.is_alive(&node.ip_address))
+            .cloned()
+            .collect();
+        for (datacenter, required) in &required {
+            let replicas = count_in_datacenter(&alive, datacenter);
+            if *required > replicas {
+                let error = create_error_response(
+                    ErrorCode::UnavailableError,
+                    "Cannot achieve consistency level",
+                    Some(HashMap::from([
+                        ("consistency".to_string(), cl.to_string()),
+                        ("required".to_string(), required.to_string()),
+                        ("alive".to_string(), replicas.to_string()),
+                    ])),
+                );
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        }
+        ack_groups.push(required);
+    }
+
+    let batchlog = if batch_type == BatchType::Logged {
+        let entry = BatchLogEntry {
+            id: format!("{timestamp}-{:08x}", rand::random::<u32>()),
+            written_at: current_timestamp(),
+            queries,
+        };
+        match write_batchlog(&entry, partitioner, &failure_detector, ctx) {
+            Ok(endpoints) => Some((entry.id, endpoints)),
+            Err(e) => {
+                let error = create_error_response(
+                    ErrorCode::ServerError,
+                    &format!("Cannot write the batchlog: {e}"),
+                    None,
+                );
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut applied = true;
+    for (group, required) in groups.iter().zip(&ack_groups) {
+        let acked = match apply_group(group, partitioner, &failure_detector, ctx) {
+            Ok(acked) => acked,
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        };
+        applied &= required
+            .iter()
+            .all(|(datacenter, required)| count_in_datacenter(&acked, datacenter) >= *required);
+    }
+    // Every replica acked or got a hint of its statements, so the batch is not replayed.
+    if let Some((id, endpoints)) = batchlog {
+        remove_from_batchlog(&id, &endpoints, partitioner, ctx);
+    }
+    if !applied {
+        let error = create_error_response(
+            ErrorCode::ServerError,
+            "Not enough nodes responded to batch",
+            None,
+        );
+        println!("Not enough nodes responded to batch");
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    let result = create_result_response(None);
+    write_response(writer, RESULT, frame.header.stream, result)?;
+    println!("Batch executed successfully");
+    Ok(())
+}
+
+/// Groups the statements of a batch by the replicas of their partitions.
+///
+/// # Errors
+///
+/// * Returns an `Error` if a table does not exist or a statement does not give its primary key.
+fn group_by_replicas(
+    queries: Vec<inc::query::Query>,
+    partitioner: &Partitioner,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<ReplicaGroup>> {
+    let mut groups: Vec<ReplicaGroup> = Vec::new();
+    for query in queries {
+        let (keyspace, table) = resolve_table(&query.table)?;
+        let (schema, replication) = {
+            let ctx = ctx.read().unwrap();
+            (
+                ctx.get_table_schema(&keyspace, &table)?,
+                ctx.get_replication(&keyspace)?,
+            )
+        };
+        let key = get_query_key(&query.query, &schema)?;
+        let nodes = partitioner.get_nodes(&key, &replication)?;
+        let pending = partitioner.get_pending_nodes(&key, &replication)?;
+        match groups.iter_mut().find(|group| {
+            group.keyspace == keyspace && group.nodes == nodes && group.pending == pending
+        }) {
+            Some(group) => group.queries.push(query),
+            None => groups.push(ReplicaGroup {
+                keyspace,
+                nodes,
+                pending,
+                queries: vec![query],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Sends the statements of a group to its replicas, and returns the ones that acked them, without the
+/// pending ones. The replicas that are down or do not ack get the statements as hints.
+///
+/// # Errors
+///
+/// * Returns an `Error` if this node is a replica and cannot apply the statements, or if the hints of a
+///   replica cannot be written.
+fn apply_group(
+    group: &ReplicaGroup,
+    partitioner: &Partitioner,
+    failure_detector: &FailureDetector,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<Node>> {
+    let mut acked = Vec::new();
+    for node in group.nodes.iter().chain(&group.pending) {
+        if partitioner.is_me(node) {
+            println!("Executing batch...");
+            apply_batch(&group.queries, ctx)?;
+            acked.push(node.clone());
+            continue;
+        }
+        let sent =
+            failure_detector.is_alive(&node.ip_address) && send_batch(node, &group.queries).is_ok();
+        if sent {
+            acked.push(node.clone());
+            continue;
+        }
+        println!("Hinting the batch of {}", node.ip_address);
+        let node_dir = ctx.read().unwrap().node_dir.clone();
+        for query in &group.queries {
+            add_hint(&node_dir, &node.ip_address, &query.query, &query.table)?;
+        }
+    }
+    acked.retain(|node| !group.pending.contains(node));
+    Ok(acked)
+}
+
+/// Sends statements to a replica, which applies them all before acking.
+fn send_batch(node: &Node, queries: &[inc::query::Query]) -> std::io::Result<()> {
+    println!("Forwarding batch to {}", node.ip_address);
+    let mut stream = TcpStream::connect((&node.ip_address[..], node.port + 1))?;
+    let body = Body::Batch(Batch {
+        queries: queries.to_vec(),
+    });
+    send_message(&mut stream, FrameType::Batch, &body)?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::Result, Body::Result(_)) => Ok(()),
+        (FrameType::Error, Body::Error(error)) => Err(io_error!(error.message)),
+        res => Err(io_error!(format!(
+            "Invalid frame type after batch: {res:?}"
+        ))),
+    }
+}
+
+/// Applies the statements of a batch as one of their replicas, acking once they are all applied.
+pub(crate) fn handle_batch_frame(batch: Batch, mut stream: TcpStream, ctx: &RwLock<Context>) {
+    let res = apply_batch(&batch.queries, ctx);
+    send_result(&mut stream, res.map(|_| None));
+}
+
+/// Applies the statements of a batch as one of their replicas. The statements of each partition are
+/// applied as a single write, so the readers of the partition see all of them or none.
+///
+/// # Errors
+///
+/// * Returns an `Error` if a table does not exist or a statement cannot be applied, in which case none of
+///   the statements of its partition are.
+fn apply_batch(queries: &[inc::query::Query], ctx: &RwLock<Context>) -> std::io::Result<()> {
+    let ctx = ctx.read().unwrap();
+    let mut partitions: Vec<PartitionStatements> = Vec::new();
+    for query in queries {
+        let (keyspace, table) = resolve_table(&query.table)?;
+        let schema = ctx.get_table_schema(&keyspace, &table)?;
+        let partition = (query.table.as_str(), get_query_key(&query.query, &schema)?);
+        match partitions.iter_mut().find(|(key, _)| key == &partition) {
+            Some((_, statements)) => statements.push(&query.query),
+            None => partitions.push((partition, vec![&query.query])),
+        }
+    }
+    for ((name, _), statements) in partitions {
+        let (keyspace, table) = resolve_table(name)?;
+        let mut batch = ctx.write_batch(&ctx.node_dir.join(keyspace).join(table))?;
+        for statement in statements {
+            statement.write_rows(&mut batch, &ctx)?;
+        }
+        batch.apply()?;
+    }
+    Ok(())
+}
+
+/// Keeps a logged batch in the batchlog of up to `BATCHLOG_REPLICAS` other nodes that are up, preferring
+/// the ones of the datacenter of this node, or of this node if none of them keeps it. Returns the nodes
+/// that keep it.
+fn write_batchlog(
+    entry: &BatchLogEntry,
+    partitioner: &Partitioner,
+    failure_detector: &FailureDetector,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<Node>> {
+    let mut candidates: Vec<Node> = partitioner
+        .get_normal_nodes()
+        .into_iter()
+        .filter(|node| !partitioner.is_me(node) && failure_detector.is_alive(&node.ip_address))
+        .collect();
+    candidates.sort_by_key(|node| node.datacenter != partitioner.self_node.datacenter);
+    let mut endpoints = Vec::new();
+    for node in candidates {
+        if endpoints.len() == BATCHLOG_REPLICAS {
+            break;
+        }
+        match send_batchlog(&node, BatchLog::Store(entry.clone())) {
+            Ok(()) => endpoints.push(node),
+            Err(e) => println!("Failed to write the batchlog to {}: {e}", node.ip_address),
+        }
+    }
+    if endpoints.is_empty() {
+        let node_dir = ctx.read().unwrap().node_dir.clone();
+        store_batch(&node_dir, entry)?;
+        endpoints.push(partitioner.self_node.clone());
+    }
+    Ok(endpoints)
+}
+
+/// Removes an applied batch from the batchlog of the nodes that keep it.
+fn remove_from_batchlog(
+    id: &str,
+    endpoints: &[Node],
+    partitioner: &Partitioner,
+    ctx: &RwLock<Context>,
+) {
+    for node in endpoints {
+        let res = if partitioner.is_me(node) {
+            remove_batch(&ctx.read().unwrap().node_dir, id)
+        } else {
+            send_batchlog(node, BatchLog::Remove(id.to_string()))
+        };
+        if let Err(e) = res {
+            println!("Failed to remove batch {id} from {}: {e}", node.ip_address);
+        }
+    }
+}
+
+fn send_batchlog(node: &Node, batch_log: BatchLog) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect((&node.ip_address[..], node.port + 1))?;
+    send_message(&mut stream, FrameType::BatchLog, &Body::BatchLog(batch_log))?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::Result, Body::Result(_)) => Ok(()),
+        (FrameType::Error, Body::Error(error)) => Err(io_error!(error.message)),
+        res => Err(io_error!(format!(
+            "Invalid frame type after batchlog: {res:?}"
+        ))),
+    }
+}
+
+/// Changes the batchlog of this node as a coordinator asks, acking once it is changed.
+pub(crate) fn handle_batchlog(batch_log: BatchLog, mut stream: TcpStream, node_dir: &Path) {
+    let res = match batch_log {
+        BatchLog::Store(entry) => store_batch(node_dir, &entry),
+        BatchLog::Remove(id) => remove_batch(node_dir, &id),
+    };
+    send_result(&mut stream, res.map(|_| None));
+}
+
+fn store_batch(node_dir: &Path, entry: &BatchLogEntry) -> std::io::Result<()> {
+    let dir = node_dir.join(BATCHLOG_DIR);
+    create_dir_all(&dir)?;
+    let file = File::create(dir.join(&entry.id).with_extension("json"))?;
+    serde_json::to_writer(file, entry).map_err(map_io_error!("Cannot write the batchlog"))
+}
+
+fn remove_batch(node_dir: &Path, id: &str) -> std::io::Result<()> {
+    let path = node_dir.join(BATCHLOG_DIR).join(id).with_extension("json");
+    if path.exists() {
+        remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Returns the batches of the batchlog of this node written before the time, in microseconds since the
+/// epoch.
+fn read_batchlog(node_dir: &Path, before: i64) -> std::io::Result<Vec<BatchLogEntry>> {
+    let dir = node_dir.join(BATCHLOG_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for file in read_dir(dir)? {
+        let entry: BatchLogEntry = serde_json::from_reader(File::open(file?.path())?)
+            .map_err(map_io_error!("Invalid batchlog entry"))?;
+        if entry.written_at < before {
+            entries.push(entry);
+        }
+    }
+    entries.sort_by_key(|entry| entry.written_at);
+    Ok(entries)
+}
+
+/// Replays the batches of the batchlog of this node whose coordinator did not remove them in time, which
+/// most likely died before applying them.
+///
+/// The statements keep the timestamps the coordinator gave them, so replaying a batch that was already
+/// applied, even partially, does not change its replicas.
+pub(crate) fn replay_batchlog(
+    partitioner: Arc<Partitioner>,
+    manager: Arc<RwLock<GossipManager>>,
+    ctx: Arc<RwLock<Context>>,
+) {
+    loop {
+        thread::sleep(REPLAY_INTERVAL);
+        let failure_detector = Arc::clone(&manager.read().unwrap().failure_detector);
+        let before = current_timestamp() - BATCHLOG_TIMEOUT;
+        if let Err(e) = replay_batches(before, &partitioner, &failure_detector, &ctx) {
+            println!("Failed to read the batchlog: {e}");
+        }
+    }
+}
+
+/// Replays the batches of the batchlog written before the time, in microseconds since the epoch.
+///
+/// A batch is removed once it is replayed, or once it never can be, such as the batches of a dropped
+/// table. The batches that fail for any other reason, such as a local I/O error or hints that cannot be
+/// written, are kept for the next replay.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the batchlog cannot be read.
+fn replay_batches(
+    before: i64,
+    partitioner: &Partitioner,
+    failure_detector: &FailureDetector,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let node_dir = ctx.read().unwrap().node_dir.clone();
+    for entry in read_batchlog(&node_dir, before)? {
+        println!("Replaying batch {}", entry.id);
+        let res = group_by_replicas(entry.queries, partitioner, ctx).and_then(|groups| {
+            groups.iter().try_for_each(|group| {
+                apply_group(group, partitioner, failure_detector, ctx).map(|_| ())
+            })
+        });
+        match res {
+            Ok(()) => {}
+            // The keyspace or the table of a statement no longer exists.
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                println!("Dropping batch {}: {e}", entry.id);
+            }
+            Err(e) => {
+                println!("Failed to replay batch {}, retrying later: {e}", entry.id);
+                continue;
+            }
+        }
+        remove_batch(&node_dir, &entry.id).unwrap_or(());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use db::initialize_context;
+    use query::process_query;
+
+    use super::*;
+    use crate::{
+        connections::{hinted::has_hints, node::process_replica_query},
+        test_utils::node,
+    };
+
+    fn entry(id: &str, written_at: i64) -> BatchLogEntry {
+        let (query, table) =
+            process_query("INSERT INTO ks.users (id, name) VALUES (1, 'Jo')").unwrap();
+        BatchLogEntry {
+            id: id.to_string(),
+            written_at,
+            queries: vec![inc::query::Query { query, table }],
+        }
+    }
+
+    #[test]
+    fn test_batchlog_keeps_batches_until_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_dir = dir.path();
+        store_batch(node_dir, &entry("old", 1)).unwrap();
+        store_batch(node_dir, &entry("new", 3)).unwrap();
+
+        let ids = |before| -> Vec<String> {
+            read_batchlog(node_dir, before)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect()
+        };
+        assert_eq!(ids(2), vec!["old"]);
+        assert_eq!(ids(4), vec!["old", "new"]);
+
+        remove_batch(node_dir, "old").unwrap();
+        remove_batch(node_dir, "missing").unwrap();
+        assert_eq!(ids(4), vec!["new"]);
+    }
+
+    #[test]
+    fn test_batch_applies_the_statements_of_each_partition_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = RwLock::new(initialize_context(dir.path()).unwrap());
+        for query in [
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}",
+            "CREATE TABLE ks.t (id int, seq int, PRIMARY KEY (id, seq))",
+        ] {
+            let (mut query, name) = process_query(query).unwrap();
+            process_replica_query(&mut query, &name, &ctx).unwrap();
+        }
+        let queries: Vec<inc::query::Query> = [
+            "INSERT INTO ks.t (id, seq) VALUES (1, 1)",
+            "INSERT INTO ks.t (id, seq) VALUES (2, 1)",
+            "INSERT INTO ks.t (id, seq) VALUES (1, 2)",
+            "UPDATE ks.t SET age = 3 WHERE id = 2 AND seq = 2",
+        ]
+        .into_iter()
+        .map(|query| {
+            let (query, table) = process_query(query).unwrap();
+            inc::query::Query { query, table }
+        })
+        .collect();
+
+        assert!(apply_batch(&queries, &ctx).is_err());
+        let mut rows = Vec::new();
+        let ctx = ctx.read().unwrap();
+        ctx.read_table(&dir.path().join("ks").join("t"), &mut |row| {
+            rows.push((row["id"].clone(), row["seq"].clone()));
+            Ok(())
+        })
+        .unwrap();
+        rows.sort();
+        // The partition with an invalid statement is not written at all.
+        assert_eq!(
+            rows,
+            vec![
+                ("1".to_string(), "1".to_string()),
+                ("1".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_replays_keep_the_batch_until_it_is_replayed() {
+        let nodes = vec![
+            node("127.0.0.1", "datacenter1", "rack1", &[100]),
+            node("127.0.0.2", "datacenter1", "rack1", &[200]),
+        ];
+        let partitioner = Partitioner::new(nodes[0].clone(), nodes, Vec::new()).unwrap();
+        // No heartbeat is expected in time, so the other replica is down and gets hints.
+        let failure_detector = FailureDetector::new(0.0);
+        let dir = tempfile::tempdir().unwrap();
+        let node_dir = dir.path();
+        let ctx = RwLock::new(initialize_context(node_dir).unwrap());
+        for query in [
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 2}",
+            "CREATE TABLE ks.users (id int, name text, PRIMARY KEY (id))",
+        ] {
+            let (mut query, name) = process_query(query).unwrap();
+            process_replica_query(&mut query, &name, &ctx).unwrap();
+        }
+        store_batch(node_dir, &entry("users", 1)).unwrap();
+        let mut dropped = entry("dropped", 2);
+        dropped.queries[0].table = "ks.dropped".to_string();
+        store_batch(node_dir, &dropped).unwrap();
+
+        let ids = || -> Vec<String> {
+            read_batchlog(node_dir, i64::MAX)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect()
+        };
+        // A file in place of the hints dir makes the hints of the down replica fail.
+        std::fs::write(node_dir.join("hints"), "").unwrap();
+        replay_batches(i64::MAX, &partitioner, &failure_detector, &ctx).unwrap();
+        assert_eq!(ids(), vec!["users"]);
+
+        std::fs::remove_file(node_dir.join("hints")).unwrap();
+        replay_batches(i64::MAX, &partitioner, &failure_detector, &ctx).unwrap();
+        assert!(ids().is_empty());
+        assert!(has_hints(node_dir, "127.0.0.2"));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    net::TcpStream,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use db::Context;
+use inc::{
+    gossip::peer::Status,
+    read_inc_frame,
+    stream::{StreamRequest, StreamResponse, StreamedKeyspace, StreamedTable},
+    Body, FrameType,
+};
+use shared::io_error;
+
+use crate::partitioner::{
+    murmur3::{JoiningRanges, Partitioner},
+    node::Node,
+};
+
+use super::{
+    gossip::manager::GossipManager,
+    node::send_message,
+    schema::{record_schema_change, request_schema_changes},
+};
+
+/// Time the gossip takes to spread the state of a node that joins or leaves the ring, and to teach a
+/// joining node the ring.
+pub(crate) const RING_DELAY: Duration = Duration::from_secs(15);
+
+/// Joins the node to the ring.
+///
+/// The node announces itself as joining through gossip, so the coordinators start sending it the writes
+/// of the tokens it is going to own. Then it creates the keyspaces and tables of the ring, streams the
+/// rows of each range of tokens it is going to own from one of its current replicas, and switches to
+/// normal, after which it is also read from.
+///
+/// **Must** be called once the gossip is running, and before accepting clients.
+///
+/// # Errors
+///
+/// * Returns an `Error` if a range cannot be streamed from any of its replicas, or if the streamed data
+///   cannot be written. The node is not announced as normal then.
+pub(crate) fn bootstrap(
+    partitioner: &Partitioner,
+    manager: &RwLock<GossipManager>,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    println!("Joining the ring...");
+    thread::sleep(RING_DELAY);
+    let sources = partitioner.get_normal_nodes();
+    if !sources.is_empty() {
+        for keyspace in stream_schema(&sources, ctx)? {
+            let replication = ctx.read().unwrap().get_replication(&keyspace.name)?;
+            let ranges = partitioner.get_joining_ranges(&partitioner.self_node, &replication)?;
+            for table in keyspace.tables {
+                stream_table(&keyspace.name, &table.name, &ranges, ctx)?;
+            }
+        }
+    }
+    manager.read().unwrap().set_status(Status::Normal)?;
+    println!("Joined the ring");
+    Ok(())
+}
+
+/// Creates the keyspaces and tables of the first source that answers, that the node does not have yet.
+fn stream_schema(
+    sources: &[Node],
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<StreamedKeyspace>> {
+    let mut error = io_error!("No node to stream the keyspaces from");
+    for source in sources {
+        match request_stream(source, StreamRequest::Schema) {
+            Ok(StreamResponse::Schema(keyspaces)) => {
+                create_schema(&keyspaces, ctx)?;
+                // The node takes the schema as it is, so the changes that led to it are kept without
+                // applying them again.
+                let changes = request_schema_changes(&source.ip_address, source.port + 1)?;
+                let node_dir = ctx.read().unwrap().node_dir.clone();
+                let _ctx = ctx.write().unwrap();
+                for change in changes {
+                    record_schema_change(&node_dir, &change)?;
+                }
+                return Ok(keyspaces);
+            }
+            Ok(_) => error = io_error!("Invalid stream response to a schema request"),
+            Err(e) => error = e,
+        }
+        println!(
+            "Failed to stream the keyspaces from {}: {error}",
+            source.ip_address
+        );
+    }
+    Err(error)
+}
+
+/// Streams the rows of the ranges of a table, asking each range to the first of its replicas that
+/// answers. The ranges whose replica fails are asked to their next replica.
+fn stream_table(
+    keyspace: &str,
+    name: &str,
+    ranges: &JoiningRanges,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let table = format!("{keyspace}.{name}");
+    let table_dir = ctx.read().unwrap().node_dir.join(keyspace).join(name);
+    let mut pending: Vec<((i64, i64), &[Node])> = ranges
+        .iter()
+        .map(|(range, sources)| (*range, &sources[..]))
+        .collect();
+    while !pending.is_empty() {
+        let mut by_source: Vec<(&Node, Vec<(i64, i64)>)> = Vec::new();
+        for (range, sources) in &pending {
+            let source = sources.first().ok_or(io_error!(format!(
+                "Cannot stream the range {range:?} of {table} from any of its replicas"
+            )))?;
+            match by_source
+                .iter_mut()
+                .find(|(node, _)| node.ip_address == source.ip_address)
+            {
+                Some((_, ranges)) => ranges.push(*range),
+                None => by_source.push((source, vec![*range])),
+            }
+        }
+        let mut failed = Vec::new();
+        for (source, ranges) in by_source {
+            println!(
+                "Streaming {} ranges of {table} from {}",
+                ranges.len(),
+                source.ip_address
+            );
+            let request = StreamRequest::Rows {
+                table: table.clone(),
+                ranges: ranges.clone(),
+            };
+            let error = match request_stream(source, request) {
+                Ok(StreamResponse::Rows(rows)) => {
+                    if !rows.is_empty() {
+                        ctx.write().unwrap().write_stored_rows(&table_dir, rows)?;
+                    }
+                    continue;
+                }
+                Ok(_) => io_error!("Invalid stream response to a rows request"),
+                Err(e) => e,
+            };
+            println!(
+                "Failed to stream {table} from {}: {error}",
+                source.ip_address
+            );
+            failed.extend(ranges);
+        }
+        pending = pending
+            .into_iter()
+            .filter(|(range, _)| failed.contains(range))
+            .map(|(range, sources)| (range, &sources[1..]))
+            .collect();
+    }
+    Ok(())
+}
+
+fn request_stream(source: &Node, request: StreamRequest) -> std::io::Result<StreamResponse> {
+    let mut stream = TcpStream::connect((&source.ip_address[..], source.port + 1))?;
+    send_message(
+        &mut stream,
+        FrameType::StreamRequest,
+        &Body::StreamRequest(request),
+    )?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::StreamResponse, Body::StreamResponse(response)) => Ok(response),
+        _ => Err(io_error!("Invalid frame type after stream request")),
+    }
+}
+
+/// Creates the keyspaces and tables that the node does not have yet.
+fn create_schema(keyspaces: &[StreamedKeyspace], ctx: &RwLock<Context>) -> std::io::Result<()> {
+    let mut ctx = ctx.write().unwrap();
+    let node_dir = ctx.node_dir.clone();
+    for keyspace in keyspaces {
+        let keyspace_dir = node_dir.join(&keyspace.name);
+        if !ctx.is_a_keyspace(&keyspace.name) {
+            ctx.create_keyspace(&keyspace_dir, &keyspace.options)?;
+        }
+        for table in &keyspace.tables {
+            if ctx.get_table_schema(&keyspace.name, &table.name).is_err() {
+                ctx.create_table(&keyspace_dir.join(&table.name), &table.schema)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Answers the stream request of a joining node with the keyspaces and tables of this node, or with the
+/// stored rows of the ranges of tokens of a table.
+pub(crate) fn handle_stream_request(
+    request: StreamRequest,
+    mut stream: TcpStream,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    let ctx = ctx.read().unwrap();
+    let response = match request {
+        StreamRequest::Schema => {
+            let mut keyspaces = Vec::new();
+            for keyspace in ctx.get_keyspaces() {
+                let mut tables = Vec::new();
+                for table in ctx.get_tables(&keyspace)? {
+                    tables.push(StreamedTable {
+                        schema: ctx.get_table_schema(&keyspace, &table)?,
+                        name: table,
+                    });
+                }
+                keyspaces.push(StreamedKeyspace {
+                    options: ctx.get_keyspace_options(&ctx.node_dir.join(&keyspace))?,
+                    name: keyspace,
+                    tables,
+                });
+            }
+            StreamResponse::Schema(keyspaces)
+        }
+        StreamRequest::Rows { table, ranges } => {
+            let (keyspace, table) = table
+                .split_once('.')
+                .ok_or(io_error!("The table is not qualified with its keyspace"))?;
+            let mut rows = Vec::new();
+            ctx.scan_stored_rows(&ctx.node_dir.join(keyspace).join(table), None, &mut |row| {
+                let token = row.token();
+                if ranges
+                    .iter()
+                    .any(|(start, end)| *start <= token && token <= *end)
+                {
+                    rows.push(row);
+                }
+                Ok(())
+            })?;
+            StreamResponse::Rows(rows)
+        }
+    };
+    send_message(
+        &mut stream,
+        FrameType::StreamResponse,
+        &Body::StreamResponse(response),
+    )
+}
@@ -1,304 +1,1176 @@
 use std::{
     collections::HashMap,
-    io::BufReader,
+    io::{BufReader, ErrorKind, Write},
     net::TcpStream,
-    sync::{Arc, RwLock},
+    sync::{Arc, Condvar, Mutex, RwLock},
+    thread::{self, Scope},
 };
 
-use db::Context;
+use db::{current_timestamp, get_live_rows, reconcile, Context, Schema, SchemaType};
 use inc::{read_inc_frame, Body, FrameType};
 use native::{
-    client::{ConsistencyLevel, STARTUP},
+    client::{ConsistencyLevel, BATCH, EXECUTE, OPTIONS, PREPARE, QUERY, STARTUP},
     server::{
-        create_error_response, create_ready_response, create_response_frame,
-        create_result_response, read_request, ColumnSpec, DataTypeFlags, ErrorCode, RowMetadata,
-        Rows as NativeRows, RowsMetadaFlagsMask, ERROR, READY, RESULT,
+        create_error_response, create_prepared_response, create_ready_response,
+        create_response_frame, create_result_response, create_set_keyspace_response,
+        create_supported_response, create_unprepared_response, read_request_body,
+        read_request_header, ColumnSpec, DataTypeFlags, ErrorCode, Frame, Opcode, PreparedMetadata,
+        Response, RowMetadata, Rows as NativeRows, RowsMetadaFlagsMask, ERROR, READY, RESULT,
+        SUPPORTED,
     },
 };
-use shared::{get_keyspace, get_keyspace_name};
+use query::{prepare_query, BindMarker, Query};
+use shared::{
+    get_connection_ctx, io_error, is_startup, resolve_table, set_connection_ctx, set_keyspace,
+    set_startup, set_startup_options,
+};
 
 use crate::{
-    connections::{hinted::add_hint, node::send_message, read_repair::handle_read_repair},
-    partitioner::murmur3::{Partitioner, ALL_NODES},
+    connections::{
+        batch::{get_batch_query, handle_batch},
+        gossip::manager::GossipManager,
+        hinted::add_hint,
+        node::{process_replica_query, send_message},
+        paging::{
+            get_page_request, process_replica_page, select_page, select_replica_page, PageRequest,
+        },
+        prepared::{bind_values, PreparedStatement, PreparedStatements},
+        read_repair::handle_read_repair,
+        schema::wait_for_schema_agreement,
+        system::{is_system_keyspace, read_virtual_table},
+    },
+    partitioner::{murmur3::Partitioner, node::Node},
 };
 
 pub(crate) type Row = Vec<String>;
 pub(crate) type Rows = Vec<Row>;
 
-pub fn handle_connection(
-    mut stream: TcpStream,
+/// The most requests of a connection processed at the same time. The next requests are not read until
+/// one of them is answered, so a single client cannot spawn an unbounded number of threads.
+const MAX_IN_FLIGHT_REQUESTS: usize = 32;
+
+/// Handles a client connection until the client disconnects.
+///
+/// The connection must be started with a `STARTUP` message. After that, every `QUERY` frame
+/// is answered on the same connection, keeping the state of the session (the keyspace in use and
+/// the options negotiated in the `STARTUP` message) alive between queries. `OPTIONS` requests, which
+/// drivers send to find out what they can ask for in the `STARTUP` message, are answered at any point.
+///
+/// Queries can also be prepared once with a `PREPARE` and run with an `EXECUTE` of the id it answers
+/// with, binding the values of their bind markers. The prepared queries are kept by the node for every
+/// connection.
+///
+/// Requests are multiplexed by their stream id: each query is processed in its own thread, with a
+/// copy of the session state, so up to `MAX_IN_FLIGHT_REQUESTS` requests can be in flight at the same
+/// time. Responses are written as soon as they are ready, possibly out of order, tagged with the stream
+/// id of the request they answer.
+///
+/// The queries are only forwarded to the replicas the failure detector considers up, and the DDL queries
+/// are answered once the nodes that are up agree on the schema.
+pub(crate) fn handle_connection(
+    stream: TcpStream,
     partitioner: &Partitioner,
+    manager: &RwLock<GossipManager>,
+    prepared: &PreparedStatements,
     ctx: Arc<RwLock<Context>>,
 ) {
     let mut stream_clone = stream.try_clone().unwrap();
     let mut reader = BufReader::new(&mut stream_clone);
-    let frame = read_request(&mut reader).unwrap();
-    if frame.header.opcode != STARTUP {
+    let writer = Mutex::new(stream);
+    let limiter = RequestLimiter::new(MAX_IN_FLIGHT_REQUESTS);
+    thread::scope(|scope| loop {
+        let header = match read_request_header(&mut reader) {
+            Ok(header) => header,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                println!("Client disconnected");
+                return;
+            }
+            Err(e) => {
+                println!("Error while reading frame, closing connection: {e}");
+                return;
+            }
+        };
+        let stream_id = header.stream;
+        let frame = match read_request_body(&mut reader, header) {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                println!("Client disconnected");
+                return;
+            }
+            Err(e) => {
+                let error = create_error_response(ErrorCode::SyntaxError, &e.to_string(), None);
+                if write_response(&writer, ERROR, stream_id, error).is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let res = match frame.header.opcode {
+            STARTUP => handle_startup(&writer, &frame),
+            OPTIONS => write_response(&writer, SUPPORTED, stream_id, create_supported_response()),
+            QUERY | PREPARE | EXECUTE | BATCH if !is_startup() => {
+                let error = create_error_response(
+                    ErrorCode::ProtocolError,
+                    "Connection not started with startup message",
+                    None,
+                );
+                write_response(&writer, ERROR, stream_id, error)
+            }
+            PREPARE => handle_prepare(&writer, &frame, manager, prepared, &ctx),
+            BATCH => match get_batch_query(&writer, &frame, manager, prepared, &ctx) {
+                Err(e) => Err(e),
+                Ok(None) => Ok(()),
+                Ok(Some(query)) => {
+                    let session = get_connection_ctx();
+                    let (writer, ctx) = (&writer, &ctx);
+                    spawn_request(scope, &limiter, move || {
+                        set_connection_ctx(session);
+                        if let Err(e) =
+                            handle_batch(writer, &frame, query, partitioner, manager, ctx)
+                        {
+                            println!("Error while answering stream {stream_id}: {e}");
+                        }
+                    });
+                    Ok(())
+                }
+            },
+            QUERY | EXECUTE => {
+                match get_request_query(&writer, &frame, manager, prepared, &ctx) {
+                    Err(e) => Err(e),
+                    Ok(None) => Ok(()),
+                    // The keyspace is changed before the next requests are read, so they are all run in it.
+                    Ok(Some((query, keyspace))) if query.is_use() => {
+                        handle_use(&writer, stream_id, &keyspace, &ctx)
+                    }
+                    Ok(Some((query, name))) => {
+                        let session = get_connection_ctx();
+                        let (writer, ctx) = (&writer, &ctx);
+                        spawn_request(scope, &limiter, move || {
+                            set_connection_ctx(session);
+                            let res = if query.is_batch() {
+                                handle_batch(writer, &frame, query, partitioner, manager, ctx)
+                            } else {
+                                handle_query(writer, &frame, query, name, partitioner, manager, ctx)
+                            };
+                            if let Err(e) = res {
+                                println!("Error while answering stream {stream_id}: {e}");
+                            }
+                        });
+                        Ok(())
+                    }
+                }
+            }
+            _ => {
+                let error = create_error_response(
+                    ErrorCode::ProtocolError,
+                    &format!("Unexpected opcode: {}", frame.header.opcode),
+                    None,
+                );
+                write_response(&writer, ERROR, stream_id, error)
+            }
+        };
+        if let Err(e) = res {
+            println!("Error while writing response, closing connection: {e}");
+            return;
+        }
+    });
+}
+
+/// Counts the requests of a connection being processed, to cap them at a maximum.
+struct RequestLimiter {
+    in_flight: Mutex<usize>,
+    released: Condvar,
+    max: usize,
+}
+
+/// A request counted by a `RequestLimiter` until it is dropped.
+struct RequestPermit<'a> {
+    limiter: &'a RequestLimiter,
+}
+
+impl RequestLimiter {
+    fn new(max: usize) -> Self {
+        RequestLimiter {
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+            max,
+        }
+    }
+
+    /// Waits until fewer than the maximum requests are in flight, and counts one more.
+    fn acquire(&self) -> RequestPermit<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight >= self.max {
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        RequestPermit { limiter: self }
+    }
+}
+
+impl Drop for RequestPermit<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self
+            .limiter
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *in_flight -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+/// Processes a request in its own thread of the connection, once the limiter lets one more in.
+fn spawn_request<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    limiter: &'scope RequestLimiter,
+    request: impl FnOnce() + Send + 'scope,
+) {
+    let permit = limiter.acquire();
+    scope.spawn(move || {
+        let _permit = permit;
+        request();
+    });
+}
+
+/// Writes a whole response frame at once, so responses written concurrently by different
+/// requests of the same connection do not interleave.
+pub(crate) fn write_response(
+    writer: &Mutex<TcpStream>,
+    opcode: Opcode,
+    stream_id: u16,
+    response: Response,
+) -> std::io::Result<()> {
+    let mut buffer = Vec::new();
+    create_response_frame(opcode, stream_id, response)?.write(&mut buffer)?;
+    let mut stream = writer
+        .lock()
+        .map_err(|_| io_error!("Connection writer poisoned"))?;
+    stream.write_all(&buffer)?;
+    stream.flush()
+}
+
+fn handle_startup(writer: &Mutex<TcpStream>, frame: &Frame) -> std::io::Result<()> {
+    if is_startup() {
+        let error =
+            create_error_response(ErrorCode::ProtocolError, "Connection already started", None);
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    let options = frame
+        .body
+        .get_startup_options()
+        .map(|options| options.clone().into_iter().collect())
+        .unwrap_or_default();
+    set_startup_options(options);
+    set_startup(true);
+    write_response(writer, READY, frame.header.stream, create_ready_response())
+}
+
+/// Sets the keyspace of the connection, for the tables of the next queries that are not qualified with one.
+fn handle_use(
+    writer: &Mutex<TcpStream>,
+    stream_id: u16,
+    keyspace: &str,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    if !is_system_keyspace(keyspace) && !ctx.read().unwrap().is_a_keyspace(keyspace) {
         let error = create_error_response(
-            ErrorCode::ProtocolError,
-            "Connection not started with startup message",
+            ErrorCode::Invalid,
+            &format!("Keyspace '{keyspace}' does not exist"),
             None,
         );
-        let response = create_response_frame(ERROR, frame.header.stream, error).unwrap();
-        response.write(&mut stream).unwrap();
+        return write_response(writer, ERROR, stream_id, error);
     }
-    create_response_frame(READY, frame.header.stream, create_ready_response())
-        .unwrap()
-        .write(&mut stream)
-        .unwrap();
+    set_keyspace(keyspace.to_string());
+    let result = create_set_keyspace_response(keyspace);
+    write_response(writer, RESULT, stream_id, result)
+}
 
-    println!("Waiting for query...");
-    let frame = read_request(&mut reader).unwrap();
-    let (mut query, table) = frame.body.get_query().unwrap();
-    println!("Received query: {}", frame.body.get_query_str().unwrap());
+/// Parses a query with bind markers and keeps it in the prepared queries of the node, answering with the
+/// id to execute it with, the columns its markers are bound to and the columns of the rows it returns.
+fn handle_prepare(
+    writer: &Mutex<TcpStream>,
+    frame: &Frame,
+    manager: &RwLock<GossipManager>,
+    prepared: &PreparedStatements,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    let query_str = frame.body.get_query_str().unwrap();
+    println!("Preparing query: {query_str}");
+    let (query, name, markers) = match prepare_query(&query_str) {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            let error = create_error_response(ErrorCode::SyntaxError, &e.to_string(), None);
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    };
+    let metadata = if query.is_keyspace_query() {
+        Ok((name.clone(), name, None))
+    } else {
+        resolve_table(&name).and_then(|(keyspace, table)| {
+            let schema = if query.is_ddl() {
+                None
+            } else {
+                Some(get_schema(&keyspace, &table, manager, ctx)?)
+            };
+            Ok((keyspace, table, schema))
+        })
+    }
+    .and_then(|(keyspace, table, schema)| {
+        let metadata = get_prepared_metadata(&query, &markers, schema.as_ref(), &keyspace, &table)?;
+        Ok((keyspace, table, metadata))
+    });
+    let (keyspace, table, (metadata, result_metadata)) = match metadata {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    };
+    let name = if query.is_keyspace_query() {
+        keyspace.clone()
+    } else {
+        format!("{keyspace}.{table}")
+    };
+    let id = prepared.insert(
+        &keyspace,
+        PreparedStatement {
+            query,
+            name,
+            markers,
+            query_str,
+        },
+    );
+    let result = create_prepared_response(id, metadata, result_metadata);
+    write_response(writer, RESULT, frame.header.stream, result)
+}
 
-    let key;
-    if query.is_ddl() {
-        key = vec![ALL_NODES.to_string()];
+/// Returns the metadata of a prepared query: the columns its markers are bound to, with the markers of
+/// the partition key, and the columns of the rows it returns if it is a `SELECT`.
+fn get_prepared_metadata(
+    query: &Query,
+    markers: &[BindMarker],
+    schema: Option<&Schema>,
+    keyspace: &str,
+    table: &str,
+) -> std::io::Result<(PreparedMetadata, RowMetadata)> {
+    let no_metadata = RowMetadata::new(RowsMetadaFlagsMask::NoMetadata as i32, 0, None, None)?;
+    let Some(schema) = schema else {
+        return Ok((
+            PreparedMetadata {
+                pk_indexes: Vec::new(),
+                global_table_spec: None,
+                column_specs: Vec::new(),
+            },
+            no_metadata,
+        ));
+    };
+    let marker_columns: Vec<String> = markers.iter().map(|marker| marker.column.clone()).collect();
+    let column_specs = get_column_specs(&marker_columns, schema)?
+        .into_iter()
+        .zip(markers)
+        .map(|(spec, marker)| match &marker.name {
+            Some(name) => ColumnSpec::new(name.clone(), spec.data_type),
+            None => spec,
+        })
+        .collect();
+    let pk_indexes = schema
+        .get_primary_key()
+        .get_partition_key()
+        .iter()
+        .map(|col| {
+            markers
+                .iter()
+                .position(|marker| &marker.column == col)
+                .map(|index| index as u16)
+        })
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+    let result_metadata = if query.is_not_select() {
+        no_metadata
     } else {
-        let binding = query.get_keys();
-        let read_guard = ctx.read().unwrap();
-        let schema = read_guard
-            .get_table_schema(&get_keyspace_name().unwrap(), &table)
-            .unwrap();
-        drop(read_guard);
-        let primary_key = schema.get_primary_key();
-        let mut keys = binding
-            .iter()
-            .filter(|(col, _)| {
-                primary_key.get_partition_key().contains(col)
-                    || primary_key.get_clustering_key().contains(col)
-            })
-            .collect::<Vec<_>>();
-
-        if keys.len()
-            != primary_key.get_partition_key().len() + primary_key.get_clustering_key().len()
-        {
-            let error =
-                create_error_response(ErrorCode::Invalid, "Primary key columns not provided", None);
-            let response = create_response_frame(ERROR, frame.header.stream, error).unwrap();
-            response.write(&mut stream).unwrap();
-            return;
+        let cols = query.get_selected_columns(schema);
+        RowMetadata::new(
+            RowsMetadaFlagsMask::GlobalTablesSpec as i32,
+            cols.len() as i32,
+            Some((keyspace.to_string(), table.to_string())),
+            Some(get_column_specs(&cols, schema)?),
+        )?
+    };
+    Ok((
+        PreparedMetadata {
+            pk_indexes,
+            global_table_spec: Some((keyspace.to_string(), table.to_string())),
+            column_specs,
+        },
+        result_metadata,
+    ))
+}
+
+/// Returns the query of a `QUERY`, or the prepared one of an `EXECUTE`, with the values of the request
+/// bound to its markers, and the table it runs on. If the query is not prepared or its values are not
+/// valid, the error is answered and `None` is returned.
+fn get_request_query(
+    writer: &Mutex<TcpStream>,
+    frame: &Frame,
+    manager: &RwLock<GossipManager>,
+    prepared: &PreparedStatements,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<Option<(Query, String)>> {
+    let (query, name, markers) = if let Some(execute) = frame.body.get_execute() {
+        let Some(statement) = prepared.get(&execute.id) else {
+            let error = create_unprepared_response(&execute.id);
+            return write_response(writer, ERROR, frame.header.stream, error).map(|_| None);
+        };
+        println!("Executing prepared query: {}", statement.query_str);
+        (statement.query, statement.name, statement.markers)
+    } else {
+        println!("Received query: {}", frame.body.get_query_str().unwrap());
+        let (query, name) = frame.body.get_query().unwrap();
+        let markers = frame.body.get_bind_markers().unwrap_or_default().to_vec();
+        (query, name, markers)
+    };
+    let values = &frame.body.get_parameters().unwrap().values;
+    if markers.is_empty() && values.is_empty() {
+        return Ok(Some((query, name)));
+    }
+    let bound = resolve_table(&name)
+        .and_then(|(keyspace, table)| get_schema(&keyspace, &table, manager, ctx))
+        .and_then(|schema| bind_values(&query, &markers, values, Some(&schema)));
+    match bound {
+        Ok(query) => Ok(Some((query, name))),
+        Err(e) => {
+            let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+            write_response(writer, ERROR, frame.header.stream, error).map(|_| None)
         }
-        keys.sort_by(|(a, _), (b, _)| {
-            if primary_key.get_partition_key().contains(&a) {
-                return std::cmp::Ordering::Less;
-            } else if primary_key.get_partition_key().contains(&b) {
-                return std::cmp::Ordering::Greater;
-            } else {
-                return std::cmp::Ordering::Equal;
+    }
+}
+
+/// Returns the schema of a table, or of a virtual table of the system keyspaces.
+pub(crate) fn get_schema(
+    keyspace: &str,
+    table: &str,
+    manager: &RwLock<GossipManager>,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<Schema> {
+    if is_system_keyspace(keyspace) {
+        let manager = manager.read().unwrap();
+        let ctx = ctx.read().unwrap();
+        return read_virtual_table(keyspace, table, &manager, &ctx).map(|(schema, _)| schema);
+    }
+    ctx.read().unwrap().get_table_schema(keyspace, table)
+}
+
+fn handle_query(
+    writer: &Mutex<TcpStream>,
+    frame: &Frame,
+    mut query: Query,
+    name: String,
+    partitioner: &Partitioner,
+    manager: &RwLock<GossipManager>,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    let failure_detector = Arc::clone(&manager.read().unwrap().failure_detector);
+
+    // The replicas do not know the keyspace of the connection, so the table is sent qualified with it.
+    let (keyspace, table, name) = if query.is_keyspace_query() {
+        (name.clone(), String::new(), name)
+    } else {
+        match resolve_table(&name) {
+            Ok((keyspace, table)) => {
+                let name = format!("{keyspace}.{table}");
+                (keyspace, table, name)
             }
-        });
-        key = keys.iter().map(|(_, v)| v.clone()).collect();
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        }
     };
 
-    let mut all_rows = Vec::new();
-    let nodes: Vec<_> = partitioner
-        .get_nodes(&key[0])
-        .unwrap()
-        .into_iter()
+    if is_system_keyspace(&keyspace) {
+        return handle_system_query(writer, frame, &query, &keyspace, &table, manager, ctx);
+    }
+
+    let (key, schema) = if query.is_ddl() {
+        (Vec::new(), None)
+    } else {
+        let schema = match ctx.read().unwrap().get_table_schema(&keyspace, &table) {
+            Ok(schema) => schema,
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        };
+        let key = match get_query_key(&query, &schema) {
+            Ok(key) => key,
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        };
+        (key, Some(schema))
+    };
+
+    let parameters = frame.body.get_parameters();
+    if query.is_not_select() && query.timestamp().is_none() {
+        // Every replica writes the query with the same timestamp, so they all keep the same
+        // value when it races with other writes. Writes take the default timestamp of the client
+        // if it sent one, unless they set their own with `USING TIMESTAMP`.
+        let default_timestamp = parameters
+            .and_then(|parameters| parameters.timestamp)
+            .filter(|_| !query.is_ddl());
+        query.set_timestamp(default_timestamp.unwrap_or_else(current_timestamp));
+    }
+    let serial_cl = parameters.and_then(|parameters| parameters.serial_consistency.as_ref());
+    if serial_cl.is_some_and(|serial_cl| !serial_cl.is_serial()) {
+        let error = create_error_response(
+            ErrorCode::Invalid,
+            "The serial consistency level must be SERIAL or LOCAL_SERIAL",
+            None,
+        );
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    let cl = frame.body.get_consistency().unwrap();
+    if cl.is_serial() && query.is_not_select() {
+        let error = create_error_response(
+            ErrorCode::Invalid,
+            "You must use conditional updates for serializable writes",
+            None,
+        );
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    if cl.is_write_only() && !query.is_not_select() {
+        let error = create_error_response(
+            ErrorCode::Invalid,
+            &format!("{cl} ConsistencyLevel is only supported for writes"),
+            None,
+        );
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    // The replicas of a paged `SELECT` only read the rows of its page.
+    let page = if query.is_not_select() {
+        None
+    } else {
+        let page = get_page_request(frame.body.get_parameters().unwrap()).and_then(|page| {
+            if let (Some(page), Some(schema)) = (&page, &schema) {
+                query.check_page(schema, page.start.as_deref(), page.page_size)?;
+            }
+            Ok(page)
+        });
+        match page {
+            Ok(page) => page,
+            Err(e) => {
+                let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+                return write_response(writer, ERROR, frame.header.stream, error);
+            }
+        }
+    };
+    // DDL queries go to every node of the cluster, the rest to the replicas of the keyspace.
+    // Writes also go to the nodes that are joining the ring as replicas of the key, which do not
+    // count for the consistency level.
+    let (nodes, pending, ack_groups) = if query.is_ddl() {
+        let nodes = partitioner.get_all_nodes();
+        let ack_groups = get_ack_groups(cl, nodes.len(), &HashMap::new(), partitioner);
+        (nodes, Vec::new(), ack_groups)
+    } else {
+        let replication = ctx.read().unwrap().get_replication(&keyspace)?;
+        let nodes = partitioner.get_nodes(&key, &replication)?;
+        let pending = if query.is_not_select() {
+            partitioner.get_pending_nodes(&key, &replication)?
+        } else {
+            Vec::new()
+        };
+        let ack_groups = get_ack_groups(
+            cl,
+            replication.replication_factor as usize,
+            &replication.datacenters,
+            partitioner,
+        );
+        (nodes, pending, ack_groups)
+    };
+    // The replicas known to be down are not waited on, and their writes are hinted right away.
+    let alive: Vec<Node> = nodes
+        .iter()
+        .filter(|node| partitioner.is_me(node) || failure_detector.is_alive(&node.ip_address))
         .cloned()
         .collect();
-    let mut acks = 0;
-    // Add last_update column to compare the results and return the most recent one and update the rest
-    // by Read Repair.
-    // For SELECT queries, the last_update column is the last one in the result, so we can just slice it
-    query.add_col("last_update", &chrono::Utc::now().to_rfc3339());
+    for (datacenter, required) in &ack_groups {
+        let replicas = count_in_datacenter(&alive, datacenter);
+        if *required > replicas {
+            let error = create_error_response(
+                ErrorCode::UnavailableError,
+                "Cannot achieve consistency level",
+                Some(HashMap::from([
+                    ("consistency".to_string(), cl.to_string()),
+                    ("required".to_string(), required.to_string()),
+                    ("alive".to_string(), replicas.to_string()),
+                ])),
+            );
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    }
+    let mut responses = Vec::new();
+    let mut acked = Vec::new();
 
-    for node in &nodes {
+    for node in nodes.iter().chain(&pending) {
         if partitioner.is_me(node) {
             println!("Executing query...");
-            let mut ctx_write = ctx.write().unwrap();
-            let res = query.process(&get_keyspace().join(table.clone()), &mut *ctx_write);
-            drop(ctx_write);
-
-            if res.is_err() {
-                let error = create_error_response(
-                    ErrorCode::AlreadyExists,
-                    &res.unwrap_err().to_string(),
-                    Some(HashMap::from([
-                        ("keyspace".to_string(), get_keyspace_name().unwrap()),
-                        ("table".to_string(), table.clone()),
-                    ])),
-                );
-                let response = create_response_frame(ERROR, frame.header.stream, error).unwrap();
-                response.write(&mut stream).unwrap();
-                return;
+            let res = match &page {
+                Some(page) => process_replica_page(&page_query(&query, &name, page), ctx).map(Some),
+                None => process_replica_query(&mut query, &name, ctx),
+            };
+            match res {
+                Ok(rows) => {
+                    if let Some(rows) = rows {
+                        responses.push((node.clone(), rows));
+                    }
+                    acked.push(node.clone());
+                }
+                Err(e) => {
+                    let error = create_error_response(
+                        ErrorCode::AlreadyExists,
+                        &e.to_string(),
+                        Some(HashMap::from([
+                            ("keyspace".to_string(), keyspace.clone()),
+                            ("table".to_string(), table.clone()),
+                        ])),
+                    );
+                    return write_response(writer, ERROR, frame.header.stream, error);
+                }
+            }
+            continue;
+        }
+        if !failure_detector.is_alive(&node.ip_address) {
+            println!("Skipping {}, which is down", node.ip_address);
+            // The nodes that miss a DDL query pull it once they agree on the schema again.
+            if query.is_not_select() && !query.is_ddl() {
+                let node_dir = ctx.read().unwrap().node_dir.clone();
+                if let Err(e) = add_hint(&node_dir, &node.ip_address, &query, &name) {
+                    println!("Failed to add a hint for {}: {e}", node.ip_address);
+                }
             }
-
-            all_rows.push(res.unwrap());
-            acks += 1;
             continue;
         }
         println!("Forwarding query to {}", node.ip_address);
-        let frame_type = FrameType::Query;
         let query_clone = query.clone();
-        let body = Body::Query(inc::query::Query {
-            query: query_clone.clone(),
-            table: table.clone(),
-        });
+        let (frame_type, body) = match &page {
+            Some(page) => (
+                FrameType::PageQuery,
+                Body::PageQuery(page_query(&query, &name, page)),
+            ),
+            None => (
+                FrameType::Query,
+                Body::Query(inc::query::Query {
+                    query: query_clone.clone(),
+                    table: name.clone(),
+                }),
+            ),
+        };
         let Ok(mut stream) = TcpStream::connect((&node.ip_address[..], node.port + 1)) else {
             println!("Failed to connect to {}", node.ip_address);
-            if query_clone.is_not_select() {
-                add_hint(
-                    &ctx.read().unwrap().node_dir,
-                    &node.ip_address,
-                    &frame.body.get_query_str().unwrap(),
-                );
+            if query_clone.is_not_select() && !query_clone.is_ddl() {
+                let node_dir = ctx.read().unwrap().node_dir.clone();
+                if let Err(e) = add_hint(&node_dir, &node.ip_address, &query_clone, &name) {
+                    println!("Failed to add a hint for {}: {e}", node.ip_address);
+                }
             }
             continue;
         };
-        send_message(&mut stream, frame_type, &body).unwrap();
-        let res = read_inc_frame(&mut stream).unwrap();
-        if let (FrameType::Result, Body::Result(result)) = res {
-            all_rows.push(result.rows);
-            acks += 1;
-        } else {
-            println!("Invalid frame type after query: {:?}", res);
+        if send_message(&mut stream, frame_type, &body).is_err() {
+            println!("Failed to forward query to {}", node.ip_address);
+            continue;
+        }
+        match read_inc_frame(&mut stream) {
+            Ok((FrameType::Result, Body::Result(result))) => {
+                if let Some(rows) = result.rows {
+                    responses.push((node.clone(), rows));
+                }
+                acked.push(node.clone());
+            }
+            Ok((FrameType::Error, Body::Error(error))) => {
+                println!("{} failed the query: {}", node.ip_address, error.message);
+            }
+            res => println!("Invalid frame type after query: {:?}", res),
         }
     }
 
-    let cl = frame.body.get_consistency().unwrap();
-    if (acks < cl.to_u16() && cl != &ConsistencyLevel::Quorum && cl != &ConsistencyLevel::All)
-        || (acks < 3 && cl == &ConsistencyLevel::All)
+    acked.retain(|node| !pending.contains(node));
+    if ack_groups
+        .iter()
+        .any(|(datacenter, required)| count_in_datacenter(&acked, datacenter) < *required)
     {
         let error = create_error_response(
             ErrorCode::ServerError,
             "Not enough nodes responded to query",
             None,
         );
-        let response = create_response_frame(ERROR, frame.header.stream, error).unwrap();
-        response.write(&mut stream).unwrap();
         println!("Not enough nodes responded to query");
-        return;
+        return write_response(writer, ERROR, frame.header.stream, error);
     }
 
-    let rows = if let Some(mut rows) = compare_responses(all_rows.clone(), cl) {
-        remove_last_update(&mut rows);
-        Some(rows)
-    } else {
-        None
+    let node_dir = ctx.read().unwrap().node_dir.clone();
+    if query.is_ddl() {
+        if let Err(e) = wait_for_schema_agreement(manager, &node_dir) {
+            println!("{e}");
+            let error = create_error_response(ErrorCode::ServerError, &e.to_string(), None);
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    }
+
+    let Some(schema) = schema.filter(|_| !query.is_not_select()) else {
+        let result = create_result_response(None);
+        write_response(writer, RESULT, frame.header.stream, result)?;
+        println!("Query executed successfully");
+        return Ok(());
     };
-    query.remove_col("last_update");
-    let (opcode, result) = (
-        RESULT,
-        create_result_response(vec_to_rows(
-            rows.clone(),
-            &query.get_cols(),
-            &table,
-            ctx.clone(),
-        )),
-    );
-    let res_frame = create_response_frame(opcode, frame.header.stream, result).unwrap();
-    res_frame.write(&mut stream).unwrap();
+    let selected = match &page {
+        Some(page) => select_replica_page(&query, &mut responses, &schema, page),
+        None => {
+            let merged = reconcile(responses.iter().map(|(_, rows)| rows.clone()).collect());
+            select_page(&query, get_live_rows(&merged, &schema), &schema, None)
+                .map(|page| (merged, page))
+        }
+    };
+    let (merged, (rows, paging_state)) = match selected {
+        Ok(selected) => selected,
+        Err(e) => {
+            let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    };
+    let result = create_result_response(vec_to_rows(
+        rows,
+        paging_state,
+        &query.get_selected_columns(&schema),
+        &schema,
+        &keyspace,
+        &table,
+    ));
+    write_response(writer, RESULT, frame.header.stream, result)?;
     println!("Query executed successfully");
 
-    if rows.is_some() {
-        query.add_col("last_update", &chrono::Utc::now().to_rfc3339());
-        handle_read_repair(
-            &table,
-            query.get_cols(),
-            nodes.as_slice(),
-            all_rows.iter().map(|rows| rows.clone().unwrap()).collect(),
-            query.get_keys(),
-            partitioner,
-            ctx,
+    handle_read_repair(&name, &responses, &merged, partitioner, ctx);
+    Ok(())
+}
+
+/// Returns the request for a page of a `SELECT` sent to its replicas.
+fn page_query(query: &Query, name: &str, page: &PageRequest) -> inc::query::PageQuery {
+    inc::query::PageQuery {
+        query: query.clone(),
+        table: name.to_string(),
+        start: page.start.clone(),
+        page_size: page.page_size,
+    }
+}
+
+/// Returns the values of the partition key columns a query is run on, in the order of the schema, which
+/// the replicas of its partition are found with.
+///
+/// # Errors
+///
+/// * Returns an `Error` if an `INSERT` or an `UPDATE` does not give the whole primary key, or if a
+///   `SELECT` or a `DELETE`, which may read or remove a whole partition or a range of its rows, does not
+///   give the partition key.
+pub(crate) fn get_query_key(query: &Query, schema: &Schema) -> std::io::Result<Vec<String>> {
+    let keys = query.get_keys();
+    let primary_key = schema.get_primary_key();
+    let value = |col: &String| keys.iter().find(|(key, _)| key == col).map(|(_, v)| v);
+
+    let required: Vec<&String> = if query.is_delete() || !query.is_not_select() {
+        primary_key.get_partition_key().iter().collect()
+    } else {
+        primary_key
+            .get_partition_key()
+            .iter()
+            .chain(primary_key.get_clustering_key())
+            .collect()
+    };
+    if required.into_iter().any(|col| value(col).is_none()) {
+        return Err(io_error!("Primary key columns not provided"));
+    }
+    Ok(primary_key
+        .get_partition_key()
+        .iter()
+        .filter_map(|col| value(col).cloned())
+        .collect())
+}
+
+/// Answers a query on a table of the system keyspaces from the state of this node, without asking the
+/// replicas. The tables are read-only, so only `SELECT` queries are allowed.
+fn handle_system_query(
+    writer: &Mutex<TcpStream>,
+    frame: &Frame,
+    query: &Query,
+    keyspace: &str,
+    table: &str,
+    manager: &RwLock<GossipManager>,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    if query.is_not_select() {
+        let error = create_error_response(
+            ErrorCode::Unauthorized,
+            &format!("Keyspace '{keyspace}' is read-only"),
+            None,
         );
+        return write_response(writer, ERROR, frame.header.stream, error);
+    }
+    let virtual_table = {
+        let manager = manager.read().unwrap();
+        let ctx = ctx.read().unwrap();
+        read_virtual_table(keyspace, table, &manager, &ctx)
+    };
+    let page = get_page_request(frame.body.get_parameters().unwrap()).and_then(|page| {
+        virtual_table.and_then(|(schema, rows)| {
+            Ok((select_page(query, rows, &schema, page.as_ref())?, schema))
+        })
+    });
+    let ((rows, paging_state), schema) = match page {
+        Ok(rows) => rows,
+        Err(e) => {
+            let error = create_error_response(ErrorCode::Invalid, &e.to_string(), None);
+            return write_response(writer, ERROR, frame.header.stream, error);
+        }
+    };
+    let result = create_result_response(vec_to_rows(
+        rows,
+        paging_state,
+        &query.get_selected_columns(&schema),
+        &schema,
+        keyspace,
+        table,
+    ));
+    write_response(writer, RESULT, frame.header.stream, result)
+}
+
+/// Returns the groups of replicas whose acks count for the consistency level, as the datacenter of
+/// the group, or `None` for every replica, and the acks it requires.
+///
+/// The local levels only count the replicas of the datacenter of the coordinator, and `EACH_QUORUM`
+/// requires a quorum of each datacenter. Keyspaces without replication factors per datacenter hold
+/// their replicas regardless of the datacenters, so every replica counts.
+pub(crate) fn get_ack_groups(
+    cl: &ConsistencyLevel,
+    replication_factor: usize,
+    datacenters: &HashMap<String, i32>,
+    partitioner: &Partitioner,
+) -> Vec<(Option<String>, usize)> {
+    if datacenters.is_empty() {
+        return vec![(None, cl.required_acks(replication_factor))];
+    }
+    if cl.is_local() {
+        let local = &partitioner.self_node.datacenter;
+        let factor = datacenters.get(local).copied().unwrap_or_default();
+        return vec![(Some(local.clone()), cl.required_acks(factor as usize))];
+    }
+    if cl == &ConsistencyLevel::EachQuorum {
+        return datacenters
+            .iter()
+            .map(|(datacenter, factor)| {
+                (Some(datacenter.clone()), cl.required_acks(*factor as usize))
+            })
+            .collect();
     }
+    vec![(None, cl.required_acks(replication_factor))]
 }
 
+pub(crate) fn count_in_datacenter(nodes: &[Node], datacenter: &Option<String>) -> usize {
+    nodes
+        .iter()
+        .filter(|node| datacenter.as_ref().is_none_or(|dc| &node.datacenter == dc))
+        .count()
+}
+
+/// Returns the specs of the columns, with the types of the schema. `TTL(<col>)` returns the seconds left
+/// until the cell expires, so it is an int.
+fn get_column_specs(cols: &[String], schema: &Schema) -> std::io::Result<Vec<ColumnSpec>> {
+    cols.iter()
+        .map(|col_name| {
+            let column = col_name
+                .strip_prefix("TTL(")
+                .and_then(|col| col.strip_suffix(')'));
+            let schema_type = match column {
+                Some(column) if schema.get_schema_type(column).is_some() => &SchemaType::Int,
+                _ => schema
+                    .get_schema_type(column.unwrap_or(col_name))
+                    .ok_or(io_error!(format!("Column '{col_name}' does not exist")))?,
+            };
+            Ok(ColumnSpec::new(
+                col_name.clone(),
+                DataTypeFlags::from_schema_type(schema_type),
+            ))
+        })
+        .collect()
+}
+
+/// Returns the rows to answer a `SELECT` with, and the paging state of its next page if it has more.
+/// A page without rows that is not the last one is still answered with rows, to carry its paging state.
 fn vec_to_rows(
     rows: Option<Rows>,
+    paging_state: Option<Vec<u8>>,
     cols: &[String],
+    schema: &Schema,
+    keyspace: &str,
     table: &str,
-    ctx: Arc<RwLock<Context>>,
 ) -> Option<NativeRows> {
-    match rows {
+    match rows.or_else(|| paging_state.as_ref().map(|_| Vec::new())) {
         Some(some_rows) => {
-            let read_guard = ctx.read().unwrap();
-            let cols_specs = cols
-                .iter()
-                .map(|col_name| {
-                    ColumnSpec::new(
-                        col_name.clone(),
-                        DataTypeFlags::from_schema_type(
-                            read_guard
-                                .get_table_schema(&get_keyspace_name().unwrap(), table)
-                                .unwrap()
-                                .get_schema_type(&col_name)
-                                .unwrap(),
-                        ),
-                    )
-                })
-                .collect();
-            drop(read_guard);
-            let metadata = RowMetadata::new(
+            let mut metadata = RowMetadata::new(
                 RowsMetadaFlagsMask::GlobalTablesSpec as i32,
                 cols.len() as i32,
-                Some((get_keyspace_name().unwrap(), table.to_string())),
-                Some(cols_specs),
+                Some((keyspace.to_string(), table.to_string())),
+                Some(get_column_specs(cols, schema).unwrap()),
             )
             .unwrap();
+            metadata.set_paging_state(paging_state);
             Some(NativeRows::new(metadata, some_rows.len() as i32, some_rows))
         }
         None => None,
     }
 }
 
-fn compare_responses(responses: Vec<Option<Rows>>, cl: &ConsistencyLevel) -> Option<Rows> {
-    let valid_responses: Vec<Rows> = responses
-        .into_iter()
-        .filter_map(|response| response)
-        .collect();
-    if valid_responses.is_empty() {
-        return None;
-    }
-    match cl {
-        ConsistencyLevel::Any | ConsistencyLevel::One => valid_responses.into_iter().next(),
-        ConsistencyLevel::Two | ConsistencyLevel::Three | ConsistencyLevel::Quorum => {
-            let target = if cl == &ConsistencyLevel::Quorum {
-                (valid_responses.len() / 2) + 1
-            } else {
-                cl.to_u16() as usize
-            };
-            let mut response_count: HashMap<Rows, usize> = HashMap::new();
-            for response in valid_responses.iter() {
-                *response_count.entry(response.clone()).or_insert(0) += 1;
-                if response_count[response] >= target {
-                    return Some(response.clone());
-                }
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use db::{initialize_context, PrimaryKey};
+    use native::client::{create_query_request, read_response, QueryParameters};
+    use query::process_query;
+
+    use super::*;
+    use crate::{
+        connections::gossip::failure_detector::FailureDetector, partitioner::murmur3::Partitioner,
+        test_utils::node,
+    };
+
+    #[test]
+    fn test_composite_partition_keys_follow_the_schema_order() {
+        let schema = Schema::new(
+            HashMap::from([
+                ("a".to_string(), SchemaType::Int),
+                ("b".to_string(), SchemaType::Text),
+                ("c".to_string(), SchemaType::Int),
+            ]),
+            PrimaryKey::new(
+                vec!["a".to_string(), "b".to_string()],
+                vec!["c".to_string()],
+            ),
+        );
+        let (insert, _) = process_query("INSERT INTO ks.t (c, b, a) VALUES (3, 'two', 1)").unwrap();
+        let (select, _) =
+            process_query("SELECT * FROM ks.t WHERE c = 3 AND b = 'two' AND a = 1").unwrap();
+        let (delete, _) = process_query("DELETE FROM ks.t WHERE b = 'two' AND a = 1").unwrap();
+
+        let key = get_query_key(&insert, &schema).unwrap();
+        assert_eq!(key, vec!["1", "two"]);
+        assert_eq!(get_query_key(&select, &schema).unwrap(), key);
+        assert_eq!(get_query_key(&delete, &schema).unwrap(), key);
+
+        let (partition, _) = process_query("SELECT * FROM ks.t WHERE b = 'two' AND a = 1").unwrap();
+        assert_eq!(get_query_key(&partition, &schema).unwrap(), key);
+
+        let (missing, _) = process_query("DELETE FROM ks.t WHERE a = 1").unwrap();
+        assert!(get_query_key(&missing, &schema).is_err());
+        let (missing, _) = process_query("SELECT * FROM ks.t WHERE a = 1").unwrap();
+        assert!(get_query_key(&missing, &schema).is_err());
+        let (missing, _) =
+            process_query("UPDATE ks.t SET c = 4 WHERE b = 'two' AND a = 1").unwrap();
+        assert!(get_query_key(&missing, &schema).is_err());
+    }
+
+    /// A single node cluster, which coordinates the queries of a client connected to it.
+    struct Coordinator {
+        ctx: Arc<RwLock<Context>>,
+        partitioner: Arc<Partitioner>,
+        manager: RwLock<GossipManager>,
+        writer: Mutex<TcpStream>,
+        client: TcpStream,
+    }
+
+    impl Coordinator {
+        /// Starts the node with the queries applied, without coordinating them.
+        fn new(dir: &std::path::Path, queries: &[String]) -> Self {
+            let ctx = Arc::new(RwLock::new(initialize_context(dir).unwrap()));
+            for query in queries {
+                let (mut query, name) = process_query(query).unwrap();
+                process_replica_query(&mut query, &name, &ctx).unwrap();
             }
-            let mut res = valid_responses[0].clone();
-            for response in valid_responses.iter().skip(1) {
-                for (idx, row) in response.iter().enumerate() {
-                    if row.last().unwrap() > &res[idx].last().unwrap() {
-                        res[idx] = row.clone();
-                    }
-                }
+            let self_node = node("a", "datacenter1", "rack1", &[0]);
+            let partitioner =
+                Arc::new(Partitioner::new(self_node.clone(), vec![self_node], Vec::new()).unwrap());
+            let manager = RwLock::new(GossipManager::new(
+                Arc::clone(&partitioner),
+                &[],
+                Arc::new(FailureDetector::new(8.0)),
+            ));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let writer = Mutex::new(listener.accept().unwrap().0);
+            Coordinator {
+                ctx,
+                partitioner,
+                manager,
+                writer,
+                client,
             }
-            Some(res)
         }
-        ConsistencyLevel::All => {
-            let first_response = &valid_responses[0];
-            if valid_responses.iter().all(|r| r == first_response) {
-                Some(first_response.clone())
-            } else {
-                let mut res = valid_responses[0].clone();
-                for response in valid_responses.iter().skip(1) {
-                    for (idx, row) in response.iter().enumerate() {
-                        if row.last().unwrap() > &res[idx].last().unwrap() {
-                            res[idx] = row.clone();
-                        }
-                    }
-                }
-                Some(res)
+
+        /// Coordinates a query sent with the parameters, and returns the response of the client.
+        fn run(&mut self, query_str: &str, parameters: QueryParameters) -> Frame {
+            let frame = create_query_request(1, query_str, parameters).unwrap();
+            let (query, name) = process_query(query_str).unwrap();
+            handle_query(
+                &self.writer,
+                &frame,
+                query,
+                name,
+                &self.partitioner,
+                &self.manager,
+                &self.ctx,
+            )
+            .unwrap();
+            read_response(&mut self.client).unwrap()
+        }
+    }
+
+    fn schema_queries() -> Vec<String> {
+        vec![
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}".to_string(),
+            "CREATE TABLE ks.t (id int, seq int, name text, PRIMARY KEY (id, seq))".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_coordinator_pages_the_rows_of_a_partition() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queries = schema_queries();
+        queries.push("INSERT INTO ks.t (id, seq) VALUES (2, 1)".to_string());
+        queries.extend((1..=5).map(|seq| format!("INSERT INTO ks.t (id, seq) VALUES (1, {seq})")));
+        let mut coordinator = Coordinator::new(dir.path(), &queries);
+
+        let mut pages = Vec::new();
+        let mut paging_state = None;
+        loop {
+            let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+            parameters.page_size = Some(2);
+            parameters.paging_state = paging_state;
+            let response = coordinator.run("SELECT seq FROM ks.t WHERE id = 1", parameters);
+            assert_eq!(response.header.opcode, RESULT);
+            let rows = response.body.get_rows().unwrap_or_default();
+            pages.push(
+                rows.into_iter()
+                    .map(|row| row[0].clone())
+                    .collect::<Vec<_>>(),
+            );
+            paging_state = response.body.get_paging_state().map(<[u8]>::to_vec);
+            if paging_state.is_none() {
+                break;
             }
         }
+        assert_eq!(pages, vec![vec!["1", "2"], vec!["3", "4"], vec!["5"]]);
     }
-}
 
-fn remove_last_update(rows: &mut Rows) {
-    for row in rows.iter_mut() {
-        row.pop();
+    #[test]
+    fn test_writes_take_the_default_timestamp_of_the_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(dir.path(), &schema_queries());
+        let write = |name: &str, timestamp: i64| {
+            let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+            parameters.timestamp = Some(timestamp);
+            (
+                format!("INSERT INTO ks.t (id, seq, name) VALUES (1, 1, '{name}')"),
+                parameters,
+            )
+        };
+        // The write sent later with an older timestamp does not override the first one.
+        for (query, parameters) in [write("new", 2_000), write("old", 1_000)] {
+            assert_eq!(coordinator.run(&query, parameters).header.opcode, RESULT);
+        }
+        let response = coordinator.run(
+            "SELECT name FROM ks.t WHERE id = 1",
+            QueryParameters::new(ConsistencyLevel::One),
+        );
+        assert_eq!(
+            response.body.get_rows(),
+            Some(vec![vec!["new".to_string()]])
+        );
+
+        let mut parameters = QueryParameters::new(ConsistencyLevel::One);
+        parameters.serial_consistency = Some(ConsistencyLevel::Quorum);
+        let (query, _) = write("new", 3_000);
+        assert_eq!(coordinator.run(&query, parameters).header.opcode, ERROR);
+    }
+
+    #[test]
+    fn test_reads_at_any_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(dir.path(), &schema_queries());
+        let response = coordinator.run(
+            "INSERT INTO ks.t (id, seq, name) VALUES (1, 1, 'Jo')",
+            QueryParameters::new(ConsistencyLevel::Any),
+        );
+        assert_eq!(response.header.opcode, RESULT);
+        let response = coordinator.run(
+            "SELECT name FROM ks.t WHERE id = 1",
+            QueryParameters::new(ConsistencyLevel::Any),
+        );
+        assert_eq!(response.header.opcode, ERROR);
+    }
+
+    #[test]
+    fn test_responses_are_written_out_of_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let writer = Mutex::new(listener.accept().unwrap().0);
+        let limiter = RequestLimiter::new(MAX_IN_FLIGHT_REQUESTS);
+
+        thread::scope(|scope| {
+            // The first request takes longer, so the second one is answered first.
+            for (stream_id, delay) in [(1, 200), (2, 0)] {
+                let writer = &writer;
+                spawn_request(scope, &limiter, move || {
+                    thread::sleep(Duration::from_millis(delay));
+                    write_response(writer, READY, stream_id, create_ready_response()).unwrap();
+                });
+            }
+        });
+
+        let first = read_response(&mut client).unwrap();
+        let second = read_response(&mut client).unwrap();
+        assert_eq!(first.header.stream, 2);
+        assert_eq!(second.header.stream, 1);
+        assert_eq!(second.header.opcode, READY);
+    }
+
+    #[test]
+    fn test_requests_wait_for_the_limiter() {
+        let limiter = RequestLimiter::new(1);
+        let events = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for id in 0..3 {
+                let events = &events;
+                spawn_request(scope, &limiter, move || {
+                    events.lock().unwrap().push(("start", id));
+                    thread::sleep(Duration::from_millis(20));
+                    events.lock().unwrap().push(("end", id));
+                });
+            }
+        });
+
+        // With a single request in flight, each request ends before the next one starts.
+        let events = events.into_inner().unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ("start", 0),
+                ("end", 0),
+                ("start", 1),
+                ("end", 1),
+                ("start", 2),
+                ("end", 2)
+            ]
+        );
     }
 }
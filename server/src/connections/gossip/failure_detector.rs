@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// The time between two gossip rounds of a node.
+pub(crate) const GOSSIP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The phi above which a node is considered down, if `cassandra.json` does not set `phi_convict_threshold`.
+pub(crate) const DEFAULT_PHI_CONVICT_THRESHOLD: f64 = 8.0;
+
+/// The most intervals between heartbeats kept for each node.
+const ARRIVAL_WINDOW_SIZE: usize = 1000;
+
+/// Converts the time since the last heartbeat, in means of the intervals, to phi, assuming the
+/// intervals follow an exponential distribution.
+const PHI_FACTOR: f64 = std::f64::consts::LOG10_E;
+
+/// A phi-accrual failure detector.
+///
+/// Instead of marking a node as down when a single message to it fails, it keeps the intervals between
+/// the heartbeats of each node it learned through gossip, and computes from them how suspicious the
+/// time since the last one is (phi). A node is down while its phi is above the `phi_convict_threshold`,
+/// so nodes that are slow under load are not marked as down as long as their heartbeats keep arriving.
+///
+/// The nodes no heartbeat arrived from yet, such as the seeds and the members of the ring loaded on
+/// startup, are treated as if one arrived when the detector was created, so they are up until they
+/// miss the heartbeats of the first gossip rounds.
+pub(crate) struct FailureDetector {
+    phi_convict_threshold: f64,
+    started: Instant,
+    windows: RwLock<HashMap<String, ArrivalWindow>>,
+}
+
+/// The arrival times of the heartbeats of a node.
+struct ArrivalWindow {
+    last_arrival: Instant,
+    intervals: VecDeque<Duration>,
+}
+
+impl ArrivalWindow {
+    fn new(arrival: Instant) -> Self {
+        ArrivalWindow {
+            last_arrival: arrival,
+            intervals: VecDeque::new(),
+        }
+    }
+
+    fn add(&mut self, arrival: Instant) {
+        if self.intervals.len() == ARRIVAL_WINDOW_SIZE {
+            self.intervals.pop_front();
+        }
+        self.intervals
+            .push_back(arrival.saturating_duration_since(self.last_arrival));
+        self.last_arrival = arrival;
+    }
+
+    /// The mean interval between heartbeats. Until a node sent two heartbeats, it is expected to send
+    /// one every gossip round.
+    fn mean(&self) -> f64 {
+        if self.intervals.is_empty() {
+            return GOSSIP_INTERVAL.as_secs_f64();
+        }
+        self.intervals
+            .iter()
+            .map(Duration::as_secs_f64)
+            .sum::<f64>()
+            / self.intervals.len() as f64
+    }
+
+    fn phi(&self, now: Instant) -> f64 {
+        let since_last = now
+            .saturating_duration_since(self.last_arrival)
+            .as_secs_f64();
+        PHI_FACTOR * since_last / self.mean()
+    }
+}
+
+impl FailureDetector {
+    pub(crate) fn new(phi_convict_threshold: f64) -> Self {
+        FailureDetector {
+            phi_convict_threshold,
+            started: Instant::now(),
+            windows: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Records that a new heartbeat of the node arrived.
+    pub(crate) fn report(&self, ip: &str) {
+        self.report_at(ip, Instant::now());
+    }
+
+    /// Returns whether the node is up. The nodes that no heartbeat arrived from yet are up until they
+    /// miss the heartbeats expected since the detector was created.
+    pub(crate) fn is_alive(&self, ip: &str) -> bool {
+        self.is_alive_at(ip, Instant::now())
+    }
+
+    /// Forgets the heartbeats of the node, such as when it leaves the ring.
+    pub(crate) fn remove(&self, ip: &str) {
+        self.windows.write().unwrap().remove(ip);
+    }
+
+    fn report_at(&self, ip: &str, arrival: Instant) {
+        let mut windows = self.windows.write().unwrap();
+        match windows.get_mut(ip) {
+            Some(window) => window.add(arrival),
+            None => {
+                windows.insert(ip.to_string(), ArrivalWindow::new(arrival));
+            }
+        }
+    }
+
+    fn phi_at(&self, ip: &str, now: Instant) -> f64 {
+        match self.windows.read().unwrap().get(ip) {
+            Some(window) => window.phi(now),
+            None => ArrivalWindow::new(self.started).phi(now),
+        }
+    }
+
+    fn is_alive_at(&self, ip: &str, now: Instant) -> bool {
+        self.phi_at(ip, now) < self.phi_convict_threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_nodes_are_up_until_they_miss_heartbeats() {
+        let detector = FailureDetector::new(DEFAULT_PHI_CONVICT_THRESHOLD);
+        assert!(detector.is_alive("node1"));
+
+        // Without intervals yet, a heartbeat is expected every gossip round since the detector started.
+        let rounds = DEFAULT_PHI_CONVICT_THRESHOLD / PHI_FACTOR;
+        assert!(detector.is_alive_at(
+            "node1",
+            detector.started + GOSSIP_INTERVAL.mul_f64(rounds - 1.0)
+        ));
+        assert!(!detector.is_alive_at(
+            "node1",
+            detector.started + GOSSIP_INTERVAL.mul_f64(rounds + 1.0)
+        ));
+    }
+
+    #[test]
+    fn test_phi_grows_with_the_time_since_the_last_heartbeat() {
+        let detector = FailureDetector::new(DEFAULT_PHI_CONVICT_THRESHOLD);
+        let start = Instant::now();
+        for i in 0..10 {
+            detector.report_at("node1", start + Duration::from_secs(i));
+        }
+        let last = start + Duration::from_secs(9);
+
+        // The heartbeats arrived every second, so phi is 0.434 per second without one.
+        let phi = detector.phi_at("node1", last + Duration::from_secs(2));
+        assert!((phi - 2.0 * PHI_FACTOR).abs() < 1e-9);
+        assert!(detector.is_alive_at("node1", last + Duration::from_secs(18)));
+        assert!(!detector.is_alive_at("node1", last + Duration::from_secs(19)));
+
+        // A new heartbeat brings the node back up.
+        detector.report_at("node1", last + Duration::from_secs(30));
+        assert!(detector.is_alive_at("node1", last + Duration::from_secs(31)));
+    }
+
+    #[test]
+    fn test_slow_nodes_take_longer_to_be_convicted() {
+        let detector = FailureDetector::new(DEFAULT_PHI_CONVICT_THRESHOLD);
+        let start = Instant::now();
+        for i in 0..10 {
+            detector.report_at("fast", start + Duration::from_secs(i));
+            detector.report_at("slow", start + Duration::from_secs(i * 5));
+        }
+        let now = start + Duration::from_secs(45 + 30);
+
+        assert!(!detector.is_alive_at("fast", now));
+        assert!(detector.is_alive_at("slow", now));
+    }
+
+    #[test]
+    fn test_threshold_is_configurable() {
+        let strict = FailureDetector::new(1.0);
+        let lenient = FailureDetector::new(DEFAULT_PHI_CONVICT_THRESHOLD);
+        let start = Instant::now();
+        strict.report_at("node1", start);
+        lenient.report_at("node1", start);
+
+        // Without intervals yet, a heartbeat is expected every gossip round.
+        let now = start + 6 * GOSSIP_INTERVAL;
+        assert!(!strict.is_alive_at("node1", now));
+        assert!(lenient.is_alive_at("node1", now));
+
+        // A removed node is judged again from when the detector started.
+        strict.remove("node1");
+        assert_eq!(strict.phi_at("node1", now), strict.phi_at("node2", now));
+    }
+}
@@ -1,98 +1,64 @@
 use std::{
+    io,
     net::TcpStream,
     path::Path,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use inc::{
-    gossip::{ack::Ack, peer::Peer, syn::Syn},
-    Body, FrameType,
-};
+use inc::{gossip::syn::Syn, read_inc_frame, Body, FrameType};
 
 use crate::connections::{
     hinted::{handle_hinted_handoff, has_hints},
     node::send_message,
 };
 
-use super::manager::GossipManager;
+use super::manager::{apply_gossiped_states, GossipManager};
 
-/// Handle a gossip message from a peer
+/// Handle a gossip message from a peer.
+///
+/// The SYN is answered with an ACK holding the states the peer is missing and the digests of the states
+/// this node is missing, which the peer sends back in an ACK2.
 pub(crate) fn handle_gossip(
     syn: Syn,
     mut stream: TcpStream,
     manager: Arc<RwLock<GossipManager>>,
     node_dir: &Path,
 ) {
-    let mut new_peers = Vec::new();
-    let mut send_peers = Vec::new();
-    let heartbeat;
-    {
-        let manager_read = manager.read().unwrap();
-        let my_id;
-        {
-            let self_node = manager_read.self_node.read().unwrap();
-            my_id = self_node.ip.clone();
-            heartbeat = self_node.last_heartbeat;
-        }
-
-        // Update the peer that sent the SYN
-        if let Some(peer_lock) = manager_read.peers.get(&syn.sender) {
-            let mut peer = peer_lock.write().unwrap();
-            peer.last_heartbeat = syn.heartbeat;
-            peer.alive = true;
-        } else {
-            new_peers.push(Peer {
-                ip: syn.ip.clone(),
-                port: syn.port,
-                last_heartbeat: syn.heartbeat,
-                alive: true,
-            });
-        }
-
-        // Look for peers that the sender doesn't know about
-        for peer_lock in manager_read.peers.values() {
-            let peer_data = peer_lock.read().unwrap();
-            if syn.known_peers.iter().all(|p| p.ip != peer_data.ip) {
-                send_peers.push(peer_data.clone());
-            }
-        }
+    let ack = manager.read().unwrap().examine(&syn);
+    let requested = !ack.digests.is_empty();
+    if let Err(e) = send_message(&mut stream, FrameType::Ack, &Body::Ack(ack)) {
+        println!("Failed to send Ack to {}: {e}", syn.sender);
+        return;
+    }
 
-        // Update the peers that the sender knows about
-        for peer in &syn.known_peers {
-            if peer.ip == my_id {
-                continue;
-            }
-            if let Some(peer_lock) = manager_read.peers.get(&peer.ip) {
-                let mut peer_data = peer_lock.write().unwrap();
-                // Maybe until I get the write lock, the peer has been updated
-                if peer_data.last_heartbeat < peer.last_heartbeat {
-                    peer_data.last_heartbeat = peer.last_heartbeat;
-                    peer_data.alive = peer.alive;
-                }
-            } else {
-                new_peers.push(peer.clone());
+    if requested {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(3)))
+            .unwrap();
+        match read_inc_frame(&mut stream) {
+            Ok((FrameType::Ack2, Body::Ack2(ack2))) => {
+                apply_gossiped_states(&manager, ack2.states, node_dir);
             }
-        }
-    }
-    if !new_peers.is_empty() {
-        let mut manager_write = manager.write().unwrap();
-        for peer in new_peers {
-            manager_write.add_peer(peer.ip.clone(), peer.port);
-            if let Some(peer_lock) = manager_write.peers.get_mut(&peer.ip) {
-                let mut peer_data = peer_lock.write().unwrap();
-                peer_data.last_heartbeat = peer.last_heartbeat;
-                peer_data.alive = peer.alive;
+            Ok(_) => println!("Invalid frame type"),
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                println!("Timeout by waiting Ack2 from {}", syn.sender);
             }
+            Err(e) => println!("Error while trying to read Ack2 from {}: {e}", syn.sender),
         }
     }
 
-    let body = Body::Ack(Ack {
-        heartbeat,
-        update_peers: send_peers,
-    });
-    send_message(&mut stream, FrameType::Ack, &body).unwrap();
-
-    if has_hints(node_dir, &syn.sender) {
-        handle_hinted_handoff(node_dir, &syn.sender, &format!("{}:{}", syn.ip, syn.port));
+    let port = manager
+        .read()
+        .unwrap()
+        .peers
+        .get(&syn.sender)
+        .map(|peer| peer.read().unwrap().port);
+    if let Some(port) = port {
+        if has_hints(node_dir, &syn.sender) {
+            handle_hinted_handoff(node_dir, &syn.sender, &format!("{}:{port}", syn.sender));
+        }
     }
 }
@@ -1,57 +1,566 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use inc::gossip::peer::Peer;
+use inc::gossip::{
+    ack::Ack,
+    digest::GossipDigest,
+    peer::{ApplicationState, EndpointState, Peer, Status},
+    syn::Syn,
+};
+use rand::seq::SliceRandom;
+use shared::{io_error, not_found_error};
 
-use crate::partitioner::node::Node;
+use crate::{
+    connections::{
+        hinted::remove_hints,
+        schema::{get_schema_change_count, get_schema_version},
+    },
+    partitioner::{
+        murmur3::Partitioner,
+        node::{Node, DEFAULT_PORT},
+    },
+};
+
+use super::failure_detector::FailureDetector;
 
 pub(crate) struct GossipManager {
     pub(crate) self_node: RwLock<Peer>,
     pub(crate) peers: HashMap<String, RwLock<Peer>>,
+    pub(crate) partitioner: Arc<Partitioner>,
+    /// Tells which peers are up from the arrivals of their heartbeats.
+    pub(crate) failure_detector: Arc<FailureDetector>,
+    /// The number of schema changes kept by the node when the schema version was last computed, so it is
+    /// only hashed again once a change is kept.
+    schema_changes: RwLock<Option<u64>>,
 }
 
 impl GossipManager {
-    pub(crate) fn new(self_node: &Node, nodes: &[Node]) -> Self {
+    /// Creates the gossip state of the node, knowing the nodes of its ring and the seeds.
+    ///
+    /// The generation of the node is the time it starts, so the other nodes take its state over the
+    /// one of its previous run. The nodes of the ring are known with the state of the configuration until
+    /// they announce their own, and the state of the seeds that are not in the ring is learned when they
+    /// answer. Both are up until the failure detector tells otherwise.
+    pub(crate) fn new(
+        partitioner: Arc<Partitioner>,
+        seeds: &[String],
+        failure_detector: Arc<FailureDetector>,
+    ) -> Self {
+        let status = if partitioner.is_joining() {
+            Status::Joining
+        } else {
+            Status::Normal
+        };
+        let self_node = &partitioner.self_node;
         let mut peers = HashMap::new();
-        for node in nodes {
-            if node.ip_address == self_node.ip_address {
+        for node in partitioner.get_normal_nodes() {
+            if partitioner.is_me(&node) {
                 continue;
             }
+            let mut state = EndpointState::default();
+            if let Err(e) = node.set_application_states(&mut state, Status::Normal) {
+                println!("Failed to get the state of {}: {e}", node.ip_address);
+            }
             peers.insert(
                 node.ip_address.clone(),
                 RwLock::new(Peer {
                     ip: node.ip_address.clone(),
                     port: node.port + 1,
-                    last_heartbeat: 0,
-                    alive: false,
+                    alive: true,
+                    state,
+                }),
+            );
+        }
+        for seed in seeds {
+            if seed == &self_node.ip_address || peers.contains_key(seed) {
+                continue;
+            }
+            peers.insert(
+                seed.clone(),
+                RwLock::new(Peer {
+                    ip: seed.clone(),
+                    port: DEFAULT_PORT + 1,
+                    alive: true,
+                    state: EndpointState::default(),
                 }),
             );
         }
 
+        let generation = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs() as i64)
+            .unwrap_or_default();
+        let mut state = EndpointState::new(generation);
+        if let Err(e) = self_node.set_application_states(&mut state, status) {
+            println!("Failed to get the state of this node: {e}");
+        }
+        state.set(
+            ApplicationState::ReleaseVersion,
+            env!("CARGO_PKG_VERSION").to_string(),
+        );
         GossipManager {
             self_node: RwLock::new(Peer {
                 ip: self_node.ip_address.clone(),
                 port: self_node.port + 1,
-                last_heartbeat: 0,
                 alive: true,
+                state,
             }),
             peers,
+            partitioner,
+            failure_detector,
+            schema_changes: RwLock::new(None),
         }
     }
 
-    pub(crate) fn add_peer(&mut self, ip: String, port: u16) {
-        self.peers.insert(
-            ip.clone(),
-            RwLock::new(Peer {
-                ip,
-                port,
-                last_heartbeat: 0,
-                alive: true,
-            }),
-        );
+    /// Adds a peer learned through another node, counting the heartbeat of its state as the first one.
+    pub(crate) fn add_peer(&mut self, mut peer: Peer) {
+        self.failure_detector.report(&peer.ip);
+        peer.alive = self.is_alive(&peer);
+        self.peers.insert(peer.ip.clone(), RwLock::new(peer));
     }
 
     #[allow(dead_code)]
     pub(crate) fn remove_peer(&mut self, id: &str) {
         self.peers.remove(id);
     }
+
+    /// Moves the heartbeat of this node forward, and updates the data it holds and its schema version, for
+    /// the next gossip round.
+    pub(crate) fn beat(&self, node_dir: &Path) {
+        self.update_schema(node_dir);
+        let mut self_node = self.self_node.write().unwrap();
+        self_node.state.beat();
+        self_node
+            .state
+            .set(ApplicationState::Load, get_load(node_dir).to_string());
+    }
+
+    /// Announces the schema version of this node, from the schema changes applied to it.
+    /// The version is only computed again when a schema change was applied since the last time.
+    pub(crate) fn update_schema(&self, node_dir: &Path) {
+        let mut known_changes = self.schema_changes.write().unwrap();
+        let changes = get_schema_change_count(node_dir);
+        if *known_changes == Some(changes) {
+            return;
+        }
+        match get_schema_version(node_dir) {
+            Ok(version) => {
+                self.self_node
+                    .write()
+                    .unwrap()
+                    .state
+                    .set(ApplicationState::Schema, version);
+                *known_changes = Some(changes);
+            }
+            Err(e) => println!("Failed to get the schema version: {e}"),
+        }
+    }
+
+    /// Returns whether every peer that is up announces the schema version of this node.
+    pub(crate) fn has_schema_agreement(&self) -> bool {
+        self.get_disagreeing_peers().is_empty()
+    }
+
+    /// Returns the address of a random peer that is up and announces a different schema version than this
+    /// node, to pull the schema from.
+    pub(crate) fn get_schema_source(&self) -> Option<(String, u16)> {
+        self.get_disagreeing_peers()
+            .choose(&mut rand::thread_rng())
+            .cloned()
+    }
+
+    fn get_disagreeing_peers(&self) -> Vec<(String, u16)> {
+        let self_node = self.self_node.read().unwrap();
+        let version = self_node.state.get(ApplicationState::Schema);
+        self.peers
+            .values()
+            .map(|peer| peer.read().unwrap())
+            .filter(|peer| {
+                let schema = peer.state.get(ApplicationState::Schema);
+                self.is_alive(peer) && schema.is_some() && schema != version
+            })
+            .map(|peer| (peer.ip.clone(), peer.port))
+            .collect()
+    }
+
+    /// Returns the digests of every node this node knows, itself included, to start a gossip round.
+    pub(crate) fn create_syn(&self) -> Syn {
+        let self_node = self.self_node.read().unwrap();
+        let mut digests = vec![GossipDigest::new(&self_node.ip, &self_node.state)];
+        for peer in self.peers.values() {
+            let peer = peer.read().unwrap();
+            digests.push(GossipDigest::new(&peer.ip, &peer.state));
+        }
+        Syn {
+            sender: self_node.ip.clone(),
+            digests,
+        }
+    }
+
+    /// Compares the digests of a `Syn` with the states this node knows. It answers with the nodes it
+    /// needs a newer state of, and with what the sender is missing of the states it knows, including
+    /// the nodes the sender does not know.
+    pub(crate) fn examine(&self, syn: &Syn) -> Ack {
+        let mut requests = Vec::new();
+        let mut states = Vec::new();
+        for digest in &syn.digests {
+            let Some(local) = self.get_peer(&digest.ip) else {
+                requests.push(GossipDigest {
+                    ip: digest.ip.clone(),
+                    generation: 0,
+                    max_version: 0,
+                });
+                continue;
+            };
+            let local_digest = GossipDigest::new(&local.ip, &local.state);
+            let remote = (digest.generation, digest.max_version);
+            let known = (local_digest.generation, local_digest.max_version);
+            if remote > known {
+                requests.push(local_digest);
+            } else if remote < known {
+                states.extend(self.get_delta(digest));
+            }
+        }
+        let is_known = |ip: &str| syn.digests.iter().any(|digest| digest.ip == ip);
+        for peer in self.peers.values().chain([&self.self_node]) {
+            let peer = peer.read().unwrap();
+            if !is_known(&peer.ip) {
+                states.push(peer.clone());
+            }
+        }
+        Ack {
+            digests: requests,
+            states,
+        }
+    }
+
+    /// Returns what is newer of the states this node knows than the digests another node sent.
+    pub(crate) fn get_requested_states(&self, digests: &[GossipDigest]) -> Vec<Peer> {
+        digests
+            .iter()
+            .filter_map(|digest| self.get_delta(digest))
+            .collect()
+    }
+
+    /// Returns the state of a node with only what is newer than the digest, or the whole state if it is
+    /// of a newer generation. Returns `None` if the digest is of a newer generation, or if this node does
+    /// not know the node.
+    fn get_delta(&self, digest: &GossipDigest) -> Option<Peer> {
+        let mut peer = self.get_peer(&digest.ip)?;
+        if peer.state.heartbeat.generation < digest.generation {
+            return None;
+        }
+        if peer.state.heartbeat.generation == digest.generation {
+            peer.state = peer.state.delta(digest.max_version);
+        }
+        Some(peer)
+    }
+
+    fn get_peer(&self, ip: &str) -> Option<Peer> {
+        let self_node = self.self_node.read().unwrap();
+        if self_node.ip == ip {
+            return Some(self_node.clone());
+        }
+        self.peers.get(ip).map(|peer| peer.read().unwrap().clone())
+    }
+
+    /// Takes what is newer of the states gossiped by another node, and returns the peers this node did not
+    /// know, to be added. A peer whose heartbeat went forward is up, as the failure detector tells; a peer
+    /// of a newer generation restarted, so its previous heartbeats are forgotten.
+    pub(crate) fn apply_states(&self, states: Vec<Peer>) -> Vec<Peer> {
+        let my_ip = self.self_node.read().unwrap().ip.clone();
+        let mut new_peers = Vec::new();
+        for remote in states {
+            if remote.ip == my_ip {
+                continue;
+            }
+            let Some(peer_lock) = self.peers.get(&remote.ip) else {
+                new_peers.push(remote);
+                continue;
+            };
+            let mut peer = peer_lock.write().unwrap();
+            let generation = peer.state.heartbeat.generation;
+            if !peer.state.apply(&remote.state) {
+                continue;
+            }
+            if peer.state.heartbeat.generation > generation && generation != 0 {
+                println!("{} restarted", peer.ip);
+                self.failure_detector.remove(&peer.ip);
+            }
+            self.failure_detector.report(&peer.ip);
+            peer.alive = self.is_alive(&peer);
+        }
+        new_peers
+    }
+
+    /// Marks each peer as up or down, as the failure detector tells.
+    pub(crate) fn interpret_liveness(&self) {
+        for peer in self.peers.values() {
+            let mut peer = peer.write().unwrap();
+            let alive = self.is_alive(&peer);
+            if alive != peer.alive {
+                println!("{} is now {}", peer.ip, if alive { "UP" } else { "DOWN" });
+            }
+            peer.alive = alive;
+        }
+    }
+
+    /// Returns whether the peer is up. The nodes that left the ring are never up.
+    fn is_alive(&self, peer: &Peer) -> bool {
+        peer.state.status() != Some(Status::Left) && self.failure_detector.is_alive(&peer.ip)
+    }
+
+    /// Changes the status of this node in the ring, which the gossip spreads to the other nodes.
+    pub(crate) fn set_status(&self, status: Status) -> std::io::Result<()> {
+        let mut self_node = self.self_node.write().unwrap();
+        self_node
+            .state
+            .set(ApplicationState::Status, status.to_string());
+        self.partitioner
+            .update_node(self.partitioner.self_node.clone(), status)
+    }
+
+    /// Changes the status of a peer in the ring, such as when a dead node is removed from it.
+    /// The status takes the next version of the peer, so the other nodes take it over the one they know.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an `Error` if the state of the peer is unknown.
+    pub(crate) fn set_peer_status(&self, ip: &str, status: Status) -> std::io::Result<()> {
+        let (node, _) = {
+            let mut peer = self
+                .peers
+                .get(ip)
+                .ok_or(not_found_error!("Node not found in the ring"))?
+                .write()
+                .unwrap();
+            if peer.state.status().is_none() {
+                return Err(io_error!("The state of the node is unknown"));
+            }
+            peer.state.set(ApplicationState::Status, status.to_string());
+            Node::from_peer(&peer).ok_or(io_error!("The node has no tokens"))?
+        };
+        self.partitioner.update_node(node, status)
+    }
+
+    /// Updates the ring of the partitioner with the tokens and the status the peers announced.
+    /// The hints of the nodes that left the ring are dropped, as they are not replicas anymore.
+    pub(crate) fn update_ring(&self, node_dir: &Path) {
+        for peer in self.peers.values() {
+            let Some((node, status)) = Node::from_peer(&peer.read().unwrap()) else {
+                continue;
+            };
+            if status == Status::Left {
+                remove_hints(node_dir, &node.ip_address);
+                self.failure_detector.remove(&node.ip_address);
+            }
+            if let Err(e) = self.partitioner.update_node(node, status) {
+                println!("Failed to update the ring: {e}");
+            }
+        }
+    }
+}
+
+/// Takes the states gossiped by another node, adding the peers this node did not know, and updates the
+/// ring with them.
+pub(crate) fn apply_gossiped_states(
+    manager: &RwLock<GossipManager>,
+    states: Vec<Peer>,
+    node_dir: &Path,
+) {
+    let new_peers = manager.read().unwrap().apply_states(states);
+    if !new_peers.is_empty() {
+        let mut manager_write = manager.write().unwrap();
+        for peer in new_peers {
+            manager_write.add_peer(peer);
+        }
+    }
+    manager.read().unwrap().update_ring(node_dir);
+}
+
+/// Returns the bytes of the files of a directory and its subdirectories.
+fn get_load(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => get_load(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connections::schema::record_schema_change, test_utils::node};
+
+    fn manager(self_ip: &str) -> GossipManager {
+        let nodes = vec![
+            node("a", "datacenter1", "rack1", &[100]),
+            node("b", "datacenter1", "rack1", &[200]),
+        ];
+        let self_node = nodes
+            .iter()
+            .find(|node| node.ip_address == self_ip)
+            .unwrap()
+            .clone();
+        let partitioner = Partitioner::new(self_node, nodes, Vec::new()).unwrap();
+        GossipManager::new(
+            Arc::new(partitioner),
+            &[],
+            Arc::new(FailureDetector::new(8.0)),
+        )
+    }
+
+    fn state_of(manager: &GossipManager, ip: &str) -> EndpointState {
+        manager.get_peer(ip).unwrap().state
+    }
+
+    fn peer_with(ip: &str, generation: i64, version: u64) -> Peer {
+        let mut state = EndpointState::new(generation);
+        state.set(ApplicationState::Status, Status::Normal.to_string());
+        state.heartbeat.version = version;
+        Peer {
+            ip: ip.to_string(),
+            port: DEFAULT_PORT + 1,
+            alive: false,
+            state,
+        }
+    }
+
+    #[test]
+    fn test_restarted_node_replaces_its_state() {
+        let manager = manager("a");
+        assert!(manager
+            .apply_states(vec![peer_with("b", 100, 5)])
+            .is_empty());
+        assert_eq!(state_of(&manager, "b").heartbeat.version, 5);
+        assert!(manager.peers["b"].read().unwrap().alive);
+
+        // An older heartbeat of the same run is ignored.
+        manager.apply_states(vec![peer_with("b", 100, 3)]);
+        assert_eq!(state_of(&manager, "b").heartbeat.version, 5);
+
+        // The node restarted, so its heartbeat starts over with a newer generation.
+        manager.apply_states(vec![peer_with("b", 200, 1)]);
+        let state = state_of(&manager, "b");
+        assert_eq!(
+            (state.heartbeat.generation, state.heartbeat.version),
+            (200, 1)
+        );
+
+        // The state of its previous run is not taken back.
+        manager.apply_states(vec![peer_with("b", 100, 9)]);
+        assert_eq!(state_of(&manager, "b").heartbeat.generation, 200);
+    }
+
+    #[test]
+    fn test_unknown_peers_are_returned_to_be_added() {
+        let manager = manager("a");
+        let new_peers = manager.apply_states(vec![peer_with("c", 100, 1), peer_with("a", 100, 1)]);
+        assert_eq!(new_peers.len(), 1);
+        assert_eq!(new_peers[0].ip, "c");
+    }
+
+    #[test]
+    fn test_gossip_round_sends_only_deltas() {
+        let node_dir = Path::new("/nonexistent");
+        let a = manager("a");
+        let b = manager("b");
+
+        // The first round exchanges the whole states, as each node only knows the configuration of the other.
+        a.beat(node_dir);
+        let ack = b.examine(&a.create_syn());
+        assert_eq!(ack.digests.len(), 1);
+        assert_eq!(ack.digests[0].ip, "a");
+        assert_eq!(ack.states.len(), 1);
+        assert_eq!(ack.states[0].ip, "b");
+        a.apply_states(ack.states);
+        b.apply_states(a.get_requested_states(&ack.digests));
+        assert_eq!(state_of(&a, "b"), state_of(&b, "b"));
+        assert_eq!(state_of(&a, "a"), state_of(&b, "a"));
+
+        // Once they know each other, only what changed is sent.
+        a.self_node
+            .write()
+            .unwrap()
+            .state
+            .set(ApplicationState::Load, "1024".to_string());
+        let ack = b.examine(&a.create_syn());
+        assert!(ack.states.is_empty());
+        let states = a.get_requested_states(&ack.digests);
+        assert_eq!(states.len(), 1);
+        assert_eq!(
+            states[0]
+                .state
+                .application_states
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&ApplicationState::Load]
+        );
+        b.apply_states(states);
+        assert_eq!(state_of(&b, "a").get(ApplicationState::Load), Some("1024"));
+
+        // Nothing is exchanged when they agree.
+        let ack = b.examine(&a.create_syn());
+        assert!(ack.digests.is_empty() && ack.states.is_empty());
+    }
+
+    #[test]
+    fn test_schema_version_is_only_hashed_again_after_a_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = manager("a");
+        let schema_of = |manager: &GossipManager| {
+            manager
+                .self_node
+                .read()
+                .unwrap()
+                .state
+                .get(ApplicationState::Schema)
+                .map(str::to_string)
+        };
+        let change = |query: &str, timestamp: i64| {
+            let (mut query, table) = query::process_query(query).unwrap();
+            query.set_timestamp(timestamp);
+            inc::query::Query { query, table }
+        };
+        let create = "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}";
+        record_schema_change(dir.path(), &change(create, 1)).unwrap();
+        manager.update_schema(dir.path());
+        let version = schema_of(&manager);
+        assert_eq!(version, Some(get_schema_version(dir.path()).unwrap()));
+
+        // A change that leaves the file with the same size is hashed again.
+        fs::remove_file(dir.path().join("schema_changes.txt")).unwrap();
+        record_schema_change(dir.path(), &change(create, 2)).unwrap();
+        manager.update_schema(dir.path());
+        let version_of_change = schema_of(&manager);
+        assert_ne!(version_of_change, version);
+        assert_eq!(
+            version_of_change,
+            Some(get_schema_version(dir.path()).unwrap())
+        );
+
+        // The file is not read again until a change is kept.
+        fs::remove_file(dir.path().join("schema_changes.txt")).unwrap();
+        manager.update_schema(dir.path());
+        assert_eq!(schema_of(&manager), version_of_change);
+
+        record_schema_change(dir.path(), &change("DROP KEYSPACE ks", 3)).unwrap();
+        manager.update_schema(dir.path());
+        assert_ne!(schema_of(&manager), version_of_change);
+        assert_eq!(
+            schema_of(&manager),
+            Some(get_schema_version(dir.path()).unwrap())
+        );
+    }
 }
@@ -1,3 +1,4 @@
+pub mod failure_detector;
 pub mod handler;
 pub mod manager;
 pub mod starter;
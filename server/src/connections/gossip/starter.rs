@@ -7,8 +7,9 @@ use std::{
     time::Duration,
 };
 
+use db::Context;
 use inc::{
-    gossip::{peer::Peer, syn::Syn},
+    gossip::{ack2::Ack2, peer::Status},
     read_inc_frame, Body, FrameType,
 };
 use rand::seq::SliceRandom;
@@ -16,24 +17,37 @@ use rand::seq::SliceRandom;
 use crate::connections::{
     hinted::{handle_hinted_handoff, has_hints},
     node::send_message,
+    schema::pull_schema,
 };
 
-use super::manager::GossipManager;
+use super::{
+    failure_detector::GOSSIP_INTERVAL,
+    manager::{apply_gossiped_states, GossipManager},
+};
+
+/// The peers each gossip round is sent to.
+pub(crate) const GOSSIP_FANOUT: usize = 3;
 
-/// Start the gossip process. This will send a SYN message to 3 random peers every 5 seconds.
+/// Start the gossip process. This will send a SYN message with the digests of the known states to 3
+/// random peers every 5 seconds, and mark the peers as up or down as the failure detector tells.
+/// After each round, the schema is pulled from a node that is up and disagrees on it.
 ///
 /// **MUST** be run in a separate thread and called only once.
-pub(crate) fn gossip_starter(manager: Arc<RwLock<GossipManager>>, node_dir: &Path) {
+pub(crate) fn gossip_starter(manager: Arc<RwLock<GossipManager>>, ctx: Arc<RwLock<Context>>) {
+    let node_dir = &ctx.read().unwrap().node_dir.clone();
     loop {
         // id, ip:port
         let selected_peers = {
             let manager_read = manager.read().unwrap();
-            manager_read.self_node.write().unwrap().last_heartbeat += 1;
+            manager_read.beat(node_dir);
+            manager_read.interpret_liveness();
             let peers: Vec<(String, String)> = manager_read
                 .peers
                 .values()
+                .map(|peer| peer.read().unwrap())
+                // The nodes that left the ring are not gossiped to anymore.
+                .filter(|peer| peer.state.status() != Some(Status::Left))
                 .map(|peer| {
-                    let peer = peer.read().unwrap();
                     (
                         peer.ip.clone(),
                         peer.ip.clone() + ":" + &peer.port.to_string(),
@@ -41,7 +55,7 @@ pub(crate) fn gossip_starter(manager: Arc<RwLock<GossipManager>>, node_dir: &Pat
                 })
                 .collect();
             peers
-                .choose_multiple(&mut rand::thread_rng(), 3)
+                .choose_multiple(&mut rand::thread_rng(), GOSSIP_FANOUT)
                 .cloned()
                 .collect::<Vec<_>>()
         };
@@ -65,7 +79,9 @@ pub(crate) fn gossip_starter(manager: Arc<RwLock<GossipManager>>, node_dir: &Pat
             }
         }
 
-        std::thread::sleep(std::time::Duration::from_secs(5));
+        pull_schema(&manager, &ctx);
+
+        std::thread::sleep(GOSSIP_INTERVAL);
     }
 }
 
@@ -77,53 +93,19 @@ fn gossip_to_peer(
 ) {
     let Ok(mut addrs) = peer_address.to_socket_addrs() else {
         println!("Error while trying to resolve address of {peer_id}");
-        let manager_read = manager.read().unwrap();
-        let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-        peer.alive = false;
         return;
     };
     let Some(address) = addrs.next() else {
         println!("No address found for {}", peer_id);
-        let manager_read = manager.read().unwrap();
-        let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-        peer.alive = false;
         return;
     };
-    let syn = {
-        let manager_read = manager.read().unwrap();
-        let self_node = manager_read.self_node.read().unwrap();
-        Syn {
-            sender: self_node.ip.clone(),
-            ip: self_node.ip.clone(),
-            port: self_node.port,
-            heartbeat: self_node.last_heartbeat,
-            known_peers: manager_read
-                .peers
-                .values()
-                .map(|peer| {
-                    let peer_data = peer.read().unwrap();
-                    Peer {
-                        ip: peer_data.ip.clone(),
-                        port: peer_data.port,
-                        last_heartbeat: peer_data.last_heartbeat,
-                        alive: peer_data.alive,
-                    }
-                })
-                .collect(),
-        }
-    };
+    let syn = manager.read().unwrap().create_syn();
     let body = Body::Syn(syn);
     let Ok(mut stream) = TcpStream::connect(address) else {
         println!("Error while trying to connect to {}", peer_id);
-        let manager_read = manager.read().unwrap();
-        let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-        peer.alive = false;
         return;
     };
     if send_message(&mut stream, FrameType::Syn, &body).is_err() {
-        let manager_read = manager.read().unwrap();
-        let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-        peer.alive = false;
         return;
     }
 
@@ -134,62 +116,27 @@ fn gossip_to_peer(
         Ok(frame) => frame,
         Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
             println!("Timeout by waiting Ack from {peer_id}");
-            let manager_read = manager.read().unwrap();
-            let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-            peer.alive = false;
             return;
         }
         Err(e) => {
             println!("Error while trying to read Ack from {}: {:?}", peer_id, e);
-            let manager_read = manager.read().unwrap();
-            let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-            peer.alive = false;
             return;
         }
     };
     match frame {
         (FrameType::Ack, Body::Ack(ack)) => {
-            let mut new_peers = Vec::new();
-            {
-                let manager_read = manager.read().unwrap();
-
-                let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-                peer.alive = true;
-                peer.last_heartbeat = ack.heartbeat;
-
-                ack.update_peers.iter().for_each(|peer| {
-                    if peer.ip == manager_read.self_node.read().unwrap().ip {
-                        return;
-                    }
-                    if let Some(peer_lock) = manager_read.peers.get(&peer.ip) {
-                        let mut peer_data = peer_lock.write().unwrap();
-                        if peer_data.last_heartbeat < peer.last_heartbeat {
-                            peer_data.last_heartbeat = peer.last_heartbeat;
-                            peer_data.alive = peer.alive;
-                        }
-                    } else {
-                        new_peers.push(peer.clone());
-                    }
-                });
-            }
-
-            if !new_peers.is_empty() {
-                let mut manager_write = manager.write().unwrap();
-                for peer in new_peers {
-                    manager_write.add_peer(peer.ip.clone(), peer.port);
-                    if let Some(peer_lock) = manager_write.peers.get_mut(&peer.ip) {
-                        let mut peer_data = peer_lock.write().unwrap();
-                        peer_data.last_heartbeat = peer.last_heartbeat;
-                        peer_data.alive = peer.alive;
-                    }
+            apply_gossiped_states(&manager, ack.states, node_dir);
+            if !ack.digests.is_empty() {
+                let ack2 = Ack2 {
+                    states: manager.read().unwrap().get_requested_states(&ack.digests),
+                };
+                if let Err(e) = send_message(&mut stream, FrameType::Ack2, &Body::Ack2(ack2)) {
+                    println!("Failed to send Ack2 to {peer_id}: {e}");
                 }
             }
         }
         _ => {
             println!("Invalid frame type");
-            let manager_read = manager.read().unwrap();
-            let mut peer = manager_read.peers.get(peer_id).unwrap().write().unwrap();
-            peer.alive = false;
         }
     }
     if has_hints(node_dir, peer_id) {
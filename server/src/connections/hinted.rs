@@ -1,67 +1,207 @@
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs::{create_dir_all, OpenOptions},
     io::{BufRead, BufReader, Write},
     net::TcpStream,
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
 };
 
-use inc::{hinted::Hinted, query::Query, Body, FrameType};
-use query::process_query;
+use inc::{hinted::Hinted, query::Query, read_inc_frame, Body, FrameType};
 
 use super::node::send_message;
 
+/// Returns the lock of the hints file of a node. The hints are written by the threads of the requests,
+/// so each one is appended whole, and the ones the handoff sent are removed without the ones appended
+/// meanwhile.
+fn hints_lock(node_hints: &Path) -> Arc<Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> = OnceLock::new();
+    let mut locks = LOCKS.get_or_init(Default::default).lock().unwrap();
+    Arc::clone(locks.entry(node_hints.to_path_buf()).or_default())
+}
+
 pub(crate) fn has_hints(node_dir: &Path, node: &str) -> bool {
     let hints_dir = node_dir.join("hints");
     hints_dir.join(node).with_extension("txt").exists()
 }
 
+/// Drops the hints stored for a node, such as when it leaves the ring.
+pub(crate) fn remove_hints(node_dir: &Path, node: &str) {
+    let node_hints = node_dir.join("hints").join(node).with_extension("txt");
+    let lock = hints_lock(&node_hints);
+    let _guard = lock.lock().unwrap();
+    if node_hints.exists() && std::fs::remove_file(node_hints).is_ok() {
+        println!("Removed the hints for {node}");
+    }
+}
+
 pub(crate) fn handle_hinted_handoff(node_dir: &Path, peer_id: &str, peer_addr: &str) {
     println!("Handling hinted handoff for {peer_id}");
     let hints_dir = node_dir.join("hints");
     let node_hints = hints_dir.join(peer_id).with_extension("txt");
-    if !node_hints.exists() {
-        println!("No hints for {peer_id}");
-        return;
-    }
-    let reader = BufReader::new(std::fs::File::open(&node_hints).unwrap());
+    let lock = hints_lock(&node_hints);
+    let content = {
+        let _guard = lock.lock().unwrap();
+        if !node_hints.exists() {
+            println!("No hints for {peer_id}");
+            return;
+        }
+        match std::fs::read(&node_hints) {
+            Ok(content) => content,
+            Err(e) => {
+                println!("Failed to read the hints for {peer_id}: {e}");
+                return;
+            }
+        }
+    };
     let mut queries = vec![];
-    for hint in reader.lines() {
-        let hint = hint.unwrap();
-        if hint.is_empty() {
-            continue;
+    for hint in BufReader::new(&content[..]).lines() {
+        // A hint that cannot be read is skipped, so it does not hold back the others.
+        let hint = match hint {
+            Ok(hint) if hint.is_empty() => continue,
+            Ok(hint) => hint,
+            Err(e) => {
+                println!("Skipping an invalid hint for {peer_id}: {e}");
+                continue;
+            }
+        };
+        match serde_json::from_str(&hint) {
+            Ok(query) => queries.push(query),
+            Err(e) => println!("Skipping an invalid hint for {peer_id}: {e}"),
         }
-        let query = process_query(&hint).unwrap();
-        queries.push(Query {
-            table: query.1,
-            query: query.0,
-        });
     }
-    let mut stream = TcpStream::connect(peer_addr).unwrap();
-    match send_message(
+    let Ok(mut stream) = TcpStream::connect(peer_addr) else {
+        println!("Failed to connect to {peer_id} for hinted handoff");
+        return;
+    };
+    if let Err(e) = send_message(
         &mut stream,
         FrameType::Hinted,
         &Body::Hinted(Hinted { queries }),
     ) {
-        Ok(_) => {
+        println!("Failed to send hinted handoff to {}: {}", peer_id, e);
+        return;
+    }
+    // The hints are kept until the node applied them, so they are sent again if it could not.
+    match read_inc_frame(&mut stream) {
+        Ok((FrameType::Result, Body::Result(_))) => {
             println!("Succesfully sent Hinted Handoff to {} ", peer_id);
         }
-        Err(e) => {
-            println!("Failed to send hinted handoff to {}: {}", peer_id, e);
+        Ok((FrameType::Error, Body::Error(error))) => {
+            println!("{peer_id} failed to apply the hints: {}", error.message);
+            return;
+        }
+        res => {
+            println!("Invalid frame type after hinted handoff: {res:?}");
             return;
         }
     }
-    std::fs::remove_file(node_hints).unwrap();
+    if let Err(e) = remove_sent_hints(&node_hints, content.len(), &lock) {
+        println!("Failed to remove the hints sent to {peer_id}: {e}");
+    }
 }
 
-pub(crate) fn add_hint(node_dir: &Path, node: &str, query_str: &str) {
-    println!("Adding hint for {node}: {query_str}");
-    let node_hints = node_dir.join("hints").join(node).with_extension("txt");
-    if !node_hints.exists() {
-        File::create(&node_hints).unwrap();
+/// Removes the hints of the file that were sent, which are its first `sent` bytes, keeping the ones
+/// appended after they were read.
+fn remove_sent_hints(node_hints: &Path, sent: usize, lock: &Mutex<()>) -> std::io::Result<()> {
+    let _guard = lock.lock().unwrap();
+    let content = std::fs::read(node_hints)?;
+    if content.len() <= sent {
+        return std::fs::remove_file(node_hints);
     }
-    let mut file = std::fs::OpenOptions::new()
+    std::fs::write(node_hints, &content[sent..])
+}
+
+/// Stores a query that could not be sent to a node, to send it when the node is back.
+/// The query keeps its timestamp, so it does not override the writes made while the node was down.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the hint cannot be written.
+pub(crate) fn add_hint(
+    node_dir: &Path,
+    node: &str,
+    query: &query::Query,
+    table: &str,
+) -> std::io::Result<()> {
+    let hint = serde_json::to_string(&Query {
+        query: query.clone(),
+        table: table.to_string(),
+    })?;
+    let node_hints = node_dir.join("hints").join(node).with_extension("txt");
+    let lock = hints_lock(&node_hints);
+    let _guard = lock.lock().unwrap();
+    create_dir_all(node_dir.join("hints"))?;
+    let mut file = OpenOptions::new()
+        .create(true)
         .append(true)
-        .open(&node_hints)
+        .open(&node_hints)?;
+    file.write_all(format!("{hint}\n").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use query::process_query;
+
+    use super::*;
+
+    fn read_hints(node_hints: &Path) -> Vec<Query> {
+        std::fs::read_to_string(node_hints)
+            .unwrap()
+            .lines()
+            .map(|hint| serde_json::from_str(hint).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_concurrent_hints_are_appended_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_dir = dir.path();
+        thread::scope(|scope| {
+            for writer in 0..4 {
+                scope.spawn(move || {
+                    for i in 0..25 {
+                        let insert = format!(
+                            "INSERT INTO ks.users (id, name) VALUES ({}, '{}')",
+                            writer * 25 + i,
+                            "x".repeat(4096)
+                        );
+                        let (query, table) = process_query(&insert).unwrap();
+                        add_hint(node_dir, "b", &query, &table).unwrap();
+                    }
+                });
+            }
+        });
+
+        let node_hints = node_dir.join("hints").join("b").with_extension("txt");
+        assert_eq!(read_hints(&node_hints).len(), 100);
+    }
+
+    #[test]
+    fn test_sent_hints_are_removed_without_the_later_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_dir = dir.path();
+        let node_hints = node_dir.join("hints").join("b").with_extension("txt");
+        let (query, table) = process_query("INSERT INTO ks.users (id) VALUES (1)").unwrap();
+        add_hint(node_dir, "b", &query, &table).unwrap();
+        let sent = std::fs::read(&node_hints).unwrap().len();
+        let (later, table) = process_query("INSERT INTO ks.users (id) VALUES (2)").unwrap();
+        add_hint(node_dir, "b", &later, &table).unwrap();
+
+        let lock = hints_lock(&node_hints);
+        remove_sent_hints(&node_hints, sent, &lock).unwrap();
+        let hints = read_hints(&node_hints);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].query.get_keys(), later.get_keys());
+
+        remove_sent_hints(
+            &node_hints,
+            std::fs::read(&node_hints).unwrap().len(),
+            &lock,
+        )
         .unwrap();
-    writeln!(file, "{}", query_str).unwrap();
+        assert!(!node_hints.exists());
+    }
 }
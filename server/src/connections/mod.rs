@@ -1,5 +1,13 @@
+pub mod batch;
+pub mod bootstrap;
 pub mod client;
 pub mod gossip;
 pub mod hinted;
 pub mod node;
+pub mod operation;
+pub mod paging;
+pub mod prepared;
 pub mod read_repair;
+pub mod repair;
+pub mod schema;
+pub mod system;
@@ -1,15 +1,25 @@
 use std::{
     io::Write,
     net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
     thread,
 };
 
-use db::Context;
-use inc::{read_inc_frame, result::Result, write_inc_frame, Body, FrameType};
-use shared::{get_keyspace, set_keyspace};
+use db::{Context, StoredRow};
+use inc::{error::Error, read_inc_frame, result::Result, write_inc_frame, Body, FrameType};
+use query::Query;
+use shared::resolve_table;
 
-use crate::connections::gossip::handler::handle_gossip;
+use crate::connections::{
+    batch::{handle_batch_frame, handle_batchlog},
+    bootstrap::handle_stream_request,
+    gossip::handler::handle_gossip,
+    operation::handle_operation,
+    paging::process_replica_page,
+    repair::{handle_merkle_tree_request, handle_range_request},
+    schema::{handle_schema_request, record_schema_change},
+};
 
 use super::gossip::{manager::GossipManager, starter::gossip_starter};
 
@@ -18,19 +28,16 @@ pub(crate) fn handle_internode_communication(
     ctx: Arc<RwLock<Context>>,
     manager: Arc<RwLock<GossipManager>>,
 ) {
-    let ks = get_keyspace();
     let manager_clone = Arc::clone(&manager);
-    let node_dir = ctx.read().unwrap().node_dir.clone();
+    let ctx_clone = Arc::clone(&ctx);
     thread::spawn(move || {
-        gossip_starter(manager_clone, &node_dir);
+        gossip_starter(manager_clone, ctx_clone);
     });
 
     while let Ok(stream) = socket.accept() {
         let ctx_clone = Arc::clone(&ctx);
-        let ks_clone = ks.clone();
         let manager_clone = Arc::clone(&manager);
         thread::spawn(move || {
-            set_keyspace(ks_clone);
             handle_connection(stream.0, ctx_clone, manager_clone);
         });
     }
@@ -41,20 +48,41 @@ fn handle_connection(
     ctx: Arc<RwLock<Context>>,
     manager: Arc<RwLock<GossipManager>>,
 ) {
-    let frame = read_inc_frame(&mut stream).unwrap();
+    let frame = match read_inc_frame(&mut stream) {
+        Ok(frame) => frame,
+        Err(e) => {
+            println!("Failed to read internode request: {e}");
+            send_result(&mut stream, Err(e));
+            return;
+        }
+    };
     match frame {
         (FrameType::Query, Body::Query(mut query)) => {
             println!("Received query from internode: '{:?}'", query.query);
-            let res = query
-                .query
-                .process(&get_keyspace().join(query.table), &mut ctx.write().unwrap())
-                .unwrap();
-            send_message(
-                &mut stream,
-                FrameType::Result,
-                &Body::Result(Result { rows: res }),
-            )
-            .unwrap_or_else(|_| {});
+            let res = process_replica_query(&mut query.query, &query.table, &ctx);
+            if res.is_ok() && query.query.is_ddl() {
+                // The new schema version is announced right away, so the coordinator sees the nodes
+                // agree without waiting for the next heartbeat of this one.
+                let node_dir = ctx.read().unwrap().node_dir.clone();
+                manager.read().unwrap().update_schema(&node_dir);
+            }
+            send_result(&mut stream, res);
+        }
+        (FrameType::PageQuery, Body::PageQuery(query)) => {
+            println!("Received page query from internode: '{:?}'", query.query);
+            let res = process_replica_page(&query, &ctx);
+            send_result(&mut stream, res.map(Some));
+        }
+        (FrameType::Batch, Body::Batch(batch)) => {
+            println!(
+                "Received batch of {} queries from internode",
+                batch.queries.len()
+            );
+            handle_batch_frame(batch, stream, &ctx);
+        }
+        (FrameType::BatchLog, Body::BatchLog(batch_log)) => {
+            let node_dir = ctx.read().unwrap().node_dir.clone();
+            handle_batchlog(batch_log, stream, &node_dir);
         }
         (FrameType::Syn, Body::Syn(syn)) => {
             println!("Handling gossip syn message");
@@ -62,14 +90,39 @@ fn handle_connection(
         }
         (FrameType::Hinted, Body::Hinted(mut hinted)) => {
             println!("Starting hinted handoff process");
-            for query in hinted.queries.iter_mut() {
+            let node_dir = ctx.read().unwrap().node_dir.clone();
+            let res = hinted.queries.iter_mut().try_for_each(|query| {
+                let path = get_query_path(&node_dir, &query.query, &query.table)?;
                 query
                     .query
-                    .process(
-                        &get_keyspace().join(query.table.clone()),
-                        &mut ctx.write().unwrap(),
-                    )
-                    .unwrap();
+                    .process_rows(&path, &ctx.read().unwrap())
+                    .map(|_| ())
+            });
+            send_result(&mut stream, res.map(|_| None));
+        }
+        (FrameType::StreamRequest, Body::StreamRequest(request)) => {
+            if let Err(e) = handle_stream_request(request, stream, &ctx) {
+                println!("Failed to stream data: {e}");
+            }
+        }
+        (FrameType::Operation, Body::Operation(operation)) => {
+            println!("Handling operation {:?}", operation);
+            handle_operation(operation, stream, &manager, &ctx);
+        }
+        (FrameType::MerkleTreeRequest, Body::MerkleTreeRequest(request)) => {
+            println!("Building Merkle trees of {}", request.table);
+            if let Err(e) = handle_merkle_tree_request(request, stream, &ctx) {
+                println!("Failed to send Merkle trees: {e}");
+            }
+        }
+        (FrameType::RangeRequest, Body::RangeRequest(request)) => {
+            if let Err(e) = handle_range_request(request, stream, &ctx) {
+                println!("Failed to send rows for repair: {e}");
+            }
+        }
+        (FrameType::SchemaRequest, Body::SchemaRequest(_)) => {
+            if let Err(e) = handle_schema_request(stream, &ctx) {
+                println!("Failed to send the schema changes: {e}");
             }
         }
         _ => {
@@ -78,6 +131,86 @@ fn handle_connection(
     }
 }
 
+/// Answers the node that sent a request with its result, or with the error that kept this node from
+/// carrying it out, so the sender does not wait for an answer that never comes.
+pub(crate) fn send_result(stream: &mut TcpStream, res: std::io::Result<Option<Vec<StoredRow>>>) {
+    let (frame_type, body) = match res {
+        Ok(rows) => (FrameType::Result, Body::Result(Result { rows })),
+        Err(e) => {
+            println!("Failed to process internode request: {e}");
+            let error = Error {
+                message: e.to_string(),
+            };
+            (FrameType::Error, Body::Error(error))
+        }
+    };
+    send_message(stream, frame_type, &body).unwrap_or(());
+}
+
+/// Processes a query as one of the replicas of its partition.
+///
+/// # Arguments
+///
+/// * `query` - The query to process.
+/// * `name` - The name of the keyspace for the keyspace queries, or else of the table qualified with its keyspace
+///   as `<keyspace>.<table>`.
+/// * `ctx` - The context of the node.
+///
+/// # Returns
+///
+/// * The stored rows of the selected partition for a `SELECT`, with their write times and tombstones,
+///   so the coordinator can reconcile the responses of the replicas.
+/// * `None` for any other query.
+pub(crate) fn process_replica_query(
+    query: &mut Query,
+    name: &str,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Option<Vec<StoredRow>>> {
+    let node_dir = ctx.read().unwrap().node_dir.clone();
+    let table = get_query_path(&node_dir, query, name)?;
+    if query.is_ddl() {
+        // The changes of the schema are kept, so the nodes that missed them pull them later.
+        let mut ctx = ctx.write().unwrap();
+        query.process(&table, &mut ctx)?;
+        let change = inc::query::Query {
+            query: query.clone(),
+            table: name.to_string(),
+        };
+        record_schema_change(&node_dir, &change).map(|_| None)
+    } else if query.is_keyspace_query() {
+        query
+            .process(&table, &mut ctx.write().unwrap())
+            .map(|_| None)
+    } else if query.is_not_select() {
+        // Each table guards its own data, so the writes to the node run concurrently.
+        query
+            .process_rows(&table, &ctx.read().unwrap())
+            .map(|_| None)
+    } else {
+        query
+            .read_stored_rows(&table, &ctx.read().unwrap())
+            .map(Some)
+    }
+}
+
+/// Returns the path a query is processed against: the keyspace dir for the keyspace queries, or else the
+/// table dir.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the table name is not qualified with its keyspace and the connection has no keyspace set.
+pub(crate) fn get_query_path(
+    node_dir: &Path,
+    query: &Query,
+    name: &str,
+) -> std::io::Result<PathBuf> {
+    if query.is_keyspace_query() {
+        return Ok(node_dir.join(name));
+    }
+    let (keyspace, table) = resolve_table(name)?;
+    Ok(node_dir.join(keyspace).join(table))
+}
+
 pub(crate) fn send_message<W: Write>(
     writer: &mut W,
     frame_type: FrameType,
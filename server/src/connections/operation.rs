@@ -0,0 +1,369 @@
+use std::{
+    collections::HashMap,
+    net::TcpStream,
+    sync::{Arc, RwLock},
+    thread,
+};
+
+use db::{Context, Replication, StoredRow};
+use inc::{
+    gossip::peer::{Peer, Status},
+    operation::{Operation, OperationResult},
+    read_inc_frame, Body, FrameType,
+};
+use query::Query;
+use shared::{io_error, not_found_error};
+
+use crate::partitioner::{
+    murmur3::{Partitioner, Ring},
+    node::Node,
+};
+
+use super::{
+    bootstrap::RING_DELAY, gossip::manager::GossipManager, node::send_message, repair::repair,
+};
+
+/// Carries out an operation sent to this node, and answers with its outcome once it is finished.
+pub(crate) fn handle_operation(
+    operation: Operation,
+    mut stream: TcpStream,
+    manager: &Arc<RwLock<GossipManager>>,
+    ctx: &Arc<RwLock<Context>>,
+) {
+    let result = match operation {
+        Operation::Decommission => decommission(manager, ctx),
+        Operation::RemoveNode(ip) => remove_node(&ip, manager, ctx),
+        Operation::Restream(node) => {
+            let partitioner = Arc::clone(&manager.read().unwrap().partitioner);
+            restream(&node, &partitioner, ctx)
+        }
+        Operation::Repair(options) => {
+            let partitioner = Arc::clone(&manager.read().unwrap().partitioner);
+            repair(options, &partitioner, ctx)
+        }
+    };
+    if let Err(e) = &result {
+        println!("Failed to carry out the operation: {e}");
+    }
+    let body = Body::OperationResult(OperationResult {
+        error: result.err().map(|e| e.to_string()),
+    });
+    send_message(&mut stream, FrameType::OperationResult, &body).unwrap_or(());
+}
+
+/// Sends an operation to a node and waits until it is finished.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the node cannot be reached or if the operation fails.
+pub(crate) fn send_operation(ip: &str, port: u16, operation: Operation) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect((ip, port))?;
+    send_message(
+        &mut stream,
+        FrameType::Operation,
+        &Body::Operation(operation),
+    )?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::OperationResult, Body::OperationResult(result)) => match result.error {
+            Some(error) => Err(io_error!(error)),
+            None => Ok(()),
+        },
+        _ => Err(io_error!("Invalid frame type after operation")),
+    }
+}
+
+/// Removes this node from the ring.
+///
+/// The node announces itself as leaving through gossip, so the coordinators also send its writes to the
+/// nodes that are going to own its tokens. Then it streams its rows to them, and announces it left.
+fn decommission(manager: &RwLock<GossipManager>, ctx: &RwLock<Context>) -> std::io::Result<()> {
+    let partitioner = Arc::clone(&manager.read().unwrap().partitioner);
+    let me = partitioner.self_node.clone();
+    let nodes = partitioner.get_normal_nodes();
+    if !nodes.iter().any(|node| partitioner.is_me(node)) {
+        return Err(io_error!("The node does not own tokens of the ring"));
+    }
+    if nodes.len() == 1 {
+        return Err(io_error!("Cannot decommission the last node of the ring"));
+    }
+    println!("Leaving the ring...");
+    manager.read().unwrap().set_status(Status::Leaving)?;
+    thread::sleep(RING_DELAY);
+    let current = partitioner.get_ring_with(&me)?;
+    let future = partitioner.get_ring_without(&me.ip_address)?;
+    stream_to_new_replicas(&current, &future, &partitioner, ctx)?;
+    manager.read().unwrap().set_status(Status::Left)?;
+    // Gives the gossip time to tell the ring that the node left.
+    thread::sleep(RING_DELAY);
+    println!("Left the ring");
+    Ok(())
+}
+
+/// Removes a dead node from the ring, and asks the nodes that hold its data to stream it to the nodes that
+/// replace it as replicas.
+///
+/// # Errors
+///
+/// * Returns an `Error` naming the nodes that failed to restream the data, once the node is removed. The
+///   operation can be carried out again to retry them.
+fn remove_node(
+    ip: &str,
+    manager: &RwLock<GossipManager>,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let (partitioner, peer, alive) = {
+        let manager = manager.read().unwrap();
+        let peer = manager
+            .peers
+            .get(ip)
+            .map(|peer| peer.read().unwrap().clone());
+        (
+            Arc::clone(&manager.partitioner),
+            peer,
+            manager.failure_detector.is_alive(ip),
+        )
+    };
+    if ip == partitioner.self_node.ip_address {
+        return Err(io_error!(
+            "Cannot remove this node, decommission it instead"
+        ));
+    }
+    let peer = peer
+        .filter(|peer| peer.state.status().is_some())
+        .ok_or(not_found_error!("Node not found in the ring"))?;
+    if alive {
+        return Err(io_error!("The node is alive, decommission it instead"));
+    }
+    println!("Removing {ip} from the ring...");
+    manager.read().unwrap().set_peer_status(ip, Status::Left)?;
+    let mut failed = Vec::new();
+    for node in partitioner.get_normal_nodes() {
+        let result = if partitioner.is_me(&node) {
+            restream(&peer, &partitioner, ctx)
+        } else {
+            send_operation(
+                &node.ip_address,
+                node.port + 1,
+                Operation::Restream(peer.clone()),
+            )
+        };
+        if let Err(e) = result {
+            println!(
+                "Failed to restream the data of {ip} from {}: {e}",
+                node.ip_address
+            );
+            failed.push(node.ip_address);
+        }
+    }
+    if !failed.is_empty() {
+        return Err(io_error!(format!(
+            "Removed {ip} from the ring, but its data could not be restreamed from {}. Remove it again to retry",
+            failed.join(", ")
+        )));
+    }
+    println!("Removed {ip} from the ring");
+    Ok(())
+}
+
+/// Streams the rows of a removed node that this node holds to the nodes that replace it as replicas.
+fn restream(
+    removed: &Peer,
+    partitioner: &Partitioner,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let (node, _) = Node::from_peer(removed).ok_or(io_error!("The removed node has no tokens"))?;
+    let previous = partitioner.get_ring_with(&node)?;
+    let current = partitioner.get_ring_without(&node.ip_address)?;
+    stream_to_new_replicas(&previous, &current, partitioner, ctx)
+}
+
+/// Sends the rows this node holds to the nodes that are replicas of them in the new ring, but were not
+/// in the old one.
+fn stream_to_new_replicas(
+    old: &Ring,
+    new: &Ring,
+    partitioner: &Partitioner,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    // The rows of each table to send to each node.
+    let mut streams: HashMap<String, (Node, HashMap<String, Vec<StoredRow>>)> = HashMap::new();
+    {
+        let ctx = ctx.read().unwrap();
+        for keyspace in ctx.get_keyspaces() {
+            let replication = ctx.get_replication(&keyspace)?;
+            for table in ctx.get_tables(&keyspace)? {
+                let name = format!("{keyspace}.{table}");
+                ctx.scan_stored_rows(
+                    &ctx.node_dir.join(&keyspace).join(&table),
+                    None,
+                    &mut |row| {
+                        for replica in
+                            get_new_replicas(row.token(), old, new, &replication, partitioner)?
+                        {
+                            streams
+                                .entry(replica.ip_address.clone())
+                                .or_insert_with(|| (replica.clone(), HashMap::new()))
+                                .1
+                                .entry(name.clone())
+                                .or_default()
+                                .push(row.clone());
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+        }
+    }
+    for (node, tables) in streams.into_values() {
+        for (table, rows) in tables {
+            println!(
+                "Streaming {} rows of {table} to {}",
+                rows.len(),
+                node.ip_address
+            );
+            let mut stream = TcpStream::connect((&node.ip_address[..], node.port + 1))?;
+            let body = Body::Query(inc::query::Query {
+                query: Query::repair(rows),
+                table,
+            });
+            send_message(&mut stream, FrameType::Query, &body)?;
+            match read_inc_frame(&mut stream)? {
+                (FrameType::Result, Body::Result(_)) => {}
+                (FrameType::Error, Body::Error(error)) => return Err(io_error!(error.message)),
+                _ => return Err(io_error!("Invalid frame type after streaming rows")),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the replicas of the token in the new ring that were not replicas of it in the old one, other
+/// than this node.
+fn get_new_replicas<'a>(
+    token: i64,
+    old: &Ring,
+    new: &'a Ring,
+    replication: &Replication,
+    partitioner: &Partitioner,
+) -> std::io::Result<Vec<&'a Node>> {
+    let old_replicas = old.get_replicas(token, replication)?;
+    Ok(new
+        .get_replicas(token, replication)?
+        .into_iter()
+        .filter(|replica| {
+            !partitioner.is_me(replica)
+                && !old_replicas
+                    .iter()
+                    .any(|old| old.ip_address == replica.ip_address)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use db::initialize_context;
+
+    use super::*;
+    use crate::{
+        connections::gossip::failure_detector::FailureDetector,
+        test_utils::{node, simple},
+    };
+
+    fn partitioner(self_ip: &str) -> Partitioner {
+        let nodes = vec![
+            node("a", "datacenter1", "rack1", &[100]),
+            node("b", "datacenter1", "rack1", &[200]),
+            node("c", "datacenter1", "rack1", &[300]),
+        ];
+        let self_node = nodes
+            .iter()
+            .find(|node| node.ip_address == self_ip)
+            .unwrap()
+            .clone();
+        Partitioner::new(self_node, nodes, Vec::new()).unwrap()
+    }
+
+    fn new_replicas(
+        token: i64,
+        old: &Ring,
+        new: &Ring,
+        replication: &Replication,
+        partitioner: &Partitioner,
+    ) -> Vec<String> {
+        get_new_replicas(token, old, new, replication, partitioner)
+            .unwrap()
+            .iter()
+            .map(|node| node.ip_address.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_decommission_streams_to_the_next_replicas() {
+        let partitioner = partitioner("b");
+        let replication = simple(2);
+        let current = partitioner
+            .get_ring_with(&partitioner.self_node.clone())
+            .unwrap();
+        let future = partitioner.get_ring_without("b").unwrap();
+
+        // The ranges of b go to c, which already holds them, and to a, which takes b's place.
+        assert_eq!(
+            new_replicas(150, &current, &future, &replication, &partitioner),
+            vec!["a"]
+        );
+        // The ranges b was the second replica of go to the node that follows it.
+        assert_eq!(
+            new_replicas(50, &current, &future, &replication, &partitioner),
+            vec!["c"]
+        );
+        // The ranges b was not a replica of keep their replicas.
+        assert!(new_replicas(250, &current, &future, &replication, &partitioner).is_empty());
+    }
+
+    #[test]
+    fn test_restream_sends_the_ranges_of_the_removed_node() {
+        let partitioner = partitioner("a");
+        let replication = simple(2);
+        let previous = partitioner
+            .get_ring_with(&node("c", "datacenter1", "rack1", &[300]))
+            .unwrap();
+        let current = partitioner.get_ring_without("c").unwrap();
+
+        // The ranges c owned are now owned by a, followed by b.
+        assert_eq!(
+            new_replicas(250, &previous, &current, &replication, &partitioner),
+            vec!["b"]
+        );
+        // a replaces c as the second replica of b, but this node does not stream to itself.
+        assert!(new_replicas(150, &previous, &current, &replication, &partitioner).is_empty());
+        assert!(new_replicas(50, &previous, &current, &replication, &partitioner).is_empty());
+    }
+
+    #[test]
+    fn test_remove_node_fails_with_the_nodes_that_did_not_restream() {
+        let nodes = vec![
+            node("127.0.0.1", "datacenter1", "rack1", &[100]),
+            node("127.0.0.2", "datacenter1", "rack1", &[200]),
+            node("127.0.0.3", "datacenter1", "rack1", &[300]),
+        ];
+        let partitioner = Partitioner::new(nodes[0].clone(), nodes, Vec::new()).unwrap();
+        // No heartbeat is expected in time, so every peer is down and nothing listens on their ports.
+        let manager = RwLock::new(GossipManager::new(
+            Arc::new(partitioner),
+            &[],
+            Arc::new(FailureDetector::new(0.0)),
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = RwLock::new(initialize_context(dir.path()).unwrap());
+
+        for _ in 0..2 {
+            let error = remove_node("127.0.0.3", &manager, &ctx).unwrap_err();
+            assert!(error.to_string().contains("restreamed from 127.0.0.2."));
+        }
+        let peer = manager.read().unwrap().peers["127.0.0.3"]
+            .read()
+            .unwrap()
+            .clone();
+        assert_eq!(peer.state.status(), Some(Status::Left));
+    }
+}
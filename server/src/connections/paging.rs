@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use db::{get_live_rows, reconcile, Context, Schema, StoredRow};
+use inc::query::PageQuery;
+use native::server::QueryParameters;
+use query::Query;
+use shared::io_error;
+
+use crate::partitioner::node::Node;
+
+use super::{client::Rows, node::get_query_path};
+
+/// The selected columns of the rows of a page, and the paging state of the next page if there may be
+/// more.
+type Page = (Option<Rows>, Option<Vec<u8>>);
+
+/// The page of the rows of a `SELECT` a client asks for.
+#[derive(Debug, Clone)]
+pub(crate) struct PageRequest {
+    pub(crate) page_size: usize,
+    /// The position of the last row of the previous page, or `None` for the first page.
+    pub(crate) start: Option<Vec<String>>,
+}
+
+/// Returns the page the parameters of a query ask for, or `None` if they do not have a positive page
+/// size, which disables paging.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the paging state is not valid.
+pub(crate) fn get_page_request(
+    parameters: &QueryParameters,
+) -> std::io::Result<Option<PageRequest>> {
+    let Some(page_size) = parameters.page_size.filter(|page_size| *page_size > 0) else {
+        return Ok(None);
+    };
+    let start = parameters
+        .paging_state
+        .as_deref()
+        .map(decode_paging_state)
+        .transpose()?;
+    Ok(Some(PageRequest {
+        page_size: page_size as usize,
+        start,
+    }))
+}
+
+/// Returns the rows a `SELECT` query selects from the rows of the table, or only the ones of the page
+/// if there is one, with the paging state to request the next page with if there is one.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the query cannot select the rows, or if the paging state is not valid.
+pub(crate) fn select_page(
+    query: &Query,
+    rows: Vec<HashMap<String, String>>,
+    schema: &Schema,
+    page: Option<&PageRequest>,
+) -> std::io::Result<Page> {
+    let Some(page) = page else {
+        return Ok((query.select_rows(rows, schema)?, None));
+    };
+    let (rows, next) = query.select_page(rows, schema, page.start.as_deref(), page.page_size)?;
+    Ok((rows, next.map(|position| encode_paging_state(&position))))
+}
+
+/// Reconciles the pages the replicas read of a `SELECT` and selects the page of the client from them.
+///
+/// Each replica only read its first `page_size` rows after the start of the page, so the rows after the
+/// last one of a replica that read that many may be missing from it. The responses are cut at the
+/// earliest of those rows, so the page is only selected from, and read repaired with, the rows every
+/// replica read. The page may then have fewer rows than its size, even none, and still a paging state.
+///
+/// # Returns
+///
+/// * The reconciled rows of the page, to read repair the replicas with, the selected columns of the
+///   rows of the page, and the paging state of the next page if there may be more.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the query cannot select the rows, or if the paging state is not valid.
+pub(crate) fn select_replica_page(
+    query: &Query,
+    responses: &mut [(Node, Vec<StoredRow>)],
+    schema: &Schema,
+    page: &PageRequest,
+) -> std::io::Result<(Vec<StoredRow>, Page)> {
+    let position = |row: &StoredRow| query.get_position(&row.values(schema), schema);
+    let end = responses
+        .iter()
+        .filter_map(|(_, rows)| {
+            let read: Vec<Vec<String>> = rows
+                .iter()
+                .filter(|row| !row.is_partition())
+                .map(position)
+                .collect();
+            (read.len() >= page.page_size)
+                .then(|| {
+                    read.into_iter()
+                        .max_by(|a, b| query.compare_positions(a, b, schema))
+                })
+                .flatten()
+        })
+        .min_by(|a, b| query.compare_positions(a, b, schema));
+    if let Some(end) = &end {
+        for (_, rows) in responses.iter_mut() {
+            rows.retain(|row| {
+                row.is_partition() || query.compare_positions(&position(row), end, schema).is_le()
+            });
+        }
+    }
+    let merged = reconcile(responses.iter().map(|(_, rows)| rows.clone()).collect());
+    let (rows, next) = query.select_page(
+        get_live_rows(&merged, schema),
+        schema,
+        page.start.as_deref(),
+        page.page_size,
+    )?;
+    let paging_state = next.or(end).map(|position| encode_paging_state(&position));
+    Ok((merged, (rows, paging_state)))
+}
+
+/// Reads the stored rows of a page of a `SELECT` as one of the replicas of its partition.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the table does not exist, or if the query cannot read the page.
+pub(crate) fn process_replica_page(
+    query: &PageQuery,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<Vec<StoredRow>> {
+    let ctx = ctx.read().unwrap();
+    let table = get_query_path(&ctx.node_dir, &query.query, &query.table)?;
+    query
+        .query
+        .read_stored_page(&table, &ctx, query.start.as_deref(), query.page_size)
+}
+
+/// Encodes the position of the last row of a page, as returned by `Query::select_page`, as the opaque
+/// paging state the client sends back for the next page. The format is:
+/// - n: u16 = number of values of the position
+/// - n values, each a u16 length followed by the UTF-8 bytes of the value
+fn encode_paging_state(position: &[String]) -> Vec<u8> {
+    let mut paging_state = (position.len() as u16).to_be_bytes().to_vec();
+    for value in position {
+        paging_state.extend((value.len() as u16).to_be_bytes());
+        paging_state.extend(value.as_bytes());
+    }
+    paging_state
+}
+
+/// Decodes the position of the last row of a page from the paging state of a request.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the paging state is not one returned by `encode_paging_state`.
+fn decode_paging_state(paging_state: &[u8]) -> std::io::Result<Vec<String>> {
+    let invalid = || io_error!("Invalid paging state");
+    let mut rest = paging_state;
+    let mut take = |length: usize| -> std::io::Result<&[u8]> {
+        let (taken, remaining) = rest.split_at_checked(length).ok_or_else(invalid)?;
+        rest = remaining;
+        Ok(taken)
+    };
+    let count = u16::from_be_bytes(take(2)?.try_into().unwrap());
+    let mut position = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let length = u16::from_be_bytes(take(2)?.try_into().unwrap());
+        let value = String::from_utf8(take(length as usize)?.to_vec()).map_err(|_| invalid())?;
+        position.push(value);
+    }
+    if !rest.is_empty() {
+        return Err(invalid());
+    }
+    Ok(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use db::initialize_context;
+    use query::process_query;
+
+    use super::*;
+    use crate::{connections::node::process_replica_query, test_utils::node};
+
+    /// Returns the context of a replica with the rows of the sequences in the partition 1 of `ks.t`.
+    fn replica(dir: &std::path::Path, seqs: &[i32]) -> Arc<RwLock<Context>> {
+        let ctx = Arc::new(RwLock::new(initialize_context(dir).unwrap()));
+        let mut queries = vec![
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 2}".to_string(),
+            "CREATE TABLE ks.t (id int, seq int, PRIMARY KEY (id, seq))".to_string(),
+        ];
+        queries.extend(
+            seqs.iter()
+                .map(|seq| format!("INSERT INTO ks.t (id, seq) VALUES (1, {seq})")),
+        );
+        for query in queries {
+            let (mut query, name) = process_query(&query).unwrap();
+            process_replica_query(&mut query, &name, &ctx).unwrap();
+        }
+        ctx
+    }
+
+    #[test]
+    fn test_replicas_only_read_the_rows_of_the_page() {
+        let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let replicas = [
+            (
+                node("a", "datacenter1", "rack1", &[0]),
+                replica(first.path(), &[1, 2, 3, 4]),
+            ),
+            (
+                node("b", "datacenter1", "rack1", &[1]),
+                replica(second.path(), &[1, 3, 5]),
+            ),
+        ];
+        let (query, name) = process_query("SELECT seq FROM ks.t WHERE id = 1").unwrap();
+        let schema = replicas[0]
+            .1
+            .read()
+            .unwrap()
+            .get_table_schema("ks", "t")
+            .unwrap();
+
+        let mut page = PageRequest {
+            page_size: 2,
+            start: None,
+        };
+        let mut pages = Vec::new();
+        let mut repaired = Vec::new();
+        loop {
+            let mut responses: Vec<(Node, Vec<StoredRow>)> = replicas
+                .iter()
+                .map(|(node, ctx)| {
+                    let request = PageQuery {
+                        query: query.clone(),
+                        table: name.clone(),
+                        start: page.start.clone(),
+                        page_size: page.page_size,
+                    };
+                    let rows = process_replica_page(&request, ctx).unwrap();
+                    assert!(rows.len() <= page.page_size);
+                    (node.clone(), rows)
+                })
+                .collect();
+            let (merged, (rows, paging_state)) =
+                select_replica_page(&query, &mut responses, &schema, &page).unwrap();
+            pages.push(rows.unwrap_or_default());
+            repaired.push(merged.len());
+            let Some(paging_state) = paging_state else {
+                break;
+            };
+            page.start = Some(decode_paging_state(&paging_state).unwrap());
+        }
+
+        // The second replica read up to 3, but the first one only up to 2, which may miss the rest.
+        let seqs = |seqs: &[&str]| -> Vec<Vec<String>> {
+            seqs.iter().map(|seq| vec![seq.to_string()]).collect()
+        };
+        assert_eq!(
+            pages,
+            vec![seqs(&["1", "2"]), seqs(&["3", "4"]), seqs(&["5"])]
+        );
+        assert_eq!(repaired, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn test_encode_and_decode_paging_state() {
+        let position = vec!["1".to_string(), "O'Hara, Jo".to_string(), String::new()];
+        let paging_state = encode_paging_state(&position);
+        assert_eq!(decode_paging_state(&paging_state).unwrap(), position);
+
+        assert!(decode_paging_state(&[]).is_err());
+        assert!(decode_paging_state(&paging_state[..paging_state.len() - 1]).is_err());
+        assert!(decode_paging_state(&[paging_state.as_slice(), &[0]].concat()).is_err());
+    }
+}
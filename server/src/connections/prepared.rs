@@ -0,0 +1,239 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use db::Schema;
+use native::server::BoundValue;
+use query::{BindMarker, Query};
+use shared::io_error;
+
+/// A query parsed once by a `PREPARE`, to be executed many times with different values.
+#[derive(Debug, Clone)]
+pub(crate) struct PreparedStatement {
+    /// The parsed query, with its bind markers in place of the values.
+    pub(crate) query: Query,
+    /// The table of the query qualified with its keyspace, or the keyspace of the keyspace queries,
+    /// so the query runs on the same table regardless of the keyspace of the connection that
+    /// executes it.
+    pub(crate) name: String,
+    pub(crate) markers: Vec<BindMarker>,
+    pub(crate) query_str: String,
+}
+
+/// The queries prepared on the node, by id. They are shared by every connection, so a query
+/// prepared in one of them can be executed in the rest.
+#[derive(Debug, Default)]
+pub(crate) struct PreparedStatements {
+    statements: RwLock<HashMap<Vec<u8>, PreparedStatement>>,
+}
+
+impl PreparedStatements {
+    /// Keeps a prepared query and returns its id: the MD5 hash of the query and of the keyspace it
+    /// runs in, so preparing the same query again returns the same id.
+    pub(crate) fn insert(&self, keyspace: &str, statement: PreparedStatement) -> Vec<u8> {
+        let id = md5::compute(format!("{keyspace}\n{}", statement.query_str)).to_vec();
+        self.statements
+            .write()
+            .unwrap()
+            .insert(id.clone(), statement);
+        id
+    }
+
+    pub(crate) fn get(&self, id: &[u8]) -> Option<PreparedStatement> {
+        self.statements.read().unwrap().get(id).cloned()
+    }
+}
+
+/// Returns the query with the values bound to its markers, decoded with the types of the columns
+/// they are bound to, so they are never parsed as part of the query. The values are bound by name
+/// if all of them have one, where the name of a `?` marker is the name of its column, and in the
+/// order of the markers otherwise.
+///
+/// # Errors
+///
+/// * Returns an `Error` if there is not a value for each marker, or if a value is not valid for the
+///   type of its column.
+pub(crate) fn bind_values(
+    query: &Query,
+    markers: &[BindMarker],
+    values: &[BoundValue],
+    schema: Option<&Schema>,
+) -> std::io::Result<Query> {
+    if values.len() != markers.len() {
+        return Err(io_error!(format!(
+            "Expected {} values for the bind markers, got {}",
+            markers.len(),
+            values.len()
+        )));
+    }
+    let named = !values.is_empty() && values.iter().all(|value| value.name.is_some());
+    let mut bound = Vec::with_capacity(values.len());
+    for (index, marker) in markers.iter().enumerate() {
+        let name = marker.name.as_ref().unwrap_or(&marker.column);
+        let value = if named {
+            values
+                .iter()
+                .find(|value| value.name.as_ref() == Some(name))
+                .ok_or(io_error!(format!("No value for bind marker '{name}'")))?
+        } else {
+            &values[index]
+        };
+        let Some(bytes) = &value.value else {
+            bound.push("NULL".to_string());
+            continue;
+        };
+        let parse = schema
+            .and_then(|schema| schema.get_parse_function(&marker.column))
+            .ok_or(io_error!(format!(
+                "Column '{}' does not exist",
+                marker.column
+            )))?;
+        bound.push(parse(bytes)?);
+    }
+    let mut query = query.clone();
+    query.bind(&bound)?;
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use db::{PrimaryKey, SchemaType};
+    use query::prepare_query;
+
+    use super::*;
+
+    fn statement(query_str: &str) -> PreparedStatement {
+        let (query, name, markers) = prepare_query(query_str).unwrap();
+        PreparedStatement {
+            query,
+            name,
+            markers,
+            query_str: query_str.to_string(),
+        }
+    }
+
+    fn schema() -> Schema {
+        Schema::new(
+            HashMap::from([
+                ("id".to_string(), SchemaType::Int),
+                ("name".to_string(), SchemaType::Text),
+            ]),
+            PrimaryKey::new(vec!["id".to_string()], Vec::new()),
+        )
+    }
+
+    fn value(name: Option<&str>, value: Option<&[u8]>) -> BoundValue {
+        BoundValue {
+            name: name.map(str::to_string),
+            value: value.map(<[u8]>::to_vec),
+        }
+    }
+
+    #[test]
+    fn test_same_query_has_the_same_id() {
+        let prepared = PreparedStatements::default();
+        let query = "SELECT * FROM users WHERE id = ?";
+        let id = prepared.insert("ks", statement(query));
+        assert_eq!(id.len(), 16);
+        assert_eq!(prepared.insert("ks", statement(query)), id);
+        assert_ne!(prepared.insert("other", statement(query)), id);
+        assert_eq!(prepared.get(&id).unwrap().query_str, query);
+        assert!(prepared.get(&[0; 16]).is_none());
+    }
+
+    #[test]
+    fn test_values_are_bound_by_position_and_by_name() {
+        let schema = schema();
+        let statement = statement("INSERT INTO users (id, name) VALUES (?, ?)");
+        let query = bind_values(
+            &statement.query,
+            &statement.markers,
+            &[value(None, Some(&7i32.to_be_bytes())), value(None, None)],
+            Some(&schema),
+        )
+        .unwrap();
+        let mut keys = query.get_keys();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                ("id".to_string(), "7".to_string()),
+                ("name".to_string(), "NULL".to_string())
+            ]
+        );
+
+        let query = bind_values(
+            &statement.query,
+            &statement.markers,
+            &[
+                value(Some("name"), Some(b"Pepe")),
+                value(Some("id"), Some(&1i32.to_be_bytes())),
+            ],
+            Some(&schema),
+        )
+        .unwrap();
+        let mut keys = query.get_keys();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                ("id".to_string(), "1".to_string()),
+                ("name".to_string(), "Pepe".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_values_are_not_bound() {
+        let schema = schema();
+        let statement = statement("SELECT * FROM users WHERE id = :id");
+        // Too many values.
+        assert!(bind_values(
+            &statement.query,
+            &statement.markers,
+            &[value(None, Some(b"1")), value(None, Some(b"2"))],
+            Some(&schema)
+        )
+        .is_err());
+        // An int must have 4 bytes.
+        assert!(bind_values(
+            &statement.query,
+            &statement.markers,
+            &[value(None, Some(&[0x01]))],
+            Some(&schema)
+        )
+        .is_err());
+        // There is no marker with that name.
+        assert!(bind_values(
+            &statement.query,
+            &statement.markers,
+            &[value(Some("name"), Some(&1i32.to_be_bytes()))],
+            Some(&schema)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_text_values_are_not_parsed() {
+        let schema = schema();
+        let statement = statement("INSERT INTO users (id, name) VALUES (?, ?)");
+        let name = "O'Hara, 2) VALUES ('x') WHERE id = 2";
+        let query = bind_values(
+            &statement.query,
+            &statement.markers,
+            &[
+                value(None, Some(&1i32.to_be_bytes())),
+                value(None, Some(name.as_bytes())),
+            ],
+            Some(&schema),
+        )
+        .unwrap();
+        let mut keys = query.get_keys();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                ("id".to_string(), "1".to_string()),
+                ("name".to_string(), name.to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    net::TcpStream,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use db::{current_timestamp, reconcile, Context, MerkleTree, Replication, StoredRow, TokenRange};
+use inc::{
+    operation::RepairOptions,
+    read_inc_frame,
+    repair::{MerkleTreeRequest, MerkleTreeResponse, RangeRequest, RangeResponse},
+    Body, FrameType,
+};
+use shared::{io_error, map_io_error, not_found_error, resolve_table};
+
+use crate::partitioner::{murmur3::Partitioner, node::Node};
+
+use super::{node::send_message, read_repair::handle_read_repair};
+
+/// Depth of the Merkle trees of each range of tokens, which have `2^8` leaves.
+const MERKLE_DEPTH: u32 = 8;
+
+/// Repairs the tables of the options on the ranges of tokens this node is a replica of.
+///
+/// The replicas of each range build Merkle trees of their rows, and only the sub-ranges whose trees
+/// differ are read from all of them, reconciled, and written to the replicas that miss something.
+/// Incremental repairs only compare the data the replicas received since the last full-range repair of
+/// the table from this node, which is the only one that moves the repair time of the table.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the keyspace or the table do not exist, or if a table could not be repaired.
+pub(crate) fn repair(
+    options: RepairOptions,
+    partitioner: &Partitioner,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    let tables = get_tables_to_repair(&options, ctx)?;
+    let repairs_path = ctx.read().unwrap().node_dir.join("repairs.json");
+    let mut repaired_at = load_repair_times(&repairs_path);
+    let mut failed = Vec::new();
+    for (keyspace, table) in tables {
+        let name = format!("{keyspace}.{table}");
+        let started = current_timestamp();
+        let since = repaired_at.get(&name).copied().filter(|_| !options.full);
+        let replication = ctx.read().unwrap().get_replication(&keyspace)?;
+        match repair_table(&name, &replication, options.range, since, partitioner, ctx) {
+            Ok(()) if options.range.is_none() => {
+                repaired_at.insert(name, started);
+            }
+            Ok(()) => {}
+            Err(e) => {
+                println!("Failed to repair {name}: {e}");
+                failed.push(name);
+            }
+        }
+    }
+    save_repair_times(&repairs_path, &repaired_at)?;
+    if !failed.is_empty() {
+        return Err(io_error!(format!("Failed to repair {}", failed.join(", "))));
+    }
+    Ok(())
+}
+
+/// Answers a `MerkleTreeRequest` with the trees of the rows of this node.
+pub(crate) fn handle_merkle_tree_request(
+    request: MerkleTreeRequest,
+    mut stream: TcpStream,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let trees = build_merkle_trees(
+        &request.table,
+        &request.ranges,
+        request.depth,
+        request.since,
+        ctx,
+    )?;
+    let body = Body::MerkleTreeResponse(MerkleTreeResponse { trees });
+    send_message(&mut stream, FrameType::MerkleTreeResponse, &body)
+}
+
+/// Answers a `RangeRequest` with the stored rows of this node.
+pub(crate) fn handle_range_request(
+    request: RangeRequest,
+    mut stream: TcpStream,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let rows = get_rows_in_ranges(&request.table, &request.ranges, ctx)?;
+    let body = Body::RangeResponse(RangeResponse { rows });
+    send_message(&mut stream, FrameType::RangeResponse, &body)
+}
+
+/// Returns the keyspace and the name of each table to repair.
+fn get_tables_to_repair(
+    options: &RepairOptions,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<(String, String)>> {
+    let ctx = ctx.read().unwrap();
+    let keyspaces = match &options.keyspace {
+        Some(keyspace) => vec![keyspace.clone()],
+        None => ctx.get_keyspaces(),
+    };
+    let mut tables = Vec::new();
+    for keyspace in keyspaces {
+        let names = ctx.get_tables(&keyspace)?;
+        match &options.table {
+            Some(table) if !names.contains(table) => {
+                return Err(not_found_error!("Table does not exist"));
+            }
+            Some(table) => tables.push((keyspace, table.clone())),
+            None => tables.extend(names.into_iter().map(|table| (keyspace.clone(), table))),
+        }
+    }
+    Ok(tables)
+}
+
+/// Repairs a table on the ranges of tokens this node is a replica of, within the given range.
+fn repair_table(
+    table: &str,
+    replication: &Replication,
+    range: Option<TokenRange>,
+    since: Option<i64>,
+    partitioner: &Partitioner,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    // The ranges of each set of replicas, so each replica builds all its trees in a single scan.
+    let mut groups: HashMap<Vec<String>, (Vec<Node>, Vec<TokenRange>)> = HashMap::new();
+    for (replica_range, replicas) in partitioner.get_replica_ranges(replication)? {
+        let Some(replica_range) = range.map_or(Some(replica_range), |range| {
+            range.intersection(&replica_range)
+        }) else {
+            continue;
+        };
+        let mut ips: Vec<String> = replicas
+            .iter()
+            .map(|node| node.ip_address.clone())
+            .collect();
+        ips.sort();
+        groups
+            .entry(ips)
+            .or_insert_with(|| (replicas, Vec::new()))
+            .1
+            .push(replica_range);
+    }
+
+    for (replicas, ranges) in groups.into_values() {
+        if replicas.len() < 2 {
+            continue;
+        }
+        let trees = replicas
+            .iter()
+            .map(|node| get_merkle_trees(node, table, &ranges, since, partitioner, ctx))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut mismatched = Vec::new();
+        for (i, first) in trees.iter().enumerate() {
+            for second in &trees[i + 1..] {
+                for (first, second) in first.iter().zip(second) {
+                    mismatched.extend(first.difference(second)?);
+                }
+            }
+        }
+        if mismatched.is_empty() {
+            continue;
+        }
+        let mismatched = merge_ranges(mismatched);
+        println!(
+            "Repairing {} ranges of {table} out of sync between {}",
+            mismatched.len(),
+            replicas
+                .iter()
+                .map(|node| node.ip_address.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let responses = replicas
+            .iter()
+            .map(|node| {
+                get_rows(node, table, &mismatched, partitioner, ctx)
+                    .map(|rows| (node.clone(), rows))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let merged = reconcile(responses.iter().map(|(_, rows)| rows.clone()).collect());
+        handle_read_repair(table, &responses, &merged, partitioner, ctx);
+    }
+    Ok(())
+}
+
+/// Returns the Merkle trees of the rows of a replica, building them if the replica is this node.
+fn get_merkle_trees(
+    node: &Node,
+    table: &str,
+    ranges: &[TokenRange],
+    since: Option<i64>,
+    partitioner: &Partitioner,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<MerkleTree>> {
+    if partitioner.is_me(node) {
+        return build_merkle_trees(table, ranges, MERKLE_DEPTH, since, ctx);
+    }
+    let mut stream = TcpStream::connect((&node.ip_address[..], node.port + 1))?;
+    let body = Body::MerkleTreeRequest(MerkleTreeRequest {
+        table: table.to_string(),
+        ranges: ranges.to_vec(),
+        depth: MERKLE_DEPTH,
+        since,
+    });
+    send_message(&mut stream, FrameType::MerkleTreeRequest, &body)?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::MerkleTreeResponse, Body::MerkleTreeResponse(response))
+            if response.trees.len() == ranges.len() =>
+        {
+            Ok(response.trees)
+        }
+        _ => Err(io_error!("Invalid frame type after Merkle tree request")),
+    }
+}
+
+/// Returns the stored rows of a replica in the ranges, reading them if the replica is this node.
+fn get_rows(
+    node: &Node,
+    table: &str,
+    ranges: &[TokenRange],
+    partitioner: &Partitioner,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<StoredRow>> {
+    if partitioner.is_me(node) {
+        return get_rows_in_ranges(table, ranges, ctx);
+    }
+    let mut stream = TcpStream::connect((&node.ip_address[..], node.port + 1))?;
+    let body = Body::RangeRequest(RangeRequest {
+        table: table.to_string(),
+        ranges: ranges.to_vec(),
+    });
+    send_message(&mut stream, FrameType::RangeRequest, &body)?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::RangeResponse, Body::RangeResponse(response)) => Ok(response.rows),
+        _ => Err(io_error!("Invalid frame type after range request")),
+    }
+}
+
+/// Builds the Merkle tree of each range with the rows of the table of this node, only with the data
+/// this node received after `since` if it is given.
+fn build_merkle_trees(
+    table: &str,
+    ranges: &[TokenRange],
+    depth: u32,
+    since: Option<i64>,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<MerkleTree>> {
+    let mut trees: Vec<MerkleTree> = ranges
+        .iter()
+        .map(|range| MerkleTree::new(*range, depth))
+        .collect();
+    scan_table(table, since, ctx, &mut |row| {
+        if let Some(tree) = trees
+            .iter_mut()
+            .find(|tree| tree.get_range().contains(row.token()))
+        {
+            tree.add(&row);
+        }
+        Ok(())
+    })?;
+    Ok(trees)
+}
+
+/// Returns the stored rows of the table of this node whose tokens are in the ranges.
+fn get_rows_in_ranges(
+    table: &str,
+    ranges: &[TokenRange],
+    ctx: &RwLock<Context>,
+) -> std::io::Result<Vec<StoredRow>> {
+    let mut rows = Vec::new();
+    scan_table(table, None, ctx, &mut |row| {
+        if ranges.iter().any(|range| range.contains(row.token())) {
+            rows.push(row);
+        }
+        Ok(())
+    })?;
+    Ok(rows)
+}
+
+fn scan_table(
+    table: &str,
+    since: Option<i64>,
+    ctx: &RwLock<Context>,
+    visitor: &mut dyn FnMut(StoredRow) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let (keyspace, table) = resolve_table(table)?;
+    let ctx = ctx.read().unwrap();
+    ctx.scan_stored_rows(&ctx.node_dir.join(keyspace).join(table), since, visitor)
+}
+
+/// Sorts the ranges, merging the ones that overlap or are adjacent.
+fn merge_ranges(mut ranges: Vec<TokenRange>) -> Vec<TokenRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<TokenRange> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start as i128 <= last.end as i128 + 1 => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Returns the time each table was last repaired from this node, in microseconds since the epoch.
+fn load_repair_times(path: &Path) -> HashMap<String, i64> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_repair_times(path: &Path, repaired_at: &HashMap<String, i64>) -> std::io::Result<()> {
+    let content = serde_json::to_string(repaired_at)
+        .map_err(map_io_error!("Failed to serialize the repair times"))?;
+    std::fs::write(path, content)
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use db::Context;
+use inc::{
+    query::Query,
+    read_inc_frame,
+    schema::{SchemaRequest, SchemaResponse},
+    Body, FrameType,
+};
+use murmur3::murmur3_x64_128;
+use shared::{io_error, map_io_error};
+
+use super::{
+    gossip::{failure_detector::GOSSIP_INTERVAL, manager::GossipManager, starter::GOSSIP_FANOUT},
+    node::{process_replica_query, send_message},
+};
+
+/// The file of the node dir that keeps the DDL queries applied to the node.
+const SCHEMA_LOG: &str = "schema_changes.txt";
+
+/// Returns the DDL queries applied to the node, with the keyspace or table each one was applied to.
+pub(crate) fn read_schema_changes(node_dir: &Path) -> std::io::Result<Vec<Query>> {
+    let log = node_dir.join(SCHEMA_LOG);
+    if !log.exists() {
+        return Ok(Vec::new());
+    }
+    let mut changes = Vec::new();
+    for line in BufReader::new(File::open(log)?).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        changes.push(serde_json::from_str(&line).map_err(map_io_error!("Invalid schema change"))?);
+    }
+    Ok(changes)
+}
+
+/// Keeps a DDL query applied to the node, unless it is already kept.
+///
+/// **Must** be called while holding the write lock of the context, so the changes are not written at the
+/// same time.
+pub(crate) fn record_schema_change(node_dir: &Path, change: &Query) -> std::io::Result<()> {
+    if read_schema_changes(node_dir)?
+        .iter()
+        .any(|known| get_change_id(known) == get_change_id(change))
+    {
+        return Ok(());
+    }
+    let line = serde_json::to_string(change).map_err(map_io_error!("Invalid schema change"))?;
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(node_dir.join(SCHEMA_LOG))?;
+    writeln!(log, "{line}")?;
+    *schema_change_counts()
+        .lock()
+        .unwrap()
+        .entry(node_dir.to_path_buf())
+        .or_default() += 1;
+    Ok(())
+}
+
+/// Returns the number of schema changes kept by the node since it started, which tells when its schema
+/// version has to be computed again.
+pub(crate) fn get_schema_change_count(node_dir: &Path) -> u64 {
+    schema_change_counts()
+        .lock()
+        .unwrap()
+        .get(node_dir)
+        .copied()
+        .unwrap_or(0)
+}
+
+fn schema_change_counts() -> &'static Mutex<HashMap<PathBuf, u64>> {
+    static COUNTS: OnceLock<Mutex<HashMap<PathBuf, u64>>> = OnceLock::new();
+    COUNTS.get_or_init(Default::default)
+}
+
+/// Returns the version of the schema of the node: a hash of the DDL queries applied to it, regardless
+/// of the order they were applied in.
+pub(crate) fn get_schema_version(node_dir: &Path) -> std::io::Result<String> {
+    let mut ids: Vec<(i64, String)> = read_schema_changes(node_dir)?
+        .iter()
+        .map(get_change_id)
+        .collect();
+    ids.sort();
+    let ids = ids
+        .iter()
+        .map(|(timestamp, name)| format!("{timestamp}:{name}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let hash = murmur3_x64_128(&mut ids.as_bytes(), 0)?;
+    Ok(format!("{hash:032x}"))
+}
+
+/// Identifies a schema change by the time the coordinator took it and the keyspace or table it changes.
+fn get_change_id(change: &Query) -> (i64, String) {
+    (
+        change.query.timestamp().unwrap_or_default(),
+        change.table.clone(),
+    )
+}
+
+/// Pulls the schema changes of a node that is up and announces a different schema version than this
+/// one, and applies the ones this node is missing in the order they were made.
+pub(crate) fn pull_schema(manager: &RwLock<GossipManager>, ctx: &RwLock<Context>) {
+    let Some((ip, port)) = manager.read().unwrap().get_schema_source() else {
+        return;
+    };
+    println!("Pulling the schema from {ip}");
+    let result =
+        request_schema_changes(&ip, port).and_then(|changes| apply_schema_changes(changes, ctx));
+    if let Err(e) = result {
+        println!("Failed to pull the schema from {ip}: {e}");
+    }
+    let node_dir = ctx.read().unwrap().node_dir.clone();
+    manager.read().unwrap().update_schema(&node_dir);
+}
+
+/// Asks a node for the schema changes it applied.
+pub(crate) fn request_schema_changes(ip: &str, port: u16) -> std::io::Result<Vec<Query>> {
+    let mut stream = TcpStream::connect((ip, port))?;
+    send_message(
+        &mut stream,
+        FrameType::SchemaRequest,
+        &Body::SchemaRequest(SchemaRequest),
+    )?;
+    match read_inc_frame(&mut stream)? {
+        (FrameType::SchemaResponse, Body::SchemaResponse(response)) => Ok(response.changes),
+        _ => Err(io_error!("Invalid frame type after schema request")),
+    }
+}
+
+/// Applies the schema changes this node is missing, oldest first. A change that cannot be applied, such
+/// as a table created in a keyspace dropped later, is kept anyway, so the node agrees with the others.
+pub(crate) fn apply_schema_changes(
+    changes: Vec<Query>,
+    ctx: &RwLock<Context>,
+) -> std::io::Result<()> {
+    let node_dir = ctx.read().unwrap().node_dir.clone();
+    let known: HashSet<(i64, String)> = read_schema_changes(&node_dir)?
+        .iter()
+        .map(get_change_id)
+        .collect();
+    let mut missing: Vec<Query> = changes
+        .into_iter()
+        .filter(|change| !known.contains(&get_change_id(change)))
+        .collect();
+    missing.sort_by_key(get_change_id);
+    for mut change in missing {
+        println!("Applying the schema change of {}", change.table);
+        if let Err(e) = process_replica_query(&mut change.query, &change.table, ctx) {
+            println!("Failed to apply the schema change of {}: {e}", change.table);
+            let _ctx = ctx.write().unwrap();
+            record_schema_change(&node_dir, &change)?;
+        }
+    }
+    Ok(())
+}
+
+/// Answers a schema request with the schema changes applied to this node.
+pub(crate) fn handle_schema_request(
+    mut stream: TcpStream,
+    ctx: &Arc<RwLock<Context>>,
+) -> std::io::Result<()> {
+    let changes = {
+        let ctx = ctx.read().unwrap();
+        read_schema_changes(&ctx.node_dir)?
+    };
+    send_message(
+        &mut stream,
+        FrameType::SchemaResponse,
+        &Body::SchemaResponse(SchemaResponse { changes }),
+    )
+}
+
+/// Returns the longest a coordinator waits for the nodes of a cluster of the size to agree on the schema
+/// after a DDL query.
+///
+/// The replicas announce their new version as soon as they apply the change, and gossip takes it to
+/// every node in about as many rounds as it takes `GOSSIP_FANOUT` peers a round to reach them. The
+/// rounds are doubled, since the peers are picked at random, with one more for the next heartbeat of
+/// each node.
+pub(crate) fn get_schema_agreement_timeout(nodes: usize) -> Duration {
+    let mut rounds = 0;
+    let mut reached = 1;
+    while reached < nodes {
+        reached *= GOSSIP_FANOUT;
+        rounds += 1;
+    }
+    GOSSIP_INTERVAL * (2 * (rounds + 1))
+}
+
+/// Waits until every node that is up announces the schema version of this node, for at most the
+/// `get_schema_agreement_timeout` of the cluster.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the nodes did not agree on the schema in time. The schema change is still
+///   applied, and the nodes keep pulling it through gossip.
+pub(crate) fn wait_for_schema_agreement(
+    manager: &RwLock<GossipManager>,
+    node_dir: &Path,
+) -> std::io::Result<()> {
+    let timeout = get_schema_agreement_timeout(manager.read().unwrap().peers.len() + 1);
+    let deadline = Instant::now() + timeout;
+    manager.read().unwrap().update_schema(node_dir);
+    while !manager.read().unwrap().has_schema_agreement() {
+        if Instant::now() >= deadline {
+            return Err(io_error!(format!(
+                "The schema change was applied, but the nodes did not agree on the schema within {} seconds",
+                timeout.as_secs()
+            )));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use db::initialize_context;
+    use query::process_query;
+
+    use super::*;
+
+    fn change(query: &str, timestamp: i64) -> Query {
+        let (mut query, table) = process_query(query).unwrap();
+        query.set_timestamp(timestamp);
+        Query { query, table }
+    }
+
+    #[test]
+    fn test_schema_agreement_timeout_grows_with_the_gossip_rounds() {
+        assert_eq!(get_schema_agreement_timeout(1), GOSSIP_INTERVAL * 2);
+        assert_eq!(get_schema_agreement_timeout(3), GOSSIP_INTERVAL * 4);
+        assert_eq!(get_schema_agreement_timeout(4), GOSSIP_INTERVAL * 6);
+        assert_eq!(get_schema_agreement_timeout(100), GOSSIP_INTERVAL * 12);
+    }
+
+    #[test]
+    fn test_schema_version_does_not_depend_on_the_order() {
+        let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (first, second) = (first.path(), second.path());
+        let create = change("CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}", 1);
+        let drop = change("DROP KEYSPACE ks", 2);
+
+        let empty = get_schema_version(first).unwrap();
+        record_schema_change(first, &create).unwrap();
+        record_schema_change(first, &drop).unwrap();
+        record_schema_change(second, &drop).unwrap();
+        record_schema_change(second, &create).unwrap();
+        // A change is only kept once.
+        record_schema_change(second, &create).unwrap();
+
+        assert_eq!(read_schema_changes(second).unwrap().len(), 2);
+        assert_eq!(
+            get_schema_version(first).unwrap(),
+            get_schema_version(second).unwrap()
+        );
+        assert_ne!(get_schema_version(first).unwrap(), empty);
+    }
+
+    #[test]
+    fn test_missing_changes_are_applied_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let node = dir.path();
+        let ctx = RwLock::new(initialize_context(node).unwrap());
+        let changes = vec![
+            change("CREATE TABLE ks.users (id int, name text, PRIMARY KEY (id))", 2),
+            change("CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}", 1),
+            change("CREATE TABLE ks.missing (id int, PRIMARY KEY (id))", 4),
+            change("DROP TABLE ks.missing", 5),
+            change("CREATE TABLE other.users (id int, PRIMARY KEY (id))", 3),
+        ];
+
+        apply_schema_changes(changes.clone(), &ctx).unwrap();
+        assert!(ctx.read().unwrap().get_table_schema("ks", "users").is_ok());
+        assert!(ctx
+            .read()
+            .unwrap()
+            .get_table_schema("ks", "missing")
+            .is_err());
+        // The change that failed is kept too.
+        assert_eq!(read_schema_changes(node).unwrap().len(), 5);
+
+        // Applying them again does nothing.
+        let version = get_schema_version(node).unwrap();
+        apply_schema_changes(changes, &ctx).unwrap();
+        assert_eq!(get_schema_version(node).unwrap(), version);
+    }
+}
@@ -0,0 +1,593 @@
+use std::collections::HashMap;
+
+use db::{CompactionOptions, Context, PrimaryKey, Replication, Schema, SchemaType};
+use inc::gossip::peer::{ApplicationState, EndpointState};
+use shared::not_found_error;
+
+use super::gossip::manager::GossipManager;
+
+/// The keyspace that describes this node and the nodes it knows.
+pub(crate) const SYSTEM: &str = "system";
+
+/// The keyspace that describes the keyspaces, tables and columns of the cluster.
+pub(crate) const SYSTEM_SCHEMA: &str = "system_schema";
+
+/// The CQL version of the queries the nodes understand.
+const CQL_VERSION: &str = "3.0.0";
+
+/// The version of the native protocol the nodes speak.
+const NATIVE_PROTOCOL_VERSION: &str = "4";
+
+const PARTITIONER: &str = "org.apache.cassandra.dht.Murmur3Partitioner";
+
+/// A read-only table of the system keyspaces. Its rows are not stored, but built when it is read from
+/// the state of the node.
+struct VirtualTable {
+    keyspace: &'static str,
+    name: &'static str,
+    partition_key: &'static [&'static str],
+    clustering_key: &'static [&'static str],
+    columns: &'static [(&'static str, SchemaType)],
+}
+
+const VIRTUAL_TABLES: [VirtualTable; 5] = [
+    VirtualTable {
+        keyspace: SYSTEM,
+        name: "local",
+        partition_key: &["key"],
+        clustering_key: &[],
+        columns: &[
+            ("key", SchemaType::Text),
+            ("bootstrapped", SchemaType::Text),
+            ("broadcast_address", SchemaType::Text),
+            ("listen_address", SchemaType::Text),
+            ("rpc_address", SchemaType::Text),
+            ("rpc_port", SchemaType::Int),
+            ("cql_version", SchemaType::Text),
+            ("native_protocol_version", SchemaType::Text),
+            ("partitioner", SchemaType::Text),
+            ("data_center", SchemaType::Text),
+            ("rack", SchemaType::Text),
+            ("release_version", SchemaType::Text),
+            ("schema_version", SchemaType::Text),
+            ("tokens", SchemaType::Text),
+        ],
+    },
+    VirtualTable {
+        keyspace: SYSTEM,
+        name: "peers",
+        partition_key: &["peer"],
+        clustering_key: &[],
+        columns: &[
+            ("peer", SchemaType::Text),
+            ("rpc_address", SchemaType::Text),
+            ("data_center", SchemaType::Text),
+            ("rack", SchemaType::Text),
+            ("release_version", SchemaType::Text),
+            ("schema_version", SchemaType::Text),
+            ("tokens", SchemaType::Text),
+        ],
+    },
+    VirtualTable {
+        keyspace: SYSTEM_SCHEMA,
+        name: "keyspaces",
+        partition_key: &["keyspace_name"],
+        clustering_key: &[],
+        columns: &[
+            ("keyspace_name", SchemaType::Text),
+            ("durable_writes", SchemaType::Boolean),
+            ("replication", SchemaType::Text),
+        ],
+    },
+    VirtualTable {
+        keyspace: SYSTEM_SCHEMA,
+        name: "tables",
+        partition_key: &["keyspace_name"],
+        clustering_key: &["table_name"],
+        columns: &[
+            ("keyspace_name", SchemaType::Text),
+            ("table_name", SchemaType::Text),
+            ("compaction", SchemaType::Text),
+            ("default_time_to_live", SchemaType::Int),
+            ("gc_grace_seconds", SchemaType::Int),
+        ],
+    },
+    VirtualTable {
+        keyspace: SYSTEM_SCHEMA,
+        name: "columns",
+        partition_key: &["keyspace_name"],
+        clustering_key: &["table_name", "column_name"],
+        columns: &[
+            ("keyspace_name", SchemaType::Text),
+            ("table_name", SchemaType::Text),
+            ("column_name", SchemaType::Text),
+            ("kind", SchemaType::Text),
+            ("position", SchemaType::Int),
+            ("type", SchemaType::Text),
+        ],
+    },
+];
+
+impl VirtualTable {
+    fn schema(&self) -> Schema {
+        let columns = self
+            .columns
+            .iter()
+            .map(|(name, schema_type)| (name.to_string(), schema_type.clone()))
+            .collect();
+        let primary_key = PrimaryKey::new(
+            self.partition_key
+                .iter()
+                .map(|col| col.to_string())
+                .collect(),
+            self.clustering_key
+                .iter()
+                .map(|col| col.to_string())
+                .collect(),
+        );
+        Schema::new(columns, primary_key)
+    }
+}
+
+/// Whether the keyspace is one of the system keyspaces, whose tables are virtual and read-only.
+pub(crate) fn is_system_keyspace(keyspace: &str) -> bool {
+    keyspace == SYSTEM || keyspace == SYSTEM_SCHEMA
+}
+
+/// Returns the schema and the rows of a table of the system keyspaces, built from the schemas of the
+/// context, the ring of the partitioner and the states of the nodes known through gossip.
+///
+/// # Errors
+///
+/// * Returns a `NotFound` error if the table does not exist.
+pub(crate) fn read_virtual_table(
+    keyspace: &str,
+    table: &str,
+    manager: &GossipManager,
+    ctx: &Context,
+) -> std::io::Result<(Schema, Vec<HashMap<String, String>>)> {
+    let virtual_table = VIRTUAL_TABLES
+        .iter()
+        .find(|virtual_table| virtual_table.keyspace == keyspace && virtual_table.name == table)
+        .ok_or(not_found_error!(format!(
+            "Table '{keyspace}.{table}' does not exist"
+        )))?;
+    let rows = match table {
+        "local" => vec![get_local(manager)],
+        "peers" => get_peers(manager),
+        "keyspaces" => get_keyspaces(ctx)?,
+        "tables" => get_tables(ctx)?,
+        _ => get_columns(ctx)?,
+    };
+    // The values that are not known are `NULL`.
+    let rows = rows
+        .into_iter()
+        .map(|mut row| {
+            for (col, _) in virtual_table.columns {
+                row.entry(col.to_string())
+                    .or_insert_with(|| "NULL".to_string());
+            }
+            row
+        })
+        .collect();
+    Ok((virtual_table.schema(), rows))
+}
+
+fn get_local(manager: &GossipManager) -> HashMap<String, String> {
+    let partitioner = &manager.partitioner;
+    let self_node = &partitioner.self_node;
+    let state = manager.self_node.read().unwrap().state.clone();
+    let bootstrapped = if partitioner.is_joining() {
+        "IN_PROGRESS"
+    } else {
+        "COMPLETED"
+    };
+    let mut row = HashMap::from([
+        ("key".to_string(), "local".to_string()),
+        ("bootstrapped".to_string(), bootstrapped.to_string()),
+        (
+            "broadcast_address".to_string(),
+            self_node.ip_address.clone(),
+        ),
+        ("listen_address".to_string(), self_node.ip_address.clone()),
+        ("rpc_address".to_string(), self_node.ip_address.clone()),
+        ("rpc_port".to_string(), self_node.port.to_string()),
+        ("cql_version".to_string(), CQL_VERSION.to_string()),
+        (
+            "native_protocol_version".to_string(),
+            NATIVE_PROTOCOL_VERSION.to_string(),
+        ),
+        ("partitioner".to_string(), PARTITIONER.to_string()),
+        ("data_center".to_string(), self_node.datacenter.clone()),
+        ("rack".to_string(), self_node.rack.clone()),
+    ]);
+    add_gossiped_values(&mut row, &state);
+    row
+}
+
+/// Returns a row for each of the other nodes of the ring, with what they announced through gossip.
+fn get_peers(manager: &GossipManager) -> Vec<HashMap<String, String>> {
+    manager
+        .partitioner
+        .get_all_nodes()
+        .into_iter()
+        .filter(|node| !manager.partitioner.is_me(node))
+        .map(|node| {
+            let mut row = HashMap::from([
+                ("peer".to_string(), node.ip_address.clone()),
+                ("rpc_address".to_string(), node.ip_address.clone()),
+                ("data_center".to_string(), node.datacenter.clone()),
+                ("rack".to_string(), node.rack.clone()),
+            ]);
+            if let Some(peer) = manager.peers.get(&node.ip_address) {
+                add_gossiped_values(&mut row, &peer.read().unwrap().state);
+            }
+            row
+        })
+        .collect()
+}
+
+fn add_gossiped_values(row: &mut HashMap<String, String>, state: &EndpointState) {
+    for (col, application_state) in [
+        ("release_version", ApplicationState::ReleaseVersion),
+        ("schema_version", ApplicationState::Schema),
+        ("tokens", ApplicationState::Tokens),
+    ] {
+        if let Some(value) = state.get(application_state) {
+            row.insert(col.to_string(), value.to_string());
+        }
+    }
+}
+
+/// Returns the keyspaces of the node, followed by the system keyspaces.
+fn get_keyspaces(ctx: &Context) -> std::io::Result<Vec<HashMap<String, String>>> {
+    let mut rows = Vec::new();
+    for keyspace in ctx.get_keyspaces() {
+        let options = ctx.get_keyspace_options(&ctx.node_dir.join(&keyspace))?;
+        rows.push(HashMap::from([
+            ("keyspace_name".to_string(), keyspace),
+            (
+                "durable_writes".to_string(),
+                options.durable_writes.to_string(),
+            ),
+            (
+                "replication".to_string(),
+                format_replication(&options.replication),
+            ),
+        ]));
+    }
+    for keyspace in [SYSTEM, SYSTEM_SCHEMA] {
+        rows.push(HashMap::from([
+            ("keyspace_name".to_string(), keyspace.to_string()),
+            ("durable_writes".to_string(), "true".to_string()),
+            (
+                "replication".to_string(),
+                format_map(&[(
+                    "class".to_string(),
+                    "org.apache.cassandra.locator.LocalStrategy".to_string(),
+                )]),
+            ),
+        ]));
+    }
+    Ok(rows)
+}
+
+fn get_tables(ctx: &Context) -> std::io::Result<Vec<HashMap<String, String>>> {
+    let mut rows = Vec::new();
+    for (keyspace, table, schema) in get_schemas(ctx)? {
+        let options = schema.get_options();
+        rows.push(HashMap::from([
+            ("keyspace_name".to_string(), keyspace),
+            ("table_name".to_string(), table),
+            (
+                "compaction".to_string(),
+                format_compaction(&options.compaction),
+            ),
+            (
+                "default_time_to_live".to_string(),
+                options.default_time_to_live.to_string(),
+            ),
+            (
+                "gc_grace_seconds".to_string(),
+                options.gc_grace_seconds.to_string(),
+            ),
+        ]));
+    }
+    Ok(rows)
+}
+
+/// Returns the columns of every table. The columns of the primary key have their position in it, and the
+/// rest of them -1.
+fn get_columns(ctx: &Context) -> std::io::Result<Vec<HashMap<String, String>>> {
+    let mut rows = Vec::new();
+    for (keyspace, table, schema) in get_schemas(ctx)? {
+        let primary_key = schema.get_primary_key();
+        for column in schema.get_columns() {
+            let (kind, position) = if let Some(position) = primary_key
+                .get_partition_key()
+                .iter()
+                .position(|col| col == &column)
+            {
+                ("partition_key", position as i32)
+            } else if let Some(position) = primary_key
+                .get_clustering_key()
+                .iter()
+                .position(|col| col == &column)
+            {
+                ("clustering", position as i32)
+            } else {
+                ("regular", -1)
+            };
+            let schema_type = schema
+                .get_schema_type(&column)
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            rows.push(HashMap::from([
+                ("keyspace_name".to_string(), keyspace.clone()),
+                ("table_name".to_string(), table.clone()),
+                ("column_name".to_string(), column),
+                ("kind".to_string(), kind.to_string()),
+                ("position".to_string(), position.to_string()),
+                ("type".to_string(), schema_type),
+            ]));
+        }
+    }
+    Ok(rows)
+}
+
+/// Returns the schema of every table of the node, followed by the ones of the virtual tables.
+fn get_schemas(ctx: &Context) -> std::io::Result<Vec<(String, String, Schema)>> {
+    let mut schemas = Vec::new();
+    for keyspace in ctx.get_keyspaces() {
+        for table in ctx.get_tables(&keyspace)? {
+            let schema = ctx.get_table_schema(&keyspace, &table)?;
+            schemas.push((keyspace.clone(), table, schema));
+        }
+    }
+    for virtual_table in &VIRTUAL_TABLES {
+        schemas.push((
+            virtual_table.keyspace.to_string(),
+            virtual_table.name.to_string(),
+            virtual_table.schema(),
+        ));
+    }
+    Ok(schemas)
+}
+
+/// Writes the replication of a keyspace as the map it is set with.
+fn format_replication(replication: &Replication) -> String {
+    let mut entries = vec![(
+        "class".to_string(),
+        format!("org.apache.cassandra.locator.{}", replication.class),
+    )];
+    if replication.datacenters.is_empty() {
+        entries.push((
+            "replication_factor".to_string(),
+            replication.replication_factor.to_string(),
+        ));
+    } else {
+        let mut datacenters: Vec<_> = replication.datacenters.iter().collect();
+        datacenters.sort();
+        entries.extend(
+            datacenters
+                .into_iter()
+                .map(|(datacenter, factor)| (datacenter.clone(), factor.to_string())),
+        );
+    }
+    format_map(&entries)
+}
+
+/// Writes the compaction options of a table as the map they are set with.
+fn format_compaction(compaction: &CompactionOptions) -> String {
+    let package = "org.apache.cassandra.db.compaction";
+    let entries = match compaction {
+        CompactionOptions::SizeTiered {
+            min_threshold,
+            max_threshold,
+            bucket_low,
+            bucket_high,
+            min_sstable_size,
+        } => vec![
+            (
+                "class".to_string(),
+                format!("{package}.SizeTieredCompactionStrategy"),
+            ),
+            ("min_threshold".to_string(), min_threshold.to_string()),
+            ("max_threshold".to_string(), max_threshold.to_string()),
+            ("bucket_low".to_string(), bucket_low.to_string()),
+            ("bucket_high".to_string(), bucket_high.to_string()),
+            ("min_sstable_size".to_string(), min_sstable_size.to_string()),
+        ],
+        CompactionOptions::Leveled {
+            sstable_size_in_mb,
+            fanout_size,
+        } => vec![
+            (
+                "class".to_string(),
+                format!("{package}.LeveledCompactionStrategy"),
+            ),
+            (
+                "sstable_size_in_mb".to_string(),
+                sstable_size_in_mb.to_string(),
+            ),
+            ("fanout_size".to_string(), fanout_size.to_string()),
+        ],
+        CompactionOptions::TimeWindow {
+            compaction_window_unit,
+            compaction_window_size,
+        } => vec![
+            (
+                "class".to_string(),
+                format!("{package}.TimeWindowCompactionStrategy"),
+            ),
+            (
+                "compaction_window_unit".to_string(),
+                compaction_window_unit.clone(),
+            ),
+            (
+                "compaction_window_size".to_string(),
+                compaction_window_size.to_string(),
+            ),
+        ],
+    };
+    format_map(&entries)
+}
+
+fn format_map(entries: &[(String, String)]) -> String {
+    let entries = entries
+        .iter()
+        .map(|(key, value)| format!("'{key}': '{value}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{{{entries}}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir_all, remove_dir_all},
+        path::Path,
+        sync::{Arc, RwLock},
+    };
+
+    use db::initialize_context;
+    use query::process_query;
+
+    use super::*;
+    use crate::{
+        connections::{gossip::failure_detector::FailureDetector, node::process_replica_query},
+        partitioner::murmur3::Partitioner,
+        test_utils::node,
+    };
+
+    fn manager() -> GossipManager {
+        let nodes = vec![
+            node("a", "datacenter1", "rack1", &[100]),
+            node("b", "datacenter2", "rack2", &[200]),
+        ];
+        let partitioner = Partitioner::new(nodes[0].clone(), nodes, Vec::new()).unwrap();
+        GossipManager::new(
+            Arc::new(partitioner),
+            &[],
+            Arc::new(FailureDetector::new(8.0)),
+        )
+    }
+
+    fn select(query: &str, manager: &GossipManager, ctx: &Context) -> Vec<Vec<String>> {
+        let (query, name) = process_query(query).unwrap();
+        let (keyspace, table) = name.split_once('.').unwrap();
+        let (schema, rows) = read_virtual_table(keyspace, table, manager, ctx).unwrap();
+        query
+            .select_rows(rows, &schema)
+            .unwrap()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_local_and_peers_describe_the_ring() {
+        let node_dir = Path::new("test_system_local_and_peers");
+        create_dir_all(node_dir).unwrap();
+        let ctx = initialize_context(node_dir).unwrap();
+        let manager = manager();
+
+        let local = select(
+            "SELECT broadcast_address, data_center, tokens, release_version FROM system.local",
+            &manager,
+            &ctx,
+        );
+        assert_eq!(
+            local,
+            vec![vec![
+                "a".to_string(),
+                "datacenter1".to_string(),
+                "100".to_string(),
+                env!("CARGO_PKG_VERSION").to_string(),
+            ]]
+        );
+
+        let peers = select(
+            "SELECT peer, data_center, rack, tokens FROM system.peers",
+            &manager,
+            &ctx,
+        );
+        assert_eq!(
+            peers,
+            vec![vec![
+                "b".to_string(),
+                "datacenter2".to_string(),
+                "rack2".to_string(),
+                "200".to_string(),
+            ]]
+        );
+
+        drop(ctx);
+        remove_dir_all(node_dir).unwrap();
+    }
+
+    #[test]
+    fn test_system_schema_describes_the_tables() {
+        let node_dir = Path::new("test_system_schema");
+        create_dir_all(node_dir).unwrap();
+        let ctx = RwLock::new(initialize_context(node_dir).unwrap());
+        for query in [
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 3}",
+            "CREATE TABLE ks.users (id int, name text, age int, PRIMARY KEY (id, name))",
+        ] {
+            let (mut query, table) = process_query(query).unwrap();
+            process_replica_query(&mut query, &table, &ctx).unwrap();
+        }
+        let manager = manager();
+        let ctx = ctx.read().unwrap();
+
+        let keyspaces = select(
+            "SELECT keyspace_name, replication FROM system_schema.keyspaces WHERE keyspace_name = 'ks'",
+            &manager,
+            &ctx,
+        );
+        assert_eq!(
+            keyspaces,
+            vec![vec![
+                "ks".to_string(),
+                "{'class': 'org.apache.cassandra.locator.SimpleStrategy', 'replication_factor': '3'}"
+                    .to_string(),
+            ]]
+        );
+
+        let tables = select(
+            "SELECT table_name FROM system_schema.tables WHERE keyspace_name = 'system'",
+            &manager,
+            &ctx,
+        );
+        assert_eq!(tables.len(), 2);
+
+        let columns = select(
+            "SELECT column_name, kind, position, type FROM system_schema.columns WHERE keyspace_name = 'ks' ORDER BY column_name",
+            &manager,
+            &ctx,
+        );
+        assert_eq!(
+            columns,
+            vec![
+                vec!["age", "regular", "-1", "int"],
+                vec!["id", "partition_key", "0", "int"],
+                vec!["name", "clustering", "0", "text"],
+            ]
+        );
+
+        drop(ctx);
+        remove_dir_all(node_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_virtual_tables_do_not_exist() {
+        let node_dir = Path::new("test_system_unknown_table");
+        create_dir_all(node_dir).unwrap();
+        let ctx = initialize_context(node_dir).unwrap();
+
+        assert!(read_virtual_table(SYSTEM, "missing", &manager(), &ctx).is_err());
+        assert!(read_virtual_table(SYSTEM_SCHEMA, "local", &manager(), &ctx).is_err());
+
+        drop(ctx);
+        remove_dir_all(node_dir).unwrap();
+    }
+}
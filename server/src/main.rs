@@ -5,21 +5,79 @@ use std::{
 };
 
 use chrono::Local;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use connections::{
-    client::handle_connection, gossip::manager::GossipManager, node::handle_internode_communication,
+    batch::replay_batchlog,
+    bootstrap::bootstrap,
+    client::handle_connection,
+    gossip::{failure_detector::FailureDetector, manager::GossipManager},
+    node::handle_internode_communication,
+    operation::send_operation,
+    prepared::PreparedStatements,
 };
-use db::initialize_context;
-use partitioner::murmur3::Partitioner;
-use shared::{get_keyspace, get_workspace, set_keyspace};
+use db::initialize_context_with_commitlog;
+use db::TokenRange;
+use inc::operation::{Operation, RepairOptions};
+use partitioner::{
+    murmur3::Partitioner,
+    node::{load_commitlog_config, load_phi_convict_threshold_config, load_seeds_config},
+};
+use shared::get_workspace;
 
 mod connections;
 mod partitioner;
+#[cfg(test)]
+mod test_utils;
 
 #[derive(Parser)]
 struct Node {
     #[arg(short = 'n', long = "node")]
     ip: Option<String>,
+    /// Datacenter of a node that is not declared in `cassandra.json` and joins the cluster.
+    #[arg(short = 'd', long = "datacenter")]
+    datacenter: Option<String>,
+    /// Rack of a node that is not declared in `cassandra.json` and joins the cluster.
+    #[arg(short = 'r', long = "rack")]
+    rack: Option<String>,
+    /// Operation to send to the running node, instead of starting it.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Removes the node from the ring, streaming its data to the nodes that take over its tokens.
+    Decommission,
+    /// Removes a dead node from the ring, streaming its data from the surviving replicas.
+    Removenode {
+        /// Ip of the dead node.
+        ip: String,
+    },
+    /// Repairs the data of the ranges the node is a replica of, streaming only what differs between the
+    /// replicas.
+    Repair {
+        /// Keyspace to repair. Every keyspace is repaired if it is not given.
+        keyspace: Option<String>,
+        /// Table of the keyspace to repair. Every table of the keyspace is repaired if it is not given.
+        table: Option<String>,
+        /// Repairs only the tokens greater than this one.
+        #[arg(
+            long = "start-token",
+            requires = "end_token",
+            allow_negative_numbers = true
+        )]
+        start_token: Option<i64>,
+        /// Repairs only the tokens up to this one.
+        #[arg(
+            long = "end-token",
+            requires = "start_token",
+            allow_negative_numbers = true
+        )]
+        end_token: Option<i64>,
+        /// Repairs every row, instead of only the ones written since the last repair.
+        #[arg(long)]
+        full: bool,
+    },
 }
 
 fn main() {
@@ -31,22 +89,78 @@ fn main() {
         std::process::exit(1);
     }
 
-    let partitioner = Arc::new(Partitioner::read_config(node.ip.unwrap()));
+    if let Some(command) = node.command {
+        let operation = match command {
+            Command::Decommission => Operation::Decommission,
+            Command::Removenode { ip } => Operation::RemoveNode(ip),
+            Command::Repair {
+                keyspace,
+                table,
+                start_token,
+                end_token,
+                full,
+            } => {
+                let range = start_token.zip(end_token);
+                if range.is_some_and(|(start, end)| start >= end) {
+                    eprintln!("The start token must be lower than the end token.");
+                    std::process::exit(1);
+                }
+                Operation::Repair(RepairOptions {
+                    keyspace,
+                    table,
+                    range: range.map(|(start, end)| TokenRange::new(start + 1, end)),
+                    full,
+                })
+            }
+        };
+        if let Err(e) = send_operation(&node.ip.unwrap(), 9043, operation) {
+            eprintln!("Operation failed: {e}");
+            std::process::exit(1);
+        }
+        println!("Operation completed");
+        return;
+    }
+
+    let partitioner = Arc::new(Partitioner::read_config(
+        node.ip.unwrap(),
+        node.datacenter,
+        node.rack,
+    ));
     let node_dir = get_workspace().join("data");
-    let ctx = Arc::new(RwLock::new(initialize_context(&node_dir).unwrap()));
-    set_keyspace(node_dir.join("sim"));
+    let commitlog_options = load_commitlog_config().unwrap();
+    let ctx = Arc::new(RwLock::new(
+        initialize_context_with_commitlog(&node_dir, commitlog_options).unwrap(),
+    ));
 
     let node_listener = TcpListener::bind("0.0.0.0:9043").unwrap();
     let ctx_clone = Arc::clone(&ctx);
+    let failure_detector = Arc::new(FailureDetector::new(
+        load_phi_convict_threshold_config().unwrap(),
+    ));
     let manager = Arc::new(RwLock::new(GossipManager::new(
-        &partitioner.self_node,
-        &partitioner.ring,
+        Arc::clone(&partitioner),
+        &load_seeds_config().unwrap(),
+        Arc::clone(&failure_detector),
     )));
+    let manager_clone = Arc::clone(&manager);
+    thread::spawn(move || {
+        handle_internode_communication(node_listener, ctx_clone, manager_clone);
+    });
+
+    if partitioner.is_joining() {
+        bootstrap(&partitioner, &manager, &ctx).unwrap();
+    }
+
+    let (partitioner_clone, manager_clone, ctx_clone) = (
+        Arc::clone(&partitioner),
+        Arc::clone(&manager),
+        Arc::clone(&ctx),
+    );
     thread::spawn(move || {
-        set_keyspace(node_dir.join("sim"));
-        handle_internode_communication(node_listener, ctx_clone, manager);
+        replay_batchlog(partitioner_clone, manager_clone, ctx_clone);
     });
 
+    let prepared = Arc::new(PreparedStatements::default());
     let listener = TcpListener::bind("0.0.0.0:9042").unwrap();
     println!(
         "Server up and listening at {}",
@@ -55,12 +169,11 @@ fn main() {
     // generate_sample_keys_and_hashes(50);
     while let Ok(stream) = listener.accept() {
         let partitioner = std::sync::Arc::clone(&partitioner);
+        let manager = Arc::clone(&manager);
+        let prepared = Arc::clone(&prepared);
         let ctx_clone = Arc::clone(&ctx);
-        let ks = get_keyspace();
-        let ks_clone = ks.clone();
         thread::spawn(move || {
-            set_keyspace(ks_clone);
-            handle_connection(stream.0, &partitioner, ctx_clone);
+            handle_connection(stream.0, &partitioner, &manager, &prepared, ctx_clone);
         });
     }
 }
@@ -1,57 +1,272 @@
+use std::sync::RwLock;
+
+use db::{get_partition_token, Replication, TokenRange};
+use inc::gossip::peer::Status;
 use murmur3::murmur3_x64_128;
 use rand::{distributions::Alphanumeric, Rng};
 use shared::not_found_error;
 
 use super::node::{load_nodes_config, Node};
 
-pub(crate) const ALL_NODES: &str = "ALL_NODES";
-
+/// Places the rows of the keyspaces in the nodes of the ring, by the token of their partition key.
+///
+/// The ring starts with the nodes of `cassandra.json`, and is updated as the gossip learns about the
+/// nodes that join the cluster.
 pub struct Partitioner {
-    pub(crate) ring: Vec<Node>,
     pub(crate) self_node: Node,
+    ring: RwLock<Ring>,
 }
 
+/// The nodes of the cluster and the tokens they own.
+#[derive(Debug, Default)]
+pub(crate) struct Ring {
+    /// The nodes that own their tokens.
+    nodes: Vec<Node>,
+    /// The nodes that are joining the ring. They receive the writes of the tokens they are going to
+    /// own, but are not read from.
+    joining: Vec<Node>,
+    /// The addresses of the nodes of `nodes` that are leaving the ring. The nodes that are going to own
+    /// their tokens receive their writes.
+    leaving: Vec<String>,
+    /// The tokens of `nodes`, sorted, with the index in `nodes` of the node that owns it.
+    tokens: Vec<(i64, usize)>,
+}
+
+/// Ranges of tokens, as their first and last tokens, with the nodes to stream the rows of each one from.
+pub(crate) type JoiningRanges = Vec<((i64, i64), Vec<Node>)>;
+
 impl Partitioner {
-    /// Reads the configuration file and returns a new Partitioner instance.  
+    /// Reads the configuration file and returns a new Partitioner instance.
+    /// If the node is not declared in the configuration, it is a new node that joins the cluster, and its
+    /// ring is learned through gossip.
+    ///
     /// **Must** be execute at node startup.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The address of the node.
+    /// * `datacenter` - The datacenter of the node, if it is not declared in the configuration.
+    /// * `rack` - The rack of the node, if it is not declared in the configuration.
     #[must_use]
-    pub fn read_config(ip: String) -> Self {
+    pub fn read_config(ip: String, datacenter: Option<String>, rack: Option<String>) -> Self {
         let nodes = load_nodes_config().unwrap();
-        let self_node = nodes
-            .iter()
-            .find(|node| node.ip_address == ip)
-            .unwrap()
-            .clone();
-        Self {
-            ring: nodes,
+        let partitioner = match nodes.iter().find(|node| node.ip_address == ip) {
+            Some(self_node) => Self::new(self_node.clone(), nodes, Vec::new()),
+            None => {
+                let self_node = Node::new(ip, datacenter, rack);
+                Self::new(self_node.clone(), Vec::new(), vec![self_node])
+            }
+        };
+        partitioner.unwrap()
+    }
+
+    /// Returns the partitioner of a node, with the nodes that own their tokens and the ones that are joining
+    /// the ring.
+    pub(crate) fn new(
+        self_node: Node,
+        nodes: Vec<Node>,
+        joining: Vec<Node>,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
             self_node,
+            ring: RwLock::new(Ring::new(nodes, joining, Vec::new())?),
+        })
+    }
+
+    /// Returns the nodes that are responsible for the partition with the given partition key values, in
+    /// the order of the schema, following the replication of its keyspace.
+    ///
+    /// With `SimpleStrategy` the replicas are the node that owns the token of the key and the ones that
+    /// follow it in the ring. With `NetworkTopologyStrategy` the ring is walked from the owner, taking
+    /// the nodes of each datacenter until it has its replicas, and skipping the nodes of racks that
+    /// already hold a replica until every rack of the datacenter does.
+    pub fn get_nodes(
+        &self,
+        partition_key: &[String],
+        replication: &Replication,
+    ) -> std::io::Result<Vec<Node>> {
+        let ring = self.ring.read().unwrap();
+        Ok(ring
+            .get_replicas(get_partition_token(partition_key)?, replication)?
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the nodes that are going to be replicas of the given partition once the nodes that are joining
+    /// or leaving the ring are done, which must receive its writes in the meantime.
+    pub fn get_pending_nodes(
+        &self,
+        partition_key: &[String],
+        replication: &Replication,
+    ) -> std::io::Result<Vec<Node>> {
+        let ring = self.ring.read().unwrap();
+        if ring.joining.is_empty() && ring.leaving.is_empty() {
+            return Ok(Vec::new());
         }
+        let future = Ring::new(
+            ring.nodes
+                .iter()
+                .filter(|node| !ring.leaving.contains(&node.ip_address))
+                .chain(&ring.joining)
+                .cloned()
+                .collect(),
+            Vec::new(),
+            Vec::new(),
+        )?;
+        let token = get_partition_token(partition_key)?;
+        let current = ring.get_replicas(token, replication)?;
+        Ok(future
+            .get_replicas(token, replication)?
+            .into_iter()
+            .filter(|node| {
+                !current
+                    .iter()
+                    .any(|replica| replica.ip_address == node.ip_address)
+            })
+            .cloned()
+            .collect())
     }
 
-    /// Returns the nodes that are responsible for the given key.
-    pub fn get_nodes(&self, key: &str) -> std::io::Result<Vec<&Node>> {
-        if key == ALL_NODES {
-            return Ok(self.ring.iter().collect());
+    /// Returns the ranges of tokens this node is a replica of, with all their replicas.
+    pub(crate) fn get_replica_ranges(
+        &self,
+        replication: &Replication,
+    ) -> std::io::Result<Vec<(TokenRange, Vec<Node>)>> {
+        let ring = self.ring.read().unwrap();
+        let mut ranges = Vec::new();
+        for (range, owner) in ring.get_ranges() {
+            let replicas = ring.get_replicas(owner, replication)?;
+            if replicas.iter().any(|node| self.is_me(node)) {
+                ranges.push((range, replicas.into_iter().cloned().collect()));
+            }
         }
+        Ok(ranges)
+    }
 
-        let hash = murmur3_x64_128(&mut key.as_bytes(), 0)? as i64;
-        let mut nodes = Vec::new();
-        let mut found = -1;
-        for (idx, node) in self.ring.iter().enumerate() {
-            if hash >= node.token_range.start && hash <= node.token_range.end {
-                nodes.push(node);
-                found = idx as i32;
-                break;
+    /// Returns the ring with the node as one of the owners of its tokens, such as once it joins the ring.
+    pub(crate) fn get_ring_with(&self, node: &Node) -> std::io::Result<Ring> {
+        let ring = self.ring.read().unwrap();
+        Ring::new(
+            ring.nodes
+                .iter()
+                .filter(|current| current.ip_address != node.ip_address)
+                .chain([node])
+                .cloned()
+                .collect(),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    /// Returns the ring as it is going to be once the node leaves it.
+    pub(crate) fn get_ring_without(&self, ip: &str) -> std::io::Result<Ring> {
+        let ring = self.ring.read().unwrap();
+        Ring::new(
+            ring.nodes
+                .iter()
+                .filter(|current| current.ip_address != ip)
+                .cloned()
+                .collect(),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    /// Returns the ranges of tokens the node is going to be a replica of once it joins the ring, as their
+    /// first and last tokens, with the nodes that are replicas of each one now.
+    pub(crate) fn get_joining_ranges(
+        &self,
+        node: &Node,
+        replication: &Replication,
+    ) -> std::io::Result<JoiningRanges> {
+        let future = self.get_ring_with(node)?;
+        let ring = self.ring.read().unwrap();
+        let mut ranges = Vec::new();
+        for (i, (token, _)) in future.tokens.iter().enumerate() {
+            if !future
+                .get_replicas(*token, replication)?
+                .iter()
+                .any(|replica| replica.ip_address == node.ip_address)
+            {
+                continue;
+            }
+            // The tokens of the node only split the ranges of the ring, so the replicas of the token are
+            // the replicas of its whole range.
+            let sources: Vec<Node> = ring
+                .get_replicas(*token, replication)?
+                .into_iter()
+                .cloned()
+                .collect();
+            let previous = future.tokens[(i + future.tokens.len() - 1) % future.tokens.len()].0;
+            if i == 0 {
+                if let Some(start) = previous.checked_add(1) {
+                    ranges.push(((start, i64::MAX), sources.clone()));
+                }
+                ranges.push(((i64::MIN, *token), sources));
+            } else if previous < *token {
+                ranges.push(((previous + 1, *token), sources));
             }
         }
-        if found == -1 {
-            return Err(not_found_error!("Node not found"));
+        Ok(ranges)
+    }
+
+    /// Returns the nodes that own their tokens.
+    pub fn get_normal_nodes(&self) -> Vec<Node> {
+        self.ring.read().unwrap().nodes.clone()
+    }
+
+    /// Returns every node of the cluster, including the ones that are joining it.
+    pub fn get_all_nodes(&self) -> Vec<Node> {
+        let ring = self.ring.read().unwrap();
+        ring.nodes.iter().chain(&ring.joining).cloned().collect()
+    }
+
+    /// Whether this node is joining the ring.
+    pub fn is_joining(&self) -> bool {
+        let ring = self.ring.read().unwrap();
+        ring.joining.iter().any(|node| self.is_me(node))
+    }
+
+    /// Adds a node to the ring, updates its tokens and its status, or removes it from the ring once it
+    /// left, as announced through gossip.
+    pub(crate) fn update_node(&self, node: Node, status: Status) -> std::io::Result<()> {
+        let mut ring = self.ring.write().unwrap();
+        let ip = &node.ip_address;
+        let is_leaving = ring.leaving.contains(ip);
+        let is_updated = match status {
+            Status::Normal => ring.nodes.contains(&node) && !is_leaving,
+            Status::Joining => ring.joining.contains(&node),
+            Status::Leaving => ring.nodes.contains(&node) && is_leaving,
+            Status::Left => !ring
+                .nodes
+                .iter()
+                .chain(&ring.joining)
+                .any(|current| &current.ip_address == ip),
+        };
+        if is_updated {
+            return Ok(());
         }
-        for offset in 1..=2 {
-            let next_idx = (found + offset) % self.ring.len() as i32;
-            nodes.push(&self.ring[next_idx as usize]);
+        println!("Node {} is now {:?}", ip, status);
+        let (mut nodes, mut joining, mut leaving) = (
+            ring.nodes.clone(),
+            ring.joining.clone(),
+            ring.leaving.clone(),
+        );
+        nodes.retain(|current| &current.ip_address != ip);
+        joining.retain(|current| &current.ip_address != ip);
+        leaving.retain(|current| current != ip);
+        match status {
+            Status::Normal => nodes.push(node),
+            Status::Joining => joining.push(node),
+            Status::Leaving => {
+                leaving.push(ip.clone());
+                nodes.push(node);
+            }
+            Status::Left => {}
         }
-        Ok(nodes)
+        *ring = Ring::new(nodes, joining, leaving)?;
+        Ok(())
     }
 
     pub fn is_me(&self, node: &Node) -> bool {
@@ -59,6 +274,113 @@ impl Partitioner {
     }
 }
 
+impl Ring {
+    /// Builds the token map of the nodes.
+    fn new(nodes: Vec<Node>, joining: Vec<Node>, leaving: Vec<String>) -> std::io::Result<Self> {
+        let normalize = |mut node: Node| -> std::io::Result<Node> {
+            node.tokens = node.get_tokens()?;
+            node.num_tokens = node.tokens.len();
+            Ok(node)
+        };
+        let nodes = nodes
+            .into_iter()
+            .map(normalize)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let joining = joining
+            .into_iter()
+            .map(normalize)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut tokens = Vec::new();
+        for (idx, node) in nodes.iter().enumerate() {
+            tokens.extend(node.tokens.iter().map(|token| (*token, idx)));
+        }
+        tokens.sort();
+        Ok(Ring {
+            nodes,
+            joining,
+            leaving,
+            tokens,
+        })
+    }
+
+    /// Returns the replicas of a token, following the replication of its keyspace.
+    pub(crate) fn get_replicas(
+        &self,
+        token: i64,
+        replication: &Replication,
+    ) -> std::io::Result<Vec<&Node>> {
+        let walk = self.walk(token)?.into_iter();
+        if !replication.is_network_topology() {
+            return Ok(walk.take(replication.replication_factor as usize).collect());
+        }
+
+        let mut nodes = Vec::new();
+        for (datacenter, factor) in &replication.datacenters {
+            let mut replicas = Vec::new();
+            let mut skipped = Vec::new();
+            for node in walk.clone().filter(|node| &node.datacenter == datacenter) {
+                if replicas
+                    .iter()
+                    .any(|replica: &&Node| replica.rack == node.rack)
+                {
+                    skipped.push(node);
+                } else {
+                    replicas.push(node);
+                }
+            }
+            replicas.extend(skipped);
+            replicas.truncate(*factor as usize);
+            nodes.extend(replicas);
+        }
+        Ok(nodes)
+    }
+
+    /// Returns the ranges of tokens between the tokens of the ring, with the token that owns each one.
+    /// The range that wraps around the ring is split at its end.
+    fn get_ranges(&self) -> Vec<(TokenRange, i64)> {
+        if self.tokens.len() == 1 {
+            return vec![(TokenRange::new(i64::MIN, i64::MAX), self.tokens[0].0)];
+        }
+        let mut ranges = Vec::new();
+        for (i, (token, _)) in self.tokens.iter().enumerate() {
+            let previous = self.tokens[(i + self.tokens.len() - 1) % self.tokens.len()].0;
+            if i == 0 {
+                if let Some(start) = previous.checked_add(1) {
+                    ranges.push((TokenRange::new(start, i64::MAX), *token));
+                }
+                ranges.push((TokenRange::new(i64::MIN, *token), *token));
+            } else if previous < *token {
+                ranges.push((TokenRange::new(previous + 1, *token), *token));
+            }
+        }
+        ranges
+    }
+
+    /// Returns the nodes of the ring in the order they are found walking it from the owner of the token,
+    /// the first token equal or greater than it, skipping the tokens of the nodes already found.
+    fn walk(&self, token: i64) -> std::io::Result<Vec<&Node>> {
+        if self.tokens.is_empty() {
+            return Err(not_found_error!("Node not found"));
+        }
+        let owner = self.tokens.partition_point(|(current, _)| *current < token);
+        let mut nodes: Vec<&Node> = Vec::new();
+        for offset in 0..self.tokens.len() {
+            let (_, idx) = self.tokens[(owner + offset) % self.tokens.len()];
+            let node = &self.nodes[idx];
+            if !nodes
+                .iter()
+                .any(|found| found.ip_address == node.ip_address)
+            {
+                nodes.push(node);
+            }
+            if nodes.len() == self.nodes.len() {
+                break;
+            }
+        }
+        Ok(nodes)
+    }
+}
+
 /// Used for debugging purposes.
 ///
 /// Generates sample keys and hashes to test the partitioner and hashing function to see where the query will be routed.
@@ -83,3 +405,211 @@ pub fn generate_sample_keys_and_hashes(sample_size: usize) {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_utils::{node, simple};
+
+    fn network_topology(datacenters: &[(&str, &str)]) -> Replication {
+        let mut map = HashMap::from([("class".to_string(), "NetworkTopologyStrategy".to_string())]);
+        for (datacenter, factor) in datacenters {
+            map.insert(datacenter.to_string(), factor.to_string());
+        }
+        Replication::new(&map).unwrap()
+    }
+
+    /// Two datacenters with two racks each, whose nodes alternate in the ring.
+    fn multi_datacenter_ring() -> Ring {
+        Ring::new(
+            vec![
+                node("a", "dc1", "r1", &[10]),
+                node("b", "dc1", "r1", &[20]),
+                node("c", "dc1", "r2", &[30]),
+                node("d", "dc1", "r2", &[40]),
+                node("e", "dc2", "r1", &[15]),
+                node("f", "dc2", "r1", &[25]),
+                node("g", "dc2", "r2", &[35]),
+            ],
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap()
+    }
+
+    fn ips(nodes: &[&Node]) -> Vec<String> {
+        nodes.iter().map(|node| node.ip_address.clone()).collect()
+    }
+
+    fn replicas_in(replicas: &[&Node], datacenter: &str) -> Vec<String> {
+        replicas
+            .iter()
+            .filter(|node| node.datacenter == datacenter)
+            .map(|node| node.ip_address.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_network_topology_replicas_per_datacenter() {
+        let ring = multi_datacenter_ring();
+        let replication = network_topology(&[("dc1", "2"), ("dc2", "1")]);
+        let replicas = ring.get_replicas(0, &replication).unwrap();
+
+        assert_eq!(replicas.len(), 3);
+        assert_eq!(replicas_in(&replicas, "dc1").len(), 2);
+        assert_eq!(replicas_in(&replicas, "dc2"), vec!["e"]);
+    }
+
+    #[test]
+    fn test_network_topology_spreads_replicas_across_racks() {
+        let ring = multi_datacenter_ring();
+        let replication = network_topology(&[("dc1", "2"), ("dc2", "2")]);
+        let replicas = ring.get_replicas(0, &replication).unwrap();
+        // b follows a in the ring, but is skipped as it is in the same rack.
+        assert_eq!(replicas_in(&replicas, "dc1"), vec!["a", "c"]);
+        assert_eq!(replicas_in(&replicas, "dc2"), vec!["e", "g"]);
+
+        // Walking from g, the owner of the token, the first node of dc1 is d, and the next one of the
+        // other rack is a.
+        let replicas = ring.get_replicas(32, &replication).unwrap();
+        assert_eq!(replicas_in(&replicas, "dc1"), vec!["d", "a"]);
+        assert_eq!(replicas_in(&replicas, "dc2"), vec!["g", "e"]);
+    }
+
+    #[test]
+    fn test_network_topology_falls_back_to_used_racks() {
+        let ring = multi_datacenter_ring();
+        let replication = network_topology(&[("dc1", "3"), ("dc2", "3")]);
+        let replicas = ring.get_replicas(0, &replication).unwrap();
+        // Once every rack holds a replica, the skipped nodes are taken in the order of the ring.
+        assert_eq!(replicas_in(&replicas, "dc1"), vec!["a", "c", "b"]);
+        assert_eq!(replicas_in(&replicas, "dc2"), vec!["e", "g", "f"]);
+
+        // A datacenter with fewer nodes than its replication factor has all of them as replicas.
+        let replication = network_topology(&[("dc2", "5"), ("dc3", "1")]);
+        let replicas = ring.get_replicas(0, &replication).unwrap();
+        assert_eq!(replicas_in(&replicas, "dc2"), vec!["e", "g", "f"]);
+        assert_eq!(replicas.len(), 3);
+    }
+
+    #[test]
+    fn test_walk_starts_at_the_owner_of_the_token() {
+        let ring = vec![
+            node("a", "dc1", "r1", &[100]),
+            node("b", "dc1", "r1", &[-100]),
+            node("c", "dc1", "r1", &[0]),
+        ];
+        let ring = Ring::new(ring, Vec::new(), Vec::new()).unwrap();
+
+        // The owner is the node of the first token equal or greater than the token.
+        assert_eq!(ips(&ring.walk(-100).unwrap()), vec!["b", "c", "a"]);
+        assert_eq!(ips(&ring.walk(-99).unwrap()), vec!["c", "a", "b"]);
+        assert_eq!(ips(&ring.walk(100).unwrap()), vec!["a", "b", "c"]);
+        // The tokens after the last one wrap around to the first one.
+        assert_eq!(ips(&ring.walk(101).unwrap()), vec!["b", "c", "a"]);
+        assert_eq!(ips(&ring.walk(i64::MAX).unwrap()), vec!["b", "c", "a"]);
+        assert_eq!(ips(&ring.walk(i64::MIN).unwrap()), vec!["b", "c", "a"]);
+
+        assert!(Ring::new(Vec::new(), Vec::new(), Vec::new())
+            .unwrap()
+            .walk(0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_walk_skips_the_nodes_already_found() {
+        let ring = vec![
+            node("a", "dc1", "r1", &[10, 20, 50]),
+            node("b", "dc1", "r1", &[30, 60]),
+            node("c", "dc1", "r1", &[40]),
+        ];
+        let ring = Ring::new(ring, Vec::new(), Vec::new()).unwrap();
+
+        assert_eq!(ips(&ring.walk(15).unwrap()), vec!["a", "b", "c"]);
+        assert_eq!(ips(&ring.walk(45).unwrap()), vec!["a", "b", "c"]);
+        assert_eq!(ips(&ring.walk(55).unwrap()), vec!["b", "a", "c"]);
+        assert_eq!(ips(&ring.walk(35).unwrap()), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_get_joining_ranges_with_their_current_replicas() {
+        let nodes = vec![
+            node("a", "dc1", "r1", &[-100, 100]),
+            node("b", "dc1", "r1", &[0]),
+        ];
+        let partitioner = Partitioner {
+            self_node: nodes[0].clone(),
+            ring: RwLock::new(Ring::new(nodes, Vec::new(), Vec::new()).unwrap()),
+        };
+        let joining = node("c", "dc1", "r1", &[50]);
+        let ranges = |replication: &Replication| -> Vec<((i64, i64), Vec<String>)> {
+            partitioner
+                .get_joining_ranges(&joining, replication)
+                .unwrap()
+                .into_iter()
+                .map(|(range, sources)| (range, ips(&sources.iter().collect::<Vec<_>>())))
+                .collect()
+        };
+
+        // The token of the node splits the range of a.
+        assert_eq!(ranges(&simple(1)), vec![((1, 50), vec!["a".to_string()])]);
+        assert_eq!(
+            ranges(&simple(2)),
+            vec![
+                ((-99, 0), vec!["b".to_string(), "a".to_string()]),
+                ((1, 50), vec!["a".to_string(), "b".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_node_leaving_and_left() {
+        let key = vec!["key".to_string()];
+        let token = get_partition_token(&key).unwrap();
+        let nodes = vec![
+            node("a", "dc1", "r1", &[token]),
+            node("b", "dc1", "r1", &[token.wrapping_add(1)]),
+            node("c", "dc1", "r1", &[token.wrapping_add(2)]),
+        ];
+        let partitioner = Partitioner::new(nodes[1].clone(), nodes.clone(), Vec::new()).unwrap();
+        let replication = simple(1);
+        // The replicas of the key, and the nodes that are going to be.
+        let replicas = |partitioner: &Partitioner| {
+            let ips = |nodes: Vec<Node>| -> Vec<String> {
+                nodes.into_iter().map(|node| node.ip_address).collect()
+            };
+            (
+                ips(partitioner.get_nodes(&key, &replication).unwrap()),
+                ips(partitioner.get_pending_nodes(&key, &replication).unwrap()),
+            )
+        };
+        assert_eq!(replicas(&partitioner), (vec!["a".to_string()], vec![]));
+
+        // A leaving node keeps its tokens, and the node that takes them over receives their writes.
+        partitioner
+            .update_node(nodes[0].clone(), Status::Leaving)
+            .unwrap();
+        partitioner
+            .update_node(nodes[0].clone(), Status::Leaving)
+            .unwrap();
+        assert_eq!(partitioner.get_normal_nodes().len(), 3);
+        assert_eq!(
+            replicas(&partitioner),
+            (vec!["a".to_string()], vec!["b".to_string()])
+        );
+        let future = partitioner.get_ring_without("a").unwrap();
+        assert_eq!(ips(&future.walk(token).unwrap()), vec!["b", "c"]);
+
+        // Once it left, the node is no longer part of the ring.
+        partitioner
+            .update_node(nodes[0].clone(), Status::Left)
+            .unwrap();
+        assert_eq!(
+            ips(&partitioner.get_normal_nodes().iter().collect::<Vec<_>>()),
+            vec!["b", "c"]
+        );
+        assert_eq!(replicas(&partitioner), (vec!["b".to_string()], vec![]));
+    }
+}
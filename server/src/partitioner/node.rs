@@ -1,29 +1,243 @@
 use std::{fs::File, io::BufReader};
 
+use db::{get_token, CommitLogOptions};
+use inc::gossip::peer::{ApplicationState, EndpointState, Peer, Status};
 use serde::{Deserialize, Serialize};
 use shared::get_workspace;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct TokenRange {
-    pub start: i64,
-    pub end: i64,
-}
+use crate::connections::gossip::failure_detector::DEFAULT_PHI_CONVICT_THRESHOLD;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// A node of the cluster, as declared in `cassandra.json`.
+///
+/// The node owns `num_tokens` tokens of the ring (its virtual nodes), and each of them the range of
+/// tokens that goes from the previous token of the ring. The tokens can be set with `tokens`, and are
+/// otherwise derived from the address of the node, so every node computes the same ring.
+///
+/// The configurations that still declare the `token_range` of the node, instead of its tokens, are read
+/// as the node owning a single token, the `end` of its range.
+///
+/// The `datacenter` and `rack` of the node are used to place the replicas of the keyspaces with
+/// `NetworkTopologyStrategy`. If they are not declared, the node is in `datacenter1` and `rack1`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Node {
     pub ip_address: String,
     pub port: u16,
-    pub token_range: TokenRange,
+    #[serde(default = "default_num_tokens")]
+    pub num_tokens: usize,
+    #[serde(default)]
+    pub tokens: Vec<i64>,
+    #[serde(default = "default_datacenter")]
+    pub datacenter: String,
+    #[serde(default = "default_rack")]
+    pub rack: String,
+}
+
+/// The port clients connect to. Nodes listen to other nodes on the next one.
+pub(crate) const DEFAULT_PORT: u16 = 9042;
+
+impl Node {
+    /// Creates a node that is not declared in `cassandra.json`, such as one that joins the cluster.
+    pub(crate) fn new(
+        ip_address: String,
+        datacenter: Option<String>,
+        rack: Option<String>,
+    ) -> Self {
+        Node {
+            ip_address,
+            port: DEFAULT_PORT,
+            num_tokens: default_num_tokens(),
+            tokens: Vec::new(),
+            datacenter: datacenter.unwrap_or_else(default_datacenter),
+            rack: rack.unwrap_or_else(default_rack),
+        }
+    }
+
+    /// Returns the node a peer announced through gossip, with its status, if its state is known.
+    pub(crate) fn from_peer(peer: &Peer) -> Option<(Node, Status)> {
+        let state = &peer.state;
+        let tokens = state.tokens()?;
+        let node = Node {
+            ip_address: peer.ip.clone(),
+            port: peer.port - 1,
+            num_tokens: tokens.len(),
+            tokens,
+            datacenter: state.datacenter()?.to_string(),
+            rack: state.rack()?.to_string(),
+        };
+        Some((node, state.status()?))
+    }
+
+    /// Sets what the node announces through gossip about its place in the ring: its datacenter, rack,
+    /// tokens and status.
+    pub(crate) fn set_application_states(
+        &self,
+        state: &mut EndpointState,
+        status: Status,
+    ) -> std::io::Result<()> {
+        let tokens = self
+            .get_tokens()?
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        state.set(ApplicationState::Dc, self.datacenter.clone());
+        state.set(ApplicationState::Rack, self.rack.clone());
+        state.set(ApplicationState::Tokens, tokens);
+        state.set(ApplicationState::Status, status.to_string());
+        Ok(())
+    }
+
+    /// Returns the tokens owned by the node.
+    pub fn get_tokens(&self) -> std::io::Result<Vec<i64>> {
+        if !self.tokens.is_empty() {
+            return Ok(self.tokens.clone());
+        }
+        (0..self.num_tokens)
+            .map(|vnode| get_token(&format!("{}:{}", self.ip_address, vnode)))
+            .collect()
+    }
+}
+
+fn default_num_tokens() -> usize {
+    16
+}
+
+fn default_datacenter() -> String {
+    "datacenter1".to_string()
+}
+
+fn default_rack() -> String {
+    "rack1".to_string()
+}
+
+/// The range of tokens of a node, as the configurations declared it before the nodes had virtual nodes.
+#[derive(Debug, Deserialize)]
+struct TokenRange {
+    end: i64,
+}
+
+/// A node as declared in `cassandra.json`, which may still be in the format that gave the node a single
+/// range of tokens.
+#[derive(Debug, Deserialize)]
+struct NodeConfig {
+    #[serde(flatten)]
+    node: Node,
+    #[serde(default)]
+    token_range: Option<TokenRange>,
+}
+
+impl NodeConfig {
+    /// Returns the node, owning the token at the end of its `token_range` if it has no `tokens`.
+    fn into_node(self) -> Node {
+        let mut node = self.node;
+        if let (Some(range), true) = (self.token_range, node.tokens.is_empty()) {
+            node.tokens = vec![range.end];
+            node.num_tokens = 1;
+        }
+        node
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize)]
 struct Config {
-    nodes: Vec<Node>,
+    #[serde(default)]
+    nodes: Vec<NodeConfig>,
+    /// The nodes a node that is not in `nodes` gossips with to join the cluster.
+    #[serde(default)]
+    seeds: Vec<String>,
+    #[serde(default)]
+    commitlog: CommitLogOptions,
+    /// The phi above which the failure detector considers a node down.
+    #[serde(default = "default_phi_convict_threshold")]
+    phi_convict_threshold: f64,
 }
 
-pub(crate) fn load_nodes_config() -> std::io::Result<Vec<Node>> {
+fn default_phi_convict_threshold() -> f64 {
+    DEFAULT_PHI_CONVICT_THRESHOLD
+}
+
+fn load_config() -> std::io::Result<Config> {
     let file = File::open(get_workspace().join("cassandra.json"))?;
     let reader = BufReader::new(file);
-    let config: Config = serde_json::from_reader(reader)?;
-    Ok(config.nodes)
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub(crate) fn load_nodes_config() -> std::io::Result<Vec<Node>> {
+    Ok(load_config()?
+        .nodes
+        .into_iter()
+        .map(NodeConfig::into_node)
+        .collect())
+}
+
+/// Reads the seed nodes. If there are none, the nodes of the cluster are the seeds.
+pub(crate) fn load_seeds_config() -> std::io::Result<Vec<String>> {
+    let config = load_config()?;
+    if !config.seeds.is_empty() {
+        return Ok(config.seeds);
+    }
+    Ok(config
+        .nodes
+        .into_iter()
+        .map(|config| config.node.ip_address)
+        .collect())
+}
+
+/// Reads the configuration of the commit log. If the `commitlog` section is missing, the defaults are used.
+pub(crate) fn load_commitlog_config() -> std::io::Result<CommitLogOptions> {
+    Ok(load_config()?.commitlog)
+}
+
+/// Reads the `phi_convict_threshold` of the failure detector, which is 8 if it is not set.
+pub(crate) fn load_phi_convict_threshold_config() -> std::io::Result<f64> {
+    Ok(load_config()?.phi_convict_threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_tokens() {
+        let mut node = Node::new("node1".to_string(), None, None);
+        let derived = node.get_tokens().unwrap();
+        let mut distinct = derived.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(derived.len(), node.num_tokens);
+        assert_eq!(distinct.len(), node.num_tokens);
+        // Every node derives the same tokens from the address.
+        assert_eq!(derived, node.clone().get_tokens().unwrap());
+        assert_ne!(
+            derived,
+            Node::new("node2".to_string(), None, None)
+                .get_tokens()
+                .unwrap()
+        );
+
+        node.tokens = vec![-5, 7];
+        assert_eq!(node.get_tokens().unwrap(), vec![-5, 7]);
+    }
+
+    #[test]
+    fn test_token_range_is_migrated_to_its_end_token() {
+        let config: Config = serde_json::from_str(
+            r#"{"nodes": [
+                {"ip_address": "node1", "port": 9042, "token_range": {"start": -10, "end": 20}},
+                {"ip_address": "node2", "port": 9042, "num_tokens": 4}
+            ]}"#,
+        )
+        .unwrap();
+        let nodes: Vec<Node> = config
+            .nodes
+            .into_iter()
+            .map(NodeConfig::into_node)
+            .collect();
+
+        assert_eq!(nodes[0].tokens, vec![20]);
+        assert_eq!(nodes[0].num_tokens, 1);
+        assert_eq!(nodes[0].datacenter, "datacenter1");
+        assert!(nodes[1].tokens.is_empty());
+        assert_eq!(nodes[1].get_tokens().unwrap().len(), 4);
+    }
 }
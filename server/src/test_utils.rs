@@ -0,0 +1,30 @@
+//! Fixtures shared by the unit tests of the server.
+
+use std::collections::HashMap;
+
+use db::Replication;
+
+use crate::partitioner::node::Node;
+
+/// Returns a node of the rack of the datacenter, which owns the tokens.
+pub(crate) fn node(ip: &str, datacenter: &str, rack: &str, tokens: &[i64]) -> Node {
+    let mut node = Node::new(
+        ip.to_string(),
+        Some(datacenter.to_string()),
+        Some(rack.to_string()),
+    );
+    node.tokens = tokens.to_vec();
+    node
+}
+
+/// Returns the replication of a keyspace with `SimpleStrategy`.
+pub(crate) fn simple(replication_factor: usize) -> Replication {
+    Replication::new(&HashMap::from([
+        ("class".to_string(), "SimpleStrategy".to_string()),
+        (
+            "replication_factor".to_string(),
+            replication_factor.to_string(),
+        ),
+    ]))
+    .unwrap()
+}
@@ -27,8 +27,13 @@ pub fn get_workspace() -> PathBuf {
 
 use std::path::PathBuf;
 
-pub use thread_context::connection::get_keyspace;
+pub use thread_context::connection::get_connection_ctx;
 pub use thread_context::connection::get_keyspace_name;
+pub use thread_context::connection::get_startup_option;
 pub use thread_context::connection::is_startup;
+pub use thread_context::connection::resolve_table;
+pub use thread_context::connection::set_connection_ctx;
 pub use thread_context::connection::set_keyspace;
 pub use thread_context::connection::set_startup;
+pub use thread_context::connection::set_startup_options;
+pub use thread_context::connection::ConnectionCtx;
@@ -1,45 +1,66 @@
 use std::cell::RefCell;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
 
 use crate::io_error;
 
-struct ConnectionCtx {
-    keyspace: PathBuf,
+/// State of a client session, kept per thread.
+///
+/// A snapshot can be taken with `get_connection_ctx` and installed on another thread with
+/// `set_connection_ctx`, so requests of the same session can be processed concurrently.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionCtx {
+    /// The keyspace set with `USE`, in which the tables not qualified with a keyspace are.
+    keyspace: Option<String>,
     startup: bool,
+    options: BTreeMap<String, String>,
 }
 
 thread_local! {
-    static CONNECTION_CTX: RefCell<ConnectionCtx> = RefCell::new(ConnectionCtx {
-        keyspace: PathBuf::new(),
-        startup: false,
-    })
+    static CONNECTION_CTX: RefCell<ConnectionCtx> = const {
+        RefCell::new(ConnectionCtx {
+            keyspace: None,
+            startup: false,
+            options: BTreeMap::new(),
+        })
+    }
 }
 
-pub fn set_keyspace(keyspace: PathBuf) {
+/// Sets the keyspace in use by the connection, as done by a `USE` query.
+pub fn set_keyspace(keyspace: String) {
     CONNECTION_CTX.with(|ctx| {
-        ctx.borrow_mut().keyspace = keyspace;
+        ctx.borrow_mut().keyspace = Some(keyspace);
     });
 }
 
-pub fn get_keyspace() -> PathBuf {
-    CONNECTION_CTX.with(|ctx| ctx.borrow().keyspace.clone())
-}
-
+/// Returns the name of the keyspace in use by the connection.
+///
+/// # Errors
+///
+/// * Returns an `Error` if no keyspace was set with `USE`.
 pub fn get_keyspace_name() -> std::io::Result<String> {
     CONNECTION_CTX.with(|ctx| {
-        Ok(ctx
-            .borrow()
-            .keyspace
-            .file_name()
-            .ok_or(io_error!("Error while trying to read keyspace name"))?
-            .to_str()
-            .ok_or(io_error!(
-                "Error while trying to convert to str the keyspace name"
-            ))?
-            .to_string())
+        ctx.borrow().keyspace.clone().ok_or(io_error!(
+            "No keyspace has been specified. USE a keyspace, or explicitly specify keyspace.tablename"
+        ))
     })
 }
 
+/// Splits the name of a table, which may be qualified with its keyspace as `<keyspace>.<table>`, into
+/// the names of the keyspace and the table. Tables that are not qualified are in the keyspace in use.
+///
+/// # Errors
+///
+/// * Returns an `Error` if the name is not qualified and no keyspace was set with `USE`.
+pub fn resolve_table(table: &str) -> std::io::Result<(String, String)> {
+    match table.split_once('.') {
+        Some((keyspace, table)) if !keyspace.is_empty() && !table.is_empty() => {
+            Ok((keyspace.to_string(), table.to_string()))
+        }
+        Some(_) => Err(io_error!(format!("Invalid table name '{table}'"))),
+        None => Ok((get_keyspace_name()?, table.to_string())),
+    }
+}
+
 pub fn set_startup(startup: bool) {
     CONNECTION_CTX.with(|ctx| {
         ctx.borrow_mut().startup = startup;
@@ -49,3 +70,26 @@ pub fn set_startup(startup: bool) {
 pub fn is_startup() -> bool {
     CONNECTION_CTX.with(|ctx| ctx.borrow().startup)
 }
+
+/// Stores the options negotiated in the `STARTUP` message of the connection.
+pub fn set_startup_options(options: BTreeMap<String, String>) {
+    CONNECTION_CTX.with(|ctx| {
+        ctx.borrow_mut().options = options;
+    });
+}
+
+pub fn get_startup_option(key: &str) -> Option<String> {
+    CONNECTION_CTX.with(|ctx| ctx.borrow().options.get(key).cloned())
+}
+
+/// Returns a snapshot of the session state of the current thread.
+pub fn get_connection_ctx() -> ConnectionCtx {
+    CONNECTION_CTX.with(|ctx| ctx.borrow().clone())
+}
+
+/// Replaces the session state of the current thread with the given one.
+pub fn set_connection_ctx(connection_ctx: ConnectionCtx) {
+    CONNECTION_CTX.with(|ctx| {
+        *ctx.borrow_mut() = connection_ctx;
+    });
+}